@@ -0,0 +1,81 @@
+#ifndef RSKEY_H
+#define RSKEY_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// An open store whose values are strings.
+typedef struct RskeyStore RskeyStore;
+
+// Opens the store whose data file is at `path`, creating an empty store if
+// there's no such file. Returns null on failure.
+//
+// # Safety
+//
+// `path` must be a NUL-terminated string.
+struct RskeyStore *rskey_open(const char *path);
+
+// Returns a copy of the value of `key`, resolving aliases, or null if
+// there's no such key or on failure. The copy must be freed with
+// [`rskey_free_string()`].
+//
+// # Safety
+//
+// `store` must be a pointer returned by [`rskey_open()`] and not yet
+// closed, and `key` a NUL-terminated string.
+char *rskey_get(struct RskeyStore *store, const char *key);
+
+// Sets `key` to `value`. The change isn't written to the data file until
+// [`rskey_sync()`] is called.
+//
+// # Safety
+//
+// `store` must be a pointer returned by [`rskey_open()`] and not yet
+// closed, and `key` and `value` NUL-terminated strings.
+int rskey_set(struct RskeyStore *store, const char *key, const char *value);
+
+// Removes `key`, if present. The change isn't written to the data file
+// until [`rskey_sync()`] is called.
+//
+// # Safety
+//
+// `store` must be a pointer returned by [`rskey_open()`] and not yet
+// closed, and `key` a NUL-terminated string.
+int rskey_del(struct RskeyStore *store, const char *key);
+
+// Atomically writes the store to its data file, so that other programs,
+// in any language, see either all of the changes or none of them.
+//
+// # Safety
+//
+// `store` must be a pointer returned by [`rskey_open()`] and not yet
+// closed.
+int rskey_sync(struct RskeyStore *store);
+
+// Frees the store, discarding any changes not yet synced. Does nothing if
+// `store` is null.
+//
+// # Safety
+//
+// `store` must be null, or a pointer returned by [`rskey_open()`] and not
+// yet closed. It mustn't be used afterwards.
+void rskey_close(struct RskeyStore *store);
+
+// Frees a string returned by [`rskey_get()`]. Does nothing if `s` is null.
+//
+// # Safety
+//
+// `s` must be null, or a pointer returned by [`rskey_get()`] and not yet
+// freed.
+void rskey_free_string(char *s);
+
+// Returns a description of the last error on the calling thread, or null
+// if the last call succeeded. The string is valid until the next call on
+// the same thread, and mustn't be freed.
+const char *rskey_last_error(void);
+
+#endif  /* RSKEY_H */
@@ -0,0 +1,5 @@
+fn main() {
+    // Node.js addons are linked against symbols provided by the `node`
+    // process that loads them.
+    napi_build::setup();
+}
@@ -0,0 +1,165 @@
+//! Node.js bindings for [`rskey`], as a native addon.
+//!
+//! Build the addon with `cargo build --release -p rskey-node`, and copy the
+//! library to `rskey.node`:
+//!
+//! ```js
+//! const { open } = require("./rskey.node");
+//!
+//! const store = await open("store.kv");
+//! store.set("build", "1234");
+//! console.log(store.get("build"));
+//! await store.sync();
+//! ```
+//!
+//! Opening and syncing read and write the data file on a worker thread, and
+//! return promises. The other methods only touch the copy in memory.
+
+// napi only registers the exports outside of tests.
+#![cfg_attr(test, allow(dead_code))]
+// The exports are called from JavaScript, which sees errors as exceptions
+// and can't be made to use return values.
+#![allow(clippy::missing_errors_doc, clippy::must_use_candidate)]
+
+use napi::bindgen_prelude::AsyncTask;
+use napi::{Env, Error, Result, Status, Task};
+use napi_derive::napi;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use rskey::Store;
+
+/// An open store whose values are strings.
+#[napi(js_name = "Store")]
+pub struct JsStore {
+    store: Arc<Mutex<Store<String>>>,
+}
+
+fn to_error(message: String) -> Error {
+    Error::new(Status::GenericFailure, message)
+}
+
+impl JsStore {
+    fn store(&self) -> MutexGuard<'_, Store<String>> {
+        self.store.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[napi]
+impl JsStore {
+    /// Returns the value of `key`, resolving aliases, or `null` if there's
+    /// no such key.
+    // Arguments from JavaScript are always passed by value.
+    #[allow(clippy::needless_pass_by_value)]
+    #[napi]
+    pub fn get(&self, key: String) -> Option<String> {
+        self.store().lookup(&key).cloned()
+    }
+
+    /// Sets `key` to `value`. The change isn't written to the data file
+    /// until `sync()` is called.
+    #[napi]
+    pub fn set(&self, key: String, value: String) -> Result<()> {
+        self.store()
+            .try_insert(key, value)
+            .map_err(|e| to_error(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Removes `key`, returning `true` if it was present.
+    #[allow(clippy::needless_pass_by_value)]
+    #[napi]
+    pub fn delete(&self, key: String) -> Result<bool> {
+        let old = self
+            .store()
+            .remove(&key)
+            .map_err(|e| to_error(e.to_string()))?;
+        Ok(old.is_some())
+    }
+
+    /// Atomically writes the store to its data file.
+    #[napi(ts_return_type = "Promise<void>")]
+    pub fn sync(&self) -> AsyncTask<SyncTask> {
+        AsyncTask::new(SyncTask(Arc::clone(&self.store)))
+    }
+}
+
+/// Opens the store whose data file is at `path`, creating an empty store if
+/// there's no such file.
+#[napi(ts_return_type = "Promise<Store>")]
+pub fn open(path: String) -> AsyncTask<OpenTask> {
+    AsyncTask::new(OpenTask(path))
+}
+
+/// Reads a data file on a worker thread.
+pub struct OpenTask(String);
+
+impl Task for OpenTask {
+    type Output = Store<String>;
+    type JsValue = JsStore;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        Store::open(&self.0).map_err(|e| to_error(format!("opening {}: {e}", self.0)))
+    }
+
+    fn resolve(&mut self, _env: Env, store: Self::Output) -> Result<Self::JsValue> {
+        Ok(JsStore {
+            store: Arc::new(Mutex::new(store)),
+        })
+    }
+}
+
+/// Writes a data file on a worker thread.
+pub struct SyncTask(Arc<Mutex<Store<String>>>);
+
+impl Task for SyncTask {
+    type Output = ();
+    type JsValue = ();
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let store = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        store
+            .sync()
+            .map_err(|e| to_error(format!("writing {}: {e}", store.path().display())))
+    }
+
+    fn resolve(&mut self, _env: Env, (): Self::Output) -> Result<Self::JsValue> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn store_methods_and_tasks_read_and_write_the_data_file() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        let path = path.to_string_lossy().into_owned();
+        let store = OpenTask(path.clone()).compute().unwrap();
+        let js = JsStore {
+            store: Arc::new(Mutex::new(store)),
+        };
+        js.set("a".to_string(), "1".to_string()).unwrap();
+        js.set("b".to_string(), "2".to_string()).unwrap();
+        js.store().alias("first", "a").unwrap();
+        assert_eq!(Some("1".to_string()), js.get("first".to_string()));
+        assert!(
+            js.delete("b".to_string()).unwrap(),
+            "present key not deleted"
+        );
+        assert!(!js.delete("b".to_string()).unwrap(), "absent key deleted");
+        js.store().protect("a");
+        assert!(js.set("a".to_string(), "3".to_string()).is_err());
+        SyncTask(Arc::clone(&js.store)).compute().unwrap();
+        let store = OpenTask(path).compute().unwrap();
+        assert_eq!(Some(&"1".to_string()), store.get("a"), "change not synced");
+        assert!(!store.contains_key("b"), "deletion not synced");
+        let missing = tmp_dir.path().join("missing").join("store.kv");
+        let err = SyncTask(Arc::new(Mutex::new(Store::from_entries(missing, []))))
+            .compute()
+            .unwrap_err();
+        assert!(err.reason.starts_with("writing "), "wrong error {err}");
+    }
+}
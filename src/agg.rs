@@ -0,0 +1,98 @@
+//! Summarizing numeric values.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::Store;
+
+/// A way of combining numeric values, for [`Store::aggregate()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Agg {
+    /// The total of the values.
+    Sum,
+    /// The smallest value.
+    Min,
+    /// The largest value.
+    Max,
+    /// The number of values.
+    Count,
+    /// The mean of the values.
+    Avg,
+}
+
+impl<V: Serialize> Store<V> {
+    /// Combines the numeric values of the keys starting with `prefix`, as
+    /// specified by `agg`. Pass an empty prefix to combine every value.
+    ///
+    /// A value is numeric if it serializes to a JSON number, or to a string
+    /// containing one, such as `"42"`, so this works for stores of strings
+    /// as well as of numbers. Other values are ignored.
+    ///
+    /// Returns `None` for [`Agg::Min`], [`Agg::Max`], or [`Agg::Avg`] if
+    /// there are no numeric values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// use rskey::{Agg, Store};
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<u32>::open(path)?;
+    /// s.try_insert("metrics:hits".to_string(), 40)?;
+    /// s.try_insert("metrics:misses".to_string(), 2)?;
+    /// s.try_insert("limit".to_string(), 100)?;
+    /// assert_eq!(s.aggregate("metrics:", Agg::Sum), Some(42.0));
+    /// assert_eq!(s.aggregate("", Agg::Max), Some(100.0));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn aggregate(&self, prefix: &str, agg: Agg) -> Option<f64> {
+        let values = self
+            .inner
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .filter_map(|(_, value)| as_number(value));
+        match agg {
+            Agg::Sum => Some(values.sum()),
+            Agg::Min => values.reduce(f64::min),
+            Agg::Max => values.reduce(f64::max),
+            Agg::Count => Some(values.count() as f64),
+            Agg::Avg => {
+                let (sum, count) = values.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+                (count > 0).then(|| sum / f64::from(count))
+            }
+        }
+    }
+}
+
+/// Returns the numeric value of `value`, if it has one.
+fn as_number<V: Serialize>(value: &V) -> Option<f64> {
+    match serde_json::to_value(value).ok()? {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok().filter(|n: &f64| n.is_finite()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregate_ignores_non_numeric_values() {
+        let entries = [("m:a", "1"), ("m:b", " 2.5 "), ("m:c", "n/a"), ("x", "10")]
+            .map(|(k, v)| (k.to_string(), v.to_string()));
+        let s = Store::from_entries("unused.kv", entries);
+        assert_eq!(Some(3.5), s.aggregate("m:", Agg::Sum), "wrong sum");
+        assert_eq!(Some(1.0), s.aggregate("m:", Agg::Min), "wrong min");
+        assert_eq!(Some(10.0), s.aggregate("", Agg::Max), "wrong max");
+        assert_eq!(Some(2.0), s.aggregate("m:", Agg::Count), "wrong count");
+        assert_eq!(Some(1.75), s.aggregate("m:", Agg::Avg), "wrong average");
+        assert_eq!(None, s.aggregate("none:", Agg::Avg), "average of nothing");
+        assert_eq!(Some(0.0), s.aggregate("none:", Agg::Sum), "sum of nothing");
+    }
+}
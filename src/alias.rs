@@ -0,0 +1,112 @@
+//! Alternative names for keys.
+
+use crate::{Store, StoreError};
+
+impl<V> Store<V> {
+    /// Makes `alias` an alternative name for `key`, so that
+    /// [`Self::lookup()`] and [`Self::resolve()`] treat them as the same.
+    /// Aliases are persisted with the store, and may refer to other aliases.
+    ///
+    /// This is useful when renaming keys: the old name can be kept as an
+    /// alias for the new one, until everything has been updated to use it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("db.url".to_string(), "postgres://db1".to_string())?;
+    /// s.alias("database_url", "db.url")?;
+    /// assert_eq!(s.lookup("database_url").unwrap(), "postgres://db1");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::AliasConflict`] if `alias` is already a key in
+    /// the store, or [`StoreError::AliasCycle`] if `key` is, or resolves
+    /// to, `alias`.
+    pub fn alias(&mut self, alias: &str, key: &str) -> Result<(), StoreError> {
+        let alias = self.normalize(alias).into_owned();
+        let key = self.normalize(key).into_owned();
+        if self.inner.contains_key(&alias) {
+            return Err(StoreError::AliasConflict(alias));
+        }
+        if self.resolve_chain(&key).any(|k| k == alias) {
+            return Err(StoreError::AliasCycle(alias));
+        }
+        self.meta.aliases.insert(alias, key);
+        self.touch();
+        Ok(())
+    }
+
+    /// Removes `alias`. The key it referred to is unaffected.
+    ///
+    /// Returns `false` if there was no such alias.
+    pub fn unalias(&mut self, alias: &str) -> bool {
+        let changed = self
+            .meta
+            .aliases
+            .remove(self.normalize(alias).as_ref())
+            .is_some();
+        if changed {
+            self.touch();
+        }
+        changed
+    }
+
+    /// Returns the key that `key` refers to, following any aliases. If `key`
+    /// isn't an alias, it's returned unchanged.
+    #[must_use]
+    pub fn resolve<'a>(&'a self, key: &'a str) -> &'a str {
+        self.resolve_chain(key).last().unwrap_or(key)
+    }
+
+    /// Returns the value for `key`, following any aliases.
+    #[must_use]
+    pub fn lookup(&self, key: &str) -> Option<&V> {
+        self.inner.get(self.resolve(&self.normalize(key)))
+    }
+
+    /// Returns an iterator over `key` and each key it's an alias for, in
+    /// turn.
+    pub(crate) fn resolve_chain<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> {
+        // `alias` never creates a cycle, but the data file might have been
+        // edited, so stop after visiting every alias once.
+        std::iter::successors(Some(key), |k| self.meta.aliases.get(*k).map(String::as_str))
+            .take(self.meta.aliases.len() + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn alias_resolves_chains_and_rejects_cycles() {
+        let mut s = Store::<u8>::new(PathBuf::from("unused.kv"));
+        s.try_insert("c".to_string(), 1).unwrap();
+        s.alias("b", "c").unwrap();
+        s.alias("a", "b").unwrap();
+        assert_eq!("c", s.resolve("a"), "chain not followed");
+        assert_eq!(Some(&1), s.lookup("a"), "expected data not returned");
+        assert_eq!("x", s.resolve("x"), "non-alias changed");
+        assert!(
+            matches!(s.alias("c", "a"), Err(StoreError::AliasConflict(_))),
+            "existing key aliased"
+        );
+        s.alias("d", "a").unwrap();
+        assert!(
+            matches!(s.alias("b", "d"), Err(StoreError::AliasCycle(_))),
+            "cycle not detected"
+        );
+        assert!(s.unalias("b"), "alias not removed");
+        assert_eq!(None, s.lookup("a"), "removed alias still resolved");
+    }
+}
@@ -0,0 +1,324 @@
+//! Where a store's data file is kept.
+
+use std::fmt::Debug;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Storage for a store's data file.
+///
+/// All the file operations a store performs go through its backend. By
+/// default, that's [`FileBackend`], which uses the filesystem. A different
+/// backend can be supplied using
+/// [`StoreBuilder::backend()`](crate::StoreBuilder::backend); for example,
+/// to keep the data in memory during tests, or to simulate I/O failures
+/// (see the `testing` module, which requires the `testing` feature).
+pub trait Backend: Debug + Send + Sync {
+    /// Returns the contents of the file at `path`, or `None` if there is no
+    /// such file.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error reading the file, other than its not existing.
+    fn read(&self, path: &Path) -> std::io::Result<Option<Vec<u8>>>;
+
+    /// Returns a reader for the file at `path`, or `None` if there is no
+    /// such file.
+    ///
+    /// The default implementation reads the whole file with
+    /// [`read`](Self::read). Backends that can stream a file should
+    /// override it, so that stores too large to fit in memory can be
+    /// scanned (see [`Store::scan()`](crate::Store::scan)).
+    ///
+    /// # Errors
+    ///
+    /// Returns any error opening the file, other than its not existing.
+    fn open(&self, path: &Path) -> std::io::Result<Option<Box<dyn Read + Send>>> {
+        let data = self.read(path)?;
+        Ok(data.map(|data| Box::new(Cursor::new(data)) as Box<dyn Read + Send>))
+    }
+
+    /// Replaces the contents of the file at `path` with `data`, creating
+    /// the file if necessary.
+    ///
+    /// This needn't be atomic: stores write to a temporary file first, and
+    /// then [`rename`](Self::rename) it into place.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error writing the file.
+    fn write(&self, path: &Path, data: &[u8]) -> std::io::Result<()>;
+
+    /// Adds `data` to the end of the file at `path`, creating the file if
+    /// necessary.
+    ///
+    /// The default implementation reads the whole file, and writes it back
+    /// with `data` added, so it's neither atomic nor efficient. Backends
+    /// that can append to a file should override it.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error reading or writing the file.
+    fn append(&self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+        let mut file = self.read(path)?.unwrap_or_default();
+        file.extend_from_slice(data);
+        self.write(path, &file)
+    }
+
+    /// Atomically replaces the file at `to` with the file at `from`.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error renaming the file, including if `from` doesn't
+    /// exist.
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()>;
+
+    /// Deletes the file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error deleting the file, including if it doesn't exist.
+    fn remove(&self, path: &Path) -> std::io::Result<()>;
+
+    /// Returns `true` if there is a file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error checking for the file.
+    fn exists(&self, path: &Path) -> std::io::Result<bool>;
+
+    /// Returns the size of the file at `path` in bytes, or `None` if there
+    /// is no such file.
+    ///
+    /// The default implementation reads the whole file with
+    /// [`read`](Self::read).
+    ///
+    /// # Errors
+    ///
+    /// Returns any error checking the file, other than its not existing.
+    fn size(&self, path: &Path) -> std::io::Result<Option<u64>> {
+        Ok(self.read(path)?.map(|data| data.len() as u64))
+    }
+
+    /// Returns the paths of the files in the directory `dir`, in arbitrary
+    /// order, or none if there is no such directory.
+    ///
+    /// The default implementation fails with
+    /// [`std::io::ErrorKind::Unsupported`], so features that list files,
+    /// such as snapshots, aren't available.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error listing the directory, other than its not existing.
+    fn list(&self, dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+        let _ = dir;
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    /// Creates the directory `dir`, and any missing parent directories.
+    ///
+    /// The default implementation does nothing, which suits backends that
+    /// have no directories.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error creating the directories, other than their already
+    /// existing.
+    fn create_dir_all(&self, dir: &Path) -> std::io::Result<()> {
+        let _ = dir;
+        Ok(())
+    }
+
+    /// Checks that a file could be created at `path`.
+    ///
+    /// The default implementation accepts any path.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing why `path` isn't usable.
+    fn check_path(&self, path: &Path) -> std::io::Result<()> {
+        let _ = path;
+        Ok(())
+    }
+}
+
+/// A [`Backend`] that keeps data files in the filesystem.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FileBackend;
+
+impl Backend for FileBackend {
+    fn read(&self, path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+        if fs::exists(path)? {
+            Ok(Some(fs::read(path)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn open(&self, path: &Path) -> std::io::Result<Option<Box<dyn Read + Send>>> {
+        match File::open(path) {
+            Ok(file) => Ok(Some(Box::new(file))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(data)?;
+        // Make sure the data is on disk before it's renamed into place.
+        file.sync_all()
+    }
+
+    fn append(&self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(data)
+    }
+
+    /// On Windows, an existing file at `to` is replaced using
+    /// `ReplaceFileW`, which keeps its attributes, such as being hidden,
+    /// and its permissions. Otherwise, the file is moved with
+    /// `MoveFileExW`. In both cases, the call doesn't return until the
+    /// change is on disk.
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        #[cfg(windows)]
+        return windows::replace(from, to);
+        #[cfg(not(windows))]
+        fs::rename(from, to)
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn exists(&self, path: &Path) -> std::io::Result<bool> {
+        fs::exists(path)
+    }
+
+    fn size(&self, path: &Path) -> std::io::Result<Option<u64>> {
+        match fs::metadata(path) {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn list(&self, dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut paths = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                paths.push(entry.path());
+            }
+        }
+        Ok(paths)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> std::io::Result<()> {
+        fs::create_dir_all(dir)
+    }
+
+    /// Rejects paths that are existing directories, or whose parent
+    /// directory doesn't exist.
+    fn check_path(&self, path: &Path) -> std::io::Result<()> {
+        if path.is_dir() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is a directory", path.display()),
+            ));
+        }
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            if !parent.is_dir() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("directory {} does not exist", parent.display()),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns `true` if `path` names a file directly in `dir`, for backends
+/// that keep files by path rather than in directories.
+#[cfg(any(feature = "testing", feature = "web"))]
+pub(crate) fn in_dir(path: &Path, dir: &Path) -> bool {
+    fn or_dot(dir: &Path) -> &Path {
+        if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        }
+    }
+    path.parent().map(or_dot) == Some(or_dot(dir))
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+    use std::ptr;
+    use windows_sys::Win32::Foundation::{ERROR_FILE_NOT_FOUND, ERROR_UNABLE_TO_REMOVE_REPLACED};
+    use windows_sys::Win32::Storage::FileSystem::{
+        MoveFileExW, ReplaceFileW, MOVEFILE_REPLACE_EXISTING, MOVEFILE_WRITE_THROUGH,
+        REPLACEFILE_IGNORE_MERGE_ERRORS, REPLACEFILE_WRITE_THROUGH,
+    };
+
+    /// Atomically replaces the file at `to` with the file at `from`.
+    pub(super) fn replace(from: &Path, to: &Path) -> io::Result<()> {
+        let from = wide(from);
+        let to = wide(to);
+        // SAFETY: both paths are NUL-terminated, and the optional arguments
+        // may be null.
+        let replaced = unsafe {
+            ReplaceFileW(
+                to.as_ptr(),
+                from.as_ptr(),
+                ptr::null(),
+                REPLACEFILE_WRITE_THROUGH | REPLACEFILE_IGNORE_MERGE_ERRORS,
+                ptr::null(),
+                ptr::null(),
+            )
+        };
+        if replaced != 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        // Either there's no file to replace yet, or it couldn't be replaced
+        // (for example, because it's read-only), but both files are still
+        // where they were, so a plain move is worth trying.
+        #[allow(clippy::cast_possible_wrap)]
+        let retryable = [ERROR_FILE_NOT_FOUND, ERROR_UNABLE_TO_REMOVE_REPLACED]
+            .map(|code| Some(code as i32))
+            .contains(&err.raw_os_error());
+        if !retryable {
+            return Err(err);
+        }
+        // SAFETY: both paths are NUL-terminated.
+        let moved = unsafe {
+            MoveFileExW(
+                from.as_ptr(),
+                to.as_ptr(),
+                MOVEFILE_REPLACE_EXISTING | MOVEFILE_WRITE_THROUGH,
+            )
+        };
+        if moved == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Returns `path` as a NUL-terminated UTF-16 string.
+    fn wide(path: &Path) -> Vec<u16> {
+        path.as_os_str().encode_wide().chain(Some(0)).collect()
+    }
+}
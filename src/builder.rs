@@ -0,0 +1,191 @@
+//! Configuring a store before opening it.
+
+use crate::retry::RetryBackend;
+use crate::{Backend, Clock, KeyNormalization, Retry, SigningKey, Store, StoreError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// A builder for opening a [`Store`] with non-default options, as returned
+/// by [`Store::builder()`].
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), rskey::StoreError> {
+/// use rskey::{SigningKey, Store};
+/// # use tempfile::TempDir;
+///
+/// # let tmp_dir = TempDir::new()?;
+/// # let path = tmp_dir.path().join("data.kv");
+/// let s: Store<String> = Store::builder(path)
+///     .signing_key(SigningKey::from_passphrase("secret"))
+///     .capacity(1000)
+///     .open()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+#[must_use]
+pub struct StoreBuilder<V> {
+    path: PathBuf,
+    signing_key: Option<SigningKey>,
+    capacity: usize,
+    backend: Option<Arc<dyn Backend>>,
+    clock: Option<Arc<dyn Clock>>,
+    normalization: Option<KeyNormalization>,
+    byte_limit: Option<usize>,
+    warn_at_entries: Option<usize>,
+    warn_at_bytes: Option<usize>,
+    git_autocommit: bool,
+    ops_log: bool,
+    key_index: bool,
+    retry: Option<Retry>,
+    _value: PhantomData<fn() -> V>,
+}
+
+impl<V> StoreBuilder<V> {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            signing_key: None,
+            capacity: 0,
+            backend: None,
+            clock: None,
+            normalization: None,
+            byte_limit: None,
+            warn_at_entries: None,
+            warn_at_bytes: None,
+            git_autocommit: false,
+            ops_log: false,
+            key_index: false,
+            retry: None,
+            _value: PhantomData,
+        }
+    }
+
+    /// Signs the data file with `key`, as with [`Store::open_signed()`].
+    pub fn signing_key(mut self, key: SigningKey) -> Self {
+        self.signing_key = Some(key);
+        self
+    }
+
+    /// Reserves room for at least `capacity` more entries, as with
+    /// [`Store::with_capacity()`].
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Keeps the data file in `backend`, instead of the filesystem.
+    pub fn backend(mut self, backend: impl Backend + 'static) -> Self {
+        self.backend = Some(Arc::new(backend));
+        self
+    }
+
+    /// Takes the current time from `clock`, instead of the system clock.
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// Limits the store's data to `limit` bytes, as with
+    /// [`Store::set_byte_limit()`]. Combine this with [`Self::capacity()`]
+    /// to allocate room for the expected number of entries up front.
+    pub fn byte_limit(mut self, limit: usize) -> Self {
+        self.byte_limit = Some(limit);
+        self
+    }
+
+    /// Warns when the store has more than `threshold` entries, as with
+    /// [`Store::set_warn_at_entries()`].
+    pub fn warn_at_entries(mut self, threshold: usize) -> Self {
+        self.warn_at_entries = Some(threshold);
+        self
+    }
+
+    /// Warns when the store's data is larger than `threshold` bytes, as
+    /// with [`Store::set_warn_at_bytes()`].
+    pub fn warn_at_bytes(mut self, threshold: usize) -> Self {
+        self.warn_at_bytes = Some(threshold);
+        self
+    }
+
+    /// Commits the data file to git after each sync, as with
+    /// [`Store::set_git_autocommit()`].
+    pub fn git_autocommit(mut self, enabled: bool) -> Self {
+        self.git_autocommit = enabled;
+        self
+    }
+
+    /// Records each change in an operations log when the store is synced,
+    /// as with [`Store::set_ops_log()`].
+    pub fn ops_log(mut self, enabled: bool) -> Self {
+        self.ops_log = enabled;
+        self
+    }
+
+    /// Keeps an ordered index of the store's keys, as with
+    /// [`Store::set_key_index()`].
+    pub fn key_index(mut self, enabled: bool) -> Self {
+        self.key_index = enabled;
+        self
+    }
+
+    /// Retries file operations that fail transiently, according to `retry`.
+    /// This applies to opening and syncing the store, and to any other
+    /// operation that uses its data file.
+    pub fn retry(mut self, retry: Retry) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Normalizes keys as specified, as with
+    /// [`Store::set_key_normalization()`]. If this isn't called, the store
+    /// keeps whatever setting it was last synced with.
+    pub fn key_normalization(mut self, normalization: KeyNormalization) -> Self {
+        self.normalization = Some(normalization);
+        self
+    }
+}
+
+impl<V> StoreBuilder<V>
+where
+    V: DeserializeOwned + Serialize,
+{
+    /// Opens the store with the configured options.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Tampered`] if a signing key was given and the
+    /// file's signature is missing or doesn't match,
+    /// [`StoreError::KeyCollision`] if normalizing the existing keys would
+    /// merge two of them, or any error opening the file.
+    pub fn open(self) -> Result<Store<V>, StoreError> {
+        let mut store = Store::new(self.path);
+        store.signing_key = self.signing_key;
+        if let Some(backend) = self.backend {
+            store.backend = backend;
+        }
+        if let Some(clock) = self.clock {
+            store.clock = clock;
+        }
+        if let Some(retry) = self.retry {
+            store.backend = Arc::new(RetryBackend::new(store.backend, retry));
+        }
+        let mut store = store.load()?;
+        store.inner.reserve(self.capacity);
+        store.byte_limit = self.byte_limit;
+        store.warn_at_entries = self.warn_at_entries;
+        store.warn_at_bytes = self.warn_at_bytes;
+        store.git_autocommit = self.git_autocommit;
+        store.ops_log = self.ops_log;
+        store.set_key_index(self.key_index);
+        if let Some(normalization) = self.normalization {
+            store.set_key_normalization(normalization)?;
+        }
+        Ok(store)
+    }
+}
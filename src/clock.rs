@@ -0,0 +1,44 @@
+//! Telling the time.
+
+use std::fmt::Debug;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A source of the current time, used for expiry times and snapshots.
+///
+/// By default, a store uses [`SystemClock`]. A different clock can be
+/// supplied using [`StoreBuilder::clock()`](crate::StoreBuilder::clock);
+/// for example, to control the time during tests (see `MockClock` in the
+/// `testing` module, which requires the `testing` feature), or on a device
+/// with its own time source.
+pub trait Clock: Debug + Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// A [`Clock`] that reads the system's clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[cfg(not(all(target_arch = "wasm32", feature = "web")))]
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    /// Browsers have no system clock that the standard library can use, so
+    /// ask JavaScript instead.
+    #[cfg(all(target_arch = "wasm32", feature = "web"))]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn now(&self) -> SystemTime {
+        let millis = js_sys::Date::now() as u64;
+        UNIX_EPOCH + std::time::Duration::from_millis(millis)
+    }
+}
+
+/// Returns the time given by `clock`, in seconds since the Unix epoch.
+pub(crate) fn unix_secs(clock: &dyn Clock) -> u64 {
+    clock
+        .now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
@@ -0,0 +1,160 @@
+//! Compressing large values individually in the data file.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::de::Error as _;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::Store;
+
+impl<V> Store<V> {
+    /// Returns the size above which values are compressed in the data
+    /// file, if any; see [`Self::set_compression_threshold()`].
+    #[must_use]
+    pub fn compression_threshold(&self) -> Option<usize> {
+        self.meta.compress_over
+    }
+
+    /// Compresses each value whose JSON is longer than `threshold` bytes
+    /// individually when the store is synced, or stops compressing values
+    /// if `threshold` is `None`. The threshold is persisted with the store.
+    ///
+    /// Compressed values are kept in a `compressed` section of the data
+    /// file, apart from the other entries, and are decompressed when the
+    /// store is opened, so the store in memory is unchanged. When the file
+    /// is read one entry at a time (see [`Self::scan()`]), only the value
+    /// being read is decompressed. This makes large, repetitive values,
+    /// such as documents or logs, much smaller on disk, at the cost of a
+    /// little time syncing and opening the store.
+    ///
+    /// Values are compressed with LZ4 and base64-encoded, so a value that
+    /// doesn't compress well is left as it is. Versions of `rskey` before
+    /// compression was added don't see compressed values, and drop them if
+    /// they sync the store, so don't compress stores they share.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(&path)?;
+    /// s.set_compression_threshold(Some(1024));
+    /// s.try_insert("log".to_string(), "all quiet\n".repeat(1000))?;
+    /// s.sync()?;
+    /// assert!(std::fs::metadata(&path)?.len() < 1024);
+    /// let s = Store::<String>::open(&path)?;
+    /// assert_eq!(s["log"].len(), 10_000);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_compression_threshold(&mut self, threshold: Option<usize>) {
+        if self.meta.compress_over != threshold {
+            self.meta.compress_over = threshold;
+            self.touch();
+        }
+    }
+}
+
+/// Returns the compressed form of each of `entries` whose value's JSON is
+/// longer than `threshold` bytes, and shrinks by compressing.
+pub(crate) fn compress_entries<'a, V: Serialize + 'a>(
+    entries: impl IntoIterator<Item = (&'a String, &'a V)>,
+    threshold: usize,
+) -> Result<BTreeMap<String, String>, serde_json::Error> {
+    let mut compressed = BTreeMap::new();
+    for (key, value) in entries {
+        if let Some(text) = compress(&serde_json::to_vec(value)?, threshold) {
+            compressed.insert(key.clone(), text);
+        }
+    }
+    Ok(compressed)
+}
+
+/// Compresses and base64-encodes `json`, if it's longer than `threshold`
+/// bytes and that makes it shorter.
+pub(crate) fn compress(json: &[u8], threshold: usize) -> Option<String> {
+    if json.len() <= threshold {
+        return None;
+    }
+    let text = BASE64.encode(lz4_flex::compress_prepend_size(json));
+    (text.len() < json.len()).then_some(text)
+}
+
+/// Decodes and decompresses the value for `key`, as compressed by
+/// [`compress()`], returning its JSON.
+pub(crate) fn decompress(key: &str, text: &str) -> Result<Vec<u8>, serde_json::Error> {
+    BASE64
+        .decode(text)
+        .ok()
+        .and_then(|bytes| lz4_flex::decompress_size_prepended(&bytes).ok())
+        .ok_or_else(|| serde_json::Error::custom(format!("can't decompress value of {key:?}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::ops::ControlFlow;
+    use tempfile::TempDir;
+
+    #[test]
+    fn values_over_threshold_are_compressed_on_disk_and_restored() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        let big = "abc".repeat(100);
+        let mut s = Store::<String>::open(&path).unwrap();
+        s.set_compression_threshold(Some(100));
+        s.try_insert("big".to_string(), big.clone()).unwrap();
+        s.try_insert("small".to_string(), "abc".to_string())
+            .unwrap();
+        s.sync().unwrap();
+        let file = fs::read_to_string(&path).unwrap();
+        assert!(!file.contains(&big), "value not compressed: {file}");
+        assert!(file.contains(r#""small":"abc""#), "small value compressed");
+        let s = Store::<String>::open(&path).unwrap();
+        assert_eq!(big, s["big"], "wrong value");
+        assert_eq!(2, s.len(), "wrong number of entries");
+        assert_eq!(Some(100), s.compression_threshold(), "threshold lost");
+        #[cfg(feature = "rayon")]
+        {
+            s.sync_parallel().unwrap();
+            let file = fs::read_to_string(&path).unwrap();
+            assert!(!file.contains(&big), "value not compressed in parallel");
+            let s = Store::<String>::open_parallel(&path).unwrap();
+            assert_eq!(big, s["big"], "wrong value read in parallel");
+        }
+        #[cfg(feature = "dashmap")]
+        {
+            let s = crate::ConcurrentStore::<String>::open(&path).unwrap();
+            s.sync().unwrap();
+            let file = fs::read_to_string(&path).unwrap();
+            assert!(!file.contains(&big), "value not compressed concurrently");
+        }
+        let s = Store::<String>::open_metadata(&path).unwrap();
+        let mut scanned = Vec::new();
+        s.scan(|k, v| {
+            scanned.push((k, v.len()));
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        scanned.sort();
+        assert_eq!(
+            vec![("big".to_string(), 300), ("small".to_string(), 3)],
+            scanned,
+            "wrong entries scanned"
+        );
+    }
+
+    #[test]
+    fn values_that_dont_shrink_or_are_corrupt_are_handled() {
+        assert_eq!(None, compress(b"\"x\"", 0), "tiny value compressed");
+        let text = compress(&[b'a'; 100], 10).expect("value not compressed");
+        assert_eq!(vec![b'a'; 100], decompress("k", &text).unwrap());
+        let err = decompress("k", "not base64!").unwrap_err();
+        assert!(err.to_string().contains("\"k\""), "wrong error {err}");
+    }
+}
@@ -0,0 +1,477 @@
+//! A store that can be shared between threads.
+
+use crate::compress;
+use crate::format::{self, ContentsRef, Meta};
+use crate::schema;
+use crate::{Backend, SigningKey, Store, StoreError};
+use dashmap::mapref::one::Ref;
+use dashmap::DashMap;
+use serde::de::DeserializeOwned;
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard};
+
+/// A key-value store that many threads can read and write at once.
+///
+/// Unlike [`Store`], whose methods need exclusive (`&mut`) access to change
+/// the data, all the methods of a `ConcurrentStore` take `&self`, so it can
+/// be shared between threads using an [`Arc`](std::sync::Arc). The data is
+/// held in a sharded map, so threads using different keys rarely contend
+/// with each other.
+///
+/// Keys are normalized, and protected keys and schemas are enforced, as for
+/// a [`Store`], and changing a key updates its version, and clears its
+/// expiry time, just as [`Store::insert()`] does. A `ConcurrentStore` can't
+/// have a byte limit, an operations log, git autocommit, or derived keys,
+/// though, and doesn't keep a key index; see [`Self::try_from()`]. Values
+/// must be [`Clone`], since the store keeps a copy-on-write copy of its
+/// entries to share with views; see [`Self::view()`].
+///
+/// Requires the `dashmap` feature.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), rskey::StoreError> {
+/// use rskey::ConcurrentStore;
+/// use std::sync::Arc;
+/// use std::thread;
+/// # use tempfile::TempDir;
+///
+/// # let tmp_dir = TempDir::new()?;
+/// # let path = tmp_dir.path().join("data.kv");
+/// let s = Arc::new(ConcurrentStore::<usize>::open(path)?);
+/// let handles: Vec<_> = (0..4)
+///     .map(|i| {
+///         let s = Arc::clone(&s);
+///         thread::spawn(move || s.insert(format!("key{i}"), i))
+///     })
+///     .collect();
+/// for handle in handles {
+///     handle.join().unwrap()?;
+/// }
+/// assert_eq!(s.len(), 4);
+/// s.sync()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ConcurrentStore<V> {
+    path: PathBuf,
+    inner: DashMap<String, V>,
+    /// Changed along with the entries, while holding `writers` shared.
+    meta: RwLock<Meta>,
+    signing_key: Option<SigningKey>,
+    backend: Arc<dyn Backend>,
+    /// The generation of the data file, as last loaded or synced.
+    generation: AtomicU64,
+    /// Held shared by writers, and exclusively by `sync` while it takes a
+    /// snapshot of the data, so that the snapshot never includes only some
+    /// of the changes made concurrently with it.
+    writers: RwLock<()>,
+    /// Held by `sync` while writing, so that concurrent syncs can't write
+    /// snapshots out of order.
+    syncing: Mutex<()>,
+    /// A copy of the entries, shared with any views taken since it last
+    /// changed, and copied by the next change if so. Held while changing
+    /// `inner`, so that both see the same changes in the same order.
+    current: Mutex<Arc<HashMap<String, V>>>,
+}
+
+impl<V> ConcurrentStore<V>
+where
+    V: Clone + DeserializeOwned + Serialize,
+{
+    /// Creates a [`ConcurrentStore`] associated with a data file at the
+    /// given `path`, as with [`Store::open()`].
+    ///
+    /// # Errors
+    ///
+    /// Returns any error opening the file (if it exists).
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, std::io::Error> {
+        Store::open(path).map(Self::from_store)
+    }
+
+    /// Writes a consistent snapshot of the store data to the associated
+    /// file.
+    ///
+    /// Other threads can keep reading the store while it's being synced,
+    /// but changes will wait until the snapshot has been taken.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` for any error creating the file or serializing the
+    /// JSON to it.
+    pub fn sync(&self) -> Result<(), std::io::Error> {
+        let _syncing = self.syncing.lock().unwrap_or_else(PoisonError::into_inner);
+        let (generation, doc) = {
+            let _writers = self.writers.write().unwrap_or_else(PoisonError::into_inner);
+            let generation = self.generation.load(Ordering::Relaxed) + 1;
+            let meta = self.meta.read().unwrap_or_else(PoisonError::into_inner);
+            let mut compressed = BTreeMap::new();
+            if let Some(threshold) = meta.compress_over {
+                for entry in &self.inner {
+                    let json = serde_json::to_vec(entry.value())?;
+                    if let Some(text) = compress::compress(&json, threshold) {
+                        compressed.insert(entry.key().clone(), text);
+                    }
+                }
+            }
+            let entries = Entries(&self.inner, &compressed);
+            let contents =
+                ContentsRef::new(generation, &meta, &entries).with_compressed(&compressed);
+            let doc = serde_json::to_vec(&contents)?;
+            (generation, doc)
+        };
+        format::write_file(&*self.backend, &self.path, doc, self.signing_key.as_ref())?;
+        self.generation.store(generation, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl<V: Clone> ConcurrentStore<V> {
+    /// Returns a reference to the value for `key`, if any.
+    ///
+    /// The shard containing `key` stays locked for reading until the
+    /// reference is dropped, so don't hold on to it for longer than
+    /// necessary.
+    pub fn get(&self, key: &str) -> Option<Ref<'_, String, V>> {
+        self.inner.get(self.normalize(key).as_ref())
+    }
+
+    /// Returns `true` if the store contains `key`.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.inner.contains_key(self.normalize(key).as_ref())
+    }
+
+    /// Returns the number of entries in the store.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns `true` if the store contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Inserts a key-value pair into the store, returning the previous value
+    /// for `key`, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Protected`] if `key` is protected, or
+    /// [`StoreError::Invalid`] if the value doesn't match the schema for
+    /// `key` (see [`Store::set_schema()`]).
+    pub fn insert(&self, key: String, value: V) -> Result<Option<V>, StoreError>
+    where
+        V: Serialize,
+    {
+        let key = self.normalize_owned(key);
+        if self.is_protected(&key) {
+            return Err(StoreError::Protected(key));
+        }
+        schema::validate_entry(&self.read_meta(), &key, &value)?;
+        Ok(self.force_insert(key, value))
+    }
+
+    /// Inserts a key-value pair into the store, even if `key` is protected.
+    ///
+    /// Any expiry time set for `key` is cleared, and if it was a scratch
+    /// entry (see [`Store::scratch()`]), it becomes permanent.
+    pub fn force_insert(&self, key: String, value: V) -> Option<V> {
+        let key = self.normalize_owned(key);
+        let _writers = self.writers.read().unwrap_or_else(PoisonError::into_inner);
+        {
+            let mut meta = self.meta.write().unwrap_or_else(PoisonError::into_inner);
+            let version = meta.versions.get(&key).copied().unwrap_or_default() + 1;
+            meta.versions.insert(key.clone(), version);
+            meta.expires.remove(&key);
+            meta.scratch.remove(&key);
+            meta.encrypted.remove(&key);
+        }
+        let mut current = self.lock_current();
+        Arc::make_mut(&mut current).insert(key.clone(), value.clone());
+        self.inner.insert(key, value)
+    }
+
+    /// Removes `key` from the store, returning its value, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Protected`] if `key` is protected.
+    pub fn remove(&self, key: &str) -> Result<Option<V>, StoreError> {
+        let key = self.normalize(key);
+        if self.is_protected(&key) {
+            return Err(StoreError::Protected(key.into_owned()));
+        }
+        Ok(self.force_remove(&key))
+    }
+
+    /// Removes `key` from the store, even if it is protected.
+    pub fn force_remove(&self, key: &str) -> Option<V> {
+        let key = self.normalize(key);
+        let _writers = self.writers.read().unwrap_or_else(PoisonError::into_inner);
+        let value = {
+            let mut current = self.lock_current();
+            if current.contains_key(key.as_ref()) {
+                Arc::make_mut(&mut current).remove(key.as_ref());
+            }
+            self.inner.remove(key.as_ref()).map(|(_, value)| value)
+        };
+        let mut meta = self.meta.write().unwrap_or_else(PoisonError::into_inner);
+        let encrypted = meta.encrypted.remove(key.as_ref());
+        if value.is_some() || encrypted.is_some() {
+            meta.versions.remove(key.as_ref());
+            meta.expires.remove(key.as_ref());
+            meta.scratch.remove(key.as_ref());
+        }
+        value
+    }
+
+    /// Returns `true` if `key` is protected.
+    ///
+    /// Keys can't be protected or unprotected through a `ConcurrentStore`;
+    /// use a [`Store`] to do that.
+    pub fn is_protected(&self, key: &str) -> bool {
+        let key = self.normalize(key);
+        self.read_meta().protected.contains(key.as_ref())
+    }
+
+    /// Returns the normalized form of `key`, according to the store's
+    /// setting.
+    fn normalize<'k>(&self, key: &'k str) -> Cow<'k, str> {
+        self.read_meta().normalize.apply(key)
+    }
+
+    /// Returns the normalized form of `key`, reusing it if it's unchanged.
+    fn normalize_owned(&self, key: String) -> String {
+        let normalized = match self.normalize(&key) {
+            Cow::Owned(normalized) => Some(normalized),
+            Cow::Borrowed(_) => None,
+        };
+        normalized.unwrap_or(key)
+    }
+
+    /// Returns the store's metadata, locked for reading.
+    fn read_meta(&self) -> RwLockReadGuard<'_, Meta> {
+        self.meta.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the shared copy of the entries, locked.
+    fn lock_current(&self) -> MutexGuard<'_, Arc<HashMap<String, V>>> {
+        self.current.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns a [`ConcurrentStore`] with the entries and metadata of
+    /// `store`, which must not use any of the features it doesn't support.
+    fn from_store(store: Store<V>) -> Self {
+        Self {
+            path: store.path,
+            inner: store
+                .inner
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            current: Mutex::new(Arc::new(store.inner)),
+            meta: RwLock::new(store.meta),
+            signing_key: store.signing_key,
+            backend: store.backend,
+            generation: store.generation,
+            writers: RwLock::default(),
+            syncing: Mutex::default(),
+        }
+    }
+
+    /// Returns a read-only view of the store's current entries, which later
+    /// changes to the store don't affect.
+    ///
+    /// Use a view to iterate over all the entries, for example to export
+    /// them, while other threads go on changing the store. Taking a view is
+    /// cheap, since it shares the store's own copy of the entries; the
+    /// first change made while any view of that copy is still alive
+    /// copies the entries again, so views are never affected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// use rskey::ConcurrentStore;
+    /// # use tempfile::TempDir;
+    ///
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let s = ConcurrentStore::<usize>::open(path)?;
+    /// s.insert("a".to_string(), 1)?;
+    /// let view = s.view();
+    /// s.insert("b".to_string(), 2)?;
+    /// assert_eq!(view.len(), 1);
+    /// assert_eq!(view.get("a"), Some(&1));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn view(&self) -> StoreView<V> {
+        StoreView(Arc::clone(&self.lock_current()))
+    }
+}
+
+/// A read-only view of the entries of a [`ConcurrentStore`] at some point
+/// in time; see [`ConcurrentStore::view()`].
+///
+/// Cloning a view is cheap, since the entries are shared.
+#[derive(Debug)]
+pub struct StoreView<V>(Arc<HashMap<String, V>>);
+
+impl<V> Clone for StoreView<V> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<V> Deref for StoreView<V> {
+    type Target = HashMap<String, V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<V: Clone> TryFrom<Store<V>> for ConcurrentStore<V> {
+    type Error = StoreError;
+
+    /// Converts `store` to a [`ConcurrentStore`], keeping its entries,
+    /// metadata, backend, and signing key. Its key index, if any, is
+    /// dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Io`] if `store` has a byte limit, an
+    /// operations log, git autocommit, or derived keys, none of which a
+    /// `ConcurrentStore` supports.
+    fn try_from(store: Store<V>) -> Result<Self, Self::Error> {
+        let unsupported = if store.byte_limit.is_some() {
+            Some("a byte limit")
+        } else if store.ops_log {
+            Some("an operations log")
+        } else if store.git_autocommit {
+            Some("git autocommit")
+        } else if !store.derived.is_empty() {
+            Some("derived keys")
+        } else {
+            None
+        };
+        if let Some(feature) = unsupported {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("a ConcurrentStore can't have {feature}"),
+            )
+            .into());
+        }
+        Ok(Self::from_store(store))
+    }
+}
+
+/// Serializes the entries of a [`DashMap`] as a JSON object, except for
+/// the compressed ones.
+struct Entries<'a, V>(&'a DashMap<String, V>, &'a BTreeMap<String, String>);
+
+impl<V: Serialize> Serialize for Entries<'_, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for entry in self
+            .0
+            .iter()
+            .filter(|entry| !self.1.contains_key(entry.key()))
+        {
+            map.serialize_entry(entry.key(), entry.value())?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use tempfile::TempDir;
+
+    #[test]
+    fn sync_persists_changes_from_many_threads() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        let s = Arc::new(ConcurrentStore::<usize>::open(&path).unwrap());
+        let handles: Vec<_> = (0..8)
+            .map(|t| {
+                let s = Arc::clone(&s);
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        s.insert(format!("k{t}-{i}"), i).unwrap();
+                        if i % 25 == 0 {
+                            s.sync().unwrap();
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        s.sync().unwrap();
+        let s2 = Store::<usize>::open(&path).unwrap();
+        assert_eq!(800, s2.len(), "wrong number of entries persisted");
+        assert_eq!(Some(&99), s2.get("k7-99"), "expected data not returned");
+    }
+
+    #[test]
+    fn view_is_shared_until_the_store_changes() {
+        let s = ConcurrentStore::try_from(Store::<usize>::new("unused.kv".into())).unwrap();
+        s.insert("a".to_string(), 1).unwrap();
+        let view = s.view();
+        assert!(Arc::ptr_eq(&view.0, &s.view().0), "unchanged view copied");
+        s.remove("a").unwrap();
+        let later = s.view();
+        assert_eq!(Some(&1), view.get("a"), "view changed");
+        assert!(later.is_empty(), "stale view returned");
+        s.insert("b".to_string(), 2).unwrap();
+        assert_eq!(Some(&2), s.view().get("b"), "change not in new view");
+        assert!(later.is_empty(), "view changed by insert");
+    }
+
+    #[test]
+    fn changes_normalize_keys_and_update_per_key_metadata() {
+        let mut store = Store::<usize>::new("unused.kv".into());
+        store
+            .set_key_normalization(crate::KeyNormalization {
+                case_fold: true,
+                nfc: false,
+            })
+            .unwrap();
+        store.try_insert("a".to_string(), 1).unwrap();
+        store.meta.expires.insert("a".to_string(), 4_000_000_000);
+        let s = ConcurrentStore::try_from(store).unwrap();
+        s.insert("A".to_string(), 2).unwrap();
+        assert_eq!(Some(2), s.get("a").map(|v| *v), "key not normalized");
+        let meta = s.read_meta().clone();
+        assert_eq!(Some(&2), meta.versions.get("a"), "version not bumped");
+        assert!(meta.expires.is_empty(), "expiry time not cleared");
+        s.remove("A").unwrap();
+        assert!(!s.contains_key("a"), "key not removed");
+        assert!(s.read_meta().versions.is_empty(), "version not cleared");
+    }
+
+    #[test]
+    fn try_from_refuses_unsupported_features() {
+        let mut store = Store::<usize>::new("unused.kv".into());
+        store.set_byte_limit(Some(100));
+        let err = ConcurrentStore::try_from(store).unwrap_err();
+        assert!(
+            err.to_string().contains("byte limit"),
+            "wrong error {err:?}"
+        );
+    }
+}
@@ -0,0 +1,311 @@
+//! Reading string values as numbers, flags, durations, and times.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{Store, StoreError};
+
+impl Store<String> {
+    /// Returns the value for `key` (following any aliases), if any, as an
+    /// integer. Surrounding whitespace, a leading `+`, and `_` separators
+    /// between digits are allowed, as in `1_000`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("workers".to_string(), " 1_000 ".to_string())?;
+    /// s.try_insert("name".to_string(), "web".to_string())?;
+    /// assert_eq!(s.get_int("workers")?, Some(1000));
+    /// assert_eq!(s.get_int("missing")?, None);
+    /// assert!(s.get_int("name").is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::TypeMismatch`] if the value isn't an integer.
+    pub fn get_int(&self, key: &str) -> Result<Option<i64>, StoreError> {
+        self.get_parsed(key, "an integer", parse_int)
+    }
+
+    /// Returns the value for `key` (following any aliases), if any, as a
+    /// boolean. Any of `true`, `yes`, `y`, `on`, or `1` is `true`, and any
+    /// of `false`, `no`, `n`, `off`, or `0` is `false`, ignoring case and
+    /// surrounding whitespace.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::TypeMismatch`] if the value isn't one of
+    /// these.
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, StoreError> {
+        self.get_parsed(key, "a boolean", parse_bool)
+    }
+
+    /// Returns the value for `key` (following any aliases), if any, as a
+    /// duration.
+    ///
+    /// A duration is a number of seconds, such as `90`, or a sequence of
+    /// numbers with units, such as `1h 30m` or `1.5h`. The units are `ms`,
+    /// `s`, `m`, `h`, `d`, and `w`, and their longer forms, such as `sec`,
+    /// `mins`, or `hours`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// use std::time::Duration;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("timeout".to_string(), "1m30s".to_string())?;
+    /// assert_eq!(s.get_duration("timeout")?, Some(Duration::from_secs(90)));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::TypeMismatch`] if the value isn't a duration.
+    pub fn get_duration(&self, key: &str) -> Result<Option<Duration>, StoreError> {
+        self.get_parsed(key, "a duration", parse_duration)
+    }
+
+    /// Returns the value for `key` (following any aliases), if any, as a
+    /// time.
+    ///
+    /// The time may be in RFC 3339 format, such as `2024-05-01T12:00:00Z`
+    /// or `2024-05-01 12:00:00.5+02:00`, a date, such as `2024-05-01`, for
+    /// midnight UTC, or a number of seconds since the Unix epoch. A time
+    /// with no offset is taken to be UTC.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("launch".to_string(), "1970-01-02T00:00:10Z".to_string())?;
+    /// assert_eq!(
+    ///     s.get_datetime("launch")?,
+    ///     Some(UNIX_EPOCH + Duration::from_secs(86_410))
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::TypeMismatch`] if the value isn't a time.
+    pub fn get_datetime(&self, key: &str) -> Result<Option<SystemTime>, StoreError> {
+        self.get_parsed(key, "a date and time", parse_datetime)
+    }
+
+    /// Returns the value for `key`, if any, converted by `parse`, which
+    /// returns `None` if the value isn't `what`.
+    fn get_parsed<T>(
+        &self,
+        key: &str,
+        what: &str,
+        parse: impl FnOnce(&str) -> Option<T>,
+    ) -> Result<Option<T>, StoreError> {
+        let Some(value) = self.lookup(key) else {
+            return Ok(None);
+        };
+        parse(value)
+            .map(Some)
+            .ok_or_else(|| StoreError::TypeMismatch {
+                key: key.to_string(),
+                message: format!("expected {what}, found {value:?}"),
+            })
+    }
+}
+
+fn parse_int(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let digits = text.strip_prefix(['+', '-']).unwrap_or(text);
+    if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+        return None;
+    }
+    text.replace('_', "").parse().ok()
+}
+
+fn parse_bool(text: &str) -> Option<bool> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" | "y" | "on" | "1" => Some(true),
+        "false" | "no" | "n" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    if let Ok(secs) = text.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let mut total = Duration::ZERO;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..end].parse().ok()?;
+        rest = rest[end..].trim_start();
+        let end = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let scale = match &rest[..end] {
+            "ms" | "msec" | "msecs" | "millisecond" | "milliseconds" => 0.001,
+            "s" | "sec" | "secs" | "second" | "seconds" => 1.0,
+            "m" | "min" | "mins" | "minute" | "minutes" => 60.0,
+            "h" | "hr" | "hrs" | "hour" | "hours" => 3600.0,
+            "d" | "day" | "days" => 86_400.0,
+            "w" | "week" | "weeks" => 604_800.0,
+            _ => return None,
+        };
+        total = total.checked_add(Duration::try_from_secs_f64(number * scale).ok()?)?;
+        rest = rest[end..].trim_start();
+    }
+    Some(total)
+}
+
+fn parse_datetime(text: &str) -> Option<SystemTime> {
+    let text = text.trim();
+    if let Ok(secs) = text.parse::<i64>() {
+        return time_from_secs(secs, 0);
+    }
+    let (date, time) = match text.get(10..) {
+        Some("") => (text, None),
+        Some(rest) if rest.starts_with(['T', 't', ' ']) => (&text[..10], Some(&rest[1..])),
+        _ => return None,
+    };
+    let year = number(date.get(0..4)?)?;
+    let month = number(date.get(5..7)?)?;
+    let day = number(date.get(8..10)?)?;
+    if &date[4..5] != "-" || &date[7..8] != "-" || !(1..=12).contains(&month) {
+        return None;
+    }
+    if day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    let mut secs = days_from_civil(year, month, day) * 86_400;
+    let mut nanos = 0;
+    if let Some(time) = time {
+        let (clock, offset) = match time.find(['Z', 'z', '+', '-']) {
+            Some(i) => (&time[..i], Some(&time[i..])),
+            None => (time, None),
+        };
+        let (clock, fraction) = clock.split_once('.').unwrap_or((clock, ""));
+        let mut fields = clock.split(':').map(number);
+        let hour = fields.next()??;
+        let minute = fields.next()??;
+        let second = fields.next().unwrap_or(Some(0))?;
+        if fields.next().is_some() || hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+        secs += hour * 3600 + minute * 60 + second;
+        if !fraction.is_empty() {
+            let digits: String = fraction
+                .chars()
+                .chain("000000000".chars())
+                .take(9)
+                .collect();
+            nanos = digits.parse().ok()?;
+        }
+        secs -= match offset {
+            None | Some("Z" | "z") => 0,
+            Some(offset) => {
+                let sign = if offset.starts_with('-') { -1 } else { 1 };
+                let offset = offset[1..].replace(':', "");
+                if offset.len() != 4 {
+                    return None;
+                }
+                sign * (number(offset.get(..2)?)? * 3600 + number(offset.get(2..)?)? * 60)
+            }
+        };
+    }
+    time_from_secs(secs, nanos)
+}
+
+/// Parses a string of ASCII digits.
+fn number(digits: &str) -> Option<i64> {
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Returns the number of days from the Unix epoch to the given date, using
+/// Howard Hinnant's `days_from_civil` algorithm.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn time_from_secs(secs: i64, nanos: u32) -> Option<SystemTime> {
+    let since_epoch = Duration::new(secs.unsigned_abs(), 0);
+    let time = if secs < 0 {
+        UNIX_EPOCH.checked_sub(since_epoch)?
+    } else {
+        UNIX_EPOCH.checked_add(since_epoch)?
+    };
+    time.checked_add(Duration::from_nanos(nanos.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_functions_accept_common_spellings() {
+        assert_eq!(Some(-1_000_000), parse_int(" -1_000_000"));
+        assert_eq!(None, parse_int("1__0"));
+        assert_eq!(Some(true), parse_bool("Yes"));
+        assert_eq!(Some(false), parse_bool("off "));
+        assert_eq!(None, parse_bool("maybe"));
+        let secs = Duration::from_secs;
+        assert_eq!(Some(secs(5400)), parse_duration("1h 30m"));
+        assert_eq!(Some(secs(5400)), parse_duration("1.5 hours"));
+        assert_eq!(Some(Duration::from_millis(250)), parse_duration("250ms"));
+        assert_eq!(None, parse_duration("5 parsecs"));
+        let at = |s| Some(UNIX_EPOCH + secs(s));
+        assert_eq!(at(1_714_564_800), parse_datetime("2024-05-01T12:00:00Z"));
+        assert_eq!(at(1_714_557_600), parse_datetime("2024-05-01 12:00+02:00"));
+        assert_eq!(at(1_709_164_800), parse_datetime("2024-02-29"));
+        assert_eq!(
+            at(10).map(|t| t + Duration::from_millis(500)),
+            parse_datetime("1970-01-01T00:00:10.5Z")
+        );
+        assert_eq!(
+            UNIX_EPOCH.checked_sub(secs(86_400)),
+            parse_datetime("1969-12-31")
+        );
+        assert_eq!(None, parse_datetime("2023-02-29"));
+        assert_eq!(None, parse_datetime("2024-05-01T25:00:00Z"));
+    }
+}
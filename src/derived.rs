@@ -0,0 +1,132 @@
+//! Keys whose values are computed from other entries.
+
+use std::borrow::Cow;
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+use crate::Store;
+
+/// The function that computes a derived key's value; see [`Store::derive()`].
+pub(crate) type DeriveFn<V> = Arc<dyn Fn(&Store<V>) -> Option<V> + Send + Sync>;
+
+/// A registered derivation, which can't be printed, so that the store can
+/// still be.
+pub(crate) struct Derivation<V>(pub(crate) DeriveFn<V>);
+
+impl<V> Debug for Derivation<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Derivation").finish_non_exhaustive()
+    }
+}
+
+impl<V> Store<V> {
+    /// Registers `key` as a derived key, whose value is computed by `f` from
+    /// the store's other entries each time it's read, replacing any previous
+    /// derivation for `key`.
+    ///
+    /// Derived keys aren't entries: they're not in the underlying `HashMap`,
+    /// and their values are never written to the data file. Read them with
+    /// [`Self::get_or_derive()`], [`Self::derived_value()`], or
+    /// [`Self::derived_entries()`]. The derivation isn't persisted either,
+    /// so register it each time the store is opened.
+    ///
+    /// `f` returns `None` if the value can't be computed, for example
+    /// because an entry it depends on is missing. If `key` is also an
+    /// entry, the entry takes precedence. A derivation that reads its own
+    /// key with [`Self::get_or_derive()`], directly or through other
+    /// derived keys, recurses forever.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("host".to_string(), "example.com".to_string())?;
+    /// s.try_insert("port".to_string(), "8080".to_string())?;
+    /// s.derive("full_url", |s| {
+    ///     Some(format!("https://{}:{}", s.get("host")?, s.get("port")?))
+    /// });
+    /// let url = s.get_or_derive("full_url").unwrap();
+    /// assert_eq!(url.as_str(), "https://example.com:8080");
+    /// assert!(!s.contains_key("full_url"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn derive(
+        &mut self,
+        key: &str,
+        f: impl Fn(&Store<V>) -> Option<V> + Send + Sync + 'static,
+    ) {
+        let key = self.normalize(key).into_owned();
+        self.derived.insert(key, Derivation(Arc::new(f)));
+    }
+
+    /// Removes the derivation for `key`, returning `true` if there was one.
+    pub fn underive(&mut self, key: &str) -> bool {
+        let key = self.normalize(key);
+        self.derived.remove(key.as_ref()).is_some()
+    }
+
+    /// Returns the derived keys, in order.
+    pub fn derived_keys(&self) -> impl Iterator<Item = &str> {
+        self.derived.keys().map(String::as_str)
+    }
+
+    /// Computes the value of the derived key `key`, returning `None` if it's
+    /// not a derived key, or its value can't be computed. Any entry for
+    /// `key` is ignored.
+    #[must_use]
+    pub fn derived_value(&self, key: &str) -> Option<V> {
+        let Derivation(f) = self.derived.get(self.normalize(key).as_ref())?;
+        f(self)
+    }
+
+    /// Returns the value for `key`, which is either an entry (following any
+    /// aliases) or, failing that, the computed value of a derived key.
+    #[must_use]
+    pub fn get_or_derive(&self, key: &str) -> Option<Cow<'_, V>>
+    where
+        V: Clone,
+    {
+        match self.lookup(key) {
+            Some(value) => Some(Cow::Borrowed(value)),
+            None => self.derived_value(key).map(Cow::Owned),
+        }
+    }
+
+    /// Computes the values of all the derived keys that aren't also entries,
+    /// in key order, leaving out any that can't be computed.
+    #[must_use]
+    pub fn derived_entries(&self) -> Vec<(&str, V)> {
+        self.derived
+            .iter()
+            .filter(|(key, _)| !self.inner.contains_key(*key))
+            .filter_map(|(key, Derivation(f))| Some((key.as_str(), f(self)?)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derived_values_follow_their_inputs_and_yield_to_entries() {
+        let entries = [("a", 1), ("b", 2)].map(|(k, v)| (k.to_string(), v));
+        let mut s = Store::from_entries("unused.kv", entries);
+        s.derive("sum", |s| Some(s.get("a")? + s.get("b")?));
+        s.derive("missing", |s| s.get("nonexistent").copied());
+        assert_eq!(vec![("sum", 3)], s.derived_entries(), "wrong entries");
+        s.try_insert("a".to_string(), 10).unwrap();
+        assert_eq!(Some(12), s.derived_value("sum"), "stale derived value");
+        s.try_insert("sum".to_string(), 0).unwrap();
+        assert_eq!(Some(Cow::Borrowed(&0)), s.get_or_derive("sum"));
+        assert!(s.derived_entries().is_empty(), "shadowed key listed");
+        assert!(s.underive("sum"), "derivation not removed");
+        assert_eq!(vec!["missing"], s.derived_keys().collect::<Vec<_>>());
+    }
+}
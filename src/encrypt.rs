@@ -0,0 +1,448 @@
+//! Encrypting individual values, so that a store can hold secrets alongside
+//! plaintext data.
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fmt::{self, Debug};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::format::{self, Contents};
+use crate::limit::sealed_size;
+use crate::sign::{decode_hex, encode_hex};
+use crate::{Store, StoreError};
+
+/// The length of an XChaCha20-Poly1305 nonce, in bytes.
+const NONCE_LEN: usize = 24;
+
+/// A secret key used to encrypt and decrypt individual values; see
+/// [`Store::insert_encrypted()`].
+#[derive(Clone)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    /// Creates an encryption key from a passphrase.
+    ///
+    /// The key is the SHA-256 hash of the passphrase, which does nothing to
+    /// slow down guessing, so use a long random passphrase, or a key file.
+    #[must_use]
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        Self::from_bytes(passphrase.as_bytes())
+    }
+
+    /// Creates an encryption key from the contents of a key file.
+    ///
+    /// The key is the SHA-256 hash of the whole file, including any
+    /// trailing newline. The file isn't part of any store, so it's read
+    /// from the filesystem, whatever backend the store uses.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error reading the file.
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::from_bytes(&fs::read(path)?))
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self(Sha256::digest(bytes).into())
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&self.0.into())
+    }
+}
+
+impl Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+impl<V> Store<V> {
+    /// Returns `true` if `key` (following any aliases) has an encrypted
+    /// value; see [`Self::insert_encrypted()`].
+    #[must_use]
+    pub fn is_encrypted(&self, key: &str) -> bool {
+        let key = self.normalize(key);
+        self.meta.encrypted.contains_key(self.resolve(&key))
+    }
+
+    /// Returns the keys with encrypted values, in order.
+    pub fn encrypted_keys(&self) -> impl Iterator<Item = &str> {
+        self.meta.encrypted.keys().map(String::as_str)
+    }
+}
+
+impl<V> Store<V>
+where
+    V: DeserializeOwned + Serialize,
+{
+    /// Re-encrypts every encrypted value (see [`Self::insert_encrypted()`])
+    /// with `new`, instead of `old`, and returns the number re-encrypted.
+    ///
+    /// The encrypted values in every snapshot (see [`Self::snapshot()`])
+    /// are re-encrypted too, and each snapshot is rewritten in place, so
+    /// that it can still be restored. Every value, in the store and its
+    /// snapshots, is decrypted before anything is changed. The values
+    /// themselves, and their versions, are unchanged. The store itself is
+    /// only changed in memory, so sync it afterwards.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// use rskey::{EncryptionKey, Store};
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let old = EncryptionKey::from_passphrase("old secret");
+    /// let new = EncryptionKey::from_passphrase("new secret");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.insert_encrypted("token".to_string(), &"xyz".to_string(), &old)?;
+    /// assert_eq!(s.rotate_encryption_key(&old, &new)?, 1);
+    /// assert!(s.get_encrypted("token", &old).is_err());
+    /// assert_eq!(s.get_encrypted("token", &new)?.as_deref(), Some("xyz"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Undecryptable`] if any value wasn't encrypted
+    /// with `old`, or has been changed, or [`StoreError::Tampered`] if the
+    /// store has a signing key and a snapshot's signature is missing or
+    /// doesn't match, in which case nothing is changed. Returns any error
+    /// encrypting a value, or reading or writing a snapshot.
+    pub fn rotate_encryption_key(
+        &mut self,
+        old: &EncryptionKey,
+        new: &EncryptionKey,
+    ) -> Result<usize, StoreError> {
+        let mut resealed = Vec::with_capacity(self.meta.encrypted.len());
+        for (key, sealed) in &self.meta.encrypted {
+            resealed.push((key.clone(), reseal(key, sealed, old, new)?));
+        }
+        let mut snapshots = Vec::new();
+        for snapshot in self.snapshots()? {
+            let file = self
+                .backend
+                .read(&snapshot.path)?
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+            let mut contents =
+                serde_json::from_slice::<Contents<Value>>(self.verify(&file)?)?.decompress()?;
+            if contents.meta.encrypted.is_empty() {
+                continue;
+            }
+            for (key, sealed) in &mut contents.meta.encrypted {
+                *sealed = reseal(key, sealed, old, new)?;
+            }
+            snapshots.push((snapshot.path, contents));
+        }
+        for (path, contents) in snapshots {
+            let doc = format::to_vec(contents.generation, &contents.meta, &contents.data)?;
+            format::write_file(&*self.backend, &path, doc, self.signing_key.as_ref())?;
+        }
+        let count = resealed.len();
+        if count > 0 {
+            self.meta.encrypted.extend(resealed);
+            self.touch();
+        }
+        Ok(count)
+    }
+
+    /// Encrypts `value` with `encryption_key`, and stores it for `key`
+    /// (following any aliases), replacing any value `key` already has.
+    ///
+    /// Encrypted values are kept in the data file's metadata, separately
+    /// from the other entries, which stay in plaintext. They're not in the
+    /// underlying `HashMap`, so read them with [`Self::get_encrypted()`],
+    /// but they count as entries for [`Self::contains_key()`],
+    /// [`Self::len()`], and [`Self::keys()`]. Setting `key` with [`Self::insert()`], or removing it, removes the
+    /// encrypted value. Encrypted values count towards the store's byte
+    /// limit at their encrypted size.
+    ///
+    /// Each value is encrypted with XChaCha20-Poly1305, using a random
+    /// nonce, and is bound to its key, so it can't be moved to another key
+    /// without being detected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// use rskey::{EncryptionKey, Store};
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let secret = EncryptionKey::from_passphrase("correct horse battery staple");
+    /// let mut s = Store::<String>::open(&path)?;
+    /// s.try_insert("host".to_string(), "example.com".to_string())?;
+    /// s.insert_encrypted("token".to_string(), &"xyz".to_string(), &secret)?;
+    /// s.sync()?;
+    /// assert!(!std::fs::read_to_string(&path)?.contains("xyz"));
+    /// let token = s.get_encrypted("token", &secret)?;
+    /// assert_eq!(token.as_deref(), Some("xyz"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Protected`] if `key` is protected,
+    /// [`StoreError::Invalid`] if `value` doesn't match the schema
+    /// for `key` (see [`Self::set_schema()`]), [`StoreError::Full`] if it
+    /// would take the store over its byte limit (see
+    /// [`Self::set_byte_limit()`]), or any error serializing or encrypting
+    /// it.
+    pub fn insert_encrypted(
+        &mut self,
+        key: String,
+        value: &V,
+        encryption_key: &EncryptionKey,
+    ) -> Result<(), StoreError> {
+        let key = self.normalize_owned(key);
+        let key = self.resolve(&key).to_string();
+        if self.is_protected(&key) {
+            return Err(StoreError::Protected(key));
+        }
+        self.validate(&key, value)?;
+        let sealed = seal(&key, &serde_json::to_vec(value)?, encryption_key)?;
+        let used = self.check_size(&key, sealed_size(&key, &sealed))?;
+        self.inner.remove(&key);
+        self.index_key(&key);
+        self.meta.expires.remove(&key);
+        self.meta.scratch.remove(&key);
+        self.bump_version(&key);
+        self.meta.encrypted.insert(key, sealed);
+        self.touch();
+        self.bytes_used = used;
+        Ok(())
+    }
+
+    /// Decrypts the encrypted value for `key` (following any aliases), if
+    /// any, with `encryption_key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Undecryptable`] if the value wasn't encrypted
+    /// with `encryption_key`, or has been changed, or any error
+    /// deserializing it.
+    pub fn get_encrypted(
+        &self,
+        key: &str,
+        encryption_key: &EncryptionKey,
+    ) -> Result<Option<V>, StoreError> {
+        let key = self.normalize(key);
+        let key = self.resolve(&key);
+        let Some(sealed) = self.meta.encrypted.get(key) else {
+            return Ok(None);
+        };
+        let plaintext = unseal(key, sealed, encryption_key)?;
+        Ok(Some(serde_json::from_slice(&plaintext)?))
+    }
+
+    /// Decrypts every encrypted value with `encryption_key`, and replaces
+    /// it with a plaintext entry, returning the number decrypted.
+    ///
+    /// Every value is decrypted before any is changed. Versions and other
+    /// metadata are unchanged. This is useful for exporting every entry
+    /// (see [`Self::export_matching()`]) from a store that isn't synced
+    /// afterwards: syncing it writes the values in plaintext.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// use rskey::{EncryptionKey, ExportFormat, Store};
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let secret = EncryptionKey::from_passphrase("correct horse battery staple");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.insert_encrypted("token".to_string(), &"xyz".to_string(), &secret)?;
+    /// assert!(s.export_matching(|_| true, Vec::new(), ExportFormat::Json).is_err());
+    /// assert_eq!(s.decrypt_all(&secret)?, 1);
+    /// assert_eq!(s["token"], "xyz");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Undecryptable`] if any value wasn't encrypted
+    /// with `encryption_key`, or has been changed, or any error
+    /// deserializing a value, in which case the store is unchanged.
+    pub fn decrypt_all(&mut self, encryption_key: &EncryptionKey) -> Result<usize, StoreError> {
+        let mut decrypted = Vec::with_capacity(self.meta.encrypted.len());
+        for (key, sealed) in &self.meta.encrypted {
+            let plaintext = unseal(key, sealed, encryption_key)?;
+            decrypted.push((key.clone(), serde_json::from_slice(&plaintext)?));
+        }
+        let count = decrypted.len();
+        if count > 0 {
+            self.meta.encrypted.clear();
+            self.inner.extend(decrypted);
+            self.touch();
+        }
+        Ok(count)
+    }
+}
+
+/// Encrypts `plaintext` with `encryption_key`, bound to `key`, returning the
+/// nonce and ciphertext in hex.
+fn seal(key: &str, plaintext: &[u8], encryption_key: &EncryptionKey) -> Result<String, StoreError> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let payload = Payload {
+        msg: plaintext,
+        aad: key.as_bytes(),
+    };
+    let ciphertext = encryption_key
+        .cipher()
+        .encrypt(&nonce, payload)
+        .map_err(|_| io::Error::other("value too large to encrypt"))?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&ciphertext);
+    Ok(encode_hex(&sealed))
+}
+
+/// Re-encrypts `sealed`, as returned by [`seal()`] for `key`, with `new`
+/// instead of `old`.
+fn reseal(
+    key: &str,
+    sealed: &str,
+    old: &EncryptionKey,
+    new: &EncryptionKey,
+) -> Result<String, StoreError> {
+    seal(key, &unseal(key, sealed, old)?, new)
+}
+
+/// Decrypts `sealed`, as returned by [`seal()`] for `key`, with
+/// `encryption_key`.
+fn unseal(key: &str, sealed: &str, encryption_key: &EncryptionKey) -> Result<Vec<u8>, StoreError> {
+    let undecryptable = || StoreError::Undecryptable(key.to_string());
+    let sealed = decode_hex(sealed).ok_or_else(undecryptable)?;
+    if sealed.len() < NONCE_LEN {
+        return Err(undecryptable());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let payload = Payload {
+        msg: ciphertext,
+        aad: key.as_bytes(),
+    };
+    encryption_key
+        .cipher()
+        .decrypt(XNonce::from_slice(nonce), payload)
+        .map_err(|_| undecryptable())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypted_values_need_the_right_key_and_stay_with_theirs() {
+        let secret = EncryptionKey::from_passphrase("secret");
+        let mut s = Store::<u32>::new("unused.kv".into());
+        s.try_insert("a".to_string(), 1).unwrap();
+        s.insert_encrypted("a".to_string(), &2, &secret).unwrap();
+        s.insert_encrypted("b".to_string(), &3, &secret).unwrap();
+        assert!(!s.inner.contains_key("a"), "plaintext value kept");
+        assert_eq!(Some(2), s.get_encrypted("a", &secret).unwrap());
+        let wrong = EncryptionKey::from_passphrase("wrong");
+        let err = s.get_encrypted("a", &wrong).unwrap_err();
+        assert!(
+            matches!(&err, StoreError::Undecryptable(key) if key == "a"),
+            "wrong error {err:?}"
+        );
+        // Moving a value to another key is detected.
+        let sealed = s.meta.encrypted["b"].clone();
+        s.meta.encrypted.insert("a".to_string(), sealed);
+        assert!(s.get_encrypted("a", &secret).is_err(), "moved value read");
+        s.alias("token", "b").unwrap();
+        s.insert_encrypted("token".to_string(), &5, &secret)
+            .unwrap();
+        assert_eq!(Some(5), s.get_encrypted("b", &secret).unwrap());
+        s.try_insert("a".to_string(), 4).unwrap();
+        s.remove("b").unwrap();
+        assert_eq!(0, s.encrypted_keys().count(), "encrypted values kept");
+    }
+
+    #[test]
+    fn rotate_encryption_key_re_encrypts_every_value_or_none() {
+        let old = EncryptionKey::from_passphrase("old");
+        let new = EncryptionKey::from_passphrase("new");
+        let mut s = Store::<u32>::new("unused.kv".into());
+        s.insert_encrypted("a".to_string(), &1, &old).unwrap();
+        s.insert_encrypted("b".to_string(), &2, &new).unwrap();
+        let before = s.meta.encrypted.clone();
+        let err = s.rotate_encryption_key(&old, &new).unwrap_err();
+        assert!(
+            matches!(&err, StoreError::Undecryptable(key) if key == "b"),
+            "wrong error {err:?}"
+        );
+        assert_eq!(before, s.meta.encrypted, "values changed despite error");
+        s.insert_encrypted("b".to_string(), &2, &old).unwrap();
+        let version = s.version("a");
+        assert_eq!(2, s.rotate_encryption_key(&old, &new).unwrap());
+        assert_eq!(Some(1), s.get_encrypted("a", &new).unwrap());
+        assert_eq!(Some(2), s.get_encrypted("b", &new).unwrap());
+        assert!(s.get_encrypted("a", &old).is_err(), "old key still works");
+        assert_eq!(version, s.version("a"), "version changed");
+    }
+
+    #[test]
+    fn rotate_encryption_key_re_encrypts_snapshots() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let old = EncryptionKey::from_passphrase("old");
+        let new = EncryptionKey::from_passphrase("new");
+        let signing_key = crate::SigningKey::from_passphrase("signing");
+        let path = tmp_dir.path().join("data.kv");
+        let mut s = Store::<u32>::open_signed(&path, signing_key).unwrap();
+        s.insert_encrypted("a".to_string(), &1, &old).unwrap();
+        let snapshot = s.snapshot().unwrap();
+        s.insert_encrypted("a".to_string(), &2, &old).unwrap();
+        assert_eq!(1, s.rotate_encryption_key(&old, &new).unwrap());
+        s.restore_snapshot(&snapshot).unwrap();
+        assert_eq!(Some(1), s.get_encrypted("a", &new).unwrap());
+        assert!(s.get_encrypted("a", &old).is_err(), "old key still works");
+    }
+
+    #[test]
+    fn encrypted_values_count_as_entries_and_towards_the_byte_limit() {
+        let secret = EncryptionKey::from_passphrase("secret");
+        let mut s = Store::<u32>::new("unused.kv".into());
+        s.try_insert("a".to_string(), 1).unwrap();
+        s.insert_encrypted("b".to_string(), &2, &secret).unwrap();
+        assert!(s.contains_key("b"), "encrypted key missing");
+        assert_eq!(2, s.len(), "wrong number of entries");
+        let mut keys: Vec<_> = s.keys().collect();
+        keys.sort();
+        assert_eq!(vec!["a", "b"], keys, "wrong keys");
+        let used = s.bytes_used();
+        assert_eq!(2 + sealed_size("b", &s.meta.encrypted["b"]), used);
+        s.set_byte_limit(Some(used));
+        assert!(
+            matches!(
+                s.insert_encrypted("c".to_string(), &3, &secret),
+                Err(StoreError::Full { key, .. }) if key == "c"
+            ),
+            "insert past limit allowed"
+        );
+        s.insert_encrypted("b".to_string(), &4, &secret)
+            .expect("same-size replacement rejected");
+        let err = s
+            .export_matching(|k| k == "b", Vec::new(), crate::ExportFormat::Json)
+            .unwrap_err();
+        assert!(
+            matches!(&err, StoreError::Encrypted(key) if key == "b"),
+            "wrong error {err:?}"
+        );
+        assert_eq!(1, s.decrypt_all(&secret).unwrap());
+        assert_eq!(Some(&4), s.get("b"), "value not decrypted");
+        assert_eq!(2, s.len(), "wrong number of entries");
+    }
+}
@@ -0,0 +1,113 @@
+//! In-place manipulation of a single store entry.
+
+use crate::Store;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// A function that syncs a store.
+type SyncFn<V> = fn(&Store<V>) -> std::io::Result<()>;
+
+/// A view into a single entry in a [`Store`], which may or may not be
+/// present, as returned by [`Store::entry()`].
+///
+/// Any change made through an `Entry` marks the store dirty.
+pub struct Entry<'a, V> {
+    store: &'a mut Store<V>,
+    key: String,
+    sync: Option<SyncFn<V>>,
+}
+
+impl<'a, V> Entry<'a, V> {
+    pub(crate) fn new(store: &'a mut Store<V>, key: String) -> Self {
+        Self {
+            store,
+            key,
+            sync: None,
+        }
+    }
+
+    /// Returns this entry's key.
+    #[must_use]
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Inserts `default` if the entry is empty, and returns a mutable
+    /// reference to the value.
+    pub fn or_insert(&mut self, default: V) -> &mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Inserts the result of calling `default` if the entry is empty, and
+    /// returns a mutable reference to the value.
+    pub fn or_insert_with(&mut self, default: impl FnOnce() -> V) -> &mut V {
+        self.store.touch();
+        self.store.bump_version(&self.key);
+        self.store.index_key(&self.key);
+        self.store
+            .inner
+            .entry(self.key.clone())
+            .or_insert_with(default)
+    }
+
+    /// Inserts the default value if the entry is empty, and returns a
+    /// mutable reference to the value.
+    pub fn or_default(&mut self) -> &mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Calls `f` on the value, if the entry is present.
+    #[must_use]
+    pub fn and_modify(self, f: impl FnOnce(&mut V)) -> Self {
+        if let Some(value) = self.store.inner.get_mut(&self.key) {
+            f(value);
+            self.store.touch();
+            self.store.bump_version(&self.key);
+        }
+        self
+    }
+}
+
+impl<V> Entry<'_, V>
+where
+    V: DeserializeOwned + Serialize,
+{
+    /// Makes the entry sync the store when it's dropped, if the store has
+    /// unsynced changes.
+    ///
+    /// Because the sync happens on drop, any error is ignored. If you need
+    /// to handle errors, call [`Store::sync()`] instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<usize>::open(path)?;
+    /// *s.entry("count")?.sync_on_drop().or_insert(0) += 1;
+    /// assert!(!s.is_dirty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn sync_on_drop(mut self) -> Self {
+        self.sync = Some(Store::sync);
+        self
+    }
+}
+
+impl<V> Drop for Entry<'_, V> {
+    fn drop(&mut self) {
+        if let Some(sync) = self.sync {
+            if self.store.is_dirty() {
+                let _ = sync(self.store);
+            }
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! Keys that expire after a given time.
+
+use crate::periodic::Periodic;
+use crate::{clock, Store};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+impl<V> Store<V> {
+    /// Sets `key` to expire after `ttl`, replacing any previous expiry time.
+    /// Once it has expired, the key is removed by the next call to
+    /// [`Self::purge_expired()`].
+    ///
+    /// Setting a new value for the key, or removing it, clears the expiry
+    /// time. Returns `false` if `key` isn't present.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// use std::time::Duration;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("session".to_string(), "abc123".to_string())?;
+    /// s.expire("session", Duration::ZERO);
+    /// assert_eq!(s.purge_expired(), 1);
+    /// assert!(s.is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn expire(&mut self, key: &str, ttl: Duration) -> bool {
+        let key = self.normalize(key).into_owned();
+        if !self.inner.contains_key(&key) {
+            return false;
+        }
+        let at = self.now().saturating_add(ttl.as_secs());
+        self.meta.expires.insert(key, at);
+        self.touch();
+        true
+    }
+
+    /// Clears any expiry time for `key`, so that it's kept indefinitely.
+    ///
+    /// Returns `false` if the key had no expiry time.
+    pub fn persist(&mut self, key: &str) -> bool {
+        let changed = self
+            .meta
+            .expires
+            .remove(self.normalize(key).as_ref())
+            .is_some();
+        if changed {
+            self.touch();
+        }
+        changed
+    }
+
+    /// Returns how long remains until `key` expires, or `None` if it has no
+    /// expiry time. A key that has expired, but not yet been purged, has a
+    /// TTL of zero.
+    #[must_use]
+    pub fn ttl(&self, key: &str) -> Option<Duration> {
+        let at = self.meta.expires.get(self.normalize(key).as_ref())?;
+        Some(Duration::from_secs(at.saturating_sub(self.now())))
+    }
+
+    /// Removes every key whose expiry time has passed, except for protected
+    /// keys, and returns the number removed.
+    ///
+    /// This doesn't sync the store.
+    pub fn purge_expired(&mut self) -> usize {
+        self.drain_expired().len()
+    }
+
+    /// Like [`Self::purge_expired()`], but returns the removed entries, so
+    /// that the caller can act on each expiry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// use std::time::Duration;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("session".to_string(), "abc123".to_string())?;
+    /// s.expire("session", Duration::ZERO);
+    /// let expired = s.drain_expired();
+    /// assert_eq!(expired, [("session".to_string(), "abc123".to_string())]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn drain_expired(&mut self) -> Vec<(String, V)> {
+        let doomed: Vec<_> = self
+            .meta
+            .expires
+            .keys()
+            .filter(|key| self.is_expired(key))
+            .cloned()
+            .collect();
+        let mut drained = Vec::with_capacity(doomed.len());
+        for key in doomed {
+            let value = self.force_remove(&key);
+            // The key may already have been removed without its expiry time
+            // (for example, through the underlying `HashMap`).
+            self.meta.expires.remove(&key);
+            self.touch();
+            if let Some(value) = value {
+                drained.push((key, value));
+            }
+        }
+        drained
+    }
+
+    /// Returns the current time, according to the store's clock, in seconds
+    /// since the Unix epoch.
+    fn now(&self) -> u64 {
+        clock::unix_secs(&*self.clock)
+    }
+
+    /// Returns `true` if `key` has expired and isn't protected, so it's due
+    /// to be purged.
+    pub(crate) fn is_expired(&self, key: &str) -> bool {
+        self.meta
+            .expires
+            .get(key)
+            .is_some_and(|&at| at <= self.now())
+            && !self.meta.protected.contains(key)
+    }
+}
+
+impl<V> Store<V>
+where
+    V: DeserializeOwned + Serialize + Send + 'static,
+{
+    /// Starts a background thread that purges expired keys from `store`
+    /// every `interval`, syncing it whenever anything was removed.
+    ///
+    /// The thread stops when the returned [`Sweeper`] is dropped. Any error
+    /// syncing the store is ignored, and the sync is retried next time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// use std::sync::{Arc, Mutex};
+    /// use std::time::Duration;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let store = Arc::new(Mutex::new(Store::<String>::open(path)?));
+    /// let sweeper = Store::spawn_sweeper(&store, Duration::from_secs(60));
+    /// // ...
+    /// drop(sweeper);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn spawn_sweeper(store: &Arc<Mutex<Self>>, interval: Duration) -> Sweeper {
+        Self::spawn_sweeper_with(store, interval, |_, _| {})
+    }
+
+    /// Like [`Self::spawn_sweeper()`], but calls `on_expired` with each
+    /// entry the thread removes, so that other parts of a program can react
+    /// to expiries as they happen.
+    ///
+    /// `on_expired` is called after the store has been synced and unlocked,
+    /// so it may use the store itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// use std::sync::mpsc;
+    /// use std::sync::{Arc, Mutex};
+    /// use std::time::Duration;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let store = Arc::new(Mutex::new(Store::<String>::open(path)?));
+    /// let (tx, rx) = mpsc::channel();
+    /// let sweeper = Store::spawn_sweeper_with(&store, Duration::from_millis(10), move |key, _| {
+    ///     let _ = tx.send(key);
+    /// });
+    /// {
+    ///     let mut s = store.lock().unwrap();
+    ///     s.try_insert("session".to_string(), "abc123".to_string())?;
+    ///     s.expire("session", Duration::ZERO);
+    /// }
+    /// assert_eq!(rx.recv().unwrap(), "session");
+    /// drop(sweeper);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn spawn_sweeper_with(
+        store: &Arc<Mutex<Self>>,
+        interval: Duration,
+        mut on_expired: impl FnMut(String, V) + Send + 'static,
+    ) -> Sweeper {
+        let store = Arc::clone(store);
+        let mut unsynced = false;
+        let task = Periodic::spawn(interval, move || {
+            let expired = {
+                let mut store = store.lock().unwrap_or_else(PoisonError::into_inner);
+                let expired = store.drain_expired();
+                if !expired.is_empty() || unsynced {
+                    unsynced = store.sync().is_err();
+                }
+                expired
+            };
+            for (key, value) in expired {
+                on_expired(key, value);
+            }
+        });
+        Sweeper { _task: task }
+    }
+}
+
+/// A background thread that purges expired keys from a store, as returned by
+/// [`Store::spawn_sweeper()`].
+///
+/// Dropping the `Sweeper` stops the thread, waiting for any sweep in progress
+/// to finish.
+#[derive(Debug)]
+pub struct Sweeper {
+    _task: Periodic,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn purge_expired_removes_only_expired_unprotected_keys() {
+        let entries = ["a", "b", "c", "d"].map(|k| (k.to_string(), 0));
+        let mut s = Store::<u8>::from_entries("unused.kv", entries);
+        s.expire("a", Duration::ZERO);
+        s.expire("b", Duration::ZERO);
+        s.protect("b");
+        s.expire("c", Duration::from_secs(3600));
+        assert!(!s.expire("missing", Duration::ZERO), "missing key expired");
+        assert_eq!(1, s.purge_expired(), "wrong number of keys purged");
+        assert!(!s.contains_key("a"), "expired key not purged");
+        assert!(s.contains_key("b"), "protected key purged");
+        let ttl = s.ttl("c").unwrap();
+        assert!(ttl > Duration::from_secs(3590), "wrong TTL {ttl:?}");
+        assert!(s.persist("c"), "expiry not cleared");
+        assert_eq!(None, s.ttl("c"), "expiry not cleared");
+        assert_eq!(None, s.ttl("d"), "unexpected expiry");
+    }
+
+    #[test]
+    fn setting_a_value_clears_its_expiry() {
+        let mut s = Store::<u8>::new(PathBuf::from("unused.kv"));
+        s.try_insert("a".to_string(), 1).unwrap();
+        s.expire("a", Duration::ZERO);
+        s.try_insert("a".to_string(), 2).unwrap();
+        assert_eq!(0, s.purge_expired(), "new value purged");
+    }
+
+    #[test]
+    fn expiry_methods_normalize_keys() {
+        let mut s = Store::<u8>::new(PathBuf::from("unused.kv"));
+        s.set_key_normalization(crate::KeyNormalization {
+            case_fold: true,
+            nfc: false,
+        })
+        .unwrap();
+        s.try_insert("Session".to_string(), 1).unwrap();
+        assert!(
+            s.expire("SESSION", Duration::from_secs(60)),
+            "key not found"
+        );
+        assert!(s.ttl("session").is_some(), "expiry not found");
+        assert!(s.persist("Session"), "expiry not cleared");
+        assert_eq!(None, s.ttl("SESSION"), "expiry not cleared");
+    }
+}
@@ -0,0 +1,140 @@
+//! Writing some or all of a store's entries as a document.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::{Store, StoreError};
+
+/// A document format for [`Store::export_matching()`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExportFormat {
+    /// A pretty-printed JSON object. A file in this format can be opened
+    /// as a store in its own right.
+    #[default]
+    Json,
+    /// A YAML mapping. Requires the `yaml` feature.
+    #[cfg(feature = "yaml")]
+    Yaml,
+    /// A TOML table. Requires the `toml` feature.
+    #[cfg(feature = "toml")]
+    Toml,
+}
+
+impl<V> Store<V> {
+    /// Checks that none of the keys satisfying `predicate` has an encrypted
+    /// value, which an export would otherwise leave out.
+    pub(crate) fn refuse_encrypted(
+        &self,
+        predicate: impl Fn(&str) -> bool,
+    ) -> Result<(), StoreError> {
+        match self.meta.encrypted.keys().find(|key| predicate(key)) {
+            Some(key) => Err(StoreError::Encrypted(key.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<V> Store<V>
+where
+    V: Serialize,
+{
+    /// Writes the entries whose keys satisfy `predicate` to `writer` as a
+    /// single document, with keys in order, and returns the number written.
+    ///
+    /// This extracts one application's keys from a store shared by several,
+    /// for example. Only the keys and values are written, not metadata such
+    /// as expiry times. Secret values (see [`Self::mark_secret()`]) are
+    /// written in plaintext.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// use rskey::{ExportFormat, Store};
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let entries = [("app:port", "80"), ("app:host", "web1"), ("db:port", "5432")];
+    /// let s = Store::from_entries(path, entries.map(|(k, v)| (k.to_string(), v)));
+    /// let mut doc = Vec::new();
+    /// let n = s.export_matching(|k| k.starts_with("app:"), &mut doc, ExportFormat::Json)?;
+    /// assert_eq!(n, 2);
+    /// let doc = String::from_utf8_lossy(&doc);
+    /// assert_eq!(doc, "{\n  \"app:host\": \"web1\",\n  \"app:port\": \"80\"\n}\n");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Io`] for any error writing the document, or if
+    /// a value can't be represented in `format`. Returns
+    /// [`StoreError::Encrypted`] if any of the entries has an encrypted value
+    /// (see [`Self::insert_encrypted()`]), which must be decrypted first (see
+    /// [`Self::decrypt_all()`]), in which case nothing is written.
+    pub fn export_matching(
+        &self,
+        predicate: impl Fn(&str) -> bool,
+        mut writer: impl Write,
+        format: ExportFormat,
+    ) -> Result<usize, StoreError> {
+        self.refuse_encrypted(&predicate)?;
+        let entries: BTreeMap<&str, &V> = self
+            .inner
+            .iter()
+            .filter(|(key, _)| predicate(key))
+            .map(|(key, value)| (key.as_str(), value))
+            .collect();
+        match format {
+            ExportFormat::Json => {
+                serde_json::to_writer_pretty(&mut writer, &entries)?;
+                writeln!(writer)?;
+            }
+            #[cfg(feature = "yaml")]
+            ExportFormat::Yaml => {
+                serde_yaml::to_writer(&mut writer, &entries).map_err(invalid_data)?;
+            }
+            #[cfg(feature = "toml")]
+            ExportFormat::Toml => {
+                let doc = toml::to_string(&entries).map_err(invalid_data)?;
+                writer.write_all(doc.as_bytes())?;
+            }
+        }
+        writer.flush()?;
+        Ok(entries.len())
+    }
+}
+
+#[cfg(any(feature = "yaml", feature = "toml"))]
+fn invalid_data(e: impl std::error::Error + Send + Sync + 'static) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_matching_writes_only_matching_keys_in_order() {
+        let entries = [("b", 2), ("a", 1), ("c", 3)];
+        let s = Store::from_entries("unused.kv", entries.map(|(k, v)| (k.to_string(), v)));
+        let mut doc = Vec::new();
+        let n = s
+            .export_matching(|k| k != "c", &mut doc, ExportFormat::Json)
+            .unwrap();
+        assert_eq!(2, n, "wrong count");
+        assert_eq!(
+            "{\n  \"a\": 1,\n  \"b\": 2\n}\n",
+            String::from_utf8_lossy(&doc)
+        );
+        #[cfg(feature = "toml")]
+        {
+            let mut doc = Vec::new();
+            s.export_matching(|k| k == "a", &mut doc, ExportFormat::Toml)
+                .unwrap();
+            assert_eq!("a = 1\n", String::from_utf8_lossy(&doc));
+        }
+    }
+}
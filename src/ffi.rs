@@ -0,0 +1,285 @@
+//! A C interface to stores of strings.
+//!
+//! Build the library with `cargo rustc --release --features ffi
+//! --crate-type cdylib` (or `staticlib`), and include `include/rskey.h`.
+//! The header is generated by `cbindgen`, using `cbindgen.toml`.
+//!
+//! Functions that can fail return 0 on success, or -1 on failure, in which
+//! case [`rskey_last_error()`] describes what went wrong. Strings passed in
+//! must be valid, NUL-terminated UTF-8.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::ptr;
+
+use crate::Store;
+
+/// An open store whose values are strings.
+pub struct RskeyStore(Store<String>);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Records `message` as the calling thread's last error.
+fn set_error(message: String) {
+    // Interior NULs can't be represented, so cut the message short.
+    let mut message = message.into_bytes();
+    message.truncate(
+        message
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(message.len()),
+    );
+    let message = CString::new(message).expect("NULs should have been removed");
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+fn clear_error() {
+    LAST_ERROR.with(|e| *e.borrow_mut() = None);
+}
+
+/// Returns the string at `s`, or records an error naming `what`.
+///
+/// # Safety
+///
+/// `s` must be null or point to a NUL-terminated string that outlives `'a`.
+unsafe fn to_str<'a>(s: *const c_char, what: &str) -> Option<&'a str> {
+    if s.is_null() {
+        set_error(format!("{what} is null"));
+        return None;
+    }
+    match CStr::from_ptr(s).to_str() {
+        Ok(s) => Some(s),
+        Err(e) => {
+            set_error(format!("{what} is not valid UTF-8: {e}"));
+            None
+        }
+    }
+}
+
+/// Returns the store at `store`, or records an error.
+///
+/// # Safety
+///
+/// `store` must be null or a pointer returned by [`rskey_open()`] and not
+/// yet closed.
+unsafe fn to_store<'a>(store: *mut RskeyStore) -> Option<&'a mut Store<String>> {
+    if store.is_null() {
+        set_error("store is null".to_string());
+        return None;
+    }
+    Some(&mut (*store).0)
+}
+
+/// Opens the store whose data file is at `path`, creating an empty store if
+/// there's no such file. Returns null on failure.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rskey_open(path: *const c_char) -> *mut RskeyStore {
+    clear_error();
+    let Some(path) = to_str(path, "path") else {
+        return ptr::null_mut();
+    };
+    match Store::open(path) {
+        Ok(store) => Box::into_raw(Box::new(RskeyStore(store))),
+        Err(e) => {
+            set_error(format!("opening {path}: {e}"));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Returns a copy of the value of `key`, resolving aliases, or null if
+/// there's no such key or on failure. The copy must be freed with
+/// [`rskey_free_string()`].
+///
+/// # Safety
+///
+/// `store` must be a pointer returned by [`rskey_open()`] and not yet
+/// closed, and `key` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rskey_get(store: *mut RskeyStore, key: *const c_char) -> *mut c_char {
+    clear_error();
+    let (Some(store), Some(key)) = (to_store(store), to_str(key, "key")) else {
+        return ptr::null_mut();
+    };
+    let Some(value) = store.lookup(key) else {
+        return ptr::null_mut();
+    };
+    match CString::new(value.as_str()) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            set_error(format!(
+                "value of {key} contains a NUL byte at {}",
+                e.nul_position()
+            ));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Sets `key` to `value`. The change isn't written to the data file until
+/// [`rskey_sync()`] is called.
+///
+/// # Safety
+///
+/// `store` must be a pointer returned by [`rskey_open()`] and not yet
+/// closed, and `key` and `value` NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn rskey_set(
+    store: *mut RskeyStore,
+    key: *const c_char,
+    value: *const c_char,
+) -> c_int {
+    clear_error();
+    let (Some(store), Some(key), Some(value)) =
+        (to_store(store), to_str(key, "key"), to_str(value, "value"))
+    else {
+        return -1;
+    };
+    match store.try_insert(key.to_string(), value.to_string()) {
+        Ok(_) => 0,
+        Err(e) => {
+            set_error(e.to_string());
+            -1
+        }
+    }
+}
+
+/// Removes `key`, if present. The change isn't written to the data file
+/// until [`rskey_sync()`] is called.
+///
+/// # Safety
+///
+/// `store` must be a pointer returned by [`rskey_open()`] and not yet
+/// closed, and `key` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rskey_del(store: *mut RskeyStore, key: *const c_char) -> c_int {
+    clear_error();
+    let (Some(store), Some(key)) = (to_store(store), to_str(key, "key")) else {
+        return -1;
+    };
+    match store.remove(key) {
+        Ok(_) => 0,
+        Err(e) => {
+            set_error(e.to_string());
+            -1
+        }
+    }
+}
+
+/// Atomically writes the store to its data file, so that other programs,
+/// in any language, see either all of the changes or none of them.
+///
+/// # Safety
+///
+/// `store` must be a pointer returned by [`rskey_open()`] and not yet
+/// closed.
+#[no_mangle]
+pub unsafe extern "C" fn rskey_sync(store: *mut RskeyStore) -> c_int {
+    clear_error();
+    let Some(store) = to_store(store) else {
+        return -1;
+    };
+    match store.sync() {
+        Ok(()) => 0,
+        Err(e) => {
+            set_error(format!("writing {}: {e}", store.path().display()));
+            -1
+        }
+    }
+}
+
+/// Frees the store, discarding any changes not yet synced. Does nothing if
+/// `store` is null.
+///
+/// # Safety
+///
+/// `store` must be null, or a pointer returned by [`rskey_open()`] and not
+/// yet closed. It mustn't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rskey_close(store: *mut RskeyStore) {
+    if !store.is_null() {
+        drop(Box::from_raw(store));
+    }
+}
+
+/// Frees a string returned by [`rskey_get()`]. Does nothing if `s` is null.
+///
+/// # Safety
+///
+/// `s` must be null, or a pointer returned by [`rskey_get()`] and not yet
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn rskey_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Returns a description of the last error on the calling thread, or null
+/// if the last call succeeded. The string is valid until the next call on
+/// the same thread, and mustn't be freed.
+#[no_mangle]
+pub extern "C" fn rskey_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    #[test]
+    fn ffi_functions_set_get_and_delete_persistent_values() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = c(tmp_dir.path().join("store.kv").to_str().unwrap());
+        unsafe {
+            let s = rskey_open(path.as_ptr());
+            assert!(!s.is_null(), "store not opened");
+            assert_eq!(0, rskey_set(s, c("k1").as_ptr(), c("v1").as_ptr()));
+            assert_eq!(0, rskey_set(s, c("k2").as_ptr(), c("v2").as_ptr()));
+            assert_eq!(0, rskey_del(s, c("k2").as_ptr()));
+            assert_eq!(0, rskey_sync(s), "sync failed");
+            rskey_close(s);
+
+            let s = rskey_open(path.as_ptr());
+            let value = rskey_get(s, c("k1").as_ptr());
+            assert_eq!(c"v1", CStr::from_ptr(value), "expected data not returned");
+            rskey_free_string(value);
+            assert!(
+                rskey_get(s, c("k2").as_ptr()).is_null(),
+                "deleted key found"
+            );
+            assert!(
+                rskey_last_error().is_null(),
+                "missing key reported as error"
+            );
+            rskey_close(s);
+        }
+    }
+
+    #[test]
+    fn ffi_functions_report_errors() {
+        let s = Store::from_entries("unused.kv", [("k1".to_string(), "v1".to_string())]);
+        let s = Box::into_raw(Box::new(RskeyStore(s)));
+        unsafe {
+            (*s).0.protect("k1");
+            assert_eq!(-1, rskey_del(s, c("k1").as_ptr()), "protected key removed");
+            let error = CStr::from_ptr(rskey_last_error()).to_str().unwrap();
+            assert!(error.contains("k1"), "wrong error: {error}");
+            assert_eq!(-1, rskey_set(s, ptr::null(), c("v").as_ptr()));
+            let error = CStr::from_ptr(rskey_last_error()).to_str().unwrap();
+            assert_eq!("key is null", error, "wrong error");
+            rskey_close(s);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Converting between nested documents and dotted keys.
+
+use serde_json::{Map, Value};
+
+use crate::{ProgressSink, Store, StoreError};
+
+impl Store<String> {
+    /// Inserts the leaf values of the nested document `doc`, with keys made
+    /// by joining the path to each value with dots. Array elements are
+    /// keyed by their index. Returns the number of entries inserted.
+    ///
+    /// Strings are inserted as they are, and numbers, booleans, and nulls as
+    /// their JSON text. Empty objects and arrays have no leaf values, so
+    /// they're skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// use rskey::Store;
+    /// use serde_json::json;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// let doc = json!({"server": {"host": "web1", "ports": [80, 443]}});
+    /// s.insert_flattened(doc.as_object().unwrap())?;
+    /// assert_eq!(s["server.host"], "web1");
+    /// assert_eq!(s["server.ports.1"], "443");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from [`Self::try_insert()`]. Entries before it
+    /// will already have been inserted.
+    pub fn insert_flattened(&mut self, doc: &Map<String, Value>) -> Result<usize, StoreError> {
+        self.insert_flattened_with_progress(doc, &())
+    }
+
+    /// Like [`Self::insert_flattened()`], but reports each entry inserted
+    /// to `progress`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from [`Self::try_insert()`].
+    pub fn insert_flattened_with_progress(
+        &mut self,
+        doc: &Map<String, Value>,
+        progress: &dyn ProgressSink,
+    ) -> Result<usize, StoreError> {
+        let mut entries = Vec::new();
+        for (key, value) in doc {
+            flatten(key.clone(), value, &mut entries);
+        }
+        let count = entries.len();
+        progress.start(count as u64);
+        let result = entries.into_iter().try_for_each(|(key, value)| {
+            self.try_insert(key, value)?;
+            progress.advance(1);
+            Ok(())
+        });
+        progress.finish();
+        result.map(|()| count)
+    }
+
+    /// Returns the store's contents as a nested document, the reverse of
+    /// [`Self::insert_flattened()`]: each dot in a key starts a nested
+    /// object, and objects whose keys are `0`, `1`, `2`, and so on become
+    /// arrays.
+    ///
+    /// Values that are valid JSON numbers, booleans, or `null` become those
+    /// types. Everything else is a string.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::NestingConflict`] if a key has a value, but is
+    /// also the start of another key (for example, `server` and
+    /// `server.port`), or [`StoreError::Encrypted`] if a key has an
+    /// encrypted value (see [`Self::insert_encrypted()`]).
+    pub fn to_nested(&self) -> Result<Value, StoreError> {
+        self.refuse_encrypted(|_| true)?;
+        let mut keys: Vec<_> = self.keys().collect();
+        keys.sort();
+        let mut root = Map::new();
+        for key in keys {
+            let mut parts = key.split('.').peekable();
+            let mut node = &mut root;
+            while let Some(part) = parts.next() {
+                if parts.peek().is_none() {
+                    if node.contains_key(part) {
+                        return Err(StoreError::NestingConflict(key.clone()));
+                    }
+                    node.insert(part.to_string(), scalar(&self[key]));
+                    break;
+                }
+                let child = node
+                    .entry(part)
+                    .or_insert_with(|| Value::Object(Map::new()));
+                let Value::Object(child) = child else {
+                    return Err(StoreError::NestingConflict(key.clone()));
+                };
+                node = child;
+            }
+        }
+        Ok(into_arrays(Value::Object(root)))
+    }
+}
+
+/// Appends the leaf values of `value` to `entries`, with keys starting with
+/// `prefix`.
+fn flatten(prefix: String, value: &Value, entries: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten(format!("{prefix}.{key}"), value, entries);
+            }
+        }
+        Value::Array(items) => {
+            for (i, value) in items.iter().enumerate() {
+                flatten(format!("{prefix}.{i}"), value, entries);
+            }
+        }
+        Value::String(s) => entries.push((prefix, s.clone())),
+        Value::Null | Value::Bool(_) | Value::Number(_) => {
+            entries.push((prefix, value.to_string()));
+        }
+    }
+}
+
+/// Converts a flattened value back to the JSON value it came from.
+fn scalar(value: &str) -> Value {
+    match serde_json::from_str(value) {
+        Ok(v @ (Value::Null | Value::Bool(_) | Value::Number(_))) if value.trim() == value => v,
+        _ => Value::String(value.to_string()),
+    }
+}
+
+/// Replaces objects keyed by consecutive indexes with arrays, recursively.
+fn into_arrays(value: Value) -> Value {
+    let Value::Object(map) = value else {
+        return value;
+    };
+    let is_array = !map.is_empty() && (0..map.len()).all(|i| map.contains_key(&i.to_string()));
+    if is_array {
+        let mut map = map;
+        (0..map.len())
+            .map(|i| into_arrays(map.remove(&i.to_string()).expect("index should be present")))
+            .collect()
+    } else {
+        map.into_iter().map(|(k, v)| (k, into_arrays(v))).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    #[test]
+    fn insert_flattened_and_to_nested_round_trip_documents() {
+        let doc = json!({
+            "server": {"host": "web1", "port": 8080, "tls": true},
+            "users": [{"name": "ann"}, {"name": "bob"}],
+            "version": "v1",
+        });
+        let mut s = Store::<String>::new(PathBuf::from("unused.kv"));
+        let count = s.insert_flattened(doc.as_object().unwrap()).unwrap();
+        assert_eq!(6, count, "wrong number of entries inserted");
+        assert_eq!("8080", s["server.port"], "expected data not returned");
+        assert_eq!("bob", s["users.1.name"], "expected data not returned");
+        assert_eq!(doc, s.to_nested().unwrap(), "document not round-tripped");
+    }
+
+    #[test]
+    fn insert_flattened_with_progress_reports_each_entry() {
+        #[derive(Default)]
+        struct Recorder(std::cell::RefCell<Vec<String>>);
+
+        impl ProgressSink for Recorder {
+            fn start(&self, total: u64) {
+                self.0.borrow_mut().push(format!("start {total}"));
+            }
+            fn advance(&self, n: u64) {
+                self.0.borrow_mut().push(format!("advance {n}"));
+            }
+            fn finish(&self) {
+                self.0.borrow_mut().push("finish".to_string());
+            }
+        }
+
+        let doc = json!({"a": {"b": 1, "c": 2}});
+        let mut s = Store::<String>::new(PathBuf::from("unused.kv"));
+        let progress = Recorder::default();
+        s.insert_flattened_with_progress(doc.as_object().unwrap(), &progress)
+            .unwrap();
+        assert_eq!(
+            vec!["start 2", "advance 1", "advance 1", "finish"],
+            *progress.0.borrow(),
+            "wrong progress reported"
+        );
+    }
+
+    #[test]
+    fn to_nested_rejects_keys_that_are_also_prefixes() {
+        let entries = [("server", "web1"), ("server.port", "80")]
+            .map(|(k, v)| (k.to_string(), v.to_string()));
+        let s = Store::from_entries("unused.kv", entries);
+        assert!(
+            matches!(s.to_nested(), Err(StoreError::NestingConflict(k)) if k == "server.port"),
+            "conflict not detected"
+        );
+    }
+}
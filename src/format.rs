@@ -0,0 +1,562 @@
+//! The on-disk representation of a store.
+//!
+//! A data file is a JSON object containing a `format` marker, a `meta`
+//! section holding any metadata the store keeps about its entries, and a
+//! `data` section holding the entries themselves:
+//!
+//! ```json
+//! {"format":"rskey/1","generation":3,"meta":{"protected":["key1"]},"data":{"key1":"value1"}}
+//! ```
+//!
+//! The `generation` counts the number of times the file has been written,
+//! so readers can tell cheaply whether it has changed. If the store
+//! compresses large values (see [`Store::set_compression_threshold()`]), a
+//! `compressed` section holds them, each compressed separately, in place of
+//! their entries in `data`.
+//!
+//! Because metadata lives in its own section, it can never collide with user
+//! keys, and new kinds of metadata can be added without changing the format
+//! marker.
+//!
+//! Files written by earlier versions of `rskey` contain only the bare data
+//! map. These are still readable, and are converted to the current format
+//! the next time the store is synced. Files with any other `rskey/` marker
+//! were written by a newer version, and are rejected rather than misread as
+//! bare data.
+
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IgnoredAny, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::value::RawValue;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+use crate::compress;
+use crate::scratch::Owner;
+#[cfg(doc)]
+use crate::Store;
+use crate::{Backend, KeyNormalization, SigningKey};
+
+/// The marker identifying the current file format.
+pub(crate) const FORMAT: &str = "rskey/1";
+
+/// Metadata about a store's entries, persisted alongside the data.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub(crate) struct Meta {
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) protected: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) secret: BTreeSet<String>,
+    /// When each key expires, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) expires: BTreeMap<String, u64>,
+    /// The process that owns each scratch entry.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) scratch: BTreeMap<String, Owner>,
+    /// The key that each alias refers to.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) aliases: BTreeMap<String, String>,
+    /// The JSON schema that values must match, for each key pattern.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) schemas: BTreeMap<String, serde_json::Value>,
+    /// The version of each key, if it has been changed.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) versions: BTreeMap<String, u64>,
+    /// The nonce and ciphertext of each encrypted value, hex-encoded.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) encrypted: BTreeMap<String, String>,
+    /// How keys are normalized.
+    #[serde(default, skip_serializing_if = "KeyNormalization::is_none")]
+    pub(crate) normalize: KeyNormalization,
+    /// The length of JSON above which values are compressed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) compress_over: Option<usize>,
+}
+
+impl Meta {
+    /// Returns the metadata of the keys and aliases satisfying `keep`,
+    /// along with the secret patterns, schemas, normalization, and
+    /// compression threshold, which apply to the store as a whole.
+    pub(crate) fn subset(&self, keep: impl Fn(&str) -> bool) -> Meta {
+        fn filter<T: Clone>(
+            map: &BTreeMap<String, T>,
+            keep: impl Fn(&str) -> bool,
+        ) -> BTreeMap<String, T> {
+            map.iter()
+                .filter(|(key, _)| keep(key))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect()
+        }
+
+        Meta {
+            protected: self
+                .protected
+                .iter()
+                .filter(|key| keep(key))
+                .cloned()
+                .collect(),
+            secret: self.secret.clone(),
+            expires: filter(&self.expires, &keep),
+            scratch: filter(&self.scratch, &keep),
+            aliases: filter(&self.aliases, &keep),
+            schemas: self.schemas.clone(),
+            versions: filter(&self.versions, &keep),
+            encrypted: filter(&self.encrypted, &keep),
+            normalize: self.normalize,
+            compress_over: self.compress_over,
+        }
+    }
+}
+
+/// The contents of a data file, as read from disk.
+pub(crate) struct Contents<V> {
+    pub(crate) generation: u64,
+    pub(crate) meta: Meta,
+    /// The compressed values, which must be decompressed (see
+    /// [`Self::decompress()`]) before they can be used.
+    pub(crate) compressed: BTreeMap<String, String>,
+    pub(crate) data: HashMap<String, V>,
+}
+
+impl<V: DeserializeOwned> Contents<V> {
+    /// Decompresses the compressed values into the entries.
+    pub(crate) fn decompress(mut self) -> Result<Self, serde_json::Error> {
+        for (key, text) in std::mem::take(&mut self.compressed) {
+            let value = serde_json::from_slice(&compress::decompress(&key, &text)?)?;
+            self.data.insert(key, value);
+        }
+        Ok(self)
+    }
+}
+
+/// The contents of a data file, borrowed from a store for writing.
+#[derive(Serialize)]
+pub(crate) struct ContentsRef<'a, D> {
+    format: &'static str,
+    generation: u64,
+    meta: &'a Meta,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compressed: Option<&'a BTreeMap<String, String>>,
+    data: &'a D,
+}
+
+impl<'a, D> ContentsRef<'a, D> {
+    pub(crate) fn new(generation: u64, meta: &'a Meta, data: &'a D) -> Self {
+        Self {
+            format: FORMAT,
+            generation,
+            meta,
+            compressed: None,
+            data,
+        }
+    }
+
+    /// Writes `compressed` as the compressed values, which `data` mustn't
+    /// also contain.
+    pub(crate) fn with_compressed(mut self, compressed: &'a BTreeMap<String, String>) -> Self {
+        self.compressed = Some(compressed).filter(|c| !c.is_empty());
+        self
+    }
+}
+
+/// Serializes a data file, compressing the values over the threshold set in
+/// `meta`, if any.
+pub(crate) fn to_vec<V: Serialize>(
+    generation: u64,
+    meta: &Meta,
+    data: &HashMap<String, V>,
+) -> Result<Vec<u8>, serde_json::Error> {
+    let Some(threshold) = meta.compress_over else {
+        return serde_json::to_vec(&ContentsRef::new(generation, meta, data));
+    };
+    let compressed = compress::compress_entries(data, threshold)?;
+    let data = Uncompressed(data, &compressed);
+    serde_json::to_vec(&ContentsRef::new(generation, meta, &data).with_compressed(&compressed))
+}
+
+/// Serializes the entries that aren't among the compressed values.
+struct Uncompressed<'a, V>(&'a HashMap<String, V>, &'a BTreeMap<String, String>);
+
+impl<V: Serialize> Serialize for Uncompressed<'_, V> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().filter(|(key, _)| !self.1.contains_key(*key)))
+    }
+}
+
+/// Writes a serialized store to `path`, signing it if there's a key.
+pub(crate) fn write_file(
+    backend: &dyn Backend,
+    path: &Path,
+    mut doc: Vec<u8>,
+    signing_key: Option<&SigningKey>,
+) -> std::io::Result<()> {
+    if let Some(key) = signing_key {
+        let trailer = key.trailer(&doc);
+        doc.extend_from_slice(trailer.as_bytes());
+    }
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    // Write to a temporary file and rename it into place, so that a failed
+    // write never leaves a partly-written data file.
+    if let Err(e) = backend.write(&tmp_path, &doc) {
+        let _ = backend.remove(&tmp_path);
+        return Err(e);
+    }
+    backend.rename(&tmp_path, path)
+}
+
+/// Parses a data file, deserializing the values in parallel.
+#[cfg(feature = "rayon")]
+pub(crate) fn from_slice_parallel<V>(doc: &[u8]) -> Result<Contents<V>, serde_json::Error>
+where
+    V: serde::de::DeserializeOwned + Send,
+{
+    use rayon::prelude::*;
+
+    let raw: Contents<&RawValue> = serde_json::from_slice(doc)?;
+    let data = raw
+        .data
+        .into_par_iter()
+        .map(|(key, value)| Ok((key, serde_json::from_str(value.get())?)))
+        .collect::<Result<_, serde_json::Error>>()?;
+    Ok(Contents {
+        generation: raw.generation,
+        meta: raw.meta,
+        compressed: raw.compressed,
+        data,
+    })
+}
+
+/// Serializes a data file, serializing (and compressing) the entries in
+/// parallel.
+///
+/// The output is identical to that of [`to_vec()`], except for the order of
+/// the entries.
+#[cfg(feature = "rayon")]
+pub(crate) fn to_vec_parallel<V>(
+    generation: u64,
+    meta: &Meta,
+    data: &HashMap<String, V>,
+) -> Result<Vec<u8>, serde_json::Error>
+where
+    V: Serialize + Sync,
+{
+    use rayon::iter::Either;
+    use rayon::prelude::*;
+
+    let threshold = meta.compress_over.unwrap_or(usize::MAX);
+    let entries = data
+        .par_iter()
+        .map(|(key, value)| {
+            let json = serde_json::to_vec(value)?;
+            if let Some(text) = compress::compress(&json, threshold) {
+                return Ok(Either::Right((key.clone(), text)));
+            }
+            let mut entry = serde_json::to_vec(key)?;
+            entry.push(b':');
+            entry.extend_from_slice(&json);
+            Ok(Either::Left(entry))
+        })
+        .collect::<Result<Vec<_>, serde_json::Error>>()?;
+    let (entries, compressed): (Vec<_>, BTreeMap<_, _>) =
+        entries.into_par_iter().partition_map(|entry| entry);
+    let mut doc = serde_json::to_vec(
+        &ContentsRef::new(generation, meta, &HashMap::<String, V>::new())
+            .with_compressed(&compressed),
+    )?;
+    // Replace the empty data object's closing `}}` with the entries.
+    doc.truncate(doc.len() - 2);
+    doc.extend_from_slice(&entries.join(&b',')[..]);
+    doc.extend_from_slice(b"}}");
+    Ok(doc)
+}
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for Contents<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(ContentsVisitor(PhantomData))
+    }
+}
+
+struct ContentsVisitor<V>(PhantomData<V>);
+
+impl<'de, V: Deserialize<'de>> Visitor<'de> for ContentsVisitor<V> {
+    type Value = Contents<V>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an rskey data file")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut contents = Contents {
+            generation: 0,
+            meta: Meta::default(),
+            compressed: BTreeMap::new(),
+            data: HashMap::new(),
+        };
+        let Some(first) = map.next_key::<String>()? else {
+            return Ok(contents);
+        };
+        if first == "format" {
+            // Either the current format's marker, or a legacy file that
+            // happens to have a key named `format`.
+            let value: &'de RawValue = map.next_value()?;
+            let marker = serde_json::from_str::<&str>(value.get()).ok();
+            if let Some(marker) = marker.filter(|m| m.starts_with("rskey/") && *m != FORMAT) {
+                return Err(unsupported(marker));
+            }
+            if marker == Some(FORMAT) {
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "generation" => contents.generation = map.next_value()?,
+                        "meta" => contents.meta = map.next_value()?,
+                        "compressed" => contents.compressed = map.next_value()?,
+                        "data" => contents.data = map.next_value()?,
+                        _ => {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                }
+                return Ok(contents);
+            }
+            let value = serde_json::from_str(value.get()).map_err(de::Error::custom)?;
+            contents.data.insert(first, value);
+        } else {
+            contents.data.insert(first, map.next_value()?);
+        }
+        while let Some((key, value)) = map.next_entry()? {
+            contents.data.insert(key, value);
+        }
+        Ok(contents)
+    }
+}
+
+/// The fields of a data file that identify its format and generation.
+#[derive(Deserialize)]
+struct Header {
+    format: Option<serde_json::Value>,
+    generation: Option<serde_json::Value>,
+}
+
+impl Header {
+    fn is_current(&self) -> bool {
+        self.format.as_ref().and_then(serde_json::Value::as_str) == Some(FORMAT)
+    }
+}
+
+/// Returns `true` if a data file has the current format marker, rather than
+/// being a legacy file.
+pub(crate) fn has_header(doc: &[u8]) -> Result<bool, serde_json::Error> {
+    let header: Header = serde_json::from_slice(doc)?;
+    Ok(header.is_current())
+}
+
+/// Reads the generation of a data file, without deserializing its metadata
+/// or entries. A legacy file has generation 0.
+pub(crate) fn read_generation(doc: &[u8]) -> Result<u64, serde_json::Error> {
+    let header: Header = serde_json::from_slice(doc)?;
+    if !header.is_current() {
+        return Ok(0);
+    }
+    Ok(header
+        .generation
+        .as_ref()
+        .and_then(serde_json::Value::as_u64)
+        .unwrap_or_default())
+}
+
+/// Reads the metadata from a data file, skipping the entries, so that they
+/// needn't all be held in memory.
+pub(crate) fn read_meta(reader: impl io::Read) -> Result<Meta, serde_json::Error> {
+    let mut meta = Meta::default();
+    let mut de = serde_json::Deserializer::from_reader(reader);
+    de.deserialize_map(&mut ScanVisitor {
+        meta: Some(&mut meta),
+        on_entry: |_, _: IgnoredAny| ControlFlow::Continue(()),
+        stopped: false,
+        _value: PhantomData,
+    })?;
+    Ok(meta)
+}
+
+/// Reads the entries from a data file one at a time, calling `on_entry` with
+/// each, until it returns [`ControlFlow::Break`].
+pub(crate) fn scan_entries<V: DeserializeOwned>(
+    reader: impl io::Read,
+    on_entry: impl FnMut(String, V) -> ControlFlow<()>,
+) -> Result<(), serde_json::Error> {
+    let mut de = serde_json::Deserializer::from_reader(reader);
+    let mut visitor = ScanVisitor {
+        meta: None,
+        on_entry,
+        stopped: false,
+        _value: PhantomData,
+    };
+    match de.deserialize_map(&mut visitor) {
+        // Stopping early abandons the parse with an error, which isn't a
+        // real failure.
+        Err(_) if visitor.stopped => Ok(()),
+        result => result,
+    }
+}
+
+/// Visits a data file without keeping its entries. If `meta` is set, the
+/// file's metadata is stored there; otherwise, each entry is passed to
+/// `on_entry`.
+struct ScanVisitor<'a, V, F> {
+    meta: Option<&'a mut Meta>,
+    on_entry: F,
+    stopped: bool,
+    _value: PhantomData<fn() -> V>,
+}
+
+impl<V, F> ScanVisitor<'_, V, F>
+where
+    F: FnMut(String, V) -> ControlFlow<()>,
+{
+    fn entry<E: de::Error>(&mut self, key: String, value: V) -> Result<(), E> {
+        if (self.on_entry)(key, value).is_break() {
+            self.stopped = true;
+            return Err(E::custom("scan stopped"));
+        }
+        Ok(())
+    }
+}
+
+impl<'de, V, F> Visitor<'de> for &mut ScanVisitor<'_, V, F>
+where
+    V: DeserializeOwned,
+    F: FnMut(String, V) -> ControlFlow<()>,
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an rskey data file")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let Some(first) = map.next_key::<String>()? else {
+            return Ok(());
+        };
+        if first == "format" {
+            let value: serde_json::Value = map.next_value()?;
+            if value.as_str() == Some(FORMAT) {
+                while let Some(key) = map.next_key::<String>()? {
+                    match (key.as_str(), self.meta.as_deref_mut()) {
+                        ("meta", Some(meta)) => *meta = map.next_value()?,
+                        ("compressed", None) => {
+                            map.next_value_seed(CompressedSeed(&mut *self))?;
+                        }
+                        ("data", None) => map.next_value_seed(DataSeed(&mut *self))?,
+                        _ => {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                }
+                return Ok(());
+            }
+            if let Some(marker) = value.as_str().filter(|m| m.starts_with("rskey/")) {
+                return Err(unsupported(marker));
+            }
+            if self.meta.is_none() {
+                let value = V::deserialize(value).map_err(de::Error::custom)?;
+                self.entry(first, value)?;
+            }
+        } else if self.meta.is_none() {
+            let value = map.next_value()?;
+            self.entry(first, value)?;
+        } else {
+            map.next_value::<IgnoredAny>()?;
+        }
+        // A legacy file, which holds only entries.
+        if self.meta.is_some() {
+            while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+            return Ok(());
+        }
+        while let Some((key, value)) = map.next_entry()? {
+            self.entry(key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Visits the `data` section of a data file for a [`ScanVisitor`].
+struct DataSeed<'a, 'b, V, F>(&'a mut ScanVisitor<'b, V, F>);
+
+impl<'de, V, F> DeserializeSeed<'de> for DataSeed<'_, '_, V, F>
+where
+    V: DeserializeOwned,
+    F: FnMut(String, V) -> ControlFlow<()>,
+{
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, V, F> Visitor<'de> for DataSeed<'_, '_, V, F>
+where
+    V: DeserializeOwned,
+    F: FnMut(String, V) -> ControlFlow<()>,
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of entries")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some((key, value)) = map.next_entry()? {
+            self.0.entry(key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Visits the `compressed` section of a data file for a [`ScanVisitor`],
+/// decompressing one value at a time.
+struct CompressedSeed<'a, 'b, V, F>(&'a mut ScanVisitor<'b, V, F>);
+
+impl<'de, V, F> DeserializeSeed<'de> for CompressedSeed<'_, '_, V, F>
+where
+    V: DeserializeOwned,
+    F: FnMut(String, V) -> ControlFlow<()>,
+{
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, V, F> Visitor<'de> for CompressedSeed<'_, '_, V, F>
+where
+    V: DeserializeOwned,
+    F: FnMut(String, V) -> ControlFlow<()>,
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of compressed values")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some((key, text)) = map.next_entry::<String, String>()? {
+            let json = compress::decompress(&key, &text).map_err(de::Error::custom)?;
+            let value = serde_json::from_slice(&json).map_err(de::Error::custom)?;
+            self.0.entry(key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the error for a data file with a newer format `marker`.
+fn unsupported<E: de::Error>(marker: &str) -> E {
+    E::custom(format!(
+        "unsupported data file format {marker:?} (written by a newer version of rskey?)"
+    ))
+}
@@ -0,0 +1,99 @@
+//! A read-only store whose values borrow from the data file's contents.
+
+use crate::format::Contents;
+use crate::{sign, StoreError};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+/// A read-only view of a data file, whose values are deserialized by
+/// borrowing from the file contents rather than copying them.
+///
+/// For large stores that are only read, this avoids allocating a separate
+/// `String` for every value. The file contents must be kept in memory for as
+/// long as the `FrozenStore` is in use.
+///
+/// Values containing JSON escape sequences (such as `\n` or `\"`) can't be
+/// borrowed as `&str`, and reading a file containing them will fail. To
+/// handle such values, use a value type that can fall back to owned data,
+/// such as a struct with a `#[serde(borrow)] Cow<'a, str>` field.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), rskey::StoreError> {
+/// use rskey::{FrozenStore, Store};
+/// # use tempfile::TempDir;
+///
+/// # let tmp_dir = TempDir::new()?;
+/// # let path = tmp_dir.path().join("data.kv");
+/// # let mut s = Store::<String>::open(&path)?;
+/// # s.try_insert("key1".to_string(), "value1".to_string())?;
+/// # s.sync()?;
+/// let file = std::fs::read(&path)?;
+/// let s = FrozenStore::<&str>::from_slice(&file)?;
+/// assert_eq!(s.get("key1"), Some(&"value1"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct FrozenStore<'a, V = &'a str> {
+    inner: HashMap<String, V>,
+    _file: PhantomData<&'a [u8]>,
+}
+
+impl<'a, V: Deserialize<'a>> FrozenStore<'a, V> {
+    /// Reads a store from the contents of a data file.
+    ///
+    /// Any signature on the file is ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error deserializing the data, or [`StoreError::Io`] if
+    /// the file has compressed values (see
+    /// [`Store::set_compression_threshold()`](crate::Store::set_compression_threshold)),
+    /// which can't be borrowed.
+    pub fn from_slice(file: &'a [u8]) -> Result<Self, StoreError> {
+        let (doc, _) = sign::split(file);
+        let contents: Contents<V> = serde_json::from_slice(doc)?;
+        if !contents.compressed.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "compressed values can't be borrowed from the file",
+            )
+            .into());
+        }
+        Ok(Self {
+            inner: contents.data,
+            _file: PhantomData,
+        })
+    }
+}
+
+impl<V> Deref for FrozenStore<'_, V> {
+    type Target = HashMap<String, V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_slice_borrows_values_from_file() {
+        let file = br#"{"format":"rskey/1","meta":{},"data":{"k1":"v1","k2":"v2"}}"#;
+        let s = FrozenStore::<&str>::from_slice(file).unwrap();
+        assert_eq!(2, s.len(), "wrong number of entries");
+        let value = s.get("k1").unwrap();
+        assert_eq!("v1", *value, "expected data not returned");
+        assert!(
+            file.as_ptr_range().contains(&value.as_ptr()),
+            "value should borrow from file"
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Cleaning up files left behind by interrupted operations.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::scratch::process_exists;
+use crate::{Backend, Store};
+
+/// The files removed by [`Store::gc()`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct GcReport {
+    /// The paths of the files removed.
+    pub removed: Vec<PathBuf>,
+    /// The total size of the files removed, in bytes.
+    pub bytes: u64,
+}
+
+impl<V> Store<V> {
+    /// Removes auxiliary files that were left behind when a process exited
+    /// part-way through an operation, and reports what was removed.
+    ///
+    /// These are the temporary files that syncs and snapshots write before
+    /// renaming them into place, and lock files left over from taking over
+    /// a stale lock (see [`FileLock`](crate::FileLock)) by processes that
+    /// have since exited.
+    ///
+    /// A temporary file may belong to a sync that's still in progress in
+    /// another process, so hold the store's lock (see [`Self::lock()`])
+    /// while calling this.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// use std::time::Duration;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let s = Store::<String>::open(&path)?;
+    /// // Left behind by a sync that crashed.
+    /// std::fs::write(tmp_dir.path().join("data.kv.tmp"), "{")?;
+    /// let _lock = s.lock(Duration::from_secs(5))?;
+    /// let report = s.gc()?;
+    /// assert_eq!(report.removed.len(), 1);
+    /// assert_eq!(report.bytes, 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns any error listing or removing the files.
+    pub fn gc(&self) -> io::Result<GcReport> {
+        let mut report = GcReport::default();
+        let mut tmp_path = self.path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        if let Some(size) = self.backend.size(&tmp_path)? {
+            self.backend.remove(&tmp_path)?;
+            report.bytes += size;
+            report.removed.push(tmp_path);
+        }
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        let lock_prefix = format!("{name}.lock.stale.");
+        let dir = self
+            .path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        remove_matching(&*self.backend, dir, &mut report, |file| {
+            file.strip_prefix(&lock_prefix)
+                .and_then(|pid| pid.parse().ok())
+                .is_some_and(|pid| pid != std::process::id() && !process_exists(pid))
+        })?;
+        remove_matching(&*self.backend, &self.snapshot_dir(), &mut report, |file| {
+            file.ends_with(".kv.tmp")
+        })?;
+        Ok(report)
+    }
+}
+
+/// Removes the files in `dir` whose names satisfy `orphaned`, adding them
+/// to `report`. A missing directory has no files to remove.
+fn remove_matching(
+    backend: &dyn Backend,
+    dir: &Path,
+    report: &mut GcReport,
+    orphaned: impl Fn(&str) -> bool,
+) -> io::Result<()> {
+    for path in backend.list(dir)? {
+        let name = path.file_name().and_then(|name| name.to_str());
+        if !name.is_some_and(&orphaned) {
+            continue;
+        }
+        // If it's gone, or goes before it's removed, another process got
+        // there first.
+        let Some(size) = backend.size(&path)? else {
+            continue;
+        };
+        match backend.remove(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        }
+        report.bytes += size;
+        report.removed.push(path);
+    }
+    Ok(())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn gc_removes_only_orphaned_files() {
+        let tmp_dir = TempDir::new().unwrap();
+        let dir = tmp_dir.path();
+        let mut s = Store::<u8>::open(dir.join("store.kv")).unwrap();
+        s.try_insert("a".to_string(), 1).unwrap();
+        s.sync().unwrap();
+        s.snapshot().unwrap();
+        fs::write(dir.join("store.kv.tmp"), "abc").unwrap();
+        // No process can have this PID, as it's above the kernel's limit.
+        fs::write(dir.join(format!("store.kv.lock.stale.{}", u32::MAX)), "de").unwrap();
+        let ours = format!("store.kv.lock.stale.{}", std::process::id());
+        fs::write(dir.join(&ours), "").unwrap();
+        fs::write(dir.join("store.kv.snapshots/1.kv.tmp"), "f").unwrap();
+        fs::write(dir.join("other.kv.tmp"), "").unwrap();
+        let report = s.gc().unwrap();
+        assert_eq!(3, report.removed.len(), "wrong files removed: {report:?}");
+        assert_eq!(6, report.bytes, "wrong size reclaimed");
+        assert!(dir.join("store.kv").exists(), "data file removed");
+        assert!(dir.join(ours).exists(), "live process's file removed");
+        assert!(
+            dir.join("other.kv.tmp").exists(),
+            "other store's file removed"
+        );
+        assert_eq!(1, s.snapshots().unwrap().len(), "snapshot removed");
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn gc_removes_files_through_backend() {
+        let mock = crate::testing::StoreBackendMock::new();
+        let s: Store<u8> = Store::builder("store.kv")
+            .backend(mock.clone())
+            .open()
+            .unwrap();
+        mock.write(Path::new("store.kv.tmp"), b"abc").unwrap();
+        mock.write(Path::new("store.kv.snapshots/1.kv.tmp"), b"d")
+            .unwrap();
+        let report = s.gc().unwrap();
+        assert_eq!(2, report.removed.len(), "wrong files removed: {report:?}");
+        assert_eq!(4, report.bytes, "wrong size reclaimed");
+        assert!(mock.contents("store.kv.tmp").is_none(), "file not removed");
+    }
+}
@@ -0,0 +1,149 @@
+//! Recording each sync as a git commit.
+
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use crate::oplog::Changes;
+use crate::Store;
+
+/// The most keys to name in a commit message before just counting them.
+const MAX_NAMED_KEYS: usize = 3;
+
+impl<V> Store<V> {
+    /// Returns `true` if each sync is committed to git; see
+    /// [`Self::set_git_autocommit()`].
+    #[must_use]
+    pub fn git_autocommit(&self) -> bool {
+        self.git_autocommit
+    }
+
+    /// Sets whether to commit the data file to git after each sync, giving
+    /// a full history of changes to the store.
+    ///
+    /// The data file must be in a git work tree. Only the data file is
+    /// committed, with a message describing which keys were set or deleted,
+    /// such as `set key1`. If nothing has changed since the last sync,
+    /// nothing is committed.
+    /// Git's user name and email must be configured.
+    pub fn set_git_autocommit(&mut self, enabled: bool) {
+        self.git_autocommit = enabled;
+    }
+}
+
+/// Returns a commit message describing `changes`, or `None` if there are
+/// none.
+pub(crate) fn describe(changes: &Changes) -> Option<String> {
+    let parts: Vec<_> = [("set", &changes.set), ("delete", &changes.deleted)]
+        .into_iter()
+        .filter(|(_, keys)| !keys.is_empty())
+        .map(|(verb, keys)| {
+            if keys.len() > MAX_NAMED_KEYS {
+                format!("{verb} {} keys", keys.len())
+            } else {
+                format!("{verb} {}", keys.join(", "))
+            }
+        })
+        .collect();
+    if !parts.is_empty() {
+        Some(parts.join("; "))
+    } else if changes.meta_changed {
+        Some("update metadata".to_string())
+    } else {
+        None
+    }
+}
+
+/// Commits the file at `path` to git, with the given message, unless it's
+/// unchanged.
+pub(crate) fn commit(path: &Path, message: &str) -> io::Result<()> {
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "data file has no name"))?;
+    let git = || {
+        let mut cmd = Command::new("git");
+        cmd.arg("-C").arg(dir);
+        cmd
+    };
+    run(git().arg("add").arg("--").arg(name))?;
+    let unchanged = git()
+        .args(["diff", "--cached", "--quiet", "--"])
+        .arg(name)
+        .status()?
+        .success();
+    if !unchanged {
+        run(git().args(["commit", "-q", "-m", message, "--"]).arg(name))?;
+    }
+    Ok(())
+}
+
+/// Runs a git command, returning its error output if it fails.
+fn run(cmd: &mut Command) -> io::Result<()> {
+    let output = cmd.output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(io::Error::other(format!("git failed: {}", stderr.trim())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?} failed");
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn sync_commits_changes_with_generated_message() {
+        let tmp_dir = TempDir::new().unwrap();
+        let dir = tmp_dir.path();
+        git(dir, &["init", "-q"]);
+        git(dir, &["config", "user.name", "Test"]);
+        git(dir, &["config", "user.email", "test@example.com"]);
+        let mut s = Store::<String>::open(dir.join("store.kv")).unwrap();
+        s.set_git_autocommit(true);
+        s.try_insert("key1".to_string(), "value1".to_string())
+            .unwrap();
+        s.try_insert("key2".to_string(), "value2".to_string())
+            .unwrap();
+        s.sync().unwrap();
+        s.try_insert("key1".to_string(), "value3".to_string())
+            .unwrap();
+        s.remove("key2").unwrap();
+        s.sync().unwrap();
+        s.protect("key1");
+        s.sync().unwrap();
+        // Only the generation has changed.
+        s.sync().unwrap();
+        assert_eq!(
+            "update metadata\nset key1; delete key2\nset key1, key2\n",
+            git(dir, &["log", "--format=%s"]),
+            "wrong commits"
+        );
+    }
+
+    #[test]
+    fn describe_counts_many_keys() {
+        let entries = (0..5).map(|i| (format!("key{i}"), i));
+        let s = Store::from_entries("nonexistent.kv", entries);
+        assert_eq!(
+            Some("set 5 keys".to_string()),
+            describe(&s.changes().unwrap()),
+            "wrong message"
+        );
+    }
+}
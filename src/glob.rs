@@ -0,0 +1,57 @@
+//! Simple glob matching for keys.
+
+/// Returns `true` if `text` matches the glob `pattern`, where `*` matches any
+/// sequence of characters (including none) and `?` matches any single
+/// character. All other characters match themselves.
+pub(crate) fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // The position of the last `*` seen, and the text position it's
+    // currently matched up to, so we can backtrack on a mismatch.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_handles_wildcards() {
+        for (pattern, text, want) in [
+            ("key1", "key1", true),
+            ("key1", "key2", false),
+            ("key?", "key2", true),
+            ("key?", "key", false),
+            ("*", "", true),
+            ("*token*", "api_token_prod", true),
+            ("api:*:secret", "api:prod:secret", true),
+            ("api:*:secret", "api:prod:public", false),
+            ("a*b*c", "abxbc", true),
+            ("a*b*c", "abxbd", false),
+        ] {
+            assert_eq!(want, matches(pattern, text), "{pattern:?} vs {text:?}");
+        }
+    }
+}
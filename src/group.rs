@@ -0,0 +1,87 @@
+//! Grouping keys into a tree by their segments.
+
+use std::collections::BTreeMap;
+
+use crate::Store;
+
+/// A node in the tree of keys returned by [`Store::group_by()`].
+#[derive(Debug, PartialEq)]
+pub struct Group<'a, V> {
+    /// The value of the key ending at this node, if there is one.
+    pub value: Option<&'a V>,
+    /// The nodes below this one, keyed by the next segment of their keys.
+    pub children: BTreeMap<&'a str, Group<'a, V>>,
+}
+
+impl<V> Group<'_, V> {
+    fn new() -> Self {
+        Self {
+            value: None,
+            children: BTreeMap::new(),
+        }
+    }
+}
+
+impl<V> Store<V> {
+    /// Returns the store's entries as a tree, splitting each key into
+    /// segments at `separator`. This makes a large, structured keyspace
+    /// easier to read.
+    ///
+    /// A key can both have a value and be the start of other keys, such as
+    /// `db` and `db:host`, so any node in the tree may have a value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("db:host".to_string(), "localhost".to_string())?;
+    /// s.try_insert("db:port".to_string(), "5432".to_string())?;
+    /// let root = s.group_by(":");
+    /// let db = &root.children["db"];
+    /// assert_eq!(db.children.len(), 2);
+    /// assert_eq!(db.children["port"].value.unwrap(), "5432");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn group_by(&self, separator: &str) -> Group<'_, V> {
+        let mut root = Group::new();
+        for (key, value) in &self.inner {
+            let mut node = &mut root;
+            for segment in key.split(separator) {
+                node = node.children.entry(segment).or_insert_with(Group::new);
+            }
+            node.value = Some(value);
+        }
+        root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_by_nests_keys_by_segment() {
+        let entries =
+            [("a", 1), ("a.b", 2), ("a.c.d", 3), ("e", 4)].map(|(k, v)| (k.to_string(), v));
+        let s = Store::<u8>::from_entries("unused.kv", entries);
+        let root = s.group_by(".");
+        assert_eq!(None, root.value, "root has a value");
+        assert_eq!(
+            vec!["a", "e"],
+            root.children.keys().copied().collect::<Vec<_>>()
+        );
+        let a = &root.children["a"];
+        assert_eq!(Some(&1), a.value, "wrong value for parent key");
+        assert_eq!(Some(&2), a.children["b"].value, "wrong value");
+        let c = &a.children["c"];
+        assert_eq!(None, c.value, "intermediate node has a value");
+        assert_eq!(Some(&3), c.children["d"].value, "wrong nested value");
+    }
+}
@@ -0,0 +1,245 @@
+//! Copying entries to and from the key-value stores of etcd and Consul,
+//! using their HTTP APIs.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::json;
+use std::fmt::Write;
+use std::io;
+
+use crate::{Store, StoreError};
+
+/// An entry as listed by Consul.
+#[derive(Deserialize)]
+struct ConsulEntry {
+    #[serde(rename = "Key")]
+    key: String,
+    /// The value, in base64, or `None` for a folder.
+    #[serde(rename = "Value")]
+    value: Option<String>,
+}
+
+/// The response to an etcd range request.
+#[derive(Deserialize)]
+struct EtcdRange {
+    #[serde(default)]
+    kvs: Vec<EtcdEntry>,
+}
+
+/// An entry as listed by etcd, with its key and value in base64. Empty
+/// values are left out of the response altogether.
+#[derive(Deserialize)]
+struct EtcdEntry {
+    key: String,
+    #[serde(default)]
+    value: String,
+}
+
+impl Store<String> {
+    /// Copies the keys starting with `prefix`, such as `app/`, from the
+    /// Consul agent at `url`, such as `http://localhost:8500`, into the
+    /// store, replacing any existing values for those keys, and returns the
+    /// number copied.
+    ///
+    /// The keys are copied in full, prefix and all, so that they can be
+    /// exported back to the same place with [`Self::export_consul()`].
+    /// Folders, and values that aren't valid UTF-8, are skipped. This
+    /// doesn't sync the store. Requires the `http` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Io`] for any error reading from Consul, or
+    /// [`StoreError::Protected`] if a key being copied is protected, in
+    /// which case the keys copied so far are kept.
+    pub fn import_consul(&mut self, url: &str, prefix: &str) -> Result<usize, StoreError> {
+        let url = format!("{}?recurse=true", consul_key_url(url, prefix));
+        let entries: Vec<ConsulEntry> = match ureq::get(&url).call() {
+            Ok(response) => response.into_json()?,
+            // Consul reports a prefix with no keys as not found.
+            Err(ureq::Error::Status(404, _)) => Vec::new(),
+            Err(e) => return Err(http_error(e)),
+        };
+        let entries = entries.into_iter().filter_map(|entry| {
+            let value = decode(&entry.value?)?;
+            Some((entry.key, value))
+        });
+        self.import_entries(entries)
+    }
+
+    /// Copies the entries whose keys start with `prefix` to the Consul agent
+    /// at `url`, replacing any existing values for those keys there, and
+    /// returns the number copied. Requires the `http` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Io`] for any error writing to Consul, in which
+    /// case some of the entries may have been copied. Returns
+    /// [`StoreError::Encrypted`] if any of the entries has an encrypted value
+    /// (see [`Self::insert_encrypted()`]), in which case none are copied.
+    pub fn export_consul(&self, url: &str, prefix: &str) -> Result<usize, StoreError> {
+        self.export_entries(prefix, |key, value| {
+            ureq::put(&consul_key_url(url, key)).send_string(value)?;
+            Ok(())
+        })
+    }
+
+    /// Copies the keys starting with `prefix`, such as `app/`, from the etcd
+    /// server at `url`, such as `http://localhost:2379`, into the store,
+    /// replacing any existing values for those keys, and returns the number
+    /// copied.
+    ///
+    /// The keys are copied in full, prefix and all, so that they can be
+    /// exported back to the same place with [`Self::export_etcd()`]. Keys
+    /// or values that aren't valid UTF-8 are skipped, and any leases on the
+    /// keys are ignored. This uses the gRPC gateway of etcd 3.4 or later,
+    /// and doesn't sync the store. Requires the `http` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Io`] for any error reading from etcd, or
+    /// [`StoreError::Protected`] if a key being copied is protected, in
+    /// which case the keys copied so far are kept.
+    pub fn import_etcd(&mut self, url: &str, prefix: &str) -> Result<usize, StoreError> {
+        let range: EtcdRange = ureq::post(&format!("{}/v3/kv/range", url.trim_end_matches('/')))
+            .send_json(json!({
+                "key": BASE64.encode(range_start(prefix)),
+                "range_end": BASE64.encode(range_end(prefix)),
+            }))
+            .map_err(http_error)?
+            .into_json()?;
+        let entries = range
+            .kvs
+            .into_iter()
+            .filter_map(|entry| Some((decode(&entry.key)?, decode(&entry.value)?)));
+        self.import_entries(entries)
+    }
+
+    /// Copies the entries whose keys start with `prefix` to the etcd server
+    /// at `url`, replacing any existing values for those keys there, and
+    /// returns the number copied. Requires the `http` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Io`] for any error writing to etcd, in which
+    /// case some of the entries may have been copied. Returns
+    /// [`StoreError::Encrypted`] if any of the entries has an encrypted value
+    /// (see [`Self::insert_encrypted()`]), in which case none are copied.
+    pub fn export_etcd(&self, url: &str, prefix: &str) -> Result<usize, StoreError> {
+        let url = format!("{}/v3/kv/put", url.trim_end_matches('/'));
+        self.export_entries(prefix, |key, value| {
+            ureq::post(&url).send_json(json!({
+                "key": BASE64.encode(key),
+                "value": BASE64.encode(value),
+            }))?;
+            Ok(())
+        })
+    }
+
+    /// Inserts `entries`, returning the number inserted.
+    fn import_entries(
+        &mut self,
+        entries: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<usize, StoreError> {
+        let mut copied = 0;
+        for (key, value) in entries {
+            self.try_insert(key, value)?;
+            copied += 1;
+        }
+        Ok(copied)
+    }
+
+    /// Sends each entry whose key starts with `prefix` with `put`, in key
+    /// order, returning the number sent.
+    fn export_entries(
+        &self,
+        prefix: &str,
+        put: impl Fn(&str, &str) -> Result<(), Box<ureq::Error>>,
+    ) -> Result<usize, StoreError> {
+        let keys = self.keys_with_prefix(prefix);
+        if let Some(key) = keys
+            .iter()
+            .find(|key| self.meta.encrypted.contains_key(**key))
+        {
+            return Err(StoreError::Encrypted((*key).to_string()));
+        }
+        for key in &keys {
+            put(key, &self.inner[*key]).map_err(|e| http_error(*e))?;
+        }
+        Ok(keys.len())
+    }
+}
+
+/// Returns the URL of `key` in the Consul agent at `url`.
+fn consul_key_url(url: &str, key: &str) -> String {
+    let mut path = String::new();
+    for byte in key.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            path.push(char::from(byte));
+        } else {
+            write!(path, "%{byte:02X}").expect("writing to a String can't fail");
+        }
+    }
+    format!("{}/v1/kv/{path}", url.trim_end_matches('/'))
+}
+
+/// Returns the first key in etcd's range of keys starting with `prefix`.
+/// The range of all keys runs from the zero byte.
+fn range_start(prefix: &str) -> &[u8] {
+    if prefix.is_empty() {
+        b"\0"
+    } else {
+        prefix.as_bytes()
+    }
+}
+
+/// Returns the end of etcd's range of keys starting with `prefix`, which is
+/// the prefix with its last byte incremented, after dropping any trailing
+/// `0xff` bytes. The zero byte as the end means there's no end.
+fn range_end(prefix: &str) -> Vec<u8> {
+    let mut end = prefix.as_bytes().to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    vec![0]
+}
+
+/// Decodes base64 text to a string, returning `None` if it isn't valid
+/// base64 or UTF-8.
+fn decode(text: &str) -> Option<String> {
+    String::from_utf8(BASE64.decode(text).ok()?).ok()
+}
+
+fn http_error(e: ureq::Error) -> StoreError {
+    StoreError::Io(io::Error::other(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_escaped_and_ranged_for_each_api() {
+        assert_eq!(
+            "http://localhost:8500/v1/kv/app/a%20b%3F",
+            consul_key_url("http://localhost:8500/", "app/a b?")
+        );
+        assert_eq!(b"app/".as_slice(), range_start("app/"));
+        assert_eq!(b"app0".to_vec(), range_end("app/"));
+        assert_eq!(b"\0".as_slice(), range_start(""));
+        assert_eq!(vec![0], range_end(""));
+    }
+
+    #[test]
+    fn unreachable_servers_are_reported_without_changing_store() {
+        let mut s = Store::<String>::new("unused.kv".into());
+        let err = s.import_consul("http://127.0.0.1:1", "app/").unwrap_err();
+        assert!(matches!(err, StoreError::Io(_)), "wrong error {err:?}");
+        let err = s.import_etcd("http://127.0.0.1:1", "app/").unwrap_err();
+        assert!(matches!(err, StoreError::Io(_)), "wrong error {err:?}");
+        assert!(s.is_empty(), "store changed");
+    }
+}
@@ -0,0 +1,171 @@
+//! An ordered index of a store's keys, for fast prefix queries.
+
+use std::collections::BTreeSet;
+use std::ops::Bound;
+
+use crate::{Store, StoreError};
+
+/// The state of a store's key index; see [`Store::set_key_index()`].
+#[derive(Debug, Default)]
+pub(crate) enum KeyIndex {
+    #[default]
+    Off,
+    /// The keys may have changed in ways the index doesn't know about, such
+    /// as through the underlying `HashMap`, so it must be rebuilt.
+    Stale,
+    Fresh(BTreeSet<String>),
+}
+
+impl<V> Store<V> {
+    /// Returns `true` if the store keeps an index of its keys; see
+    /// [`Self::set_key_index()`].
+    #[must_use]
+    pub fn key_index(&self) -> bool {
+        !matches!(self.key_index, KeyIndex::Off)
+    }
+
+    /// Sets whether to keep an ordered index of the store's keys, so that
+    /// [`Self::keys_with_prefix()`] and [`Self::remove_prefix()`] don't
+    /// need to check every key. The index isn't persisted with the store.
+    ///
+    /// The index is kept up to date as keys are inserted and removed. Any
+    /// change made through the underlying `HashMap` invalidates it, and it's
+    /// rebuilt by the next method that inserts or removes a key; until then,
+    /// prefix queries check every key. This is worthwhile for stores with
+    /// many thousands of keys that are queried by prefix.
+    pub fn set_key_index(&mut self, enabled: bool) {
+        self.key_index = if enabled {
+            KeyIndex::Fresh(self.keys().cloned().collect())
+        } else {
+            KeyIndex::Off
+        };
+    }
+
+    /// Records that `key` has been inserted.
+    pub(crate) fn index_key(&mut self, key: &str) {
+        self.refresh_key_index();
+        if let KeyIndex::Fresh(keys) = &mut self.key_index {
+            if !keys.contains(key) {
+                keys.insert(key.to_string());
+            }
+        }
+    }
+
+    /// Records that `key` has been removed.
+    pub(crate) fn unindex_key(&mut self, key: &str) {
+        self.refresh_key_index();
+        if let KeyIndex::Fresh(keys) = &mut self.key_index {
+            keys.remove(key);
+        }
+    }
+
+    /// Rebuilds the index if it's stale.
+    fn refresh_key_index(&mut self) {
+        if matches!(self.key_index, KeyIndex::Stale) {
+            self.set_key_index(true);
+        }
+    }
+
+    /// Records that the keys may have changed in unknown ways.
+    pub(crate) fn invalidate_key_index(&mut self) {
+        if self.key_index() {
+            self.key_index = KeyIndex::Stale;
+        }
+    }
+
+    /// Rebuilds the index, if there is one, after replacing all the keys.
+    pub(crate) fn rebuild_key_index(&mut self) {
+        if self.key_index() {
+            self.set_key_index(true);
+        }
+    }
+
+    /// Returns the keys starting with `prefix`, in order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::builder(path).key_index(true).open()?;
+    /// s.try_insert("db_port".to_string(), "5432".to_string())?;
+    /// s.try_insert("db_host".to_string(), "localhost".to_string())?;
+    /// s.try_insert("log_level".to_string(), "info".to_string())?;
+    /// assert_eq!(s.keys_with_prefix("db_"), ["db_host", "db_port"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn keys_with_prefix(&self, prefix: &str) -> Vec<&str> {
+        let prefix = self.normalize(prefix);
+        if let KeyIndex::Fresh(keys) = &self.key_index {
+            return keys
+                .range::<str, _>((Bound::Included(prefix.as_ref()), Bound::Unbounded))
+                .take_while(|k| k.starts_with(prefix.as_ref()))
+                .map(String::as_str)
+                .collect();
+        }
+        let mut keys: Vec<_> = self
+            .keys()
+            .filter(|k| k.starts_with(prefix.as_ref()))
+            .map(String::as_str)
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    /// Removes every key starting with `prefix`, returning the number
+    /// removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Protected`] if any of the keys is protected,
+    /// in which case none are removed.
+    pub fn remove_prefix(&mut self, prefix: &str) -> Result<usize, StoreError> {
+        let doomed: Vec<_> = self
+            .keys_with_prefix(prefix)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        if let Some(key) = doomed.iter().find(|k| self.is_protected(k)) {
+            return Err(StoreError::Protected(key.clone()));
+        }
+        for key in &doomed {
+            self.force_remove(key);
+        }
+        Ok(doomed.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    #[test]
+    fn key_index_tracks_changes_made_any_way() {
+        let mut s = Store::<u8>::new(PathBuf::from("unused.kv"));
+        s.set_key_index(true);
+        s.try_insert("a/1".to_string(), 1).unwrap();
+        s.try_insert("a/2".to_string(), 2).unwrap();
+        s.try_insert("b/1".to_string(), 3).unwrap();
+        s.remove("a/1").unwrap();
+        assert_eq!(vec!["a/2"], s.keys_with_prefix("a/"), "wrong keys");
+        // Bypasses the index, so it must be rebuilt.
+        HashMap::insert(&mut s, "a/3".to_string(), 4);
+        assert_eq!(vec!["a/2", "a/3"], s.keys_with_prefix("a/"), "wrong keys");
+        s.try_insert("a/4".to_string(), 5).unwrap();
+        assert!(
+            matches!(&s.key_index, KeyIndex::Fresh(keys) if keys.len() == 4),
+            "index not rebuilt"
+        );
+        s.protect("b/1");
+        assert!(s.remove_prefix("b/").is_err(), "protected key removed");
+        assert_eq!(3, s.remove_prefix("a/").unwrap(), "wrong number removed");
+        assert_eq!(vec!["b/1"], s.keys_with_prefix(""), "wrong keys");
+    }
+}
@@ -0,0 +1,158 @@
+//! Values that refer to other keys.
+
+use crate::{Store, StoreError};
+
+impl Store<String> {
+    /// Returns the value for `key` (following any aliases), with any
+    /// references to other keys replaced by their values.
+    ///
+    /// A reference is written `${other_key}`, and is replaced by the value
+    /// for `other_key`, which may itself contain references. To include a
+    /// literal `$`, write `$$`. A `${` with no closing `}` is left as it is.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("root".to_string(), "/srv/app".to_string())?;
+    /// s.try_insert("logs".to_string(), "${root}/logs".to_string())?;
+    /// s.try_insert("price".to_string(), "$$5".to_string())?;
+    /// assert_eq!(s.get_resolved("logs")?.unwrap(), "/srv/app/logs");
+    /// assert_eq!(s.get_resolved("price")?.unwrap(), "$5");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::MissingReference`] if a referenced key isn't
+    /// present, or [`StoreError::ReferenceCycle`] if a value refers,
+    /// directly or indirectly, to itself.
+    pub fn get_resolved(&self, key: &str) -> Result<Option<String>, StoreError> {
+        let key = self.normalize(key);
+        let key = self.resolve(&key);
+        let Some(value) = self.inner.get(key) else {
+            return Ok(None);
+        };
+        let mut stack = vec![key];
+        self.interpolate(value, &mut stack).map(Some)
+    }
+
+    /// Registers `key` as a derived key (see [`Self::derive()`]) whose value
+    /// is `template`, with any references to other keys replaced by their
+    /// values, as in [`Self::get_resolved()`]. If a reference can't be
+    /// resolved, the key has no value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("host".to_string(), "example.com".to_string())?;
+    /// s.derive_template("full_url", "https://${host}/");
+    /// assert_eq!(
+    ///     s.derived_value("full_url").unwrap(),
+    ///     "https://example.com/"
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn derive_template(&mut self, key: &str, template: &str) {
+        let name = key.to_string();
+        let template = template.to_string();
+        self.derive(key, move |s| {
+            s.interpolate(&template, &mut vec![name.as_str()]).ok()
+        });
+    }
+
+    /// Replaces the references in `value`. `stack` holds the keys whose
+    /// values are currently being resolved, to detect cycles.
+    fn interpolate<'a>(
+        &'a self,
+        value: &'a str,
+        stack: &mut Vec<&'a str>,
+    ) -> Result<String, StoreError> {
+        let mut result = String::with_capacity(value.len());
+        let mut rest = value;
+        while let Some(start) = rest.find('$') {
+            result.push_str(&rest[..start]);
+            rest = &rest[start..];
+            if let Some(after) = rest.strip_prefix("$$") {
+                result.push('$');
+                rest = after;
+                continue;
+            }
+            let Some(end) = rest.strip_prefix("${").and_then(|r| r.find('}')) else {
+                result.push('$');
+                rest = &rest[1..];
+                continue;
+            };
+            let reference = self.resolve(&rest[2..end + 2]);
+            if stack.contains(&reference) {
+                return Err(StoreError::ReferenceCycle(reference.to_string()));
+            }
+            let Some(referenced) = self.inner.get(reference) else {
+                return Err(StoreError::MissingReference {
+                    key: stack[stack.len() - 1].to_string(),
+                    reference: reference.to_string(),
+                });
+            };
+            stack.push(reference);
+            result.push_str(&self.interpolate(referenced, stack)?);
+            stack.pop();
+            rest = &rest[end + 3..];
+        }
+        result.push_str(rest);
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_resolved_substitutes_references_and_detects_errors() {
+        let entries = [
+            ("host", "db1"),
+            ("port", "5432"),
+            ("addr", "${host}:${port}"),
+            ("url", "pg://${addr}/$${literal}"),
+            ("unterminated", "${host"),
+            ("dangling", "${nowhere}"),
+            ("loop1", "${loop2}"),
+            ("loop2", "x${loop1}"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        let s = Store::from_entries("unused.kv", entries);
+        let resolved = |key| s.get_resolved(key).unwrap().unwrap();
+        assert_eq!("db1:5432", resolved("addr"), "wrong resolved value");
+        assert_eq!(
+            "pg://db1:5432/${literal}",
+            resolved("url"),
+            "wrong resolved value"
+        );
+        assert_eq!("${host", resolved("unterminated"), "wrong resolved value");
+        assert_eq!(None, s.get_resolved("missing").unwrap(), "unexpected data");
+        assert!(
+            matches!(
+                s.get_resolved("dangling"),
+                Err(StoreError::MissingReference { .. })
+            ),
+            "missing reference not detected"
+        );
+        assert!(
+            matches!(s.get_resolved("loop1"), Err(StoreError::ReferenceCycle(_))),
+            "cycle not detected"
+        );
+    }
+}
@@ -25,6 +25,19 @@
 //! cargo install rskey
 //! ```
 //!
+//! The CLI tool needs the `cli` feature, which is on by default, and turns
+//! on the `encryption`, `regex`, `toml`, `unicode`, and `yaml` features. To
+//! use `rskey` as a library without the CLI's dependencies, turn off the
+//! default features, and turn on only those you need:
+//!
+//! ```toml
+//! [dependencies]
+//! rskey = { version = "0.4", default-features = false, features = ["regex"] }
+//! ```
+//!
+//! The minimum supported Rust version is 1.85, though some optional
+//! features, such as `http`, need a later one.
+//!
 //! ## Usage
 //!
 //! The `rskey` tool expects to find a data file named `store.kv` in the current
@@ -41,6 +54,41 @@
 //! key2: value2
 //! ```
 //!
+//! Values containing line breaks or `: ` make this output ambiguous. For
+//! output that can be reliably parsed, use `rskey list --format tsv`, which
+//! separates each key and value with a tab and escapes any tabs, line breaks,
+//! and backslashes in them as `\t`, `\n`, `\r`, and `\\`. Alternatively,
+//! `rskey list -0` prints every key and value followed by a NUL byte, for use
+//! with `xargs -0`.
+//!
+//! `list`, `keys`, `count`, `exists`, and `get` read the data file one entry
+//! at a time, so they work even on stores too large to fit in memory (unless
+//! the file is signed, or the value to get contains references).
+//!
+//! ### Listing keys or values
+//!
+//! To print just the keys, or just the values, one per line (for example, to
+//! pipe them to another command):
+//!
+//! ```sh
+//! rskey keys
+//! rskey keys --prefix db_
+//! rskey values 'db_*'
+//! ```
+//!
+//! `rskey values` takes a pattern, which may contain the wildcards `*` and
+//! `?`. Both commands print in key order.
+//!
+//! ### Counting and checking keys
+//!
+//! `rskey count` prints the number of keys, and also accepts `--prefix`.
+//! `rskey exists KEY` prints nothing, but exits with status 0 if the key is
+//! present, and 1 if not, so it can be used in shell conditions:
+//!
+//! ```sh
+//! if rskey exists key1; then echo "found"; fi
+//! ```
+//!
 //! ### Getting a value by key
 //!
 //! ```sh
@@ -50,28 +98,853 @@
 //! key1: value1
 //! ```
 //!
+//! Values can refer to other keys, as `${other_key}`, and `rskey get` replaces
+//! each reference with the other key's value. To include a literal `$` in a
+//! value, write `$$`. Use `rskey get --no-resolve` to see the value as stored.
+//!
+//! ```sh
+//! rskey set root /srv/app
+//! rskey set logs '${root}/logs'
+//! rskey get logs
+//! ```
+//! ```text
+//! logs: /srv/app/logs
+//! ```
+//!
+//! To check that a value is of a particular type, and show it in a
+//! standard form, use `--as` with `int`, `bool`, `duration`, or `datetime`.
+//! Durations, such as `1h30m`, and times, such as `2024-05-01T12:00:00Z`,
+//! are shown in seconds (since the Unix epoch, for times):
+//!
+//! ```sh
+//! rskey get --as duration timeout
+//! ```
+//! ```text
+//! timeout: 5400
+//! ```
+//!
 //! ### Setting a key-value pair
 //!
 //! ```sh
 //! rskey set key3 value3
 //! ```
+//!
+//! To set a value read from standard input (for example, one containing
+//! several lines), give `-` as the value, or use `--stdin`:
+//!
+//! ```sh
+//! git log -1 | rskey set last-commit -
+//! rskey set --stdin motd < /etc/motd
+//! ```
+//!
+//! To add to the end of an existing value (or set it, if there is none):
+//!
+//! ```sh
+//! rskey append log "started"
+//! ```
+//!
+//! `rskey getset KEY VALUE` does the same as `rskey set`, but first prints the
+//! key's old value, if any.
+//!
+//! Each key has a version, which increases every time it's changed. When
+//! several programs update the same store, each can check that nobody else
+//! has changed a key since it read it, by giving the version it read. If the
+//! key has changed, the command fails and the value is left as it is:
+//!
+//! ```sh
+//! rskey version counter
+//! rskey set --if-version 3 counter 42
+//! ```
+//!
+//! ### Aliases
+//!
+//! An alias is another name for a key, which `rskey get` and `rskey set` treat
+//! as the key itself. This is useful when renaming a key, so that scripts
+//! using the old name keep working:
+//!
+//! ```sh
+//! rskey alias old_name new_name
+//! rskey get old_name
+//! ```
+//! ```text
+//! old_name: value3
+//! ```
+//!
+//! To remove the alias, use `rskey unalias old_name`.
+//!
+//! ### Deleting a key
+//!
+//! ```sh
+//! rskey del key3
+//! ```
+//!
+//! `rskey del --prefix P` deletes every key starting with `P`, and `rskey
+//! clear` deletes every key, except protected ones. Before either of
+//! these, `rskey import`, or `rskey snapshots restore` changes or deletes
+//! any existing keys, `rskey` lists them and asks whether to go ahead, if
+//! run from a terminal. Pass `-y` (or `--yes`) before the command to go
+//! ahead without asking, or `--dry-run` to list the changes without making
+//! them:
+//!
+//! ```sh
+//! rskey --dry-run del --prefix tmp_
+//! ```
+//! ```text
+//! delete tmp_build
+//! delete tmp_cache
+//! ```
+//!
+//! ### Importing and exporting
+//!
+//! `rskey import` sets keys from a JSON, YAML, or TOML file. With
+//! `--flatten`, nested values are stored under dotted keys, so `port` within
+//! `server` becomes `server.port`. With `--overwrite`, it also deletes every
+//! key that isn't in the file, except protected ones. `rskey export` prints
+//! all key-value pairs as JSON, or as YAML or TOML with `--format`, and
+//! `--unflatten` nests them again:
+//!
+//! ```sh
+//! rskey import --flatten config.yaml
+//! rskey export --unflatten --format yaml > config.yaml
+//! ```
+//!
+//! Values that look like numbers, booleans, or `null` are exported as those
+//! types.
+//!
+//! To write the document to a file instead, give its path after the
+//! options. With `--prefix`, only the keys starting with that prefix are
+//! exported, so one application's keys can be extracted from a shared
+//! store. A JSON export is also a valid data file, so it can be used as a
+//! store in its own right:
+//!
+//! ```sh
+//! rskey export --prefix app: --format json /srv/app/store.kv
+//! cd /srv/app && rskey list
+//! ```
+//!
+//! To paste the store's contents into documentation, export it as a table
+//! with `--format markdown` or `--format html`. Columns for protected keys,
+//! secret keys, and expiry times are added when any key has them, and
+//! secret values are hidden unless you pass `--reveal`.
+//!
+//! If `rskey` is built with the `redis` feature, it can also copy keys from
+//! a Redis server with `--from-redis`, or to one with `--to-redis`, giving
+//! the server's URL. Keys with a TTL keep it, and `--pattern` copies only
+//! the keys matching a glob pattern:
+//!
+//! ```sh
+//! rskey import --from-redis redis://localhost/0 --pattern 'app:*'
+//! rskey export --to-redis redis://localhost/1
+//! ```
+//!
+//! Only Redis strings are copied, so hashes, lists, and the like are skipped.
+//!
+//! With the `sqlite` feature, `rskey export --format sqlite FILE` writes the
+//! key-value pairs to a table called `entries` in a SQLite database, with
+//! columns for each key's version, expiry time, and whether it's protected
+//! or secret, so you can query them with SQL. `rskey import` reads them
+//! back from a file ending in `.db`, `.sqlite`, or `.sqlite3`:
+//!
+//! ```sh
+//! rskey export --format sqlite store.db
+//! sqlite3 store.db "SELECT key FROM entries WHERE expires IS NOT NULL"
+//! rskey -n copy import store.db
+//! ```
+//!
+//! With the `parquet` feature, `rskey export --format parquet FILE` writes
+//! the same columns to a Parquet file instead, for loading into DuckDB,
+//! pandas, and other analytics tools.
+//!
+//! Similarly, with the `http` feature, `rskey` can copy keys from or to the
+//! key-value store of a Consul agent, with `--from-consul` or `--to-consul`,
+//! or of an etcd server, with `--from-etcd` or `--to-etcd`. Use `--prefix`
+//! to copy only the keys under a given prefix. Keys keep their prefix, so
+//! they can be edited locally and then copied back:
+//!
+//! ```sh
+//! rskey import --from-consul http://localhost:8500 --prefix app/
+//! rskey set app/timeout 30s
+//! rskey export --to-consul http://localhost:8500 --prefix app/
+//! ```
+//!
+//! ### Running several commands at once
+//!
+//! With `-` as its only argument, `rskey` reads commands from standard input,
+//! one per line, and syncs the data file once at the end. Blank lines and
+//! lines starting with `#` are ignored, and the value given to `set` is the
+//! rest of the line:
+//!
+//! ```sh
+//! rskey - <<EOF
+//! set greeting hello world
+//! del key3
+//! EOF
+//! ```
+//!
+//! If a command fails, the rest are skipped, but earlier changes are still
+//! written. Use `rskey - --atomic` to discard all the changes instead.
+//!
+//! ### Expiring keys
+//!
+//! A key can be set to expire after a given time, written as a number of
+//! seconds (`s`), minutes (`m`), hours (`h`), or days (`d`). Expired keys are
+//! deleted the next time `rskey` runs:
+//!
+//! ```sh
+//! rskey expire session 1h
+//! rskey ttl session
+//! ```
+//! ```text
+//! 3600s
+//! ```
+//!
+//! To stop the key expiring, use `rskey persist session`. Setting a new value
+//! for the key also clears its expiry time.
+//!
+//! ### Signing the data file
+//!
+//! To detect changes made to the data file by anything other than `rskey`,
+//! set the `RSKEY_SIGNING_KEY` environment variable to a passphrase, or
+//! `RSKEY_SIGNING_KEY_FILE` to the path of a key file. The file will be
+//! signed whenever it's written, and `rskey` will refuse to read it if the
+//! signature is missing or doesn't match.
+//!
+//! If `rskey` is built with the `keyring` feature, the passphrase can instead
+//! be kept in the operating system's credential store. Save it under the
+//! service name `rskey`, and set `RSKEY_SIGNING_KEY_KEYRING` to the account
+//! name you saved it with.
+//!
+//! To change the key, set the usual variable to the current key, and
+//! `RSKEY_NEW_SIGNING_KEY` (or `RSKEY_NEW_SIGNING_KEY_FILE`, or
+//! `RSKEY_NEW_SIGNING_KEY_KEYRING`) to the new one, and run `rskey rekey`.
+//! This checks the data file and its snapshots against the current key, then
+//! signs them with the new one:
+//!
+//! ```sh
+//! RSKEY_SIGNING_KEY=old RSKEY_NEW_SIGNING_KEY=new rskey rekey
+//! ```
+//!
+//! ### Hiding secret values
+//!
+//! Values whose keys match a secret pattern are shown as `*****` by
+//! `rskey list`, unless you pass `--reveal`. Patterns may contain the
+//! wildcards `*` and `?`:
+//!
+//! ```sh
+//! rskey secret '*_token'
+//! rskey list
+//! ```
+//! ```text
+//! github_token: *****
+//! key1: value1
+//! ```
+//!
+//! To stop hiding the values, use `rskey unsecret '*_token'`.
+//!
+//! Hidden values are still stored in plaintext. To encrypt a value, so that
+//! it can only be read with a key, set `RSKEY_ENCRYPTION_KEY` to a
+//! passphrase, or `RSKEY_ENCRYPTION_KEY_FILE` to the path of a key file, and
+//! use `rskey set --secret`. The rest of the store stays in plaintext. With
+//! the same key set, `rskey get` and `rskey export` decrypt the value;
+//! `rskey list` always shows it as `*****`:
+//!
+//! ```sh
+//! export RSKEY_ENCRYPTION_KEY='a long random passphrase'
+//! rskey set --secret api_token xyz
+//! rskey get api_token
+//! ```
+//! ```text
+//! api_token: xyz
+//! ```
+//!
+//! To change the key, set `RSKEY_NEW_ENCRYPTION_KEY` (or
+//! `RSKEY_NEW_ENCRYPTION_KEY_FILE`) to the new one as well, and run `rskey
+//! rekey`. This decrypts every encrypted value with the current key, then
+//! encrypts it with the new one, in the store and in all its snapshots.
+//!
+//! ### Checking values
+//!
+//! To make `rskey set` reject values that don't match a [JSON
+//! Schema](https://json-schema.org), set one for a key pattern. As values are
+//! strings, the most useful keywords are `enum`, `minLength`, and `maxLength`:
+//!
+//! ```sh
+//! rskey schema 'log_*' '{"enum": ["debug", "info", "warn", "error"]}'
+//! rskey set log_level verbose
+//! ```
+//! ```text
+//! Error: value of "log_level" is invalid: must be one of ["debug","info","warn","error"]
+//! ```
+//!
+//! To remove the schema, use `rskey unschema 'log_*'`.
+//!
+//! ### Checking the store for problems
+//!
+//! Editing the data file by hand, or copying it between machines, can leave
+//! it inconsistent in ways that only cause surprises later. `rskey verify`
+//! checks the file's signature and format header, that each key's metadata
+//! and aliases make sense and its value matches any schema, and that
+//! replaying the operations log would give the same data. It prints each
+//! problem it finds, and fails if there are any:
+//!
+//! ```sh
+//! rskey verify
+//! ```
+//! ```text
+//! expiry time kept for missing key "key2"
+//! alias "a" leads round in a cycle
+//! ```
+//!
+//! ### Protecting a key
+//!
+//! A protected key can't be changed by `rskey set` unless you pass `--force`:
+//!
+//! ```sh
+//! rskey protect key3
+//! rskey set --force key3 value4
+//! rskey unprotect key3
+//! ```
+//!
+//! ### Namespaces
+//!
+//! To keep a separate set of keys, give a namespace name with `-n` before
+//! any command. Each namespace has its own data file, `.rskey/ns/NAME.kv`,
+//! which is only written when that namespace changes:
+//!
+//! ```sh
+//! rskey -n staging set db_url postgres://db2
+//! rskey -n staging get db_url
+//! ```
+//!
+//! ### Workspaces
+//!
+//! To use stores kept elsewhere without typing their paths, list them by
+//! name in a workspace file, `rskey.toml`, in the current directory or any
+//! parent directory. Relative paths are relative to the workspace file:
+//!
+//! ```toml
+//! [stores]
+//! prod = "/srv/prod.kv"
+//! dev = "./dev.kv"
+//! ```
+//!
+//! Then give a store's name with `-s` before any command:
+//!
+//! ```sh
+//! rskey -s prod get db_url
+//! ```
+//!
+//! In a program, open a store by name with
+//! [`Workspace::open()`](crate::Workspace::open).
+//!
+//! ### Layering stores
+//!
+//! To override shared settings with those for a particular environment,
+//! give a comma-separated list of data files with `--overlay`. Commands
+//! that only read see the keys in all the files, each overriding the ones
+//! before it; commands that change keys change only the last file:
+//!
+//! ```sh
+//! rskey --overlay base.kv,prod.kv get log_level
+//! rskey --overlay base.kv,prod.kv set log_level warn
+//! ```
+//!
+//! In a program, use [`Store::overlay()`](crate::Store::overlay).
+//!
+//! ### Splitting and joining stores
+//!
+//! A store that has grown to hold keys for several applications can be
+//! split into one store per key prefix. `rskey split --by-prefix ':'
+//! --out-dir parts` writes the keys starting with `app:` to `parts/app.kv`,
+//! and so on, along with their metadata. Keys with no prefix are left out,
+//! with a warning. The store itself is unchanged:
+//!
+//! ```sh
+//! rskey split --by-prefix ':' --out-dir parts
+//! ```
+//! ```text
+//! parts/app.kv: 12 keys
+//! parts/db.kv: 3 keys
+//! ```
+//!
+//! `rskey join` does the reverse, copying the keys of each store it's given
+//! into the store given by `--out`, which is created if necessary. If a key
+//! has different values in two of the stores, it fails without writing
+//! anything, unless you pass `--on-conflict keep` to keep the first value,
+//! or `--on-conflict replace` to use the last:
+//!
+//! ```sh
+//! rskey join parts/*.kv --out combined.kv
+//! ```
+//!
+//! ### Derived keys
+//!
+//! To compute a key's value from other keys whenever it's read, without
+//! storing it, give its template with `--derive` before any command. The
+//! template can refer to other keys, as in `${KEY}`. Derived keys are shown
+//! by `get`, and by `list` with `--include-derived`:
+//!
+//! ```sh
+//! rskey --derive 'url=https://${host}:${port}/' get url
+//! rskey --derive 'url=https://${host}:${port}/' list --include-derived
+//! ```
+//!
+//! In a program, use [`Store::derive()`](crate::Store::derive) to compute a
+//! key with any function.
+//!
+//! ### Concurrent writers
+//!
+//! While changing the store, `rskey` holds a lock file alongside the data
+//! file (for example, `store.kv.lock`), so that several processes can share
+//! the store safely, even on network filesystems. If another process holds
+//! the lock, `rskey` waits up to 10 seconds for it to be released, or as long
+//! as given by `RSKEY_LOCK_TIMEOUT` (for example, `RSKEY_LOCK_TIMEOUT=2m`).
+//! Locks left behind by processes that have exited are taken over
+//! automatically.
+//!
+//! ### Size limits
+//!
+//! To notice a runaway producer before the data file gets huge, set
+//! `RSKEY_WARN_AT_ENTRIES` or `RSKEY_WARN_AT_BYTES`. Whenever a command
+//! leaves the store with more entries or bytes than that, `rskey` prints a
+//! warning to standard error. To stop the store growing past a given size
+//! altogether, set `RSKEY_BYTE_LIMIT`; commands that would take it over the
+//! limit then fail.
+//!
+//! To make large values take less space in the data file, compress each
+//! value over a given number of bytes separately. Commands that read the
+//! file one entry at a time only decompress the values they read:
+//!
+//! ```sh
+//! rskey compress 1024
+//! ```
+//!
+//! `rskey uncompress` stores every value in plaintext again.
+//!
+//! ### Snapshots
+//!
+//! To save a copy of the store, run `rskey snapshot`, which prints the
+//! snapshot's name. Snapshots are kept in `store.kv.snapshots`. With
+//! `--keep AGE`, snapshots older than `AGE` are then deleted, so a cron job
+//! such as `rskey snapshot --keep 7d` keeps a week of history:
+//!
+//! ```sh
+//! rskey snapshots list
+//! rskey snapshots restore 1760520000
+//! ```
+//!
+//! Programs using the library can take snapshots on a schedule with
+//! `Store::spawn_snapshotter`.
+//!
+//! ### Shipping changes to another store
+//!
+//! With `--log`, each key that a command sets or deletes is recorded in the
+//! operations log, `store.kv.ops`. `rskey export-ops` prints the logged
+//! changes as JSON, one per line, and `rskey import-ops FILE` applies them to
+//! another store. Each change is numbered with the store's generation, so
+//! `--since SEQ` prints only the changes made after sync `SEQ`:
+//!
+//! ```sh
+//! rskey --log set key1 value1
+//! rskey export-ops --since 0 >changes.jsonl
+//! rskey -n replica import-ops changes.jsonl
+//! ```
+//!
+//! The log grows with every change, even as keys are deleted. `rskey
+//! shrink` removes every change that a later one to the same key
+//! supersedes, and reports the size of the data file and log before and
+//! after. Replaying the shrunk log still gives the same data.
+//!
+//! ### Keeping history in git
+//!
+//! If the data file is in a git repository, pass `--git` before any command
+//! to commit each change to it, with a message saying which keys were set or
+//! deleted:
+//!
+//! ```sh
+//! rskey --git set key1 value1
+//! git log --oneline store.kv
+//! ```
 
+use derived::Derivation;
+use format::{Contents, Meta};
+use index::KeyIndex;
+use oplog::Changes;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::hash_map::IntoIter;
-use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::{BufReader, BufWriter};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display};
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+mod agg;
+mod alias;
+mod backend;
+mod builder;
+mod clock;
+mod compress;
+#[cfg(feature = "dashmap")]
+mod concurrent;
+mod convert;
+mod derived;
+#[cfg(feature = "encryption")]
+mod encrypt;
+mod entry;
+mod expiry;
+mod export;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod flatten;
+mod format;
+mod frozen;
+mod gc;
+mod git;
+mod glob;
+mod group;
+#[cfg(feature = "http")]
+mod http;
+mod index;
+mod interpolate;
+mod limit;
+mod lock;
+mod normalize;
+mod oplog;
+mod ops;
+mod overlay;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod periodic;
+mod poly;
+mod progress;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "redis")]
+mod redis;
+mod rekey;
+mod retry;
+mod sample;
+mod scan;
+mod schema;
+mod scratch;
+#[cfg(feature = "regex")]
+mod search;
+mod shrink;
+mod sign;
+mod snapshot;
+mod split;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod stats;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod typed;
+mod verify;
+mod version;
+#[cfg(feature = "web")]
+mod web;
+#[cfg(feature = "toml")]
+mod workspace;
+
+pub use agg::Agg;
+pub use backend::{Backend, FileBackend};
+pub use builder::StoreBuilder;
+pub use clock::{Clock, SystemClock};
+#[cfg(feature = "dashmap")]
+pub use concurrent::{ConcurrentStore, StoreView};
+#[cfg(feature = "encryption")]
+pub use encrypt::EncryptionKey;
+pub use entry::Entry;
+pub use expiry::Sweeper;
+pub use export::ExportFormat;
+pub use frozen::FrozenStore;
+pub use gc::GcReport;
+pub use group::Group;
+pub use limit::SizeWarning;
+pub use lock::FileLock;
+pub use normalize::KeyNormalization;
+pub use oplog::Op;
+pub use overlay::Overlay;
+pub use poly::{PolyEntry, PolyStore, PolyValue};
+pub use progress::ProgressSink;
+#[cfg(feature = "regex")]
+pub use regex::Regex;
+pub use retry::Retry;
+pub use scratch::Scratch;
+pub use shrink::ShrinkReport;
+pub use sign::SigningKey;
+pub use snapshot::{Snapshot, Snapshotter};
+pub use split::{Conflict, SplitReport};
+pub use stats::Stats;
+pub use typed::Typed;
+pub use verify::{IntegrityReport, Issue};
+#[cfg(feature = "web")]
+pub use web::LocalStorageBackend;
+#[cfg(feature = "toml")]
+pub use workspace::{Workspace, WORKSPACE_FILE};
+
+/// An error returned by a [`Store`] operation.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum StoreError {
+    /// An error reading or writing the data file.
+    Io(std::io::Error),
+    /// An attempt to change or remove a protected key without forcing it.
+    Protected(String),
+    /// A signed data file whose signature is missing or doesn't match its
+    /// contents.
+    Tampered,
+    /// An encrypted value that couldn't be decrypted, because the key is
+    /// wrong or the value has been changed.
+    Undecryptable(String),
+    /// An attempt to create an alias with the same name as an existing key.
+    AliasConflict(String),
+    /// An attempt to create an alias that would refer, directly or
+    /// indirectly, to itself.
+    AliasCycle(String),
+    /// A value that refers to a key that isn't present.
+    MissingReference {
+        /// The key whose value contains the reference.
+        key: String,
+        /// The key referred to.
+        reference: String,
+    },
+    /// A value that refers, directly or indirectly, to itself.
+    ReferenceCycle(String),
+    /// An attempt to change a key that isn't at the expected version.
+    VersionConflict {
+        /// The key.
+        key: String,
+        /// The version that was expected.
+        expected: u64,
+        /// The key's actual version.
+        actual: u64,
+    },
+    /// An attempt to normalize keys when two keys have the same normalized
+    /// form.
+    KeyCollision(String),
+    /// A key that has a value, but also starts other keys, so it can't be
+    /// converted to a nested document.
+    NestingConflict(String),
+    /// A value that doesn't match the schema set for its key.
+    Invalid {
+        /// The key whose value is invalid.
+        key: String,
+        /// Where the problem is in the value, as a JSON pointer (empty if
+        /// it's the value as a whole).
+        path: String,
+        /// What the problem is.
+        message: String,
+    },
+    /// An attempt to insert a value that would take the store over its byte
+    /// limit (see [`Store::set_byte_limit()`]).
+    Full {
+        /// The key being inserted.
+        key: String,
+        /// The store's byte limit.
+        limit: usize,
+    },
+    /// A value that isn't of the type it was requested as.
+    TypeMismatch {
+        /// The key whose value has the wrong type.
+        key: String,
+        /// What the problem is.
+        message: String,
+    },
+    /// A key that has different values in two stores being joined (see
+    /// [`Store::join()`]).
+    JoinConflict(String),
+    /// An attempt to move an encrypted value to another key, which would
+    /// make it impossible to decrypt, or to export it without decrypting it
+    /// first.
+    Encrypted(String),
+}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Io(e) => e.fmt(f),
+            StoreError::Protected(key) => write!(f, "key {key:?} is protected"),
+            StoreError::Tampered => f.write_str("data file signature is missing or invalid"),
+            StoreError::Undecryptable(key) => {
+                write!(f, "can't decrypt value of {key:?} (wrong key, or changed)")
+            }
+            StoreError::AliasConflict(alias) => {
+                write!(
+                    f,
+                    "can't create alias {alias:?}, as a key with that name exists"
+                )
+            }
+            StoreError::AliasCycle(alias) => {
+                write!(
+                    f,
+                    "can't create alias {alias:?}, as it would refer to itself"
+                )
+            }
+            StoreError::MissingReference { key, reference } => {
+                write!(f, "value of {key:?} refers to missing key {reference:?}")
+            }
+            StoreError::ReferenceCycle(key) => write!(f, "value of {key:?} refers to itself"),
+            StoreError::VersionConflict {
+                key,
+                expected,
+                actual,
+            } => {
+                write!(
+                    f,
+                    "key {key:?} is at version {actual}, not the expected version {expected}"
+                )
+            }
+            StoreError::KeyCollision(key) => {
+                write!(f, "more than one key normalizes to {key:?}")
+            }
+            StoreError::NestingConflict(key) => {
+                write!(f, "can't nest {key:?}, as part of it is already a key")
+            }
+            StoreError::Invalid { key, path, message } if path.is_empty() => {
+                write!(f, "value of {key:?} is invalid: {message}")
+            }
+            StoreError::Invalid { key, path, message } => {
+                write!(f, "value of {key:?} is invalid at {path}: {message}")
+            }
+            StoreError::Full { key, limit } => {
+                write!(
+                    f,
+                    "can't set {key:?}, as the store would exceed its limit of {limit} bytes"
+                )
+            }
+            StoreError::TypeMismatch { key, message } => {
+                write!(f, "value of {key:?} has the wrong type: {message}")
+            }
+            StoreError::JoinConflict(key) => {
+                write!(
+                    f,
+                    "key {key:?} has different values in the stores being joined"
+                )
+            }
+            StoreError::Encrypted(key) => {
+                write!(f, "can't move or export encrypted value of {key:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for StoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StoreError::Io(e) => Some(e),
+            StoreError::Protected(_)
+            | StoreError::Tampered
+            | StoreError::Undecryptable(_)
+            | StoreError::AliasConflict(_)
+            | StoreError::AliasCycle(_)
+            | StoreError::MissingReference { .. }
+            | StoreError::ReferenceCycle(_)
+            | StoreError::VersionConflict { .. }
+            | StoreError::KeyCollision(_)
+            | StoreError::NestingConflict(_)
+            | StoreError::Invalid { .. }
+            | StoreError::Full { .. }
+            | StoreError::TypeMismatch { .. }
+            | StoreError::JoinConflict(_)
+            | StoreError::Encrypted(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for StoreError {
+    fn from(e: std::io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError::Io(e.into())
+    }
+}
+
+impl From<StoreError> for std::io::Error {
+    fn from(e: StoreError) -> Self {
+        match e {
+            StoreError::Io(e) => e,
+            e => std::io::Error::other(e),
+        }
+    }
+}
 
 /// A key-value store associated with a particular data file.
 ///
 /// Changes to the store are persisted to the file when [`Self::sync()`] is called.
+/// The store keeps track of whether it has any unsynced changes; see
+/// [`Self::is_dirty()`].
+///
+/// The store dereferences to the underlying `HashMap`, so its methods can
+/// be used to read entries. The store's own [`Self::insert()`],
+/// [`Self::remove()`], [`Self::entry()`], [`Self::get_mut()`],
+/// [`Self::retain()`], [`Self::clear()`], and [`Self::drain()`] take the
+/// place of the `HashMap` methods of the same names, and keep per-key
+/// metadata, such as versions and expiry times, up to date. All but
+/// `insert` also refuse to change protected keys; use
+/// [`Self::try_insert()`] to insert with the same checks. **Any other
+/// `HashMap` method that changes entries, such as `values_mut` or
+/// `iter_mut`, bypasses protection, schemas, the byte limit, and per-key
+/// metadata.**
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Store<V> {
-    pub path: PathBuf,
+    path: PathBuf,
     inner: HashMap<String, V>,
+    #[serde(default)]
+    meta: Meta,
+    #[serde(skip)]
+    signing_key: Option<SigningKey>,
+    #[serde(skip)]
+    dirty: AtomicBool,
+    #[serde(skip)]
+    generation: AtomicU64,
+    #[serde(skip, default = "default_backend")]
+    backend: Arc<dyn Backend>,
+    #[serde(skip, default = "default_clock")]
+    clock: Arc<dyn Clock>,
+    #[serde(skip)]
+    byte_limit: Option<usize>,
+    #[serde(skip)]
+    warn_at_entries: Option<usize>,
+    #[serde(skip)]
+    warn_at_bytes: Option<usize>,
+    #[serde(skip)]
+    git_autocommit: bool,
+    #[serde(skip)]
+    ops_log: bool,
+    #[serde(skip)]
+    key_index: KeyIndex,
+    #[serde(skip)]
+    derived: BTreeMap<String, Derivation<V>>,
+    /// The number of bytes used, if known; see [`Self::bytes_used()`].
+    #[serde(skip)]
+    bytes_used: Option<usize>,
+}
+
+fn default_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+fn default_backend() -> Arc<dyn Backend> {
+    Arc::new(FileBackend)
+}
+
+/// Exchanges the entries for `key_a` and `key_b` in `map`.
+fn swap_entries<T>(map: &mut BTreeMap<String, T>, key_a: &str, key_b: &str) {
+    let a = map.remove(key_a);
+    let b = map.remove(key_b);
+    if let Some(a) = a {
+        map.insert(key_b.to_string(), a);
+    }
+    if let Some(b) = b {
+        map.insert(key_a.to_string(), b);
+    }
 }
 
 impl<V> Store<V>
@@ -87,31 +960,925 @@ where
     ///
     /// ```
     /// # fn main() -> std::io::Result<()> {
-    /// use rskey::Store;
+    /// use rskey::Store;
+    /// # use tempfile::TempDir;
+    ///
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let s = Store::<usize>::open(path)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns any error opening the file (if it exists).
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, std::io::Error> {
+        Ok(Self::new(path.as_ref().into()).load()?)
+    }
+
+    /// Creates a [`Store`] associated with a signed data file at the given
+    /// `path`.
+    ///
+    /// When the store is synced, an HMAC over the data is appended to the
+    /// file using `key`. When the file is opened again with this method, the
+    /// HMAC is checked, so that any changes made to the file by anything
+    /// other than the store will be detected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// use rskey::{SigningKey, Store};
+    /// # use tempfile::TempDir;
+    ///
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let key = SigningKey::from_passphrase("correct horse battery staple");
+    /// let mut s = Store::<usize>::open_signed(&path, key.clone())?;
+    /// s.try_insert("foo".to_string(), 42)?;
+    /// s.sync()?;
+    /// let s = Store::<usize>::open_signed(&path, key)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Tampered`] if the file exists but its signature
+    /// is missing or doesn't match, or any error opening the file.
+    pub fn open_signed(path: impl AsRef<Path>, key: SigningKey) -> Result<Self, StoreError> {
+        Self::builder(path).signing_key(key).open()
+    }
+
+    /// Creates a [`Store`] associated with a data file at the given `path`,
+    /// as with [`Self::open()`], with room for at least `capacity` more
+    /// entries.
+    ///
+    /// If you know how many entries you're about to insert, this avoids
+    /// repeatedly resizing the store as they're added.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error opening the file (if it exists).
+    pub fn with_capacity(path: impl AsRef<Path>, capacity: usize) -> Result<Self, std::io::Error> {
+        let mut store = Self::open(path)?;
+        store.inner.reserve(capacity);
+        Ok(store)
+    }
+
+    /// Reads the data file (if it exists) into a newly-created store.
+    fn load(self) -> Result<Self, StoreError> {
+        self.load_with(|doc| serde_json::from_slice(doc))
+    }
+
+    fn load_with(
+        mut self,
+        parse: impl FnOnce(&[u8]) -> Result<Contents<V>, serde_json::Error>,
+    ) -> Result<Self, StoreError> {
+        if let Some(file) = self.backend.read(&self.path)? {
+            let contents = parse(self.verify(&file)?)?.decompress()?;
+            self.replace_contents(contents);
+        }
+        Ok(self)
+    }
+
+    /// Re-reads the data file if another process (or store) has synced it
+    /// since this store was opened or last synced, as shown by
+    /// [`Self::generation()`]. Returns `true` if the store was reloaded.
+    ///
+    /// Checking the generation doesn't deserialize any values, so this is
+    /// cheap enough to call before every read, letting many processes share
+    /// a store without watching the file. Any unsynced changes are lost when
+    /// the store is reloaded. If the file doesn't exist, the store is left
+    /// as it is.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut reader = Store::<usize>::open(&path)?;
+    /// let mut writer = Store::<usize>::open(&path)?;
+    /// assert!(!reader.reload_if_changed()?);
+    /// writer.try_insert("hits".to_string(), 1)?;
+    /// writer.sync()?;
+    /// assert!(reader.reload_if_changed()?);
+    /// assert_eq!(reader["hits"], 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Tampered`] if the store has a signing key and
+    /// the file's signature is missing or doesn't match, or any error
+    /// reading the file.
+    pub fn reload_if_changed(&mut self) -> Result<bool, StoreError> {
+        let Some(file) = self.backend.read(&self.path)? else {
+            return Ok(false);
+        };
+        let doc = self.verify(&file)?;
+        if format::read_generation(doc)? == self.generation() {
+            return Ok(false);
+        }
+        let contents = serde_json::from_slice::<Contents<V>>(doc)?.decompress()?;
+        self.replace_contents(contents);
+        *self.dirty.get_mut() = false;
+        Ok(true)
+    }
+
+    /// Returns the JSON document from a data file, checking its signature
+    /// if the store has a signing key.
+    fn verify<'f>(&self, file: &'f [u8]) -> Result<&'f [u8], StoreError> {
+        let (doc, signature) = sign::split(file);
+        if let Some(key) = &self.signing_key {
+            if !signature.is_some_and(|signature| key.verify(doc, signature)) {
+                return Err(StoreError::Tampered);
+            }
+        }
+        Ok(doc)
+    }
+
+    /// Replaces the store's data and metadata with `contents`, read from
+    /// the data file.
+    fn replace_contents(&mut self, contents: Contents<V>) {
+        self.inner = contents.data;
+        self.meta = contents.meta;
+        *self.generation.get_mut() = contents.generation;
+        self.bytes_used = None;
+        self.rebuild_key_index();
+        self.purge_scratch();
+    }
+
+    /// Writes the store data to the associated file.
+    ///
+    /// The data is written to a temporary file alongside it, which then
+    /// replaces the data file, so a failed sync leaves the previous contents
+    /// intact.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<usize>::open(path)?;
+    /// s.try_insert("foo".to_string(), 42)?;
+    /// s.sync()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` for any error creating the file or serializing the
+    /// JSON to it.
+    pub fn sync(&self) -> Result<(), std::io::Error> {
+        let changes = self.pending_changes()?;
+        let generation = self.generation() + 1;
+        self.write_to(&self.path, generation)?;
+        self.generation.store(generation, Ordering::Relaxed);
+        self.dirty.store(false, Ordering::Relaxed);
+        self.record_changes(generation, changes.as_ref())
+    }
+
+    /// Returns the changes that the next sync will make, if they need to be
+    /// logged or committed to git.
+    fn pending_changes(&self) -> Result<Option<Changes>, std::io::Error> {
+        if self.ops_log || self.git_autocommit {
+            self.changes().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Logs and commits the changes made by a sync, as configured.
+    fn record_changes(
+        &self,
+        generation: u64,
+        changes: Option<&Changes>,
+    ) -> Result<(), std::io::Error> {
+        let Some(changes) = changes else {
+            return Ok(());
+        };
+        if self.ops_log {
+            self.append_ops(generation, changes)?;
+        }
+        if self.git_autocommit {
+            if let Some(message) = git::describe(changes) {
+                git::commit(&self.path, &message)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the store data to the file at `path`, leaving the store
+    /// associated with its current file. This is useful for exporting a
+    /// copy of the store.
+    ///
+    /// Since the store's own file isn't updated, this doesn't affect
+    /// [`Self::is_dirty()`].
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` for any error creating the file or serializing the
+    /// JSON to it.
+    pub fn sync_to(&self, path: impl AsRef<Path>) -> Result<(), std::io::Error> {
+        self.write_to(path.as_ref(), self.generation())
+    }
+
+    /// Writes the store data to the file at `path`, and associates the store
+    /// with that file from now on. This is useful for moving a store to a
+    /// new location.
+    ///
+    /// The store's previous file is left as it is.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// # let new_path = tmp_dir.path().join("new.kv");
+    /// let mut s = Store::<usize>::open(path)?;
+    /// s.try_insert("foo".to_string(), 42)?;
+    /// s.save_as(&new_path)?;
+    /// assert_eq!(s.path(), new_path);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` for any error creating the file or serializing the
+    /// JSON to it. In that case, the store remains associated with its
+    /// previous file.
+    pub fn save_as(&mut self, path: impl AsRef<Path>) -> Result<(), std::io::Error> {
+        let generation = self.generation() + 1;
+        self.write_to(path.as_ref(), generation)?;
+        *self.generation.get_mut() = generation;
+        self.path = path.as_ref().into();
+        self.dirty.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn write_to(&self, path: &Path, generation: u64) -> Result<(), std::io::Error> {
+        let doc = format::to_vec(generation, &self.meta, &self.inner)?;
+        format::write_file(&*self.backend, path, doc, self.signing_key.as_ref())
+    }
+
+    /// Returns the value for `key`. If there is none, inserts the result of
+    /// calling `default`, syncs the store, and returns the new value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// let theme = s.get_or_insert_with("theme", || "dark".to_string())?;
+    /// assert_eq!(theme, "dark");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Protected`] if there is no value for `key` and
+    /// it is protected, or any error syncing the store.
+    pub fn get_or_insert_with(
+        &mut self,
+        key: &str,
+        default: impl FnOnce() -> V,
+    ) -> Result<&V, StoreError> {
+        let key = self.normalize_owned(key.to_string());
+        if !self.inner.contains_key(&key) {
+            self.try_insert(key.clone(), default())?;
+            self.sync()?;
+        }
+        Ok(&self.inner[&key])
+    }
+
+    /// Removes every entry for which `predicate` returns `true`, then syncs
+    /// the store (if anything was removed). Protected keys are never
+    /// removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let entries = (0..10).map(|i| (format!("session:{i}"), i));
+    /// let mut s = Store::<usize>::from_entries(path, entries);
+    /// let report = s.prune(|_, v| *v < 5)?;
+    /// assert_eq!(report.removed, 5);
+    /// assert_eq!(s.len(), 5);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns any error syncing the store.
+    pub fn prune(
+        &mut self,
+        mut predicate: impl FnMut(&str, &V) -> bool,
+    ) -> Result<PruneReport, std::io::Error> {
+        let mut report = PruneReport::default();
+        let mut doomed = Vec::new();
+        for (key, value) in &self.inner {
+            if !predicate(key, value) {
+                continue;
+            }
+            if self.meta.protected.contains(key) {
+                report.protected += 1;
+                continue;
+            }
+            // The key and value, plus the `:` and `,` separating them from
+            // each other and from the next entry.
+            report.bytes += serde_json::to_vec(key)?.len() + serde_json::to_vec(value)?.len() + 2;
+            doomed.push(key.clone());
+        }
+        for key in &doomed {
+            self.force_remove(key);
+        }
+        report.removed = doomed.len();
+        if report.removed > 0 {
+            self.sync()?;
+        }
+        Ok(report)
+    }
+}
+
+/// A summary of the entries removed by [`Store::prune()`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PruneReport {
+    /// The number of entries removed.
+    pub removed: usize,
+    /// The approximate number of bytes removed from the data file.
+    pub bytes: usize,
+    /// The number of matching entries that were kept because their keys are
+    /// protected.
+    pub protected: usize,
+}
+
+#[cfg(feature = "rayon")]
+impl<V> Store<V>
+where
+    V: DeserializeOwned + Serialize + Send + Sync,
+{
+    /// Like [`Self::open()`], but deserializes the values using multiple
+    /// threads. This can make opening a large store much faster, especially
+    /// if the values are expensive to deserialize.
+    ///
+    /// Requires the `rayon` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error opening the file (if it exists).
+    pub fn open_parallel(path: impl AsRef<Path>) -> Result<Self, std::io::Error> {
+        Ok(Self::new(path.as_ref().into()).load_with(format::from_slice_parallel)?)
+    }
+
+    /// Like [`Self::sync()`], but serializes the values using multiple
+    /// threads.
+    ///
+    /// Requires the `rayon` feature.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` for any error creating the file or serializing the
+    /// JSON to it.
+    pub fn sync_parallel(&self) -> Result<(), std::io::Error> {
+        let changes = self.pending_changes()?;
+        let generation = self.generation() + 1;
+        let doc = format::to_vec_parallel(generation, &self.meta, &self.inner)?;
+        format::write_file(&*self.backend, &self.path, doc, self.signing_key.as_ref())?;
+        self.generation.store(generation, Ordering::Relaxed);
+        self.dirty.store(false, Ordering::Relaxed);
+        self.record_changes(generation, changes.as_ref())
+    }
+}
+
+impl<V> Store<V> {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            inner: HashMap::new(),
+            meta: Meta::default(),
+            signing_key: None,
+            dirty: AtomicBool::new(false),
+            generation: AtomicU64::new(0),
+            backend: default_backend(),
+            clock: default_clock(),
+            byte_limit: None,
+            warn_at_entries: None,
+            warn_at_bytes: None,
+            bytes_used: None,
+            git_autocommit: false,
+            ops_log: false,
+            key_index: KeyIndex::Off,
+            derived: BTreeMap::new(),
+        }
+    }
+
+    /// Returns a [`StoreBuilder`] for opening a store associated with a data
+    /// file at the given `path`, with non-default options.
+    pub fn builder(path: impl AsRef<Path>) -> StoreBuilder<V> {
+        StoreBuilder::new(path.as_ref().into())
+    }
+
+    /// Returns the path of the data file associated with the store.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Associates the store with the data file at `path` from now on. The
+    /// file isn't written until the store is next synced, and the previous
+    /// file is left as it is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `path` is an existing directory, or its parent
+    /// directory doesn't exist. In that case, the store remains associated
+    /// with its previous file.
+    pub fn set_path(&mut self, path: impl AsRef<Path>) -> Result<(), std::io::Error> {
+        let path = path.as_ref();
+        self.backend.check_path(path)?;
+        self.path = path.into();
+        // The new file doesn't yet reflect the store's contents.
+        self.touch();
+        Ok(())
+    }
+
+    /// Moves the store's data file to `path`, and associates the store with
+    /// it from now on.
+    ///
+    /// If the data file doesn't exist yet (because the store has never been
+    /// synced), the store is just re-pointed, as with [`Self::set_path()`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `path` isn't a valid location for the file (see
+    /// [`Self::set_path()`]), or any error moving the file. In that case,
+    /// the store remains associated with its previous file.
+    pub fn move_to(&mut self, path: impl AsRef<Path>) -> Result<(), std::io::Error> {
+        let old_path = self.path.clone();
+        let was_dirty = self.is_dirty();
+        self.set_path(&path)?;
+        if self.backend.exists(&old_path)? {
+            if let Err(e) = self.backend.rename(&old_path, path.as_ref()) {
+                self.path = old_path;
+                *self.dirty.get_mut() = was_dirty;
+                return Err(e);
+            }
+            *self.dirty.get_mut() = was_dirty;
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the store has changed since it was opened or last
+    /// synced.
+    ///
+    /// Any mutable access to the underlying `HashMap` counts as a change,
+    /// even if no data was actually modified.
+    #[must_use]
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Relaxed)
+    }
+
+    /// Returns the generation of the data file as of when the store was
+    /// opened or last synced. It increases each time the file is synced, so
+    /// stores sharing a file can tell whether it has changed (see
+    /// [`Self::reload_if_changed()`]).
+    ///
+    /// Files written by versions of `rskey` before generations were added
+    /// have generation 0.
+    #[must_use]
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    /// Records that the store has unsynced changes.
+    fn touch(&mut self) {
+        *self.dirty.get_mut() = true;
+        self.bytes_used = None;
+    }
+
+    /// Gets the entry for `key`, for in-place manipulation.
+    ///
+    /// Unlike the `entry` method of `HashMap`, any change made through the
+    /// returned [`Entry`] marks the store dirty, and the entry can also be
+    /// set to sync the store when it's dropped (see [`Entry::sync_on_drop()`]).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<usize>::open(path)?;
+    /// *s.entry("count")?.or_insert(0) += 1;
+    /// *s.entry("count")?.or_insert(0) += 1;
+    /// assert_eq!(s.get("count"), Some(&2));
+    /// assert!(s.is_dirty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Protected`] if `key` is protected.
+    pub fn entry(&mut self, key: &str) -> Result<Entry<'_, V>, StoreError> {
+        let key = self.normalize(key).into_owned();
+        if self.is_protected(&key) {
+            return Err(StoreError::Protected(key));
+        }
+        Ok(Entry::new(self, key))
+    }
+
+    /// Creates a [`Store`] associated with a data file at the given `path`,
+    /// containing the given entries.
+    ///
+    /// Any existing data file is not read, and will be replaced when the
+    /// store is synced. The entries are inserted in a single bulk operation,
+    /// which is faster than inserting them one at a time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let entries = (0..1000).map(|i| (format!("key{i}"), i));
+    /// let s = Store::<usize>::from_entries(path, entries);
+    /// assert_eq!(s.len(), 1000);
+    /// s.sync()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_entries(
+        path: impl AsRef<Path>,
+        entries: impl IntoIterator<Item = (String, V)>,
+    ) -> Self {
+        let mut store = Self::new(path.as_ref().into());
+        store.inner = entries.into_iter().collect();
+        store
+    }
+
+    /// Inserts a key-value pair into the store, returning the previous value
+    /// for `key`, if any, after checking that `key` isn't protected, that
+    /// `value` matches any schema for it, and that it fits in the store's
+    /// byte limit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<usize>::open(path)?;
+    /// assert_eq!(s.try_insert("foo".to_string(), 42)?, None);
+    /// assert_eq!(s.try_insert("foo".to_string(), 43)?, Some(42));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Protected`] if `key` is protected. Use
+    /// [`Self::force_insert()`] to change a protected key.
+    ///
+    /// Returns [`StoreError::Invalid`] if the value doesn't match the schema
+    /// for `key` (see [`Self::set_schema()`]), or [`StoreError::Full`] if
+    /// it would take the store over its byte limit (see
+    /// [`Self::set_byte_limit()`]).
+    pub fn try_insert(&mut self, key: String, value: V) -> Result<Option<V>, StoreError>
+    where
+        V: Serialize,
+    {
+        let key = self.normalize_owned(key);
+        if self.is_protected(&key) {
+            return Err(StoreError::Protected(key));
+        }
+        self.validate(&key, &value)?;
+        let used = self.check_limit(&key, &value)?;
+        let old = self.force_insert(key, value);
+        self.bytes_used = used;
+        Ok(old)
+    }
+
+    /// Inserts a key-value pair into the store, returning the previous value
+    /// for `key`, if any, just like `HashMap::insert`.
+    ///
+    /// This doesn't check protection, schemas, or the byte limit, which
+    /// [`Self::try_insert()`] does, but it does normalize `key`, and update
+    /// its metadata as [`Self::force_insert()`] does.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use rskey::Store;
+    /// let mut s = Store::<usize>::from_entries("data.kv", []);
+    /// assert_eq!(s.insert("foo".to_string(), 42), None);
+    /// assert_eq!(s.insert("foo".to_string(), 43), Some(42));
+    /// ```
+    pub fn insert(&mut self, key: String, value: V) -> Option<V> {
+        self.force_insert(key, value)
+    }
+
+    /// Inserts a key-value pair into the store, even if `key` is protected.
+    ///
+    /// Any expiry time set for `key` is cleared, and if it was a scratch
+    /// entry (see [`Self::scratch()`]), it becomes permanent.
+    pub fn force_insert(&mut self, key: String, value: V) -> Option<V> {
+        let key = self.normalize_owned(key);
+        self.touch();
+        self.bump_version(&key);
+        self.meta.expires.remove(&key);
+        self.meta.scratch.remove(&key);
+        self.meta.encrypted.remove(&key);
+        self.index_key(&key);
+        self.inner.insert(key, value)
+    }
+
+    /// Replaces the value for `key` with `new`, returning the old value, but
+    /// only if `key` is already present. Otherwise, returns `None`, and the
+    /// store is unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Protected`] if `key` is protected,
+    /// [`StoreError::Invalid`] if the new value doesn't match the schema for
+    /// `key`, or [`StoreError::Full`] if it would take the store over its
+    /// byte limit.
+    pub fn replace(&mut self, key: &str, new: V) -> Result<Option<V>, StoreError>
+    where
+        V: Serialize,
+    {
+        let key = self.normalize(key).into_owned();
+        if self.is_protected(&key) {
+            return Err(StoreError::Protected(key));
+        }
+        if !self.inner.contains_key(&key) {
+            return Ok(None);
+        }
+        self.validate(&key, &new)?;
+        let used = self.check_limit(&key, &new)?;
+        self.bump_version(&key);
+        let Some(value) = self.inner.get_mut(&key) else {
+            return Ok(None);
+        };
+        let old = std::mem::replace(value, new);
+        self.touch();
+        self.bytes_used = used;
+        Ok(Some(old))
+    }
+
+    /// Exchanges the values for `key_a` and `key_b`. If only one of them is
+    /// present, its value moves to the other key. Expiry times and scratch
+    /// ownership (see [`Self::scratch()`]) move with the values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
     /// # use tempfile::TempDir;
-    ///
+    /// # use rskey::Store;
     /// # let tmp_dir = TempDir::new()?;
     /// # let path = tmp_dir.path().join("data.kv");
-    /// let s = Store::<usize>::open(path)?;
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("primary".to_string(), "db1".to_string())?;
+    /// s.try_insert("standby".to_string(), "db2".to_string())?;
+    /// s.swap("primary", "standby")?;
+    /// assert_eq!(s["primary"], "db2");
     /// # Ok(())
     /// # }
     /// ```
     ///
     /// # Errors
     ///
-    /// Returns any error opening the file (if it exists).
-    pub fn open(path: impl AsRef<Path>) -> Result<Self, std::io::Error> {
-        let mut store = Self {
-            path: path.as_ref().into(),
-            inner: HashMap::<String, V>::new(),
-        };
-        if fs::exists(&path)? {
-            store.inner = serde_json::from_reader(BufReader::new(File::open(&path)?))?;
+    /// Returns [`StoreError::Protected`] if either key is protected,
+    /// [`StoreError::Encrypted`] if either has an encrypted value (see
+    /// [`Self::insert_encrypted()`]), or [`StoreError::Invalid`] if either
+    /// value doesn't match the schema for its new key. The store is
+    /// unchanged.
+    pub fn swap(&mut self, key_a: &str, key_b: &str) -> Result<(), StoreError>
+    where
+        V: Serialize,
+    {
+        let (key_a, key_b) = (self.normalize(key_a), self.normalize(key_b));
+        let (key_a, key_b) = (key_a.as_ref(), key_b.as_ref());
+        for key in [key_a, key_b] {
+            if self.is_protected(key) {
+                return Err(StoreError::Protected(key.to_string()));
+            }
+            if self.meta.encrypted.contains_key(key) {
+                return Err(StoreError::Encrypted(key.to_string()));
+            }
         }
-        Ok(store)
+        for (key, other) in [(key_a, key_b), (key_b, key_a)] {
+            if let Some(value) = self.inner.get(other) {
+                self.validate(key, value)?;
+            }
+        }
+        for (key, other) in [(key_a, key_b), (key_b, key_a)] {
+            if self.inner.contains_key(other) {
+                self.bump_version(key);
+            } else {
+                self.meta.versions.remove(key);
+            }
+        }
+        let a = self.inner.remove(key_a);
+        let b = self.inner.remove(key_b);
+        if let Some(a) = a {
+            self.inner.insert(key_b.to_string(), a);
+        }
+        if let Some(b) = b {
+            self.inner.insert(key_a.to_string(), b);
+        }
+        swap_entries(&mut self.meta.expires, key_a, key_b);
+        swap_entries(&mut self.meta.scratch, key_a, key_b);
+        for key in [key_a, key_b] {
+            if self.inner.contains_key(key) {
+                self.index_key(key);
+            } else {
+                self.unindex_key(key);
+            }
+        }
+        self.touch();
+        Ok(())
     }
 
-    /// Writes the store data to the associated file.
+    /// Removes `key` from the store, returning its value, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Protected`] if `key` is protected. Use
+    /// [`Self::force_remove()`] to remove a protected key.
+    pub fn remove(&mut self, key: &str) -> Result<Option<V>, StoreError> {
+        let key = self.normalize(key);
+        if self.is_protected(&key) {
+            return Err(StoreError::Protected(key.into_owned()));
+        }
+        Ok(self.force_remove(&key))
+    }
+
+    /// Removes `key` from the store, even if it is protected.
+    ///
+    /// The key stays protected until [`Self::unprotect()`] is called.
+    pub fn force_remove(&mut self, key: &str) -> Option<V> {
+        let key = self.normalize(key);
+        let value = self.inner.remove(key.as_ref());
+        let encrypted = self.meta.encrypted.remove(key.as_ref());
+        if value.is_some() || encrypted.is_some() {
+            self.unindex_key(&key);
+            self.meta.versions.remove(key.as_ref());
+            self.meta.expires.remove(key.as_ref());
+            self.meta.scratch.remove(key.as_ref());
+            self.touch();
+        }
+        value
+    }
+
+    /// Returns a mutable reference to the value for `key`, if any, and
+    /// marks the store dirty.
+    ///
+    /// This shadows `HashMap::get_mut`, so that protected keys can't be
+    /// changed. The key's version is bumped, but changes made through the
+    /// reference aren't checked against the key's schema or the store's
+    /// byte limit.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Protected`] if `key` is protected.
+    pub fn get_mut(&mut self, key: &str) -> Result<Option<&mut V>, StoreError> {
+        let key = self.normalize(key).into_owned();
+        if self.is_protected(&key) {
+            return Err(StoreError::Protected(key));
+        }
+        if !self.inner.contains_key(&key) {
+            return Ok(None);
+        }
+        self.touch();
+        self.bump_version(&key);
+        Ok(self.inner.get_mut(&key))
+    }
+
+    /// Returns `true` if the store has a value for `key`, plaintext or
+    /// encrypted (see [`Self::insert_encrypted()`]).
+    ///
+    /// This shadows `HashMap::contains_key`, so that encrypted values
+    /// count. Like it, this doesn't normalize `key`.
+    #[must_use]
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.inner.contains_key(key) || self.meta.encrypted.contains_key(key)
+    }
+
+    /// Returns the number of entries, including encrypted values.
+    ///
+    /// This shadows `HashMap::len`, so that encrypted values count.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.inner.len() + self.meta.encrypted.len()
+    }
+
+    /// Returns `true` if the store has no entries, including encrypted
+    /// values.
+    ///
+    /// This shadows `HashMap::is_empty`, so that encrypted values count.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty() && self.meta.encrypted.is_empty()
+    }
+
+    /// Returns the keys of all the entries, including encrypted values, in
+    /// arbitrary order.
+    ///
+    /// This shadows `HashMap::keys`, so that encrypted values count. Their
+    /// values are only available through [`Self::get_encrypted()`].
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.inner.keys().chain(self.meta.encrypted.keys())
+    }
+
+    /// Removes every entry for which `f` returns `false`, along with its
+    /// metadata, except for protected keys, which are always kept.
+    ///
+    /// This shadows `HashMap::retain`, so that protected keys can't be
+    /// removed.
+    pub fn retain(&mut self, mut f: impl FnMut(&String, &mut V) -> bool) {
+        let mut removed = Vec::new();
+        for (key, value) in &mut self.inner {
+            if !self.meta.protected.contains(key) && !f(key, value) {
+                removed.push(key.clone());
+            }
+        }
+        for key in removed {
+            self.force_remove(&key);
+        }
+    }
+
+    /// Removes every entry, along with its metadata, except for protected
+    /// keys.
+    ///
+    /// This shadows `HashMap::clear`, so that protected keys can't be
+    /// removed. Encrypted values (see [`Self::insert_encrypted()`]) are
+    /// removed too, as by [`Self::drain()`].
+    pub fn clear(&mut self) {
+        self.drain();
+    }
+
+    /// Removes every entry, along with its metadata, except for protected
+    /// keys, and returns the removed entries in arbitrary order.
+    ///
+    /// This shadows `HashMap::drain`, so that protected keys can't be
+    /// removed. Encrypted values (see [`Self::insert_encrypted()`]) are
+    /// removed too, as by [`Self::clear()`], but aren't returned, since
+    /// they can't be decrypted without the key.
+    pub fn drain(&mut self) -> std::vec::IntoIter<(String, V)> {
+        let keys: Vec<_> = self
+            .inner
+            .keys()
+            .chain(self.meta.encrypted.keys())
+            .filter(|key| !self.meta.protected.contains(*key))
+            .cloned()
+            .collect();
+        let mut drained = Vec::with_capacity(keys.len());
+        for key in keys {
+            if let Some(value) = self.force_remove(&key) {
+                drained.push((key, value));
+            }
+        }
+        drained.into_iter()
+    }
+
+    /// Marks `key` as protected, so that [`Self::try_insert()`] and
+    /// [`Self::remove()`] will refuse to change it. The protection is
+    /// persisted with the store.
+    ///
+    /// Returns `false` if the key was already protected.
     ///
     /// # Examples
     ///
@@ -122,21 +1889,145 @@ where
     /// # let tmp_dir = TempDir::new()?;
     /// # let path = tmp_dir.path().join("data.kv");
     /// let mut s = Store::<usize>::open(path)?;
-    /// s.insert("foo".to_string(), 42);
-    /// s.sync()?;
+    /// s.try_insert("answer".to_string(), 42)?;
+    /// s.protect("answer");
+    /// assert!(s.try_insert("answer".to_string(), 43).is_err());
+    /// s.force_insert("answer".to_string(), 43);
     /// # Ok(())
     /// # }
     /// ```
+    pub fn protect(&mut self, key: &str) -> bool {
+        let changed = self.meta.protected.insert(self.normalize(key).into_owned());
+        if changed {
+            self.touch();
+        }
+        changed
+    }
+
+    /// Removes the protection from `key`.
     ///
-    /// # Errors
+    /// Returns `false` if the key was not protected.
+    pub fn unprotect(&mut self, key: &str) -> bool {
+        let changed = self.meta.protected.remove(self.normalize(key).as_ref());
+        if changed {
+            self.touch();
+        }
+        changed
+    }
+
+    /// Returns `true` if `key` is protected.
+    #[must_use]
+    pub fn is_protected(&self, key: &str) -> bool {
+        self.meta.protected.contains(self.normalize(key).as_ref())
+    }
+
+    /// Marks all keys matching `pattern` as secret, so that their values are
+    /// hidden by [`Self::get_redacted()`]. The pattern may contain the
+    /// wildcards `*` (any sequence of characters) and `?` (any single
+    /// character), and is persisted with the store.
     ///
-    /// Will return `Err` for any error creating the file or serializing the
-    /// JSON to it.
-    pub fn sync(&self) -> Result<(), std::io::Error> {
-        let file = File::create(&self.path)?;
-        let writer = BufWriter::new(file);
-        serde_json::to_writer(writer, &self.inner)?;
-        Ok(())
+    /// Returns `false` if the pattern was already marked secret.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// use rskey::{Redacted, Store};
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("github_token".to_string(), "ghp_abc123".to_string())?;
+    /// s.mark_secret("*_token");
+    /// assert_eq!(s.get_redacted("github_token"), Some(Redacted::Hidden));
+    /// assert_eq!(s.get_redacted("github_token").unwrap().to_string(), "*****");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn mark_secret(&mut self, pattern: &str) -> bool {
+        let changed = self.meta.secret.insert(pattern.to_string());
+        if changed {
+            self.touch();
+        }
+        changed
+    }
+
+    /// Removes `pattern` from the set of secret patterns.
+    ///
+    /// Returns `false` if the pattern was not marked secret.
+    pub fn unmark_secret(&mut self, pattern: &str) -> bool {
+        let changed = self.meta.secret.remove(pattern);
+        if changed {
+            self.touch();
+        }
+        changed
+    }
+
+    /// Returns `true` if `key` matches any pattern marked secret.
+    #[must_use]
+    pub fn is_secret(&self, key: &str) -> bool {
+        self.meta
+            .secret
+            .iter()
+            .any(|pattern| glob::matches(pattern, key))
+    }
+
+    /// Returns the value for `key`, if any, hiding it if the key is secret.
+    #[must_use]
+    pub fn get_redacted(&self, key: &str) -> Option<Redacted<'_, V>> {
+        let key = self.normalize(key);
+        let value = self.inner.get(key.as_ref())?;
+        Some(if self.is_secret(&key) {
+            Redacted::Hidden
+        } else {
+            Redacted::Visible(value)
+        })
+    }
+
+    /// Returns an iterator over the keys matching `pattern`, in arbitrary
+    /// order. The pattern may contain the wildcards `*` and `?`, as with
+    /// [`Self::mark_secret()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("db_host".to_string(), "localhost".to_string())?;
+    /// s.try_insert("db_port".to_string(), "5432".to_string())?;
+    /// s.try_insert("log_level".to_string(), "info".to_string())?;
+    /// assert_eq!(s.keys_matching("db_*").count(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn keys_matching<'a>(&'a self, pattern: &'a str) -> impl Iterator<Item = &'a str> {
+        self.inner
+            .keys()
+            .map(String::as_str)
+            .filter(move |key| glob::matches(pattern, key))
+    }
+}
+
+/// A value that may be hidden because it's secret, as returned by
+/// [`Store::get_redacted()`].
+#[derive(Debug, PartialEq)]
+pub enum Redacted<'a, V> {
+    /// The value is secret, so it's not shown.
+    Hidden,
+    /// The value isn't secret.
+    Visible(&'a V),
+}
+
+impl<V: Display> Display for Redacted<'_, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Redacted::Hidden => f.write_str("*****"),
+            Redacted::Visible(value) => value.fmt(f),
+        }
     }
 }
 
@@ -148,8 +2039,12 @@ impl<V> Deref for Store<V> {
     }
 }
 
+/// Changes made through this bypass protection, schemas, the byte limit,
+/// and per-key metadata; see [`Store`].
 impl<V> DerefMut for Store<V> {
     fn deref_mut(&mut self) -> &mut Self::Target {
+        self.touch();
+        self.invalidate_key_index();
         &mut self.inner
     }
 }
@@ -164,10 +2059,38 @@ impl<V> IntoIterator for Store<V> {
     }
 }
 
+/// Generates a store with arbitrary entries, some of which are protected or
+/// secret, for use in fuzzing and property tests (see
+/// `Store::verify_roundtrip()`).
+///
+/// The store's path is `store.kv`, in the current directory.
+///
+/// Requires the `arbitrary` feature.
+#[cfg(feature = "arbitrary")]
+impl<'a, V> arbitrary::Arbitrary<'a> for Store<V>
+where
+    V: arbitrary::Arbitrary<'a>,
+{
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let entries: HashMap<String, V> = u.arbitrary()?;
+        let mut store = Self::from_entries("store.kv", entries);
+        for key in store.inner.keys() {
+            if u.arbitrary()? {
+                store.meta.protected.insert(key.clone());
+            }
+            if u.arbitrary()? {
+                store.meta.secret.insert(key.clone());
+            }
+        }
+        Ok(store)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use pretty_assertions::assert_eq;
+    use std::fs;
     use tempfile::TempDir;
 
     #[test]
@@ -180,7 +2103,10 @@ mod tests {
     fn sync_persists_changes_to_store() {
         let mut tmp = TmpStore::new();
         assert!(
-            tmp.store.insert("k1".into(), "v1".into()).is_none(),
+            tmp.store
+                .try_insert("k1".into(), "v1".into())
+                .unwrap()
+                .is_none(),
             "key should not already be present in new empty store"
         );
         tmp.store.sync().unwrap();
@@ -207,6 +2133,358 @@ mod tests {
         assert!(s.is_err(), "want error for invalid path, got {s:?}");
     }
 
+    #[test]
+    fn with_capacity_keeps_existing_data() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        let entries = (0..10).map(|i| (format!("k{i}"), format!("v{i}")));
+        Store::from_entries(&path, entries).sync().unwrap();
+        let s = Store::<String>::with_capacity(&path, 1000).unwrap();
+        assert_eq!(10, s.len(), "wrong number of entries");
+        assert_eq!("v9", s.get("k9").unwrap(), "expected data not returned");
+        assert!(s.capacity() >= 1010, "capacity not reserved");
+    }
+
+    #[test]
+    fn get_or_insert_with_persists_default_only_when_missing() {
+        let mut tmp = TmpStore::new();
+        tmp.store.try_insert("k1".into(), "v1".into()).unwrap();
+        let value = tmp
+            .store
+            .get_or_insert_with("k1", || panic!("default computed for existing key"))
+            .unwrap();
+        assert_eq!("v1", value, "wrong value returned");
+        let value = tmp.store.get_or_insert_with("k2", || "v2".into()).unwrap();
+        assert_eq!("v2", value, "wrong value returned");
+        let s2 = Store::<String>::open(&tmp.store.path).unwrap();
+        assert_eq!("v2", s2.get("k2").unwrap(), "default not persisted");
+    }
+
+    #[test]
+    fn prune_removes_and_reports_matching_unprotected_entries() {
+        let mut tmp = TmpStore::new();
+        for key in ["tmp1", "tmp2", "tmp3", "keep"] {
+            tmp.store.try_insert(key.into(), "xx".into()).unwrap();
+        }
+        tmp.store.protect("tmp3");
+        let report = tmp.store.prune(|k, _| k.starts_with("tmp")).unwrap();
+        assert_eq!(
+            PruneReport {
+                removed: 2,
+                bytes: 2 * r#""tmp1":"xx","#.len(),
+                protected: 1,
+            },
+            report
+        );
+        let s2 = Store::<String>::open(&tmp.store.path).unwrap();
+        let mut keys: Vec<_> = s2.keys().collect();
+        keys.sort();
+        assert_eq!(vec!["keep", "tmp3"], keys, "wrong keys persisted");
+    }
+
+    #[test]
+    fn sync_to_copies_data_without_repointing_store() {
+        let mut tmp = TmpStore::new();
+        tmp.store.try_insert("k1".into(), "v1".into()).unwrap();
+        let copy = tmp.store.path.with_file_name("copy.kv");
+        tmp.store.sync_to(&copy).unwrap();
+        assert_ne!(copy, tmp.store.path, "store should not be re-pointed");
+        assert!(tmp.store.is_dirty(), "store's own file was not synced");
+        let s2 = Store::<String>::open(&copy).unwrap();
+        assert_eq!("v1", s2.get("k1").unwrap(), "expected data not returned");
+    }
+
+    #[test]
+    fn save_as_repoints_store_to_new_file() {
+        let mut tmp = TmpStore::new();
+        tmp.store.try_insert("k1".into(), "v1".into()).unwrap();
+        let new_path = tmp.store.path.with_file_name("new.kv");
+        tmp.store.save_as(&new_path).unwrap();
+        assert_eq!(new_path, tmp.store.path, "store not re-pointed");
+        assert!(!tmp.store.is_dirty(), "store should be clean after save_as");
+        let s2 = Store::<String>::open(&new_path).unwrap();
+        assert_eq!("v1", s2.get("k1").unwrap(), "expected data not returned");
+    }
+
+    #[test]
+    fn set_path_rejects_invalid_locations() {
+        let mut tmp = TmpStore::new();
+        let dir = tmp.store.path.parent().unwrap().to_path_buf();
+        let res = tmp.store.set_path(&dir);
+        assert!(res.is_err(), "want error for directory, got {res:?}");
+        let res = tmp.store.set_path(dir.join("bogus/store.kv"));
+        assert!(
+            res.is_err(),
+            "want error for missing directory, got {res:?}"
+        );
+        assert_eq!(dir.join("store.kv"), tmp.store.path(), "path changed");
+    }
+
+    #[test]
+    fn move_to_moves_data_file() {
+        let mut tmp = TmpStore::new();
+        tmp.store.try_insert("k1".into(), "v1".into()).unwrap();
+        tmp.store.sync().unwrap();
+        let old_path = tmp.store.path().to_path_buf();
+        let new_path = old_path.with_file_name("moved.kv");
+        tmp.store.move_to(&new_path).unwrap();
+        assert_eq!(new_path, tmp.store.path(), "store not re-pointed");
+        assert!(!old_path.exists(), "old file still present");
+        let s2 = Store::<String>::open(&new_path).unwrap();
+        assert_eq!("v1", s2.get("k1").unwrap(), "expected data not returned");
+    }
+
+    #[test]
+    fn replace_and_swap_change_only_existing_values() {
+        let mut s = Store::<u8>::from_entries("unused.kv", [("a".to_string(), 1)]);
+        assert_eq!(None, s.replace("b", 2).unwrap(), "missing key replaced");
+        assert!(!s.contains_key("b"), "missing key inserted");
+        assert_eq!(
+            Some(1),
+            s.replace("a", 3).unwrap(),
+            "old value not returned"
+        );
+        s.swap("a", "b").unwrap();
+        assert_eq!(None, s.get("a"), "value not moved");
+        assert_eq!(Some(&3), s.get("b"), "value not moved");
+        s.try_insert("a".to_string(), 4).unwrap();
+        s.swap("a", "b").unwrap();
+        assert_eq!(
+            (Some(&3), Some(&4)),
+            (s.get("a"), s.get("b")),
+            "not swapped"
+        );
+        s.protect("b");
+        assert!(s.swap("a", "b").is_err(), "protected key swapped");
+        assert!(s.replace("b", 5).is_err(), "protected key replaced");
+    }
+
+    #[test]
+    fn swap_moves_metadata_and_checks_schemas_first() {
+        let entries = [("a", 1), ("b", 2)].map(|(k, v)| (k.to_string(), v));
+        let mut s = Store::<u8>::from_entries("unused.kv", entries);
+        s.meta.expires.insert("a".to_string(), 4_000_000_000);
+        s.swap("a", "b").unwrap();
+        assert_eq!(Some(&4_000_000_000), s.meta.expires.get("b"));
+        assert!(!s.meta.expires.contains_key("a"), "expiry not moved");
+        s.set_schema("b", serde_json::json!({"maximum": 1}))
+            .unwrap();
+        let err = s.swap("a", "b").unwrap_err();
+        assert!(
+            matches!(err, StoreError::Invalid { .. }),
+            "wrong error {err:?}"
+        );
+        assert_eq!(
+            (Some(&2), Some(&1)),
+            (s.get("a"), s.get("b")),
+            "store changed"
+        );
+        #[cfg(feature = "encryption")]
+        {
+            let secret = EncryptionKey::from_passphrase("secret");
+            s.insert_encrypted("c".to_string(), &3, &secret).unwrap();
+            assert!(s.swap("b", "c").is_err(), "encrypted value moved");
+        }
+    }
+
+    #[test]
+    fn changes_mark_store_dirty_until_synced() {
+        let mut tmp = TmpStore::new();
+        assert!(!tmp.store.is_dirty(), "new store should not be dirty");
+        *tmp.store.entry("k1").unwrap().or_default() += "v1";
+        assert!(tmp.store.is_dirty(), "entry change should mark store dirty");
+        tmp.store.sync().unwrap();
+        assert!(!tmp.store.is_dirty(), "sync should clean store");
+        tmp.store.get_mut("k1").unwrap().unwrap().push('!');
+        assert!(
+            tmp.store.is_dirty(),
+            "mutable access should mark store dirty"
+        );
+    }
+
+    #[test]
+    fn entry_with_sync_on_drop_persists_change() {
+        let mut tmp = TmpStore::new();
+        *tmp.store
+            .entry("k1")
+            .unwrap()
+            .sync_on_drop()
+            .or_insert_with(|| "v".into()) += "1";
+        assert!(!tmp.store.is_dirty(), "store should have been synced");
+        let s2 = Store::<String>::open(&tmp.store.path).unwrap();
+        assert_eq!("v1", s2.get("k1").unwrap(), "expected data not returned");
+    }
+
+    #[test]
+    fn insert_and_remove_fail_on_protected_key_unless_forced() {
+        let mut tmp = TmpStore::new();
+        tmp.store.try_insert("k1".into(), "v1".into()).unwrap();
+        assert!(
+            tmp.store.protect("k1"),
+            "key should not already be protected"
+        );
+        let res = tmp.store.try_insert("k1".into(), "v2".into());
+        assert!(
+            matches!(res, Err(StoreError::Protected(ref k)) if k == "k1"),
+            "want Protected error, got {res:?}"
+        );
+        let res = tmp.store.remove("k1");
+        assert!(
+            matches!(res, Err(StoreError::Protected(_))),
+            "want Protected error, got {res:?}"
+        );
+        assert_eq!(
+            Some("v1".into()),
+            tmp.store.force_insert("k1".into(), "v2".into())
+        );
+        assert_eq!(Some("v2".into()), tmp.store.force_remove("k1"));
+    }
+
+    #[test]
+    fn shadowed_hashmap_methods_keep_protected_keys() {
+        let entries = [("a", 1), ("b", 2), ("c", 3)].map(|(k, v)| (k.to_string(), v));
+        let mut s = Store::<u8>::from_entries("unused.kv", entries);
+        s.protect("a");
+        s.meta.expires.insert("b".to_string(), 4_000_000_000);
+        assert!(s.get_mut("a").is_err(), "protected key changed");
+        *s.get_mut("b").unwrap().unwrap() += 1;
+        s.retain(|_, v| *v > 3);
+        assert_eq!(vec!["a"], s.keys().collect::<Vec<_>>(), "wrong keys kept");
+        assert!(s.meta.expires.is_empty(), "expiry kept for removed key");
+        s.meta.encrypted.insert("e".to_string(), "00".to_string());
+        s.clear();
+        assert!(s.meta.encrypted.is_empty(), "encrypted value kept by clear");
+        s.meta.encrypted.insert("e".to_string(), "00".to_string());
+        assert_eq!(0, s.drain().count(), "protected key drained");
+        assert!(s.meta.encrypted.is_empty(), "encrypted value kept by drain");
+        assert_eq!(Some(&1), s.get("a"), "protected key removed");
+        // Only the checked insert refuses to change a protected key.
+        assert!(
+            s.try_insert("a".to_string(), 5).is_err(),
+            "protection ignored"
+        );
+        assert_eq!(Some(1), s.insert("a".to_string(), 5), "wrong old value");
+    }
+
+    #[test]
+    fn sync_persists_protected_keys() {
+        let mut tmp = TmpStore::new();
+        tmp.store.protect("k1");
+        tmp.store.sync().unwrap();
+        let s2 = Store::<String>::open(&tmp.store.path).unwrap();
+        assert!(s2.is_protected("k1"), "protection not persisted");
+    }
+
+    #[test]
+    fn get_redacted_hides_only_secret_values() {
+        let mut tmp = TmpStore::new();
+        tmp.store
+            .try_insert("api_token".into(), "abc".into())
+            .unwrap();
+        tmp.store
+            .try_insert("api_url".into(), "xyz".into())
+            .unwrap();
+        tmp.store.mark_secret("*_token");
+        tmp.store.sync().unwrap();
+        let s = Store::<String>::open(&tmp.store.path).unwrap();
+        assert_eq!(Some(Redacted::Hidden), s.get_redacted("api_token"));
+        assert_eq!(
+            Some(Redacted::Visible(&"xyz".to_string())),
+            s.get_redacted("api_url")
+        );
+        assert_eq!(None, s.get_redacted("bogus"));
+    }
+
+    #[test]
+    fn open_signed_detects_modified_data_file() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        let key = SigningKey::from_passphrase("secret");
+        let mut s = Store::<String>::open_signed(&path, key.clone()).unwrap();
+        s.try_insert("k1".into(), "v1".into()).unwrap();
+        s.sync().unwrap();
+        let s = Store::<String>::open_signed(&path, key.clone());
+        assert!(s.is_ok(), "unexpected error: {:?}", s.err());
+        let file = fs::read_to_string(&path).unwrap();
+        fs::write(&path, file.replace("v1", "v2")).unwrap();
+        let s = Store::<String>::open_signed(&path, key);
+        assert!(
+            matches!(s, Err(StoreError::Tampered)),
+            "want Tampered error, got {s:?}"
+        );
+    }
+
+    #[test]
+    fn open_signed_rejects_unsigned_data_file() {
+        let tmp = TmpStore::new();
+        tmp.store.sync().unwrap();
+        let key = SigningKey::from_passphrase("secret");
+        let s = Store::<String>::open_signed(&tmp.store.path, key);
+        assert!(
+            matches!(s, Err(StoreError::Tampered)),
+            "want Tampered error, got {s:?}"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn sync_parallel_and_open_parallel_round_trip_data() {
+        let mut tmp = TmpStore::new();
+        for i in 0..100 {
+            tmp.store
+                .try_insert(format!("k{i}"), format!("v{i}"))
+                .unwrap();
+        }
+        tmp.store.protect("k1");
+        tmp.store.sync_parallel().unwrap();
+        let s = Store::<String>::open(&tmp.store.path).unwrap();
+        assert_eq!(tmp.store.inner, s.inner, "data not round-tripped");
+        assert!(s.is_protected("k1"), "metadata not round-tripped");
+        let s = Store::<String>::open_parallel(&tmp.store.path).unwrap();
+        assert_eq!(tmp.store.inner, s.inner, "data not round-tripped");
+    }
+
+    #[test]
+    fn open_reads_legacy_data_file() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        fs::write(&path, r#"{"format":"plain","k1":"v1"}"#).unwrap();
+        let s = Store::<String>::open(&path).unwrap();
+        assert_eq!(
+            "plain",
+            s.get("format").unwrap(),
+            "expected data not returned"
+        );
+        assert_eq!("v1", s.get("k1").unwrap(), "expected data not returned");
+    }
+
+    #[test]
+    fn sync_increments_persisted_generation() {
+        let tmp = TmpStore::new();
+        assert_eq!(0, tmp.store.generation(), "wrong initial generation");
+        tmp.store.sync().unwrap();
+        tmp.store.sync().unwrap();
+        assert_eq!(2, tmp.store.generation(), "generation not incremented");
+        let copy = tmp.store.path.with_extension("copy");
+        tmp.store.sync_to(&copy).unwrap();
+        assert_eq!(2, tmp.store.generation(), "sync_to changed generation");
+        let mut s = Store::<String>::open(&tmp.store.path).unwrap();
+        assert_eq!(2, s.generation(), "generation not persisted");
+        assert!(!s.reload_if_changed().unwrap(), "unchanged store reloaded");
+    }
+
+    #[test]
+    fn open_rejects_newer_format() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        fs::write(&path, r#"{"format":"rskey/2","meta":{},"data":{}}"#).unwrap();
+        let err = Store::<String>::open(&path).unwrap_err();
+        assert!(
+            err.to_string().contains("unsupported data file format"),
+            "wrong error: {err}"
+        );
+    }
+
     struct TmpStore {
         _tmp_dir: TempDir,
         store: Store<String>,
@@ -216,13 +2494,10 @@ mod tests {
         fn new() -> Self {
             let tmp_dir = TempDir::new().unwrap();
             let path = tmp_dir.path().join("store.kv");
-            File::create(&path).unwrap();
+            fs::File::create(&path).unwrap();
             TmpStore {
                 _tmp_dir: tmp_dir,
-                store: Store {
-                    path,
-                    inner: HashMap::new(),
-                },
+                store: Store::new(path),
             }
         }
     }
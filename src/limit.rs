@@ -0,0 +1,299 @@
+//! Limiting how much data a store holds.
+
+use serde::Serialize;
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+
+use crate::{Store, StoreError};
+
+impl<V> Store<V> {
+    /// Returns the store's byte limit, if any.
+    #[must_use]
+    pub fn byte_limit(&self) -> Option<usize> {
+        self.byte_limit
+    }
+
+    /// Limits the store's data to `limit` bytes, as measured by
+    /// [`Self::bytes_used()`], or removes the limit if `limit` is `None`.
+    /// The limit isn't persisted with the store.
+    ///
+    /// This is useful on devices with little storage, or to stop a shared
+    /// store growing without bound. [`Self::try_insert()`] and
+    /// [`Self::replace()`] refuse to take the store over its limit. Other
+    /// changes, such as [`Self::force_insert()`], aren't checked, but still
+    /// count towards the limit. A store that's already over its limit can
+    /// still shrink.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// use rskey::{Store, StoreError};
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.set_byte_limit(Some(16));
+    /// s.try_insert("mode".to_string(), "auto".to_string())?;
+    /// let result = s.try_insert("log".to_string(), "x".repeat(100));
+    /// assert!(matches!(result, Err(StoreError::Full { .. })));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_byte_limit(&mut self, limit: Option<usize>) {
+        self.byte_limit = limit;
+    }
+
+    /// Returns the size of the store's data, in bytes: the total length of
+    /// its keys and of its values serialized as JSON, or, for encrypted
+    /// values (see [`Self::insert_encrypted()`]), as stored. This is close
+    /// to the size of the data file, not counting other metadata.
+    #[must_use]
+    pub fn bytes_used(&self) -> usize
+    where
+        V: Serialize,
+    {
+        self.bytes_used.unwrap_or_else(|| {
+            let plaintext: usize = self
+                .inner
+                .iter()
+                .map(|(key, value)| entry_size(key, value))
+                .sum();
+            let encrypted: usize = self
+                .meta
+                .encrypted
+                .iter()
+                .map(|(key, sealed)| sealed_size(key, sealed))
+                .sum();
+            plaintext + encrypted
+        })
+    }
+
+    /// Sets the number of entries above which [`Self::size_warnings()`]
+    /// reports that the store is getting large, or removes the threshold if
+    /// `threshold` is `None`. The threshold isn't persisted with the store.
+    pub fn set_warn_at_entries(&mut self, threshold: Option<usize>) {
+        self.warn_at_entries = threshold;
+    }
+
+    /// Sets the size, in bytes, as measured by [`Self::bytes_used()`],
+    /// above which [`Self::size_warnings()`] reports that the store is
+    /// getting large, or removes the threshold if `threshold` is `None`. The
+    /// threshold isn't persisted with the store.
+    pub fn set_warn_at_bytes(&mut self, threshold: Option<usize>) {
+        self.warn_at_bytes = threshold;
+    }
+
+    /// Returns a warning for each threshold set with
+    /// [`Self::set_warn_at_entries()`] or [`Self::set_warn_at_bytes()`] that
+    /// the store has exceeded.
+    ///
+    /// Unlike the byte limit, thresholds don't stop the store growing. They
+    /// let a program notice that something is filling the store, for
+    /// example by checking for warnings after each sync, well before the
+    /// store reaches a hard limit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// use rskey::{SizeWarning, Store};
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::builder(path).warn_at_entries(1).open()?;
+    /// s.try_insert("a".to_string(), "1".to_string())?;
+    /// assert!(s.size_warnings().is_empty());
+    /// s.try_insert("b".to_string(), "2".to_string())?;
+    /// assert_eq!(
+    ///     s.size_warnings(),
+    ///     [SizeWarning::Entries { count: 2, threshold: 1 }]
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn size_warnings(&self) -> Vec<SizeWarning>
+    where
+        V: Serialize,
+    {
+        let mut warnings = Vec::new();
+        let count = self.inner.len();
+        if let Some(threshold) = self.warn_at_entries.filter(|&t| count > t) {
+            warnings.push(SizeWarning::Entries { count, threshold });
+        }
+        if let Some(threshold) = self.warn_at_bytes {
+            let used = self.bytes_used();
+            if used > threshold {
+                warnings.push(SizeWarning::Bytes { used, threshold });
+            }
+        }
+        warnings
+    }
+
+    /// Checks that setting `key` to `value` wouldn't take the store over its
+    /// byte limit, returning the number of bytes the store would then use.
+    pub(crate) fn check_limit(&mut self, key: &str, value: &V) -> Result<Option<usize>, StoreError>
+    where
+        V: Serialize,
+    {
+        self.check_size(key, entry_size(key, value))
+    }
+
+    /// Checks that setting `key` to an entry of `size` bytes, such as an
+    /// encrypted value, wouldn't take the store over its byte limit,
+    /// returning the number of bytes the store would then use.
+    pub(crate) fn check_size(&mut self, key: &str, size: usize) -> Result<Option<usize>, StoreError>
+    where
+        V: Serialize,
+    {
+        let Some(limit) = self.byte_limit else {
+            return Ok(None);
+        };
+        let used = self.bytes_used();
+        self.bytes_used = Some(used);
+        let old = match (self.inner.get(key), self.meta.encrypted.get(key)) {
+            (Some(old), _) => entry_size(key, old),
+            (None, Some(sealed)) => sealed_size(key, sealed),
+            (None, None) => 0,
+        };
+        let new = used - old + size;
+        if new > limit && new > used {
+            return Err(StoreError::Full {
+                key: key.to_string(),
+                limit,
+            });
+        }
+        Ok(Some(new))
+    }
+}
+
+/// A warning that a store has grown past a threshold, as returned by
+/// [`Store::size_warnings()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SizeWarning {
+    /// The store has more entries than the threshold.
+    Entries {
+        /// The number of entries.
+        count: usize,
+        /// The threshold that was exceeded.
+        threshold: usize,
+    },
+    /// The store's data is larger than the threshold.
+    Bytes {
+        /// The size of the data, in bytes.
+        used: usize,
+        /// The threshold that was exceeded.
+        threshold: usize,
+    },
+}
+
+impl Display for SizeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SizeWarning::Entries { count, threshold } => {
+                write!(f, "store has {count} entries, more than {threshold}")
+            }
+            SizeWarning::Bytes { used, threshold } => {
+                write!(f, "store holds {used} bytes, more than {threshold}")
+            }
+        }
+    }
+}
+
+/// Returns the number of bytes that an entry uses.
+pub(crate) fn entry_size<V: Serialize>(key: &str, value: &V) -> usize {
+    let mut counter = Counter(0);
+    // Writing to a counter can't fail, and neither can serializing a value
+    // that's already in the store.
+    let _ = serde_json::to_writer(&mut counter, value);
+    key.len() + counter.0
+}
+
+/// Returns the number of bytes that an encrypted value, sealed as stored in
+/// the metadata, uses.
+pub(crate) fn sealed_size(key: &str, sealed: &str) -> usize {
+    key.len() + sealed.len()
+}
+
+/// A writer that only counts the bytes written to it.
+struct Counter(usize);
+
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn byte_limit_rejects_only_growth_past_limit() {
+        let mut s = Store::<String>::new(PathBuf::from("unused.kv"));
+        s.set_byte_limit(Some(20));
+        s.try_insert("k1".to_string(), "abcdef".to_string())
+            .unwrap();
+        assert_eq!(10, s.bytes_used(), "wrong size");
+        assert!(
+            matches!(
+                s.try_insert("k2".to_string(), "abcdefghijk".to_string()),
+                Err(StoreError::Full { key, limit: 20 }) if key == "k2"
+            ),
+            "insert past limit allowed"
+        );
+        assert!(!s.contains_key("k2"), "rejected entry inserted");
+        s.try_insert("k2".to_string(), "abcdef".to_string())
+            .expect("insert up to limit rejected");
+        assert!(
+            s.replace("k1", "abcdefg".to_string()).is_err(),
+            "replace past limit allowed"
+        );
+        s.force_insert("k3".to_string(), "x".to_string());
+        assert_eq!(25, s.bytes_used(), "unchecked change not counted");
+        s.replace("k1", "a".to_string())
+            .expect("shrinking over-limit store rejected");
+        assert_eq!(20, s.bytes_used(), "wrong size");
+    }
+
+    #[test]
+    fn size_warnings_report_only_exceeded_thresholds() {
+        let mut s = Store::<String>::new(PathBuf::from("unused.kv"));
+        s.set_warn_at_entries(Some(2));
+        s.set_warn_at_bytes(Some(10));
+        s.try_insert("k1".to_string(), "abcdef".to_string())
+            .unwrap();
+        assert_eq!(
+            Vec::<SizeWarning>::new(),
+            s.size_warnings(),
+            "early warning"
+        );
+        s.try_insert("k2".to_string(), "a".to_string()).unwrap();
+        assert_eq!(
+            vec![SizeWarning::Bytes {
+                used: 15,
+                threshold: 10
+            }],
+            s.size_warnings(),
+            "wrong warnings"
+        );
+        s.set_warn_at_bytes(None);
+        s.try_insert("k3".to_string(), "a".to_string()).unwrap();
+        assert_eq!(
+            vec![SizeWarning::Entries {
+                count: 3,
+                threshold: 2
+            }],
+            s.size_warnings(),
+            "wrong warnings"
+        );
+    }
+}
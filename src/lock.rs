@@ -0,0 +1,327 @@
+//! Coordinating writers with a lock file.
+
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::scratch::Owner;
+use crate::Store;
+
+/// How long a lock held by a process on another host is honoured, by
+/// default, before it's assumed to be stale.
+const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// How often to check whether a lock has been released, while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// An exclusive lock on a store's data file, released when dropped, as
+/// returned by [`Store::lock()`].
+///
+/// The lock is a file alongside the data file, with `.lock` appended to its
+/// name, recording the process that holds it and when it was acquired.
+/// Unlike `flock`, this works on network filesystems such as NFS. Every
+/// process that writes the store must take the lock for it to be effective.
+///
+/// If the holding process has exited, or the system has restarted, the lock
+/// is stale, and is taken over. Whether a process has exited can only be
+/// checked on Linux, and only for processes on the same host. Otherwise, a
+/// lock is taken over once it's older than the `stale_after` given to
+/// [`FileLock::acquire_with()`].
+#[derive(Debug)]
+pub struct FileLock {
+    path: PathBuf,
+    holder: Holder,
+}
+
+/// The contents of a lock file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct Holder {
+    #[serde(flatten)]
+    owner: Owner,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+    /// When the lock was acquired, in seconds since the Unix epoch.
+    since: u64,
+}
+
+impl Holder {
+    fn current() -> Self {
+        Self {
+            owner: Owner::current(),
+            host: hostname(),
+            since: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        }
+    }
+
+    fn is_stale(&self, stale_after: Duration) -> bool {
+        if self.host.is_some() && self.host == hostname() && !self.owner.is_alive() {
+            return true;
+        }
+        let age = SystemTime::now()
+            .duration_since(UNIX_EPOCH + Duration::from_secs(self.since))
+            .unwrap_or_default();
+        age > stale_after
+    }
+}
+
+impl FileLock {
+    /// Locks the data file at `path`, waiting up to `wait` for any other
+    /// process to release it, and taking over stale locks held by processes
+    /// on other hosts after an hour.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`io::ErrorKind::TimedOut`] if the lock
+    /// wasn't released in time, or any error creating the lock file.
+    pub fn acquire(path: impl AsRef<Path>, wait: Duration) -> io::Result<Self> {
+        Self::acquire_with(path, wait, DEFAULT_STALE_AFTER)
+    }
+
+    /// Like [`Self::acquire()`], but takes over locks once they're older
+    /// than `stale_after`, if it can't be checked whether the holding
+    /// process is still running.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`io::ErrorKind::TimedOut`] if the lock
+    /// wasn't released in time, or any error creating the lock file.
+    pub fn acquire_with(
+        path: impl AsRef<Path>,
+        wait: Duration,
+        stale_after: Duration,
+    ) -> io::Result<Self> {
+        let mut lock_path = path.as_ref().as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock = Self {
+            path: lock_path.into(),
+            holder: Holder::current(),
+        };
+        let deadline = Instant::now() + wait;
+        loop {
+            match lock.try_create() {
+                Ok(()) => return Ok(lock),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+            let holder = match fs::read(&lock.path) {
+                Ok(contents) => serde_json::from_slice::<Holder>(&contents).ok(),
+                // Released since we tried to create it.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            // A lock file that can't be parsed may be in the middle of being
+            // written, so treat it as held until it's stale by age.
+            let stale = holder.as_ref().map_or_else(
+                || lock_age(&lock.path).is_some_and(|age| age > stale_after),
+                |holder| holder.is_stale(stale_after),
+            );
+            if stale {
+                lock.take_over(holder.as_ref())?;
+                continue;
+            }
+            if Instant::now() >= deadline {
+                let by = holder.map_or_else(String::new, |h| {
+                    format!(
+                        " by process {}{}",
+                        h.owner.pid(),
+                        h.host
+                            .map_or_else(String::new, |host| format!(" on {host}"))
+                    )
+                });
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("{} is locked{by}", lock.path.display()),
+                ));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Creates the lock file, failing if it already exists.
+    fn try_create(&self) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&self.path)?;
+        let result = serde_json::to_writer(&mut file, &self.holder)
+            .map_err(io::Error::from)
+            .and_then(|()| file.flush());
+        if result.is_err() {
+            let _ = fs::remove_file(&self.path);
+        }
+        result
+    }
+
+    /// Removes a stale lock file, whose holder was `stale`.
+    ///
+    /// The file is first renamed, which only one process can do, and then
+    /// checked, in case another process has taken over the lock in the
+    /// meantime and created a new lock file.
+    fn take_over(&self, stale: Option<&Holder>) -> io::Result<()> {
+        let mut grave = self.path.as_os_str().to_owned();
+        grave.push(format!(".stale.{}", std::process::id()));
+        let grave = PathBuf::from(grave);
+        match fs::rename(&self.path, &grave) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        let removed = fs::read(&grave)
+            .ok()
+            .and_then(|contents| serde_json::from_slice::<Holder>(&contents).ok());
+        if removed.as_ref() != stale {
+            // Put back the lock we took by mistake, unless yet another has
+            // been created.
+            let _ = fs::hard_link(&grave, &self.path);
+        }
+        fs::remove_file(&grave)
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // Only remove the lock file if it's still ours, in case it was
+        // taken over as stale.
+        let ours = fs::read(&self.path)
+            .ok()
+            .and_then(|contents| serde_json::from_slice::<Holder>(&contents).ok())
+            .is_some_and(|holder| holder == self.holder);
+        if ours {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+impl<V> Store<V> {
+    /// Locks the store's data file for writing, waiting up to `wait` for any
+    /// other process to release it, as with [`FileLock::acquire()`]. The lock
+    /// is released when the returned [`FileLock`] is dropped.
+    ///
+    /// Hold the lock from before reading the data file until after syncing,
+    /// so that no other process can change the file in between. If the store
+    /// was opened before taking the lock, use [`Self::reload_if_changed()`]
+    /// to pick up any changes made in the meantime.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// use rskey::Store;
+    /// use std::time::Duration;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<usize>::open(path)?;
+    /// let lock = s.lock(Duration::from_secs(5))?;
+    /// s.reload_if_changed()?;
+    /// *s.entry("runs")?.or_insert(0) += 1;
+    /// s.sync()?;
+    /// drop(lock);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`io::ErrorKind::TimedOut`] if the lock
+    /// wasn't released in time, or any error creating the lock file.
+    pub fn lock(&self, wait: Duration) -> io::Result<FileLock> {
+        FileLock::acquire(&self.path, wait)
+    }
+}
+
+/// Returns how long ago the file at `path` was last modified.
+fn lock_age(path: &Path) -> Option<Duration> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    SystemTime::now().duration_since(modified).ok()
+}
+
+#[cfg(target_os = "linux")]
+fn hostname() -> Option<String> {
+    let name = fs::read_to_string("/proc/sys/kernel/hostname").ok()?;
+    Some(name.trim().to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn acquire_waits_for_lock_then_times_out() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        let lock = FileLock::acquire(&path, Duration::ZERO).unwrap();
+        assert!(
+            tmp_dir.path().join("store.kv.lock").exists(),
+            "lock file not created"
+        );
+        let err = FileLock::acquire(&path, Duration::from_millis(100)).unwrap_err();
+        assert_eq!(io::ErrorKind::TimedOut, err.kind(), "wrong error kind");
+        drop(lock);
+        assert!(
+            !tmp_dir.path().join("store.kv.lock").exists(),
+            "lock file not removed"
+        );
+        FileLock::acquire(&path, Duration::ZERO).expect("lock not released");
+    }
+
+    #[test]
+    fn acquire_with_takes_over_stale_locks() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        let lock_path = tmp_dir.path().join("store.kv.lock");
+        // A lock held by a process on another host, acquired long ago.
+        let holder = Holder {
+            owner: Owner::current(),
+            host: Some("elsewhere".to_string()),
+            since: 0,
+        };
+        fs::write(&lock_path, serde_json::to_vec(&holder).unwrap()).unwrap();
+        let lock = FileLock::acquire_with(&path, Duration::ZERO, Duration::from_secs(60))
+            .expect("stale lock not taken over");
+        drop(lock);
+        assert!(!lock_path.exists(), "lock file not removed");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn acquire_takes_over_locks_of_exited_processes() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        let mut holder = Holder::current();
+        // No process can have this PID, as it's above the kernel's limit.
+        holder.owner = serde_json::from_str(&format!("{{\"pid\":{}}}", u32::MAX)).unwrap();
+        fs::write(
+            tmp_dir.path().join("store.kv.lock"),
+            serde_json::to_vec(&holder).unwrap(),
+        )
+        .unwrap();
+        FileLock::acquire(&path, Duration::ZERO).expect("stale lock not taken over");
+    }
+
+    #[test]
+    fn dropping_a_taken_over_lock_leaves_the_new_one() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        let lock_path = tmp_dir.path().join("store.kv.lock");
+        let old = FileLock::acquire(&path, Duration::ZERO).unwrap();
+        let mut holder = Holder::current();
+        holder.since += 1;
+        fs::write(&lock_path, serde_json::to_vec(&holder).unwrap()).unwrap();
+        drop(old);
+        assert!(lock_path.exists(), "other process's lock removed");
+    }
+}
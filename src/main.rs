@@ -1,37 +1,1948 @@
-use anyhow::Context;
-use rskey::Store;
+use anyhow::{anyhow, bail, Context};
+use indicatif::{ProgressBar, ProgressStyle};
+use rskey::{
+    Agg, Conflict, EncryptionKey, ExportFormat, FileLock, Group, Op, ProgressSink, Redacted, Regex,
+    SigningKey, Snapshot, Store, StoreError, Workspace,
+};
+use std::collections::{BTreeSet, HashMap};
 use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 const USAGE: &str = r"Usage:
-rskey list - list all key-value pairs
-rskey get KEY - show value for KEY
-rskey set KEY VALUE - set KEY to VALUE";
+rskey list [--reveal] [--format tsv] [-0] [--include-derived] - list all key-value pairs (and derived keys)
+rskey list --group-by-prefix SEP [--reveal] - list all pairs as a tree, splitting keys at SEP
+rskey keys [--prefix P] - list all keys (or those starting with P), one per line
+rskey values [--reveal] PATTERN - list values of keys matching PATTERN, one per line
+rskey grep PATTERN [--keys-only|--values-only] - list pairs whose key or value matches regex PATTERN
+rskey agg sum|min|max|count|avg [--prefix P] - combine the numeric values (of keys starting with P)
+rskey sample N - list N key-value pairs chosen at random
+rskey count [--prefix P] - show the number of keys (or those starting with P)
+rskey exists KEY - succeed if KEY is present, and fail otherwise
+rskey get [--no-resolve] KEY - show value for KEY, replacing any ${KEY} references
+rskey get --as int|bool|duration|datetime KEY - show KEY's value converted to a number or true/false, failing if it can't be
+rskey set [--force] KEY VALUE - set KEY to VALUE
+rskey set [--force] KEY - - set KEY to everything read from stdin (also --stdin KEY)
+rskey set --secret KEY VALUE - encrypt VALUE with the key in RSKEY_ENCRYPTION_KEY, and set KEY to it
+rskey set --if-version N KEY VALUE - set KEY to VALUE, only if KEY is at version N
+rskey version KEY - show KEY's version, which increases each time it's changed
+rskey getset KEY VALUE - show the old value for KEY, then set it to VALUE
+rskey append [--force] KEY SUFFIX - add SUFFIX to the end of KEY's value
+rskey del [--force] KEY - delete KEY
+rskey del --prefix P - delete all keys starting with P
+rskey clear - delete all keys except protected ones
+rskey import [--flatten] FILE - set keys from a JSON, YAML, or TOML file, or a SQLite database (.db)
+rskey import --overwrite FILE - set keys from FILE, as import FILE does, and delete all other keys except protected ones
+rskey export [--unflatten] [--format json|yaml|toml] [FILE] - print all key-value pairs as a document, or write it to FILE
+rskey export --prefix P [--format json|yaml|toml] [FILE] - print or write only the keys starting with P
+rskey export [--reveal] --format markdown|html - print all key-value pairs as a table
+rskey export --format sqlite FILE - write all key-value pairs to a table in the SQLite database FILE
+rskey export --format parquet FILE - write all key-value pairs to the Parquet file FILE
+rskey import --from-redis URL [--pattern P] - copy string keys (matching P) from Redis
+rskey export --to-redis URL [--pattern P] - copy key-value pairs (matching P) to Redis
+rskey import --from-consul|--from-etcd URL [--prefix P] - copy keys (starting with P) from Consul or etcd
+rskey export --to-consul|--to-etcd URL [--prefix P] - copy key-value pairs (starting with P) to Consul or etcd
+rskey expire KEY TTL - delete KEY after TTL (such as 90s, 15m, 1h, or 7d)
+rskey persist KEY - stop KEY expiring
+rskey ttl KEY - show how long until KEY expires
+rskey alias NAME KEY - make NAME another name for KEY in get and set
+rskey unalias NAME - remove the alias NAME
+rskey schema PATTERN SCHEMA - require values of keys matching PATTERN to match JSON SCHEMA
+rskey unschema PATTERN - remove the schema for PATTERN
+rskey protect KEY - stop KEY being changed without --force
+rskey unprotect KEY - allow KEY to be changed again
+rskey secret PATTERN - hide values of keys matching PATTERN in listings
+rskey unsecret PATTERN - stop hiding values of keys matching PATTERN
+rskey compress BYTES - compress each value larger than BYTES separately in the data file
+rskey uncompress - stop compressing values in the data file
+rskey stats - show the number of keys, the size of the data, and how much is duplicated
+rskey top [N] - list the N (default 10) largest keys, then the total size of each key prefix
+rskey snapshot [--keep AGE] - save a snapshot, then delete those older than AGE
+rskey snapshots [list] - list snapshots, oldest first
+rskey snapshots restore NAME - replace the store's contents with snapshot NAME
+rskey export-ops [--since SEQ] - print logged changes after sync SEQ as JSON, one per line
+rskey import-ops FILE - apply changes printed by export-ops from another store
+rskey rekey - re-sign the data file and snapshots with the key in RSKEY_NEW_SIGNING_KEY, and re-encrypt secret values with the key in RSKEY_NEW_ENCRYPTION_KEY
+rskey gc - remove temporary and lock files left behind by interrupted commands
+rskey shrink - rewrite the data file, and drop superseded changes from the operations log
+rskey split --by-prefix SEP --out-dir DIR - write each key to DIR/PREFIX.kv, where PREFIX is the part of the key before SEP
+rskey join FILE... --out FILE [--on-conflict fail|keep|replace] - copy the keys of each store into the store --out
+rskey verify - check the data file, metadata, and operations log for problems
+rskey bench [N] - time common operations on N (default 10000) synthetic entries in a temporary store
+rskey - [--atomic] - run commands read from stdin, one per line, then sync once
 
-fn main() -> anyhow::Result<()> {
-    let path = "store.kv";
-    let mut s = Store::<String>::open(path).with_context(|| format!("reading {path}"))?;
+Any command may be preceded by -n NAME to use the namespace NAME, kept in
+.rskey/ns/NAME.kv, instead of store.kv, by -s NAME to use the store NAME
+listed in the nearest rskey.toml workspace file, by --overlay A.kv,B.kv
+to read keys from B.kv, falling back to A.kv, and change only B.kv, by
+--derive KEY=TEMPLATE to compute KEY from TEMPLATE, replacing any ${KEY}
+references, whenever it's read, without storing it, by --git to commit each change
+to the data file to git, and by --log to record each change in the
+operations log (store.kv.ops) for export-ops.
+
+Before del --prefix, clear, import FILE, or snapshots restore changes or deletes
+any existing keys, rskey lists them and asks to go ahead, if stdin is a
+terminal. Precede the command with -y (or --yes) to skip the question, or
+with --dry-run to list the changes without making them.";
+
+fn main() -> anyhow::Result<ExitCode> {
     let raw_args: Vec<_> = env::args().collect();
     let args: Vec<_> = raw_args.iter().map(String::as_str).collect();
-    match args.get(1..) {
-        Some(["list"]) => {
-            for (k, v) in s {
-                println!("{k}: {v}");
+    let args = args.get(1..).expect("program name should be present");
+    let (opts, args) = Options::parse(args)?;
+    let Options {
+        path,
+        git,
+        log,
+        bases,
+        derivations,
+        confirm: mode,
+    } = opts;
+    let key = signing_key()?;
+    match args {
+        ["bench"] => return bench(10_000, key.as_ref()).map(|()| ExitCode::SUCCESS),
+        ["bench", n] => return bench(parse_count(n)?, key.as_ref()).map(|()| ExitCode::SUCCESS),
+        ["join", args @ ..] => return join(args, key.as_ref()).map(|()| ExitCode::SUCCESS),
+        _ => {}
+    }
+    // Signatures can only be checked by reading the whole file, an overlay
+    // has to read every layer, and derived keys may depend on any key.
+    if key.is_none() && bases.is_empty() && derivations.is_empty() {
+        let code =
+            scan_query(&path, args).with_context(|| format!("reading {}", path.display()))?;
+        if let Some(code) = code {
+            return Ok(code);
+        }
+    }
+    // Commands that only read don't sync the store, so they don't need the
+    // lock, or the directory it goes in.
+    let read_only = is_query(args);
+    let _lock = if read_only {
+        None
+    } else {
+        if let Some(dir) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        }
+        // Held until the store is synced, so that other writers can't change
+        // the file in between.
+        let lock = FileLock::acquire(&path, lock_timeout()?)
+            .with_context(|| format!("locking {}", path.display()))?;
+        Some(lock)
+    };
+    let mut s = open(&path, key.clone())?;
+    s.set_git_autocommit(git);
+    s.set_ops_log(log);
+    s.set_warn_at_entries(env_count("RSKEY_WARN_AT_ENTRIES")?);
+    s.set_warn_at_bytes(env_count("RSKEY_WARN_AT_BYTES")?);
+    s.set_byte_limit(env_count("RSKEY_BYTE_LIMIT")?);
+    s.purge_expired();
+    decrypt_for_export(&mut s, args)?;
+    // Commands that only read see the merged layers; the rest change the top.
+    let mut merged = if bases.is_empty() {
+        None
+    } else {
+        let overlay;
+        (overlay, s) = merge_layers(&bases, s, key.as_ref())?;
+        Some(overlay)
+    };
+    for (derived, template) in derivations {
+        s.derive_template(derived, template);
+        if let Some(merged) = &mut merged {
+            merged.derive_template(derived, template);
+        }
+    }
+    let code = match args {
+        ["-"] => batch(&mut s, false)?,
+        ["-", "--atomic"] => batch(&mut s, true)?,
+        args => {
+            let value;
+            let args = match args {
+                ["set", key, "-"] | ["set", "--stdin", key] => {
+                    value = read_stdin()?;
+                    vec!["set", key, &value]
+                }
+                ["set", "--force", key, "-"] | ["set", "--force", "--stdin", key] => {
+                    value = read_stdin()?;
+                    vec!["set", "--force", key, &value]
+                }
+                _ => args.to_vec(),
+            };
+            let code = match check_plan(&mut s, &args, key.as_ref(), mode)? {
+                Next::Exit(code) => return Ok(code),
+                Next::Sync => Some(ExitCode::SUCCESS),
+                Next::Run => match &merged {
+                    Some(merged) => query(merged, &args)?,
+                    None => None,
+                },
+            };
+            if let Some(code) = code {
+                code
+            } else if let Some(code) = run(&mut s, &args)? {
+                code
+            } else {
+                println!("{USAGE}");
+                ExitCode::SUCCESS
+            }
+        }
+    };
+    if !read_only && s.is_dirty() {
+        s.sync()
+            .with_context(|| format!("writing {}", path.display()))?;
+        for warning in s.size_warnings() {
+            eprintln!("warning: {warning}");
+        }
+    }
+    Ok(code)
+}
+
+/// The options that may precede any command.
+struct Options<'a> {
+    path: PathBuf,
+    git: bool,
+    log: bool,
+    /// The layers under `path`, for `--overlay`.
+    bases: Vec<PathBuf>,
+    /// Each derived key and its template.
+    derivations: Vec<(&'a str, &'a str)>,
+    /// Whether to confirm destructive commands, for `--yes` and `--dry-run`.
+    confirm: Confirm,
+}
+
+impl<'a> Options<'a> {
+    /// Parses the options at the start of `args`, returning them and the
+    /// rest of the arguments.
+    fn parse<'b>(mut args: &'b [&'a str]) -> anyhow::Result<(Self, &'b [&'a str])> {
+        let mut opts = Self {
+            path: PathBuf::from("store.kv"),
+            git: false,
+            log: false,
+            bases: Vec::new(),
+            derivations: Vec::new(),
+            confirm: Confirm::Ask,
+        };
+        loop {
+            match args {
+                ["-n", name, rest @ ..] => (opts.path, args) = (namespace_path(name)?, rest),
+                ["-s", name, rest @ ..] => (opts.path, args) = (workspace_path(name)?, rest),
+                ["--git", rest @ ..] => (opts.git, args) = (true, rest),
+                ["--log", rest @ ..] => (opts.log, args) = (true, rest),
+                ["-y" | "--yes", rest @ ..] => (opts.confirm, args) = (Confirm::Yes, rest),
+                ["--dry-run", rest @ ..] => (opts.confirm, args) = (Confirm::DryRun, rest),
+                ["--overlay", paths, rest @ ..] => {
+                    opts.bases = paths.split(',').map(PathBuf::from).collect();
+                    opts.path = opts
+                        .bases
+                        .pop()
+                        .filter(|p| !p.as_os_str().is_empty())
+                        .ok_or_else(|| {
+                            anyhow!("--overlay needs a comma-separated list of data files")
+                        })?;
+                    args = rest;
+                }
+                ["--derive", rule, rest @ ..] => {
+                    let rule = rule
+                        .split_once('=')
+                        .ok_or_else(|| anyhow!("--derive needs KEY=TEMPLATE, not {rule:?}"))?;
+                    opts.derivations.push(rule);
+                    args = rest;
+                }
+                _ => return Ok((opts, args)),
+            }
+        }
+    }
+}
+
+/// Opens the store at `path`, checking its signature if there's a `key`.
+fn open(path: &Path, key: Option<SigningKey>) -> anyhow::Result<Store<String>> {
+    match key {
+        Some(key) => Store::open_signed(path, key).map_err(anyhow::Error::from),
+        None => Store::open(path).map_err(anyhow::Error::from),
+    }
+    .with_context(|| format!("reading {}", path.display()))
+}
+
+/// Opens the stores at `bases` and returns the result of overlaying `top`
+/// on them, as a store for reading, along with `top`.
+fn merge_layers(
+    bases: &[PathBuf],
+    top: Store<String>,
+    key: Option<&SigningKey>,
+) -> anyhow::Result<(Store<String>, Store<String>)> {
+    let mut layers = bases
+        .iter()
+        .map(|base| open(base, key.cloned()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    for layer in &mut layers {
+        layer.purge_expired();
+    }
+    layers.push(top);
+    let overlay = Store::overlay(layers);
+    let merged = overlay.merged();
+    let (_, top) = overlay.into_layers();
+    Ok((merged, top))
+}
+
+/// Returns the path of the data file for the namespace `name`.
+fn namespace_path(name: &str) -> anyhow::Result<PathBuf> {
+    let valid = name
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if name.is_empty() || name.starts_with('.') || !valid {
+        bail!("invalid namespace {name:?}");
+    }
+    Ok(Path::new(".rskey").join("ns").join(format!("{name}.kv")))
+}
+
+/// Returns the path of the data file for the store `name` in the workspace.
+fn workspace_path(name: &str) -> anyhow::Result<PathBuf> {
+    let ws = Workspace::find()?;
+    let names: Vec<_> = ws.names().collect();
+    ws.path(name).map(Path::to_path_buf).ok_or_else(|| {
+        anyhow!(
+            "no store named {name:?} in workspace (have: {})",
+            names.join(", ")
+        )
+    })
+}
+
+/// Adds a hint about `--force` to errors caused by protected keys.
+fn force_hint(e: StoreError) -> anyhow::Error {
+    match e {
+        StoreError::Protected(_) => anyhow!("{e} (use --force to override)"),
+        e => e.into(),
+    }
+}
+
+/// Reads all of standard input, for use as a value.
+fn read_stdin() -> anyhow::Result<String> {
+    io::read_to_string(io::stdin()).context("reading value from stdin")
+}
+
+/// Runs the command given by `args` against the store, without syncing it.
+/// Returns `None` if the command isn't recognised.
+fn run(s: &mut Store<String>, args: &[&str]) -> anyhow::Result<Option<ExitCode>> {
+    if let Some(code) = query(s, args)? {
+        return Ok(Some(code));
+    }
+    if let Some(code) = maintain(s, args)? {
+        return Ok(Some(code));
+    }
+    if let Some(code) = update(s, args)? {
+        return Ok(Some(code));
+    }
+    configure(s, args)
+}
+
+/// Whether to ask before running a destructive command.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Confirm {
+    /// Ask, if standard input is a terminal.
+    Ask,
+    /// Go ahead without asking, for `--yes`.
+    Yes,
+    /// Show what would change, without changing anything, for `--dry-run`.
+    DryRun,
+}
+
+/// What to do with a command once its plan has been confirmed, or not.
+enum Next {
+    /// Run the command.
+    Run,
+    /// Sync the store, which the command has already changed.
+    Sync,
+    /// Exit with the given code, without syncing the store.
+    Exit(ExitCode),
+}
+
+/// The changes that a destructive command would make, worked out before
+/// making them, so that they can be confirmed or shown.
+#[derive(Default)]
+struct Plan {
+    added: Vec<String>,
+    changed: Vec<String>,
+    deleted: Vec<String>,
+}
+
+impl Plan {
+    /// Returns the plan for changing the entries `old` to `new`: adding and
+    /// changing keys, and, if `replace` is set, deleting the keys not in
+    /// `new`.
+    fn diff(old: &HashMap<String, String>, new: &HashMap<String, String>, replace: bool) -> Self {
+        let mut plan = Self::default();
+        for key in sorted(new.keys().map(String::as_str)) {
+            match old.get(key) {
+                None => plan.added.push(key.to_string()),
+                Some(value) if *value != new[key] => plan.changed.push(key.to_string()),
+                Some(_) => {}
             }
         }
-        Some(["get", key]) => {
-            if let Some(value) = s.get(*key) {
+        if replace {
+            plan.deleted = sorted(old.keys().map(String::as_str))
+                .into_iter()
+                .filter(|key| !new.contains_key(*key))
+                .map(str::to_string)
+                .collect();
+        }
+        plan
+    }
+
+    /// Returns `true` if the plan changes or deletes any existing keys.
+    fn is_destructive(&self) -> bool {
+        !self.changed.is_empty() || !self.deleted.is_empty()
+    }
+
+    /// Writes each change to `out`, one per line.
+    fn print(&self, mut out: impl Write) -> io::Result<()> {
+        for (action, keys) in [
+            ("delete", &self.deleted),
+            ("change", &self.changed),
+            ("add", &self.added),
+        ] {
+            for key in keys {
+                writeln!(out, "{action} {key}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Works out what `args` would change, if it's a destructive command, using
+/// `key` to check the signature of any snapshot it would restore. Also
+/// returns `true` if working it out ran the command.
+///
+/// An import is planned by running it against `s`, so that the store's own
+/// key normalization and schemas apply, and the file is only read once.
+/// The changes are only kept if `s` is synced.
+fn plan(
+    s: &mut Store<String>,
+    args: &[&str],
+    key: Option<&SigningKey>,
+) -> anyhow::Result<Option<(Plan, bool)>> {
+    let plan = match args {
+        ["del", "--prefix", prefix] => {
+            let deleted = s
+                .keys_with_prefix(prefix)
+                .into_iter()
+                .filter(|k| !s.is_protected(k))
+                .map(str::to_string)
+                .collect();
+            (
+                Plan {
+                    deleted,
+                    ..Plan::default()
+                },
+                false,
+            )
+        }
+        ["snapshots", "restore", name] => {
+            let snapshot = open(&find_snapshot(s, name)?.path, key.cloned())?;
+            (Plan::diff(s, &snapshot, true), false)
+        }
+        ["clear"] => {
+            let deleted = sorted(s.keys().map(String::as_str))
+                .into_iter()
+                .filter(|k| !s.is_protected(k))
+                .map(str::to_string)
+                .collect();
+            (
+                Plan {
+                    deleted,
+                    ..Plan::default()
+                },
+                false,
+            )
+        }
+        ["import", "--flatten", path] | ["import", path] if !path.starts_with('-') => {
+            let old = HashMap::clone(s);
+            import(s, path, args[1] == "--flatten")?;
+            (Plan::diff(&old, s, false), true)
+        }
+        ["import", "--overwrite", path] => {
+            let old = HashMap::clone(s);
+            let old_keys: Vec<_> = s.keys().cloned().collect();
+            import_overwrite(s, path)?;
+            let mut plan = Plan::diff(&old, s, false);
+            // Encrypted values aren't in the entries, so deletions are
+            // worked out from the keys.
+            plan.deleted = sorted(old_keys.iter().map(String::as_str))
+                .into_iter()
+                .filter(|k| !s.contains_key(k))
+                .map(str::to_string)
+                .collect();
+            (plan, true)
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(plan))
+}
+
+/// Works out what `args` would change, and confirms it as given by `mode`,
+/// returning what to do next.
+fn check_plan(
+    s: &mut Store<String>,
+    args: &[&str],
+    key: Option<&SigningKey>,
+    mode: Confirm,
+) -> anyhow::Result<Next> {
+    // There's nothing to ask if standard input isn't a terminal, as in a
+    // script.
+    if mode == Confirm::Yes || (mode == Confirm::Ask && !io::stdin().is_terminal()) {
+        return Ok(Next::Run);
+    }
+    let Some((plan, ran)) = plan(s, args, key)? else {
+        if mode == Confirm::DryRun {
+            bail!(
+                "--dry-run can only be used with del --prefix, clear, import FILE, or snapshots restore"
+            );
+        }
+        return Ok(Next::Run);
+    };
+    if confirm(&plan, mode)? {
+        return Ok(if ran { Next::Sync } else { Next::Run });
+    }
+    Ok(Next::Exit(match mode {
+        Confirm::DryRun => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    }))
+}
+
+/// Shows `plan` and, depending on `mode`, asks whether to go ahead with it,
+/// returning `true` if so.
+///
+/// There's nothing to ask if the plan doesn't change or delete any keys.
+fn confirm(plan: &Plan, mode: Confirm) -> anyhow::Result<bool> {
+    match mode {
+        Confirm::Yes => Ok(true),
+        Confirm::DryRun => {
+            plan.print(io::stdout().lock())?;
+            Ok(false)
+        }
+        Confirm::Ask if !plan.is_destructive() => Ok(true),
+        Confirm::Ask => {
+            plan.print(io::stderr().lock())?;
+            eprint!("Go ahead? [y/N] ");
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer)?;
+            let yes = matches!(answer.trim().to_lowercase().as_str(), "y" | "yes");
+            if !yes {
+                eprintln!("cancelled");
+            }
+            Ok(yes)
+        }
+    }
+}
+
+/// Returns the snapshot of `s` called `name`.
+fn find_snapshot(s: &Store<String>, name: &str) -> anyhow::Result<Snapshot> {
+    s.snapshots()?
+        .into_iter()
+        .find(|snapshot| snapshot.name == name)
+        .ok_or_else(|| anyhow!("no snapshot named {name:?}"))
+}
+
+/// If `args` is an `export` command, decrypts any encrypted values in `s`,
+/// so that the export includes them. Exporting doesn't sync the store, so
+/// they're only decrypted in memory, and they're marked secret, so that
+/// tables hide them unless revealed.
+fn decrypt_for_export(s: &mut Store<String>, args: &[&str]) -> anyhow::Result<()> {
+    let keys: Vec<_> = s.encrypted_keys().map(str::to_string).collect();
+    if args.first() != Some(&"export") || keys.is_empty() {
+        return Ok(());
+    }
+    s.decrypt_all(&encryption_key()?)?;
+    for key in keys {
+        s.mark_secret(&key);
+    }
+    Ok(())
+}
+
+/// Returns the key that `key` refers to in `s`: its normalized form, or,
+/// if that's an alias, the key it stands for.
+fn canonical(s: &Store<String>, key: &str) -> String {
+    s.resolve(&s.key_normalization().apply(key)).to_string()
+}
+
+/// Returns the value for `key` converted to `kind`, in a canonical form:
+/// durations and times are given in seconds (since the Unix epoch, for
+/// times).
+fn get_as(s: &Store<String>, kind: &str, key: &str) -> anyhow::Result<Option<String>> {
+    Ok(match kind {
+        "int" => s.get_int(key)?.map(|n| n.to_string()),
+        "bool" => s.get_bool(key)?.map(|b| b.to_string()),
+        "duration" => s.get_duration(key)?.map(|d| d.as_secs_f64().to_string()),
+        "datetime" => s
+            .get_datetime(key)?
+            .map(|t| match t.duration_since(UNIX_EPOCH) {
+                Ok(d) => d.as_secs_f64().to_string(),
+                Err(e) => (-e.duration().as_secs_f64()).to_string(),
+            }),
+        _ => bail!("unknown type {kind:?} (use int, bool, duration, or datetime)"),
+    })
+}
+
+/// Returns `true` if `args` is one of the commands run by [`query`], which
+/// only read the store.
+fn is_query(args: &[&str]) -> bool {
+    matches!(
+        args.first(),
+        Some(
+            &("list"
+                | "export"
+                | "keys"
+                | "values"
+                | "grep"
+                | "agg"
+                | "sample"
+                | "count"
+                | "exists"
+                | "get"
+                | "version"
+                | "ttl")
+        )
+    )
+}
+
+/// Runs a command that only reads the store.
+fn query(s: &Store<String>, args: &[&str]) -> anyhow::Result<Option<ExitCode>> {
+    match args {
+        ["list", "--group-by-prefix", sep, opts @ ..] => list_tree(s, sep, opts)?,
+        ["list", opts @ ..] => list(s, opts)?,
+        ["export", to @ ("--to-redis" | "--to-consul" | "--to-etcd"), url, opts @ ..] => {
+            export_remote(s, Remote::from_flag(to), url, opts)?;
+        }
+        ["export", opts @ ..] => export(s, opts)?,
+        ["keys"] => {
+            for k in sorted(s.keys().map(String::as_str)) {
+                println!("{k}");
+            }
+        }
+        ["keys", "--prefix", prefix] => {
+            for k in s.keys_with_prefix(prefix) {
+                println!("{k}");
+            }
+        }
+        ["values", pattern] => {
+            for k in sorted(s.keys_matching(pattern)) {
+                let v = s.get_redacted(k).expect("key should be present");
+                println!("{v}");
+            }
+        }
+        ["values", "--reveal", pattern] => {
+            for k in sorted(s.keys_matching(pattern)) {
+                println!("{}", s[k]);
+            }
+        }
+        ["grep", args @ ..] => return grep(s, args).map(Some),
+        ["agg", agg] => aggregate(s, agg, "")?,
+        ["agg", agg, "--prefix", prefix] => aggregate(s, agg, prefix)?,
+        ["sample", n] => {
+            let mut sample = s.sample(parse_count(n)?);
+            sample.sort_unstable_by_key(|(k, _)| *k);
+            for (k, v) in sample {
+                print_pair(s, k, v, false, ListFormat::Text);
+            }
+        }
+        ["count"] => {
+            println!("{}", s.len());
+        }
+        ["count", "--prefix", prefix] => {
+            println!("{}", s.keys().filter(|k| k.starts_with(prefix)).count());
+        }
+        ["exists", key] => {
+            if !s.contains_key(&canonical(s, key)) {
+                return Ok(Some(ExitCode::FAILURE));
+            }
+        }
+        ["get", key] | ["get", "--no-resolve", key] if s.is_encrypted(key) => {
+            if let Some(value) = s.get_encrypted(key, &encryption_key()?)? {
+                println!("{key}: {value}");
+            }
+        }
+        ["get", key] => {
+            if let Some(value) = s.get_resolved(key)? {
+                println!("{key}: {value}");
+            } else if let Some(value) = s.derived_value(key) {
+                println!("{key}: {value}");
+            } else {
+                println!(r#"key "{key}" not found"#);
+            }
+        }
+        ["get", "--as", kind, key] => {
+            if let Some(value) = get_as(s, kind, key)? {
                 println!("{key}: {value}");
             } else {
                 println!(r#"key "{key}" not found"#);
+            }
+        }
+        ["get", "--no-resolve", key] => {
+            if let Some(value) = s.lookup(key) {
+                println!("{key}: {value}");
+            } else {
+                println!(r#"key "{key}" not found"#);
+            }
+        }
+        ["version", key] => {
+            println!("{}", s.version(&canonical(s, key)));
+        }
+        ["ttl", key] => match s.ttl(key) {
+            Some(ttl) => println!("{}s", ttl.as_secs()),
+            None if s.contains_key(&canonical(s, key)) => {
+                println!(r#"key "{key}" does not expire"#);
+            }
+            None => println!(r#"key "{key}" not found"#),
+        },
+        _ => return Ok(None),
+    }
+    Ok(Some(ExitCode::SUCCESS))
+}
+
+/// Runs a command that reports on the store, or manages its auxiliary
+/// files, without changing its data.
+fn maintain(s: &Store<String>, args: &[&str]) -> anyhow::Result<Option<ExitCode>> {
+    match args {
+        ["stats"] => {
+            let stats = s.stats();
+            println!("keys: {}", stats.keys);
+            println!("bytes: {}", stats.bytes);
+            println!("distinct values: {}", stats.distinct_values);
+            println!("duplicate value bytes: {}", stats.duplicate_bytes);
+        }
+        ["top"] => top(s, 10),
+        ["top", n] => top(s, parse_count(n)?),
+        ["gc"] => {
+            let report = s.gc()?;
+            for path in &report.removed {
+                println!("removed {}", path.display());
+            }
+            println!("reclaimed {} bytes", report.bytes);
+        }
+        ["shrink"] => {
+            let report = s.shrink()?;
+            println!("removed {} superseded operations", report.ops_removed);
+            println!(
+                "{} bytes before, {} bytes after",
+                report.before, report.after
+            );
+        }
+        ["verify"] => {
+            let report = s.check_integrity()?;
+            for issue in &report.issues {
+                println!("{issue}");
+            }
+            if !report.is_ok() {
+                return Ok(Some(ExitCode::FAILURE));
+            }
+        }
+        ["split", "--by-prefix", separator, "--out-dir", dir] => {
+            let report = s.split_by_prefix(separator, dir)?;
+            for (prefix, keys) in &report.parts {
+                let path = Path::new(dir).join(format!("{prefix}.kv"));
+                println!("{}: {keys} keys", path.display());
+            }
+            for key in &report.skipped {
+                eprintln!("warning: key {key:?} has no usable prefix, so was left out");
+            }
+        }
+        ["snapshot"] => println!("{}", s.snapshot()?.name),
+        ["snapshot", "--keep", age] => {
+            let retention = parse_duration(age)?;
+            println!("{}", s.snapshot()?.name);
+            s.prune_snapshots(retention)?;
+        }
+        ["snapshots"] | ["snapshots", "list"] => {
+            let now = SystemTime::now();
+            for snapshot in s.snapshots()? {
+                let age = now.duration_since(snapshot.taken).unwrap_or_default();
+                println!("{}\t{} ago", snapshot.name, format_age(age));
+            }
+        }
+        ["export-ops"] => export_ops(s, 0)?,
+        ["export-ops", "--since", seq] => {
+            let seq = seq
+                .parse()
+                .with_context(|| format!("invalid sequence number {seq:?}"))?;
+            export_ops(s, seq)?;
+        }
+        _ => return Ok(None),
+    }
+    Ok(Some(ExitCode::SUCCESS))
+}
+
+/// Runs a command that only reads the store by scanning its data file one
+/// entry at a time, so that it works on stores too large to fit in memory.
+/// Returns `None` if the command can't be run this way.
+///
+/// Unlike opening the store, this doesn't purge expired keys from the file,
+/// though they aren't shown.
+fn scan_query(path: &Path, args: &[&str]) -> anyhow::Result<Option<ExitCode>> {
+    if !matches!(
+        args.first(),
+        Some(&("list" | "keys" | "count" | "exists" | "get" | "sample"))
+    ) {
+        return Ok(None);
+    }
+    let s = Store::<String>::open_metadata(path)?;
+    match args {
+        ["list", opts @ ..] if !opts.contains(&"--group-by-prefix") => {
+            // There are no derived keys, so there's nothing to include.
+            let (reveal, format, _) = list_options(opts)?;
+            s.scan(|k, v| {
+                print_pair(&s, &k, &v, reveal, format);
+                ControlFlow::Continue(())
+            })?;
+            print_encrypted(&s, format);
+        }
+        ["keys"] | ["keys", "--prefix", _] => {
+            let prefix = args.get(2).copied().unwrap_or_default();
+            let mut keys: Vec<_> = s
+                .encrypted_keys()
+                .filter(|k| k.starts_with(prefix))
+                .map(str::to_string)
+                .collect();
+            s.scan(|k, _| {
+                if k.starts_with(prefix) {
+                    keys.push(k);
+                }
+                ControlFlow::Continue(())
+            })?;
+            keys.sort_unstable();
+            for k in keys {
+                println!("{k}");
+            }
+        }
+        ["count"] | ["count", "--prefix", _] => {
+            let prefix = args.get(2).copied().unwrap_or_default();
+            let mut count = s.encrypted_keys().filter(|k| k.starts_with(prefix)).count();
+            s.scan(|k, _| {
+                if k.starts_with(prefix) {
+                    count += 1;
+                }
+                ControlFlow::Continue(())
+            })?;
+            println!("{count}");
+        }
+        ["sample", n] => {
+            let mut sample = s.scan_sample(parse_count(n)?)?;
+            sample.sort_unstable();
+            for (k, v) in sample {
+                print_pair(&s, &k, &v, false, ListFormat::Text);
+            }
+        }
+        ["exists", key] => {
+            let key = canonical(&s, key);
+            // Encrypted values are in the metadata, which is loaded up front.
+            if !s.contains_key(&key) && find(&s, &key)?.is_none() {
+                return Ok(Some(ExitCode::FAILURE));
+            }
+        }
+        ["get", .., key] if s.is_encrypted(key) => return Ok(None),
+        ["get", "--no-resolve", key] | ["get", key] => {
+            let Some(value) = find(&s, &canonical(&s, key))? else {
+                println!(r#"key "{key}" not found"#);
+                return Ok(Some(ExitCode::SUCCESS));
             };
+            // Resolving references may need any other value.
+            if args.len() == 2 && value.contains('$') {
+                return Ok(None);
+            }
+            println!("{key}: {value}");
+        }
+        _ => return Ok(None),
+    }
+    Ok(Some(ExitCode::SUCCESS))
+}
+
+/// Scans the data file for `s` for the value of `key`.
+fn find(s: &Store<String>, key: &str) -> anyhow::Result<Option<String>> {
+    let mut found = None;
+    s.scan(|k, v| {
+        if k == key {
+            found = Some(v);
+            return ControlFlow::Break(());
+        }
+        ControlFlow::Continue(())
+    })?;
+    Ok(found)
+}
+
+/// Runs a command that changes the store.
+fn update(s: &mut Store<String>, args: &[&str]) -> anyhow::Result<Option<ExitCode>> {
+    match args {
+        ["rekey"] => {
+            let signing = signing_key_from("RSKEY_NEW_SIGNING_KEY")?;
+            let encryption = encryption_key_from("RSKEY_NEW_ENCRYPTION_KEY")?;
+            if signing.is_none() && encryption.is_none() {
+                bail!(
+                    "set RSKEY_NEW_SIGNING_KEY (or _FILE or _KEYRING), or \
+                     RSKEY_NEW_ENCRYPTION_KEY (or _FILE), to the new key"
+                );
+            }
+            // Re-encrypting rewrites only the snapshots, signed with the
+            // current key, so any failure comes before any file is
+            // re-signed, and the store is synced with the new key.
+            if let Some(new) = encryption {
+                s.rotate_encryption_key(&encryption_key()?, &new)?;
+            }
+            if let Some(key) = signing {
+                s.rotate_signing_key(Some(key))?;
+            }
+        }
+        ["snapshots", "restore", name] => {
+            s.restore_snapshot(&find_snapshot(s, name)?)?;
+        }
+        ["set", "--secret", key, value] => {
+            s.insert_encrypted(canonical(s, key), &(*value).to_string(), &encryption_key()?)
+                .map_err(force_hint)?;
+        }
+        ["set", "--force", key, value] => {
+            s.force_insert(canonical(s, key), (*value).to_string());
+        }
+        ["set", key, value] => {
+            s.try_insert(canonical(s, key), (*value).to_string())
+                .map_err(force_hint)?;
+        }
+        ["set", "--if-version", version, key, value] => {
+            let version = version
+                .parse()
+                .with_context(|| format!("invalid version {version:?}"))?;
+            s.insert_if_version(canonical(s, key), (*value).to_string(), version)
+                .map_err(force_hint)?;
+        }
+        ["getset", key, value] => {
+            let old = s
+                .try_insert(canonical(s, key), (*value).to_string())
+                .map_err(force_hint)?;
+            if let Some(old) = old {
+                println!("{old}");
+            }
+        }
+        ["append", key, suffix] => {
+            s.append_str(key, suffix).map_err(force_hint)?;
+        }
+        ["append", "--force", key, suffix] => {
+            let key = canonical(s, key);
+            let value = s.get(&key).cloned().unwrap_or_default();
+            s.force_insert(key, value + suffix);
+        }
+        ["del", "--force", key] => {
+            s.force_remove(key);
+        }
+        ["del", "--prefix", prefix] => {
+            s.remove_prefix(prefix).map_err(force_hint)?;
+        }
+        ["del", key] => {
+            s.remove(key).map_err(force_hint)?;
+        }
+        ["import", from @ ("--from-redis" | "--from-consul" | "--from-etcd"), url, opts @ ..] => {
+            import_remote(s, Remote::from_flag(from), url, opts)?;
+        }
+        ["import", "--flatten", path] => import(s, path, true)?,
+        ["import", "--overwrite", path] => import_overwrite(s, path)?,
+        ["import", path] => import(s, path, false)?,
+        ["clear"] => s.clear(),
+        ["import-ops", path] => {
+            let text = fs::read_to_string(path).with_context(|| format!("reading {path}"))?;
+            let ops = serde_json::Deserializer::from_str(&text)
+                .into_iter::<Op<String>>()
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("parsing {path}"))?;
+            s.apply_ops(ops).map_err(force_hint)?;
+        }
+        _ => return Ok(None),
+    }
+    Ok(Some(ExitCode::SUCCESS))
+}
+
+/// Runs a command that changes how keys are handled, rather than their
+/// values.
+fn configure(s: &mut Store<String>, args: &[&str]) -> anyhow::Result<Option<ExitCode>> {
+    match args {
+        ["expire", key, ttl] => {
+            let ttl = parse_duration(ttl)?;
+            if !s.expire(key, ttl) {
+                println!(r#"key "{key}" not found"#);
+            }
+        }
+        ["persist", key] => {
+            s.persist(key);
+        }
+        ["alias", alias, key] => {
+            s.alias(alias, key)?;
+        }
+        ["unalias", alias] => {
+            s.unalias(alias);
+        }
+        ["schema", pattern, schema] => {
+            let schema = serde_json::from_str(schema).context("parsing schema")?;
+            s.set_schema(pattern, schema)?;
+        }
+        ["unschema", pattern] => {
+            s.remove_schema(pattern);
+        }
+        ["protect", key] => {
+            s.protect(key);
+        }
+        ["unprotect", key] => {
+            s.unprotect(key);
+        }
+        ["secret", pattern] => {
+            s.mark_secret(pattern);
+        }
+        ["unsecret", pattern] => {
+            s.unmark_secret(pattern);
+        }
+        ["compress", bytes] => {
+            s.set_compression_threshold(Some(parse_count(bytes)?));
+        }
+        ["uncompress"] => {
+            s.set_compression_threshold(None);
+        }
+        _ => return Ok(None),
+    }
+    Ok(Some(ExitCode::SUCCESS))
+}
+
+/// Prints the pairs whose key or value matches the regular expression given
+/// in `args`, in order, highlighting the matches if standard output is a
+/// terminal. Secret values are neither searched nor shown. Fails if nothing
+/// matches, like `grep`.
+fn grep(s: &Store<String>, args: &[&str]) -> anyhow::Result<ExitCode> {
+    let (pattern, keys, values) = match args {
+        [pattern] => (pattern, true, true),
+        [pattern, "--keys-only"] | ["--keys-only", pattern] => (pattern, true, false),
+        [pattern, "--values-only"] | ["--values-only", pattern] => (pattern, false, true),
+        _ => bail!("usage: rskey grep PATTERN [--keys-only|--values-only]"),
+    };
+    let re = Regex::new(pattern).with_context(|| format!("invalid pattern {pattern:?}"))?;
+    let mut found = BTreeSet::new();
+    if keys {
+        found.extend(s.keys_matching_regex(&re));
+    }
+    if values {
+        found.extend(
+            s.find_values(&re)
+                .map(|(k, _)| k)
+                .filter(|k| !s.is_secret(k)),
+        );
+    }
+    let color = io::stdout().is_terminal();
+    let highlight = |text: &str, enabled| {
+        if color && enabled {
+            re.replace_all(text, "\x1b[1;31m$0\x1b[0m").into_owned()
+        } else {
+            text.to_string()
         }
-        Some(["set", key, value]) => {
-            s.insert((*key).to_string(), (*value).to_string());
-            s.sync().with_context(|| format!("writing {path}"))?;
+    };
+    for k in &found {
+        let v = if s.is_secret(k) {
+            Redacted::<String>::Hidden.to_string()
+        } else {
+            highlight(&s[*k], values)
+        };
+        println!("{}: {v}", highlight(k, keys));
+    }
+    Ok(if found.is_empty() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Prints the numeric values of the keys starting with `prefix`, combined
+/// as specified by `agg`, or nothing if there are none.
+fn aggregate(s: &Store<String>, agg: &str, prefix: &str) -> anyhow::Result<()> {
+    let agg = match agg {
+        "sum" => Agg::Sum,
+        "min" => Agg::Min,
+        "max" => Agg::Max,
+        "count" => Agg::Count,
+        "avg" => Agg::Avg,
+        other => bail!("unknown aggregate {other:?} (use sum, min, max, count, or avg)"),
+    };
+    if let Some(result) = s.aggregate(prefix, agg) {
+        println!("{result}");
+    }
+    Ok(())
+}
+
+/// Joins the stores given in `args` into the one given after `--out`,
+/// creating it if necessary, and signing it with `key`, if any. Conflicting
+/// values are handled as given by `--on-conflict` (see [`Store::join`]).
+fn join(args: &[&str], key: Option<&SigningKey>) -> anyhow::Result<()> {
+    let mut inputs = Vec::new();
+    let mut out = None;
+    let mut conflict = Conflict::Fail;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--out" => match args.next() {
+                Some(path) => out = Some(Path::new(path)),
+                None => bail!("--out needs the path of the store to write"),
+            },
+            "--on-conflict" => {
+                conflict = match args.next() {
+                    Some(&"fail") => Conflict::Fail,
+                    Some(&"keep") => Conflict::Keep,
+                    Some(&"replace") => Conflict::Replace,
+                    _ => bail!("--on-conflict needs a value (fail, keep, or replace)"),
+                }
+            }
+            other if other.starts_with('-') => bail!("unknown join option {other:?}"),
+            path => inputs.push(Path::new(path)),
+        }
+    }
+    let Some(out) = out else {
+        bail!("join needs --out and the path of the store to write");
+    };
+    let _lock = FileLock::acquire(out, lock_timeout()?)
+        .with_context(|| format!("locking {}", out.display()))?;
+    let mut s = open(out, key.cloned())?;
+    for input in inputs {
+        let other = open(input, key.cloned())?;
+        let joined = s
+            .join(&other, conflict)
+            .map_err(|e| match e {
+                StoreError::JoinConflict(_) => anyhow!("{e} (use --on-conflict keep or replace)"),
+                e => e.into(),
+            })
+            .with_context(|| format!("joining {}", input.display()))?;
+        println!("{}: {joined} keys", input.display());
+    }
+    s.sync()
+        .with_context(|| format!("writing {}", out.display()))?;
+    Ok(())
+}
+
+/// Measures how long common operations take on a store of `n` synthetic
+/// entries, signed with `key`, if any, and prints a table of the results.
+/// The store is kept in a temporary directory, which is then removed.
+fn bench(n: usize, key: Option<&SigningKey>) -> anyhow::Result<()> {
+    let dir = env::temp_dir().join(format!("rskey-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+    let result = bench_in(&dir.join("bench.kv"), n, key);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+/// Runs the benchmarks for [`bench`] on a store at `path`.
+fn bench_in(path: &Path, n: usize, key: Option<&SigningKey>) -> anyhow::Result<()> {
+    /// How many times to repeat the operations on the whole store.
+    const ROUNDS: usize = 5;
+
+    let open = || match key {
+        Some(key) => Store::<String>::open_signed(path, key.clone()).map_err(anyhow::Error::from),
+        None => Store::<String>::open(path).map_err(anyhow::Error::from),
+    };
+    let mut s = open()?;
+    let entries: Vec<_> = (0..n)
+        .map(|i| {
+            (
+                format!("key{i:08}"),
+                format!("value {i} {}", "x".repeat(i % 64)),
+            )
+        })
+        .collect();
+    let mut rows = Vec::new();
+    let start = Instant::now();
+    for (k, v) in entries.iter().cloned() {
+        s.try_insert(k, v)?;
+    }
+    rows.push(("insert", n, start.elapsed()));
+    let start = Instant::now();
+    for (k, _) in &entries {
+        std::hint::black_box(s.get(k));
+    }
+    rows.push(("get", n, start.elapsed()));
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        s.sync()?;
+    }
+    rows.push(("sync", ROUNDS, start.elapsed()));
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        std::hint::black_box(open()?);
+    }
+    rows.push(("open", ROUNDS, start.elapsed()));
+    let size = fs::metadata(path)?.len();
+    println!("{n} entries, {size} bytes");
+    println!(
+        "{:<10} {:>8} {:>12} {:>12} {:>12}",
+        "operation", "count", "total", "per op", "ops/s"
+    );
+    for (name, count, total) in rows {
+        let count = u32::try_from(count).unwrap_or(u32::MAX);
+        let per_op = total / count;
+        let rate = f64::from(count) / total.as_secs_f64();
+        println!(
+            "{name:<10} {count:>8} {:>12} {:>12} {rate:>12.0}",
+            format!("{total:.1?}"),
+            format!("{per_op:.1?}")
+        );
+    }
+    Ok(())
+}
+
+/// Parses a number of entries given on the command line.
+fn parse_count(n: &str) -> anyhow::Result<usize> {
+    n.parse().with_context(|| format!("invalid count {n:?}"))
+}
+
+/// Prints the `n` largest entries, then the total size of the entries with
+/// each key prefix, in bytes.
+fn top(s: &Store<String>, n: usize) {
+    for (key, size) in s.largest(n) {
+        println!("{size}\t{key}");
+    }
+    println!();
+    for (prefix, size) in s.prefix_sizes() {
+        println!("{size}\t{prefix}*");
+    }
+}
+
+/// Prints the operations logged after sync `seq`, as JSON, one per line.
+fn export_ops(s: &Store<String>, seq: u64) -> anyhow::Result<()> {
+    for op in s.ops_since(seq)? {
+        println!("{}", serde_json::to_string(&op)?);
+    }
+    Ok(())
+}
+
+/// Runs commands read from standard input, one per line, against the store.
+///
+/// Blank lines, and lines starting with `#`, are ignored. If a command
+/// fails, the remaining commands aren't run. If `atomic` is set, all the
+/// changes are then discarded; otherwise, the changes made so far are kept.
+fn batch(s: &mut Store<String>, atomic: bool) -> anyhow::Result<ExitCode> {
+    let mut code = ExitCode::SUCCESS;
+    for (n, line) in io::stdin().lines().enumerate() {
+        let line = line.context("reading commands from stdin")?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let result = match run(s, &split_command(line)) {
+            Ok(Some(c)) => {
+                code = c;
+                Ok(())
+            }
+            Ok(None) => Err(anyhow!("unknown command")),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            // Returning an error means the store won't be synced, so unless
+            // the batch is atomic, sync the changes made so far.
+            if !atomic && s.is_dirty() {
+                s.sync()
+                    .with_context(|| format!("writing {}", s.path().display()))?;
+            }
+            return Err(e.context(format!("line {}: {line}", n + 1)));
+        }
+    }
+    Ok(code)
+}
+
+/// Splits a command line from [`batch`] into words. The value given to
+/// `set` is the rest of the line, so it may contain spaces.
+fn split_command(line: &str) -> Vec<&str> {
+    let max_words = match line.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+        ["set", "--force" | "--secret"] => 4,
+        ["set", "--if-version"] => 5,
+        ["set" | "getset", _] => 3,
+        _ => usize::MAX,
+    };
+    let mut words = Vec::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        if words.len() + 1 == max_words {
+            words.push(rest);
+            break;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        words.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    words
+}
+
+/// Prints all key-value pairs, in the format selected by `opts`.
+///
+/// By default, pairs are printed one per line as `key: value`. With
+/// `--format tsv`, the key and value are separated by a tab, and any tabs,
+/// newlines, carriage returns, or backslashes in them are escaped. With
+/// `-0`, keys and values are printed unchanged, each followed by a NUL
+/// byte, for `xargs -0`.
+fn list(s: &Store<String>, opts: &[&str]) -> anyhow::Result<()> {
+    let (reveal, format, include_derived) = list_options(opts)?;
+    for (k, v) in s.iter() {
+        print_pair(s, k, v, reveal, format);
+    }
+    print_encrypted(s, format);
+    if include_derived {
+        for (k, v) in s.derived_entries() {
+            print_pair(s, k, &v, reveal, format);
+        }
+    }
+    Ok(())
+}
+
+/// Prints all key-value pairs as a tree, splitting keys at `sep`.
+fn list_tree(s: &Store<String>, sep: &str, opts: &[&str]) -> anyhow::Result<()> {
+    let reveal = match opts {
+        [] => false,
+        ["--reveal"] => true,
+        _ => bail!("--group-by-prefix only supports --reveal"),
+    };
+    print_group(s, &s.group_by(sep), "", sep, reveal);
+    Ok(())
+}
+
+/// Prints the children of `group` as an indented tree, hiding secret values
+/// unless `reveal` is set. The keys of the children start with `prefix`.
+fn print_group(s: &Store<String>, group: &Group<String>, prefix: &str, sep: &str, reveal: bool) {
+    let indent = "  ".repeat(prefix.matches(sep).count());
+    for (segment, child) in &group.children {
+        let key = format!("{prefix}{segment}");
+        match child.value {
+            Some(v) if reveal || !s.is_secret(&key) => println!("{indent}{segment}: {v}"),
+            Some(_) => println!("{indent}{segment}: {}", Redacted::<String>::Hidden),
+            None => println!("{indent}{segment}"),
+        }
+        print_group(s, child, &format!("{key}{sep}"), sep, reveal);
+    }
+}
+
+/// Parses the options for [`list`], returning whether to reveal secret
+/// values, the format, and whether to include derived keys.
+fn list_options(opts: &[&str]) -> anyhow::Result<(bool, ListFormat, bool)> {
+    let mut reveal = false;
+    let mut include_derived = false;
+    let mut format = ListFormat::Text;
+    let mut opts = opts.iter();
+    while let Some(opt) = opts.next() {
+        match *opt {
+            "--reveal" => reveal = true,
+            "--include-derived" => include_derived = true,
+            "-0" => format = ListFormat::Nul,
+            "--format" => match opts.next() {
+                Some(&"text") => format = ListFormat::Text,
+                Some(&"tsv") => format = ListFormat::Tsv,
+                Some(other) => bail!("unknown list format {other:?}"),
+                None => bail!("--format needs a value (text or tsv)"),
+            },
+            other => bail!("unknown list option {other:?}"),
+        }
+    }
+    Ok((reveal, format, include_derived))
+}
+
+/// Prints a key-value pair from `s` for [`list`], hiding the value if the
+/// key is secret, unless `reveal` is set.
+fn print_pair(s: &Store<String>, k: &str, v: &String, reveal: bool, format: ListFormat) {
+    let v = if reveal || !s.is_secret(k) {
+        Redacted::Visible(v)
+    } else {
+        Redacted::Hidden
+    };
+    print_redacted(k, &v, format);
+}
+
+/// Prints the encrypted keys in `s` for [`list`], with their values hidden.
+fn print_encrypted(s: &Store<String>, format: ListFormat) {
+    for k in s.encrypted_keys() {
+        print_redacted(k, &Redacted::Hidden, format);
+    }
+}
+
+fn print_redacted(k: &str, v: &Redacted<String>, format: ListFormat) {
+    match format {
+        ListFormat::Text => println!("{k}: {v}"),
+        ListFormat::Tsv => println!("{}\t{}", escape_tsv(k), escape_tsv(&v.to_string())),
+        ListFormat::Nul => print!("{k}\0{v}\0"),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum ListFormat {
+    Text,
+    Tsv,
+    Nul,
+}
+
+/// Escapes `field` for a TSV file, so that it can't contain a tab or line
+/// break.
+fn escape_tsv(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
         }
-        _ => {
-            println!("{USAGE}");
+    }
+    escaped
+}
+
+/// Sets keys from the document at `path`, whose format is given by its
+/// extension, or from a SQLite database with the extension `.db`,
+/// `.sqlite`, or `.sqlite3` (see [`Store::import_sqlite`]). With `flatten`,
+/// nested values are stored under dotted keys (see
+/// [`Store::insert_flattened`]). Otherwise, each top-level value is stored
+/// under its own key, as JSON text unless it's a string.
+fn import(s: &mut Store<String>, path: &str, flatten: bool) -> anyhow::Result<()> {
+    let extension = Path::new(path).extension().and_then(|ext| ext.to_str());
+    if let Some("db" | "sqlite" | "sqlite3") = extension {
+        if flatten {
+            bail!("--flatten can't be used with a SQLite database");
         }
+        return import_sqlite(s, path);
+    }
+    let format = Path::new(path)
+        .extension()
+        .and_then(|ext| DocFormat::parse(&ext.to_string_lossy()).ok())
+        .ok_or_else(|| anyhow!("can't tell format of {path} (use .json, .yaml, or .toml)"))?;
+    let text = fs::read_to_string(path).with_context(|| format!("reading {path}"))?;
+    let doc: serde_json::Value = match format {
+        DocFormat::Json => serde_json::from_str(&text)?,
+        DocFormat::Yaml => serde_yaml::from_str(&text)?,
+        DocFormat::Toml => toml::from_str(&text)?,
+    };
+    let serde_json::Value::Object(doc) = doc else {
+        bail!("{path} doesn't contain a map of keys to values");
+    };
+    let progress = Progress::new();
+    if flatten {
+        s.insert_flattened_with_progress(&doc, &progress)
+            .map_err(force_hint)?;
+        return Ok(());
     }
+    progress.start(doc.len() as u64);
+    let result = doc.into_iter().try_for_each(|(key, value)| {
+        let value = match value {
+            serde_json::Value::String(value) => value,
+            value => value.to_string(),
+        };
+        s.try_insert(key, value)?;
+        progress.advance(1);
+        Ok(())
+    });
+    progress.finish();
+    result.map_err(force_hint)
+}
+
+/// Replaces the keys in `s` with those from the file at `path`: sets them,
+/// as [`import`] does, then deletes every other key, except protected ones.
+fn import_overwrite(s: &mut Store<String>, path: &str) -> anyhow::Result<()> {
+    let versions: Vec<_> = s.keys().map(|k| (k.clone(), s.version(k))).collect();
+    import(s, path, false)?;
+    // Setting a key changes its version, even if its value is unchanged, so
+    // the keys whose versions are unchanged weren't in the file.
+    for (key, version) in versions {
+        if s.version(&key) == version && !s.is_protected(&key) {
+            s.remove(&key)?;
+        }
+    }
+    Ok(())
+}
+
+/// Sets keys from the SQLite database at `path`, as written by
+/// [`export_sqlite`].
+#[cfg(feature = "sqlite")]
+fn import_sqlite(s: &mut Store<String>, path: &str) -> anyhow::Result<()> {
+    s.import_sqlite(path)
+        .map_err(force_hint)
+        .with_context(|| format!("importing {path}"))?;
     Ok(())
 }
+
+#[cfg(not(feature = "sqlite"))]
+fn import_sqlite(_s: &mut Store<String>, path: &str) -> anyhow::Result<()> {
+    bail!("can't import {path}: rskey was built without the sqlite feature")
+}
+
+/// Writes all key-value pairs to a table in the SQLite database at `path`.
+#[cfg(feature = "sqlite")]
+fn export_sqlite(s: &Store<String>, path: &str) -> anyhow::Result<()> {
+    s.export_sqlite(path)
+        .with_context(|| format!("exporting to {path}"))?;
+    Ok(())
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn export_sqlite(_s: &Store<String>, path: &str) -> anyhow::Result<()> {
+    bail!("can't export to {path}: rskey was built without the sqlite feature")
+}
+
+/// Writes all key-value pairs to a Parquet file at `path`.
+#[cfg(feature = "parquet")]
+fn export_parquet(s: &Store<String>, path: &str) -> anyhow::Result<()> {
+    s.export_parquet(path)
+        .with_context(|| format!("exporting to {path}"))?;
+    Ok(())
+}
+
+#[cfg(not(feature = "parquet"))]
+fn export_parquet(_s: &Store<String>, path: &str) -> anyhow::Result<()> {
+    bail!("can't export to {path}: rskey was built without the parquet feature")
+}
+
+/// A server that keys can be copied to or from, with `--from-NAME` or
+/// `--to-NAME`.
+#[derive(Clone, Copy)]
+enum Remote {
+    Redis,
+    Consul,
+    Etcd,
+}
+
+/// The signature of [`Store::import_redis`] and the like.
+type ImportFn = fn(&mut Store<String>, &str, &str) -> Result<usize, StoreError>;
+
+/// The signature of [`Store::export_redis`] and the like.
+type ExportFn = fn(&Store<String>, &str, &str) -> Result<usize, StoreError>;
+
+impl Remote {
+    /// Returns the server named by `flag`, such as `--from-redis`.
+    fn from_flag(flag: &str) -> Self {
+        match flag.rsplit('-').next() {
+            Some("redis") => Self::Redis,
+            Some("consul") => Self::Consul,
+            _ => Self::Etcd,
+        }
+    }
+
+    /// Returns the keys to copy given by `opts`: all of them, by default, or
+    /// those matching a glob pattern for Redis, or starting with a prefix
+    /// otherwise.
+    fn selection<'a>(self, opts: &[&'a str]) -> anyhow::Result<&'a str> {
+        let (flag, all) = match self {
+            Self::Redis => ("--pattern", "*"),
+            Self::Consul | Self::Etcd => ("--prefix", ""),
+        };
+        match opts {
+            [] => Ok(all),
+            [opt, keys] if *opt == flag => Ok(keys),
+            _ => bail!(
+                "unknown options {opts:?} (use {flag} {})",
+                &flag[2..].to_uppercase()
+            ),
+        }
+    }
+
+    /// Returns the error for a build without the feature needed to copy
+    /// keys to or from this server.
+    fn missing_feature(self) -> anyhow::Error {
+        let feature = match self {
+            Self::Redis => "redis",
+            Self::Consul | Self::Etcd => "http",
+        };
+        anyhow!("rskey was built without the {feature} feature")
+    }
+
+    /// Returns the store method that copies keys from this server, or
+    /// `None` if rskey was built without the feature it needs.
+    fn importer(self) -> Option<ImportFn> {
+        match self {
+            #[cfg(feature = "redis")]
+            Self::Redis => Some(Store::import_redis),
+            #[cfg(feature = "http")]
+            Self::Consul => Some(Store::import_consul),
+            #[cfg(feature = "http")]
+            Self::Etcd => Some(Store::import_etcd),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
+    /// Returns the store method that copies keys to this server, or
+    /// `None` if rskey was built without the feature it needs.
+    fn exporter(self) -> Option<ExportFn> {
+        match self {
+            #[cfg(feature = "redis")]
+            Self::Redis => Some(Store::export_redis),
+            #[cfg(feature = "http")]
+            Self::Consul => Some(Store::export_consul),
+            #[cfg(feature = "http")]
+            Self::Etcd => Some(Store::export_etcd),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+}
+
+/// Copies the keys selected by `opts` from the server at `url`.
+fn import_remote(
+    s: &mut Store<String>,
+    remote: Remote,
+    url: &str,
+    opts: &[&str],
+) -> anyhow::Result<()> {
+    let keys = remote.selection(opts)?;
+    let import = remote.importer().ok_or_else(|| remote.missing_feature())?;
+    import(s, url, keys)
+        .map_err(force_hint)
+        .with_context(|| format!("importing from {url}"))?;
+    Ok(())
+}
+
+/// Copies the entries selected by `opts` to the server at `url`.
+fn export_remote(
+    s: &Store<String>,
+    remote: Remote,
+    url: &str,
+    opts: &[&str],
+) -> anyhow::Result<()> {
+    let keys = remote.selection(opts)?;
+    let export = remote.exporter().ok_or_else(|| remote.missing_feature())?;
+    export(s, url, keys).with_context(|| format!("exporting to {url}"))?;
+    Ok(())
+}
+
+/// A progress bar on standard error, with the rate and estimated time
+/// remaining. It's only drawn if standard error is a terminal.
+struct Progress(ProgressBar);
+
+impl Progress {
+    fn new() -> Self {
+        let style = ProgressStyle::with_template("{wide_bar} {pos}/{len} ({per_sec}, {eta} left)")
+            .expect("template should be valid");
+        Self(ProgressBar::new(0).with_style(style))
+    }
+}
+
+impl ProgressSink for Progress {
+    fn start(&self, total: u64) {
+        self.0.set_length(total);
+    }
+
+    fn advance(&self, n: u64) {
+        self.0.inc(n);
+    }
+
+    fn finish(&self) {
+        self.0.finish_and_clear();
+    }
+}
+
+/// Prints all key-value pairs as a document, in the format selected by
+/// `opts` (JSON by default), or writes it to the file given after the
+/// options. With `--unflatten`, dotted keys become nested values (see
+/// [`Store::to_nested`]), and with `--prefix`, only keys starting with the
+/// given prefix are included (see [`Store::export_matching`]).
+///
+/// With `--format markdown` or `--format html`, the pairs are printed as a
+/// table instead (see [`print_table`]). With `--format sqlite PATH` or
+/// `--format parquet PATH`, they're written to a SQLite database or a
+/// Parquet file (see [`Store::export_sqlite`] and [`Store::export_parquet`]).
+fn export(s: &Store<String>, opts: &[&str]) -> anyhow::Result<()> {
+    let mut unflatten = false;
+    let mut reveal = false;
+    let mut format = DocFormat::Json;
+    let mut table = None;
+    let mut file = None;
+    let mut prefix = None;
+    let mut output = None;
+    let mut opts = opts.iter();
+    while let Some(opt) = opts.next() {
+        match *opt {
+            "--unflatten" => unflatten = true,
+            "--reveal" => reveal = true,
+            "--prefix" => match opts.next() {
+                Some(p) => prefix = Some(*p),
+                None => bail!("--prefix needs a value"),
+            },
+            "--format" => match opts.next() {
+                Some(&"markdown") => table = Some(TableFormat::Markdown),
+                Some(&"html") => table = Some(TableFormat::Html),
+                Some(&name @ ("sqlite" | "parquet")) => match opts.next() {
+                    Some(path) => file = Some((name, *path)),
+                    None => bail!("--format {name} needs the path of the file to write"),
+                },
+                Some(name) => format = DocFormat::parse(name)?,
+                None => {
+                    bail!("--format needs a value (json, yaml, toml, markdown, html, or sqlite)")
+                }
+            },
+            other if !other.starts_with('-') && output.is_none() => output = Some(other),
+            other => bail!("unknown export option {other:?}"),
+        }
+    }
+    if prefix.is_some() && (unflatten || table.is_some() || file.is_some()) {
+        bail!("--prefix can only be used with --format json, yaml, or toml");
+    }
+    if let Some((name, path)) = file {
+        if unflatten {
+            bail!("--unflatten can't be used with --format {name}");
+        }
+        if let Some(output) = output {
+            bail!("unknown export option {output:?}");
+        }
+        return match name {
+            "sqlite" => export_sqlite(s, path),
+            _ => export_parquet(s, path),
+        };
+    }
+    if let Some(table) = table {
+        if unflatten {
+            bail!("--unflatten can't be used with a table format");
+        }
+        if output.is_some() {
+            bail!("a table can only be printed, not written to a file");
+        }
+        print_table(s, table, reveal);
+        return Ok(());
+    }
+    let mut writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).with_context(|| format!("creating {path}"))?,
+        )),
+        None => Box::new(io::stdout().lock()),
+    };
+    if unflatten {
+        let doc = s.to_nested()?;
+        match format {
+            DocFormat::Json => writeln!(writer, "{}", serde_json::to_string_pretty(&doc)?)?,
+            DocFormat::Yaml => write!(writer, "{}", serde_yaml::to_string(&doc)?)?,
+            DocFormat::Toml => write!(writer, "{}", toml::to_string(&doc)?)?,
+        }
+        writer.flush()?;
+    } else {
+        let prefix = prefix.unwrap_or_default();
+        s.export_matching(|k| k.starts_with(prefix), writer, format.export_format())?;
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
+enum DocFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl DocFormat {
+    fn parse(name: &str) -> anyhow::Result<Self> {
+        match name {
+            "json" => Ok(Self::Json),
+            "yaml" | "yml" => Ok(Self::Yaml),
+            "toml" => Ok(Self::Toml),
+            other => bail!("unknown document format {other:?}"),
+        }
+    }
+
+    fn export_format(self) -> ExportFormat {
+        match self {
+            Self::Json => ExportFormat::Json,
+            Self::Yaml => ExportFormat::Yaml,
+            Self::Toml => ExportFormat::Toml,
+        }
+    }
+}
+
+/// Prints all key-value pairs as a table, sorted by key, hiding secret
+/// values unless `reveal` is set.
+///
+/// Columns showing whether each key is protected or secret, and when it
+/// expires, are included only if some key has that metadata.
+fn print_table(s: &Store<String>, format: TableFormat, reveal: bool) {
+    let keys = sorted(s.keys().map(String::as_str));
+    let protected = keys.iter().any(|k| s.is_protected(k));
+    let secret = keys.iter().any(|k| s.is_secret(k));
+    let expires = keys.iter().any(|k| s.ttl(k).is_some());
+    let mut header = vec!["Key", "Value"];
+    if protected {
+        header.push("Protected");
+    }
+    if secret {
+        header.push("Secret");
+    }
+    if expires {
+        header.push("Expires in");
+    }
+    let flag = |set: bool| if set { "yes" } else { "" }.to_string();
+    let rows = keys.iter().map(|k| {
+        let value = if reveal {
+            Redacted::Visible(&s[*k])
+        } else {
+            s.get_redacted(k).expect("key should be present")
+        };
+        let mut row = vec![(*k).to_string(), value.to_string()];
+        if protected {
+            row.push(flag(s.is_protected(k)));
+        }
+        if secret {
+            row.push(flag(s.is_secret(k)));
+        }
+        if expires {
+            row.push(
+                s.ttl(k)
+                    .map(|t| format!("{}s", t.as_secs()))
+                    .unwrap_or_default(),
+            );
+        }
+        row
+    });
+    match format {
+        TableFormat::Markdown => {
+            println!("| {} |", header.join(" | "));
+            println!("|{}", " --- |".repeat(header.len()));
+            for row in rows {
+                let row: Vec<_> = row.iter().map(|cell| escape_markdown(cell)).collect();
+                println!("| {} |", row.join(" | "));
+            }
+        }
+        TableFormat::Html => {
+            println!("<table>");
+            println!("<tr><th>{}</th></tr>", header.join("</th><th>"));
+            for row in rows {
+                let row: Vec<_> = row.iter().map(|cell| escape_html(cell)).collect();
+                println!("<tr><td>{}</td></tr>", row.join("</td><td>"));
+            }
+            println!("</table>");
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum TableFormat {
+    Markdown,
+    Html,
+}
+
+/// Escapes `cell` for a Markdown table, so that it can't contain a `|` or
+/// line break.
+fn escape_markdown(cell: &str) -> String {
+    cell.replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace('\n', "<br>")
+}
+
+/// Escapes `cell` for HTML.
+fn escape_html(cell: &str) -> String {
+    cell.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Parses a duration such as `90s`, `15m`, `1h`, or `7d`. A number with no
+/// unit is taken as seconds.
+fn parse_duration(text: &str) -> anyhow::Result<Duration> {
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("invalid duration {text:?}"))?;
+    let scale = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => bail!("invalid duration {text:?} (use a unit of s, m, h, or d)"),
+    };
+    Ok(Duration::from_secs(number.saturating_mul(scale)))
+}
+
+/// Formats `age` in the largest whole unit accepted by [`parse_duration`].
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+/// Returns `keys` in sorted order, so that output is predictable.
+fn sorted<'a>(keys: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut keys: Vec<_> = keys.collect();
+    keys.sort_unstable();
+    keys
+}
+
+/// Returns how long to wait for another process to release the store's
+/// lock, from `RSKEY_LOCK_TIMEOUT`, or 10 seconds by default.
+fn lock_timeout() -> anyhow::Result<Duration> {
+    match env::var_os("RSKEY_LOCK_TIMEOUT") {
+        Some(text) => parse_duration(&text.to_string_lossy()).context("in RSKEY_LOCK_TIMEOUT"),
+        None => Ok(Duration::from_secs(10)),
+    }
+}
+
+/// Returns the number given by the environment variable `name`, if it's set.
+fn env_count(name: &str) -> anyhow::Result<Option<usize>> {
+    env::var_os(name)
+        .map(|text| parse_count(&text.to_string_lossy()).with_context(|| format!("in {name}")))
+        .transpose()
+}
+
+/// Returns the key to encrypt and decrypt secret values with.
+fn encryption_key() -> anyhow::Result<EncryptionKey> {
+    encryption_key_from("RSKEY_ENCRYPTION_KEY")?.ok_or_else(|| {
+        anyhow!("set RSKEY_ENCRYPTION_KEY (or RSKEY_ENCRYPTION_KEY_FILE) to use encrypted values")
+    })
+}
+
+/// Returns the encryption key given by the environment variable `var` (a
+/// passphrase), or `var` with `_FILE` appended (a key file), if either is
+/// set.
+fn encryption_key_from(var: &str) -> anyhow::Result<Option<EncryptionKey>> {
+    if let Some(passphrase) = env::var_os(var) {
+        let passphrase = passphrase
+            .into_string()
+            .map_err(|_| anyhow!("{var} is not valid UTF-8"))?;
+        return Ok(Some(EncryptionKey::from_passphrase(&passphrase)));
+    }
+    if let Some(path) = env::var_os(format!("{var}_FILE")) {
+        let key = EncryptionKey::from_file(&path)
+            .with_context(|| format!("reading key file {}", path.to_string_lossy()))?;
+        return Ok(Some(key));
+    }
+    Ok(None)
+}
+
+/// Returns the key to sign the data file with, if one is configured.
+fn signing_key() -> anyhow::Result<Option<SigningKey>> {
+    signing_key_from("RSKEY_SIGNING_KEY")
+}
+
+/// Returns the key given by the environment variable `var` (a passphrase),
+/// or `var` with `_FILE` (a key file) or `_KEYRING` (a keyring account)
+/// appended, if any of them is set.
+fn signing_key_from(var: &str) -> anyhow::Result<Option<SigningKey>> {
+    if let Some(passphrase) = env::var_os(var) {
+        let passphrase = passphrase
+            .into_string()
+            .map_err(|_| anyhow!("{var} is not valid UTF-8"))?;
+        return Ok(Some(SigningKey::from_passphrase(&passphrase)));
+    }
+    if let Some(path) = env::var_os(format!("{var}_FILE")) {
+        let key = SigningKey::from_file(&path)
+            .with_context(|| format!("reading key file {}", path.to_string_lossy()))?;
+        return Ok(Some(key));
+    }
+    if let Some(user) = env::var_os(format!("{var}_KEYRING")) {
+        return keyring_key(var, &user.to_string_lossy()).map(Some);
+    }
+    Ok(None)
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_key(_var: &str, user: &str) -> anyhow::Result<SigningKey> {
+    SigningKey::from_keyring("rskey", user)
+        .with_context(|| format!("reading signing key for {user:?} from keyring"))
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_key(var: &str, _user: &str) -> anyhow::Result<SigningKey> {
+    anyhow::bail!("{var}_KEYRING is set, but rskey was built without the keyring feature")
+}
@@ -0,0 +1,255 @@
+//! Treating different spellings of a key as the same key.
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashSet;
+#[cfg(feature = "unicode")]
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{Store, StoreError};
+
+/// How a store normalizes its keys, as set by
+/// [`Store::set_key_normalization()`].
+///
+/// By default, keys are used exactly as given.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct KeyNormalization {
+    /// Converts keys to lowercase, so that `Token` and `token` are the same
+    /// key.
+    #[serde(default)]
+    pub case_fold: bool,
+    /// Converts keys to Unicode Normalization Form C, so that a precomposed
+    /// `é` and an `e` followed by a combining accent are the same key.
+    ///
+    /// Requires the `unicode` feature. Without it, this is ignored when a
+    /// store that uses it is opened, and [`Store::set_key_normalization()`]
+    /// refuses to set it.
+    #[serde(default)]
+    pub nfc: bool,
+}
+
+impl KeyNormalization {
+    /// Returns `true` if keys are used exactly as given.
+    #[must_use]
+    pub fn is_none(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns the normalized form of `key`.
+    #[must_use]
+    pub fn apply<'k>(&self, key: &'k str) -> Cow<'k, str> {
+        let mut normalized = Cow::Borrowed(key);
+        if self.case_fold && normalized.chars().any(char::is_uppercase) {
+            normalized = Cow::Owned(normalized.to_lowercase());
+        }
+        // Lowercasing can produce combining characters, so compose them
+        // afterwards.
+        #[cfg(feature = "unicode")]
+        if self.nfc && !unicode_normalization::is_nfc(&normalized) {
+            normalized = Cow::Owned(normalized.nfc().collect());
+        }
+        normalized
+    }
+}
+
+impl<V> Store<V> {
+    /// Returns how the store normalizes its keys.
+    #[must_use]
+    pub fn key_normalization(&self) -> KeyNormalization {
+        self.meta.normalize
+    }
+
+    /// Sets how the store normalizes its keys, and normalizes the existing
+    /// ones. The setting is persisted with the store.
+    ///
+    /// Keys are normalized by [`Self::insert()`], [`Self::try_insert()`],
+    /// [`Self::force_insert()`], [`Self::remove()`], [`Self::force_remove()`],
+    /// [`Self::entry()`], [`Self::get_or_insert_with()`], [`Self::replace()`],
+    /// [`Self::swap()`], [`Self::lookup()`], [`Self::get_redacted()`],
+    /// [`Self::alias()`], [`Self::scratch()`], and the methods for protecting
+    /// keys and for expiring them. The methods of the underlying `HashMap`,
+    /// such as `get`, use keys exactly as given.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// use rskey::{KeyNormalization, Store};
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.set_key_normalization(KeyNormalization {
+    ///     case_fold: true,
+    ///     nfc: false,
+    /// })?;
+    /// s.try_insert("Token".to_string(), "abc123".to_string())?;
+    /// assert_eq!(s.lookup("TOKEN").unwrap(), "abc123");
+    /// assert_eq!(s.len(), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::KeyCollision`] if two existing keys have the
+    /// same normalized form, or [`StoreError::Io`] if NFC normalization is
+    /// requested without the `unicode` feature. The store is unchanged.
+    pub fn set_key_normalization(
+        &mut self,
+        normalization: KeyNormalization,
+    ) -> Result<(), StoreError> {
+        if normalization == self.meta.normalize {
+            return Ok(());
+        }
+        if normalization.nfc && !cfg!(feature = "unicode") {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "NFC key normalization requires the `unicode` feature",
+            )
+            .into());
+        }
+        let mut seen = HashSet::with_capacity(self.inner.len());
+        for key in self.inner.keys().chain(self.meta.encrypted.keys()) {
+            let normalized = normalization.apply(key);
+            if seen.contains(&normalized) {
+                return Err(StoreError::KeyCollision(normalized.into_owned()));
+            }
+            seen.insert(normalized);
+        }
+        let apply = |key: String| normalization.apply(&key).into_owned();
+        self.inner = std::mem::take(&mut self.inner)
+            .into_iter()
+            .map(|(k, v)| (apply(k), v))
+            .collect();
+        self.rebuild_key_index();
+        let meta = &mut self.meta;
+        meta.protected = std::mem::take(&mut meta.protected)
+            .into_iter()
+            .map(apply)
+            .collect();
+        meta.secret = std::mem::take(&mut meta.secret)
+            .into_iter()
+            .map(apply)
+            .collect();
+        meta.expires = std::mem::take(&mut meta.expires)
+            .into_iter()
+            .map(|(k, v)| (apply(k), v))
+            .collect();
+        meta.scratch = std::mem::take(&mut meta.scratch)
+            .into_iter()
+            .map(|(k, v)| (apply(k), v))
+            .collect();
+        meta.versions = std::mem::take(&mut meta.versions)
+            .into_iter()
+            .map(|(k, v)| (apply(k), v))
+            .collect();
+        meta.encrypted = std::mem::take(&mut meta.encrypted)
+            .into_iter()
+            .map(|(k, v)| (apply(k), v))
+            .collect();
+        meta.aliases = std::mem::take(&mut meta.aliases)
+            .into_iter()
+            .map(|(k, v)| (apply(k), apply(v)))
+            .collect();
+        meta.schemas = std::mem::take(&mut meta.schemas)
+            .into_iter()
+            .map(|(k, v)| (apply(k), v))
+            .collect();
+        meta.normalize = normalization;
+        self.touch();
+        Ok(())
+    }
+
+    /// Returns the normalized form of `key`, according to the store's
+    /// setting.
+    pub(crate) fn normalize<'k>(&self, key: &'k str) -> Cow<'k, str> {
+        self.meta.normalize.apply(key)
+    }
+
+    /// Returns the normalized form of `key`, reusing it if it's unchanged.
+    pub(crate) fn normalize_owned(&self, key: String) -> String {
+        let normalized = match self.normalize(&key) {
+            Cow::Owned(normalized) => Some(normalized),
+            Cow::Borrowed(_) => None,
+        };
+        normalized.unwrap_or(key)
+    }
+}
+
+#[cfg(all(test, feature = "unicode"))]
+mod tests {
+    use super::*;
+    use crate::Redacted;
+    use std::path::PathBuf;
+
+    const BOTH: KeyNormalization = KeyNormalization {
+        case_fold: true,
+        nfc: true,
+    };
+
+    #[test]
+    fn normalized_keys_refer_to_same_entry() {
+        let mut s = Store::<usize>::new(PathBuf::from("unused.kv"));
+        s.set_key_normalization(BOTH).unwrap();
+        s.try_insert("Token".to_string(), 1).unwrap();
+        s.try_insert("token".to_string(), 2).unwrap();
+        s.try_insert("toke\u{301}n".to_string(), 3).unwrap();
+        s.try_insert("TOKÉN".to_string(), 4).unwrap();
+        assert_eq!(2, s.len(), "wrong number of entries");
+        assert_eq!(Some(&2), s.lookup("TOKEN"), "expected data not returned");
+        assert_eq!(Some(&4), s.get("tokén"), "expected data not returned");
+        s.protect("TOKEN");
+        assert!(s.remove("token").is_err(), "protected key removed");
+        assert_eq!(Some(4), s.remove("Toke\u{301}n").unwrap(), "wrong value");
+    }
+
+    #[test]
+    fn read_modify_methods_normalize_keys() {
+        let mut s = Store::<usize>::new(PathBuf::from("unused.kv"));
+        s.set_key_normalization(BOTH).unwrap();
+        s.inner.insert("theme".to_string(), 1);
+        s.inner.insert("port".to_string(), 2);
+        assert_eq!(&1, s.get_or_insert_with("Theme", || 9).unwrap());
+        assert_eq!(Some(1), s.replace("THEME", 3).unwrap(), "wrong old value");
+        s.swap("Theme", "PORT").unwrap();
+        assert_eq!(Some(&2), s.get("theme"), "values not swapped");
+        s.mark_secret("port");
+        assert_eq!(Some(Redacted::Hidden), s.get_redacted("Port"));
+        assert_eq!(2, s.len(), "wrong number of entries");
+    }
+
+    #[test]
+    fn scratch_entries_are_recorded_by_normalized_key() {
+        let mut s = Store::<usize>::new(PathBuf::from("unused.kv"));
+        s.set_key_normalization(BOTH).unwrap();
+        s.scratch().insert("Tmp".to_string(), 1).unwrap();
+        assert!(s.is_scratch("tmp"), "scratch entry not found");
+        assert!(s.is_scratch("TMP"), "lookup not normalized");
+        s.try_insert("tmp".to_string(), 2).unwrap();
+        assert!(!s.is_scratch("tmp"), "permanent value still scratch");
+    }
+
+    #[test]
+    fn set_key_normalization_renames_keys_or_rejects_collisions() {
+        let entries = [("Host", 1), ("PORT", 2)].map(|(k, v)| (k.to_string(), v));
+        let mut s = Store::from_entries("unused.kv", entries);
+        s.protect("PORT");
+        s.set_key_normalization(BOTH).unwrap();
+        assert!(s.is_dirty(), "store not marked dirty");
+        assert_eq!(Some(&1), s.get("host"), "key not normalized");
+        assert!(s.is_protected("port"), "protection lost");
+
+        let entries = [("Host", 1), ("host", 2)].map(|(k, v)| (k.to_string(), v));
+        let mut s = Store::from_entries("unused.kv", entries);
+        assert!(
+            matches!(
+                s.set_key_normalization(BOTH),
+                Err(StoreError::KeyCollision(k)) if k == "host"
+            ),
+            "collision not detected"
+        );
+        assert_eq!(2, s.len(), "store changed");
+        assert!(s.key_normalization().is_none(), "setting changed");
+    }
+}
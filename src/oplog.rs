@@ -0,0 +1,267 @@
+//! Recording each change to a store in a log of operations.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader};
+use std::path::PathBuf;
+
+use crate::format::Contents;
+use crate::{sign, Store, StoreError};
+
+/// A change to a single key, as recorded in a store's operations log; see
+/// [`Store::set_ops_log()`].
+///
+/// Each operation is serialized as a JSON object, such as
+/// `{"seq":3,"op":"set","key":"key1","value":"value1"}`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Op<V> {
+    /// `key` was set to `value`.
+    Set {
+        /// The generation of the sync that recorded the operation.
+        seq: u64,
+        /// The key that was set.
+        key: String,
+        /// The key's new value.
+        value: V,
+    },
+    /// `key` was deleted.
+    Delete {
+        /// The generation of the sync that recorded the operation.
+        seq: u64,
+        /// The key that was deleted.
+        key: String,
+    },
+}
+
+impl<V> Op<V> {
+    /// Returns the generation of the sync that recorded the operation.
+    #[must_use]
+    pub fn seq(&self) -> u64 {
+        match self {
+            Op::Set { seq, .. } | Op::Delete { seq, .. } => *seq,
+        }
+    }
+}
+
+/// The keys that differ between a store and its data file.
+#[derive(Debug, Default)]
+pub(crate) struct Changes {
+    /// Keys that were added or changed, in order.
+    pub(crate) set: Vec<String>,
+    /// Keys that were deleted, in order.
+    pub(crate) deleted: Vec<String>,
+    /// Whether the metadata differs.
+    pub(crate) meta_changed: bool,
+}
+
+impl<V> Store<V> {
+    /// Returns `true` if changes are recorded in the operations log; see
+    /// [`Self::set_ops_log()`].
+    #[must_use]
+    pub fn ops_log(&self) -> bool {
+        self.ops_log
+    }
+
+    /// Sets whether to record each key that is set or deleted in an
+    /// operations log when the store is synced.
+    ///
+    /// The log is kept in a file named after the data file, with `.ops`
+    /// appended, with one [`Op`] per line. Each operation's sequence
+    /// number is the generation of the sync that recorded it, so
+    /// [`Self::ops_since()`] can return just the operations since a given
+    /// sync, to replay on another store with [`Self::apply_ops()`].
+    pub fn set_ops_log(&mut self, enabled: bool) {
+        self.ops_log = enabled;
+    }
+
+    /// Returns the path of the operations log.
+    pub(crate) fn ops_path(&self) -> PathBuf {
+        let mut path = self.path.as_os_str().to_owned();
+        path.push(".ops");
+        path.into()
+    }
+
+    /// Returns the keys that differ between the store and its data file.
+    pub(crate) fn changes(&self) -> io::Result<Changes>
+    where
+        V: Serialize,
+    {
+        let (old, old_meta) = match self.backend.read(&self.path)? {
+            Some(file) => {
+                let (doc, _) = sign::split(&file);
+                let contents = serde_json::from_slice::<Contents<Value>>(doc)?.decompress()?;
+                (contents.data, Some(contents.meta))
+            }
+            None => (HashMap::new(), None),
+        };
+        let mut changes = Changes::default();
+        for (key, value) in &self.inner {
+            if old.get(key) != Some(&serde_json::to_value(value)?) {
+                changes.set.push(key.clone());
+            }
+        }
+        changes.deleted = old
+            .into_keys()
+            .filter(|key| !self.inner.contains_key(key))
+            .collect();
+        changes.set.sort_unstable();
+        changes.deleted.sort_unstable();
+        changes.meta_changed = old_meta.as_ref() != Some(&self.meta);
+        Ok(changes)
+    }
+
+    /// Appends `changes` to the operations log, as of `generation`.
+    pub(crate) fn append_ops(&self, generation: u64, changes: &Changes) -> io::Result<()>
+    where
+        V: Serialize,
+    {
+        if changes.set.is_empty() && changes.deleted.is_empty() {
+            return Ok(());
+        }
+        let mut buf = Vec::new();
+        for key in &changes.set {
+            let op = Op::Set {
+                seq: generation,
+                key: key.clone(),
+                value: &self.inner[key],
+            };
+            serde_json::to_writer(&mut buf, &op)?;
+            buf.push(b'\n');
+        }
+        for key in &changes.deleted {
+            let op = Op::<&V>::Delete {
+                seq: generation,
+                key: key.clone(),
+            };
+            serde_json::to_writer(&mut buf, &op)?;
+            buf.push(b'\n');
+        }
+        self.backend.append(&self.ops_path(), &buf)
+    }
+
+    /// Returns the operations recorded in the log with a sequence number
+    /// greater than `seq`, oldest first. Pass 0 to get every operation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// use rskey::{Op, Store};
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::builder(path).ops_log(true).open()?;
+    /// s.try_insert("key1".to_string(), "value1".to_string())?;
+    /// s.sync()?;
+    /// s.remove("key1")?;
+    /// s.sync()?;
+    /// let ops = s.ops_since(1)?;
+    /// assert_eq!(ops, [Op::Delete { seq: 2, key: "key1".to_string() }]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns any error reading the log, or if it contains invalid JSON.
+    pub fn ops_since(&self, seq: u64) -> io::Result<Vec<Op<V>>>
+    where
+        V: DeserializeOwned,
+    {
+        let Some(file) = self.backend.open(&self.ops_path())? else {
+            return Ok(Vec::new());
+        };
+        let mut ops = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let op: Op<V> = serde_json::from_str(&line)?;
+            if op.seq() > seq {
+                ops.push(op);
+            }
+        }
+        Ok(ops)
+    }
+
+    /// Applies `ops` to the store in order, as though each key had been set
+    /// with [`Self::insert()`] or deleted with [`Self::remove()`], and
+    /// returns the number applied.
+    ///
+    /// This doesn't sync the store.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from inserting or removing a key, such as
+    /// [`StoreError::Protected`]. The operations before it have been
+    /// applied.
+    pub fn apply_ops(&mut self, ops: impl IntoIterator<Item = Op<V>>) -> Result<usize, StoreError>
+    where
+        V: Serialize,
+    {
+        let mut applied = 0;
+        for op in ops {
+            match op {
+                Op::Set { key, value, .. } => {
+                    self.try_insert(key, value)?;
+                }
+                Op::Delete { key, .. } => {
+                    self.remove(&key)?;
+                }
+            }
+            applied += 1;
+        }
+        Ok(applied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn ops_replayed_onto_another_store_reproduce_its_data() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut src = Store::<u8>::open(tmp_dir.path().join("src.kv")).unwrap();
+        src.set_ops_log(true);
+        src.try_insert("a".to_string(), 1).unwrap();
+        src.try_insert("b".to_string(), 2).unwrap();
+        src.sync().unwrap();
+        src.try_insert("a".to_string(), 3).unwrap();
+        src.remove("b").unwrap();
+        src.sync().unwrap();
+        // Nothing has changed, so nothing is logged.
+        src.sync().unwrap();
+        let ops = src.ops_since(0).unwrap();
+        assert_eq!(4, ops.len(), "wrong number of ops logged: {ops:?}");
+        assert_eq!(2, src.ops_since(1).unwrap().len(), "wrong ops since 1");
+        let mut dst = Store::<u8>::open(tmp_dir.path().join("dst.kv")).unwrap();
+        assert_eq!(4, dst.apply_ops(ops).unwrap(), "wrong number applied");
+        assert_eq!(*src, *dst, "replayed data doesn't match");
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn ops_log_goes_through_backend() {
+        let mock = crate::testing::StoreBackendMock::new();
+        let mut s: Store<u8> = Store::builder("store.kv")
+            .backend(mock.clone())
+            .ops_log(true)
+            .open()
+            .unwrap();
+        s.try_insert("a".to_string(), 1).unwrap();
+        s.sync().unwrap();
+        s.remove("a").unwrap();
+        s.sync().unwrap();
+        assert!(
+            mock.contents("store.kv.ops").is_some(),
+            "log not in backend"
+        );
+        assert_eq!(2, s.ops_since(0).unwrap().len(), "wrong number of ops");
+    }
+}
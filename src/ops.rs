@@ -0,0 +1,152 @@
+//! Operations on string and list values.
+
+use crate::{Store, StoreError};
+use std::ops::{Bound, RangeBounds};
+
+impl Store<String> {
+    /// Appends `suffix` to the value for `key`, inserting it as a new value
+    /// if there is none, and returns the result.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.append_str("log", "started\n")?;
+    /// assert_eq!(s.append_str("log", "stopped\n")?, "started\nstopped\n");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Protected`] if `key` is protected.
+    pub fn append_str(&mut self, key: &str, suffix: &str) -> Result<&String, StoreError> {
+        if self.is_protected(key) {
+            return Err(StoreError::Protected(key.to_string()));
+        }
+        self.touch();
+        self.bump_version(key);
+        self.index_key(key);
+        let value = self.inner.entry(key.to_string()).or_default();
+        value.push_str(suffix);
+        Ok(value)
+    }
+}
+
+impl<T> Store<Vec<T>> {
+    /// Appends `item` to the list for `key`, creating the list if there is
+    /// none, and returns the new length of the list.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<Vec<u32>>::open(path)?;
+    /// s.push("scores", 10)?;
+    /// s.push("scores", 20)?;
+    /// assert_eq!(s.lrange("scores", ..), [10, 20]);
+    /// assert_eq!(s.pop("scores")?, Some(20));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Protected`] if `key` is protected.
+    pub fn push(&mut self, key: &str, item: T) -> Result<usize, StoreError> {
+        if self.is_protected(key) {
+            return Err(StoreError::Protected(key.to_string()));
+        }
+        self.touch();
+        self.bump_version(key);
+        self.index_key(key);
+        let list = self.inner.entry(key.to_string()).or_default();
+        list.push(item);
+        Ok(list.len())
+    }
+
+    /// Removes the last item from the list for `key`, and returns it. The
+    /// list is left in the store, even if it's now empty.
+    ///
+    /// Returns `None` if there is no list for `key`, or it's empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Protected`] if `key` is protected.
+    pub fn pop(&mut self, key: &str) -> Result<Option<T>, StoreError> {
+        if self.is_protected(key) {
+            return Err(StoreError::Protected(key.to_string()));
+        }
+        let Some(list) = self.inner.get_mut(key) else {
+            return Ok(None);
+        };
+        let item = list.pop();
+        if item.is_some() {
+            self.touch();
+            self.bump_version(key);
+        }
+        Ok(item)
+    }
+
+    /// Returns the items of the list for `key` within `range`.
+    ///
+    /// Parts of the range beyond the end of the list are ignored, so the
+    /// result may be shorter than the range, or empty. If there is no list
+    /// for `key`, the result is empty.
+    #[must_use]
+    pub fn lrange(&self, key: &str, range: impl RangeBounds<usize>) -> &[T] {
+        let Some(list) = self.inner.get(key) else {
+            return &[];
+        };
+        let start = match range.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&n) => n.saturating_add(1),
+            Bound::Excluded(&n) => n,
+            Bound::Unbounded => list.len(),
+        };
+        let end = end.min(list.len());
+        list.get(start.min(end)..end).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn list_operations_respect_protection_and_bounds() {
+        let mut s = Store::<Vec<u8>>::new(PathBuf::from("unused.kv"));
+        assert_eq!(None, s.pop("nums").unwrap(), "unexpected data");
+        for n in 1..=5 {
+            s.push("nums", n).unwrap();
+        }
+        assert_eq!([2, 3, 4], s.lrange("nums", 1..4), "wrong range returned");
+        assert_eq!([4, 5], s.lrange("nums", 3..100), "range not clamped");
+        assert!(s.lrange("nums", 7..).is_empty(), "range not clamped");
+        assert!(s.lrange("missing", ..).is_empty(), "unexpected data");
+        s.protect("nums");
+        assert!(
+            matches!(s.push("nums", 6), Err(StoreError::Protected(_))),
+            "protected list changed"
+        );
+        assert!(
+            matches!(s.pop("nums"), Err(StoreError::Protected(_))),
+            "protected list changed"
+        );
+        assert_eq!(5, s.lrange("nums", ..).len(), "protected list changed");
+    }
+}
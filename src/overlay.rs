@@ -0,0 +1,214 @@
+//! Layering stores so that later ones override earlier ones.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io;
+
+use crate::{Store, StoreError};
+
+/// A merged view of several stores, as returned by [`Store::overlay()`].
+///
+/// Reading a key returns its value from the last layer that has it, so each
+/// layer overrides the ones before it. Changes go to the last layer, the
+/// top one; the others are never changed.
+///
+/// This is the usual way of layering configuration: shared settings in a
+/// base store, overridden by settings for a particular environment.
+#[derive(Debug)]
+pub struct Overlay<V> {
+    /// The layers below the top one, bottom first.
+    base: Vec<Store<V>>,
+    top: Store<V>,
+}
+
+impl<V> Store<V> {
+    /// Returns a merged view of `layers`, in which each store overrides the
+    /// ones before it; see [`Overlay`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let base_path = tmp_dir.path().join("base.kv");
+    /// # let prod_path = tmp_dir.path().join("prod.kv");
+    /// let mut base = Store::<String>::open(base_path)?;
+    /// base.try_insert("log_level".to_string(), "debug".to_string())?;
+    /// base.try_insert("port".to_string(), "8080".to_string())?;
+    /// let mut prod = Store::<String>::open(prod_path)?;
+    /// prod.try_insert("log_level".to_string(), "warn".to_string())?;
+    /// let mut config = Store::overlay([base, prod]);
+    /// assert_eq!(config.get("log_level"), Some(&"warn".to_string()));
+    /// assert_eq!(config.get("port"), Some(&"8080".to_string()));
+    /// config.insert("port".to_string(), "80".to_string())?;
+    /// assert_eq!(config.top().get("port"), Some(&"80".to_string()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `layers` is empty.
+    #[must_use]
+    pub fn overlay(layers: impl IntoIterator<Item = Store<V>>) -> Overlay<V> {
+        let mut base: Vec<_> = layers.into_iter().collect();
+        let top = base.pop().expect("overlay needs at least one store");
+        Overlay { base, top }
+    }
+}
+
+impl<V> Overlay<V> {
+    /// Returns the value of `key` in the last layer that has it.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&V> {
+        self.layers()
+            .rev()
+            .find_map(|layer| layer.inner.get(layer.normalize(key).as_ref()))
+    }
+
+    /// Returns `true` if any layer has `key`.
+    #[must_use]
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns every key and its value, in key order, taking each value from
+    /// the last layer that has the key.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &V)> {
+        self.merged_entries().into_iter()
+    }
+
+    /// Returns the number of distinct keys in all the layers.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.merged_entries().len()
+    }
+
+    /// Returns `true` if no layer has any keys.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.layers().all(|layer| layer.inner.is_empty())
+    }
+
+    /// Returns `true` if `key` is secret in any layer; see
+    /// [`Store::is_secret()`].
+    #[must_use]
+    pub fn is_secret(&self, key: &str) -> bool {
+        self.layers().any(|layer| layer.is_secret(key))
+    }
+
+    fn merged_entries(&self) -> BTreeMap<&str, &V> {
+        self.layers()
+            .flat_map(|layer| layer.inner.iter())
+            .map(|(k, v)| (k.as_str(), v))
+            .collect()
+    }
+
+    /// Returns the layers, top last.
+    pub fn layers(&self) -> impl DoubleEndedIterator<Item = &Store<V>> {
+        self.base.iter().chain([&self.top])
+    }
+
+    /// Returns the top layer, to which changes are made.
+    #[must_use]
+    pub fn top(&self) -> &Store<V> {
+        &self.top
+    }
+
+    /// Returns the top layer mutably.
+    pub fn top_mut(&mut self) -> &mut Store<V> {
+        &mut self.top
+    }
+
+    /// Sets `key` to `value` in the top layer, returning its previous value
+    /// there, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from [`Store::try_insert()`] on the top layer.
+    pub fn insert(&mut self, key: String, value: V) -> Result<Option<V>, StoreError>
+    where
+        V: Serialize,
+    {
+        self.top_mut().try_insert(key, value)
+    }
+
+    /// Removes `key` from the top layer, returning its value there, if any.
+    /// If a lower layer has `key`, its value shows through afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from [`Store::remove()`] on the top layer.
+    pub fn remove(&mut self, key: &str) -> Result<Option<V>, StoreError> {
+        self.top_mut().remove(key)
+    }
+
+    /// Writes the top layer to its data file.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from [`Store::sync()`] on the top layer.
+    pub fn sync(&self) -> io::Result<()>
+    where
+        V: DeserializeOwned + Serialize,
+    {
+        self.top().sync()
+    }
+
+    /// Returns a store containing the merged entries, associated with the
+    /// top layer's data file, in which every key that's secret in any layer
+    /// is secret. Syncing it would replace the top layer's data with the
+    /// merged data, so it's meant only for reading.
+    #[must_use]
+    pub fn merged(&self) -> Store<V>
+    where
+        V: Clone,
+    {
+        let entries = self
+            .merged_entries()
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.clone()));
+        let mut store = Store::from_entries(self.top().path(), entries);
+        for layer in self.layers() {
+            store.meta.secret.extend(layer.meta.secret.iter().cloned());
+        }
+        store
+    }
+
+    /// Returns the layers below the top one, bottom first, and the top
+    /// layer.
+    #[must_use]
+    pub fn into_layers(self) -> (Vec<Store<V>>, Store<V>) {
+        (self.base, self.top)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn later_layers_override_earlier_ones() {
+        let base = [("a", 1), ("b", 2)].map(|(k, v)| (k.to_string(), v));
+        let env = [("b", 3), ("c", 4)].map(|(k, v)| (k.to_string(), v));
+        let mut o = Store::overlay([
+            Store::from_entries("base.kv", base),
+            Store::from_entries("env.kv", env),
+        ]);
+        assert_eq!(
+            vec![("a", &1), ("b", &3), ("c", &4)],
+            o.iter().collect::<Vec<_>>(),
+            "wrong merged entries"
+        );
+        assert_eq!(3, o.len(), "wrong length");
+        o.remove("b").unwrap();
+        assert_eq!(Some(&2), o.get("b"), "base value not visible");
+        o.base[0].mark_secret("a");
+        let merged = o.merged();
+        assert!(merged.is_secret("a"), "secret from base layer lost");
+        assert_eq!(o.top().path(), merged.path(), "wrong merged path");
+    }
+}
@@ -0,0 +1,154 @@
+//! Exporting entries to Parquet files, for analysis with tools such as
+//! DuckDB or pandas.
+
+use ::parquet::data_type::{BoolType, ByteArray, ByteArrayType, Int64Type};
+use ::parquet::errors::ParquetError;
+use ::parquet::file::properties::WriterProperties;
+use ::parquet::file::writer::SerializedFileWriter;
+use ::parquet::schema::parser::parse_message_type;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{Store, StoreError};
+
+/// The schema of an exported file, with the same columns as a SQLite
+/// export.
+const SCHEMA: &str = "message entries {
+    REQUIRED BYTE_ARRAY key (UTF8);
+    REQUIRED BYTE_ARRAY value (UTF8);
+    REQUIRED INT64 version;
+    OPTIONAL INT64 expires (TIMESTAMP(MILLIS, true));
+    REQUIRED BOOLEAN protected;
+    REQUIRED BOOLEAN secret;
+}";
+
+impl Store<String> {
+    /// Writes the store's entries to a Parquet file at `path`, replacing any
+    /// existing file, and returns the number written.
+    ///
+    /// The file has a row for each key, in key order, with these columns:
+    ///
+    /// * `key` and `value`, as UTF-8 strings.
+    /// * `version`, the key's version (see [`Self::version()`]).
+    /// * `expires`, when the key expires (see [`Self::expire()`]), as a UTC
+    ///   timestamp, or null if it doesn't.
+    /// * `protected` and `secret`, which are true if the key is protected
+    ///   (see [`Self::protect()`]) or secret (see [`Self::mark_secret()`]).
+    ///   Secret values are written in plaintext.
+    ///
+    /// Requires the `parquet` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Io`] for any error creating or writing the
+    /// file, or [`StoreError::Encrypted`] if any entry has an encrypted value
+    /// (see [`Self::insert_encrypted()`]), in which case no file is created.
+    pub fn export_parquet(&self, path: impl AsRef<Path>) -> Result<usize, StoreError> {
+        self.refuse_encrypted(|_| true)?;
+        let mut keys: Vec<&str> = self.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        self.write_parquet(File::create(path)?, &keys)
+            .map_err(|e| StoreError::Io(io::Error::other(e)))?;
+        Ok(keys.len())
+    }
+
+    /// Writes `keys` and their values and metadata to `file`, as a single
+    /// row group.
+    fn write_parquet(&self, file: File, keys: &[&str]) -> Result<(), ParquetError> {
+        let schema = Arc::new(parse_message_type(SCHEMA)?);
+        let properties = Arc::new(WriterProperties::builder().build());
+        let mut writer = SerializedFileWriter::new(file, schema, properties)?;
+        let mut row_group = writer.next_row_group()?;
+        let names: Vec<ByteArray> = keys.iter().map(|k| ByteArray::from(*k)).collect();
+        let values: Vec<ByteArray> = keys
+            .iter()
+            .map(|k| ByteArray::from(self.inner[*k].as_str()))
+            .collect();
+        let versions: Vec<i64> = keys
+            .iter()
+            .map(|k| i64::try_from(self.version(k)).unwrap_or(i64::MAX))
+            .collect();
+        // Nulls are left out of the values, and marked by a definition level
+        // of zero.
+        let expiries: Vec<Option<i64>> = keys
+            .iter()
+            .map(|k| {
+                let at = self.meta.expires.get(*k)?;
+                Some(i64::try_from(at.saturating_mul(1000)).unwrap_or(i64::MAX))
+            })
+            .collect();
+        let levels: Vec<i16> = expiries.iter().map(|e| i16::from(e.is_some())).collect();
+        let expiries: Vec<i64> = expiries.into_iter().flatten().collect();
+        let protected: Vec<bool> = keys
+            .iter()
+            .map(|k| self.meta.protected.contains(*k))
+            .collect();
+        let secret: Vec<bool> = keys.iter().map(|k| self.is_secret(k)).collect();
+        let mut index = 0;
+        while let Some(mut column) = row_group.next_column()? {
+            match index {
+                0 => column
+                    .typed::<ByteArrayType>()
+                    .write_batch(&names, None, None)?,
+                1 => column
+                    .typed::<ByteArrayType>()
+                    .write_batch(&values, None, None)?,
+                2 => column
+                    .typed::<Int64Type>()
+                    .write_batch(&versions, None, None)?,
+                3 => column
+                    .typed::<Int64Type>()
+                    .write_batch(&expiries, Some(&levels), None)?,
+                4 => column
+                    .typed::<BoolType>()
+                    .write_batch(&protected, None, None)?,
+                _ => column
+                    .typed::<BoolType>()
+                    .write_batch(&secret, None, None)?,
+            };
+            column.close()?;
+            index += 1;
+        }
+        row_group.close()?;
+        writer.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::parquet::file::reader::{FileReader, SerializedFileReader};
+    use ::parquet::record::Field;
+    use tempfile::TempDir;
+
+    #[test]
+    fn export_parquet_writes_a_row_per_key_in_order() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("data.parquet");
+        let mut s = Store::<String>::new("unused.kv".into());
+        s.try_insert("b".to_string(), "2".to_string()).unwrap();
+        s.try_insert("a".to_string(), "1".to_string()).unwrap();
+        s.meta.expires.insert("b".to_string(), 10);
+        assert_eq!(2, s.export_parquet(&path).unwrap(), "wrong count");
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        let rows: Vec<_> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| {
+                let row = row.unwrap();
+                let fields: Vec<_> = row.get_column_iter().map(|(_, f)| f.clone()).collect();
+                (fields[0].clone(), fields[3].clone())
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (Field::Str("a".to_string()), Field::Null),
+                (Field::Str("b".to_string()), Field::TimestampMillis(10_000)),
+            ],
+            rows
+        );
+    }
+}
@@ -0,0 +1,40 @@
+//! Running a task repeatedly on a background thread.
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// A background thread that runs a task every so often, until dropped.
+///
+/// Dropping it stops the thread, waiting for any run in progress to finish.
+#[derive(Debug)]
+pub(crate) struct Periodic {
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Periodic {
+    /// Starts a thread that calls `task` every `interval`.
+    pub(crate) fn spawn(interval: Duration, mut task: impl FnMut() + Send + 'static) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                task();
+            }
+        });
+        Self {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Periodic {
+    fn drop(&mut self) {
+        // Disconnecting the channel wakes the thread and tells it to stop.
+        drop(self.stop.take());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
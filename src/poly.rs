@@ -0,0 +1,200 @@
+//! Stores holding values of several types.
+
+use crate::{Store, StoreError};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+
+/// A type that can be stored in a [`PolyStore`].
+///
+/// `TYPE` names the type in the data file, so that values can be checked
+/// when they're read back. It should be unique among the types stored in
+/// the same file, and shouldn't change once data has been written.
+///
+/// # Examples
+///
+/// ```
+/// use rskey::PolyValue;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Deserialize, Serialize)]
+/// struct Point {
+///     x: i32,
+///     y: i32,
+/// }
+///
+/// impl PolyValue for Point {
+///     const TYPE: &'static str = "point";
+/// }
+/// ```
+pub trait PolyValue: DeserializeOwned + Serialize {
+    /// The name of the type, as stored in the data file.
+    const TYPE: &'static str;
+}
+
+macro_rules! poly_value {
+    ($($t:ty),*) => {
+        $(
+            impl PolyValue for $t {
+                const TYPE: &'static str = stringify!($t);
+            }
+        )*
+    };
+}
+
+poly_value!(bool, char, String, i8, i16, i32, i64, i128, u8, u16, u32, u64, u128, f32, f64);
+
+/// A value in a [`PolyStore`], tagged with its type.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PolyEntry {
+    #[serde(rename = "type")]
+    type_name: String,
+    value: Value,
+}
+
+impl PolyEntry {
+    /// Returns the name of the value's type (see [`PolyValue::TYPE`]).
+    #[must_use]
+    pub fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    /// Returns the value, as JSON.
+    #[must_use]
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+}
+
+/// A store whose values can be of different types, such as counters,
+/// strings, and structs, in the same file.
+///
+/// Each value is stored along with the name of its type, and
+/// [`Self::get_as()`] checks that it's the type requested. Values can be
+/// of any type implementing [`PolyValue`].
+///
+/// A `PolyStore` dereferences to a [`Store`] of [`PolyEntry`] values, so
+/// the other `Store` methods (such as `remove`, `sync`, and `protect`) can
+/// be used with it too.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), rskey::StoreError> {
+/// # use tempfile::TempDir;
+/// use rskey::PolyStore;
+/// # let tmp_dir = TempDir::new()?;
+/// # let path = tmp_dir.path().join("data.kv");
+///
+/// let mut s = PolyStore::open(path)?;
+/// s.insert_as("visits".into(), &42_u64)?;
+/// s.insert_as("greeting".into(), &"hello".to_string())?;
+/// assert_eq!(s.get_as::<u64>("visits")?, Some(42));
+/// assert!(s.get_as::<u64>("greeting").is_err());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct PolyStore {
+    store: Store<PolyEntry>,
+}
+
+impl PolyStore {
+    /// Creates a [`PolyStore`] associated with a data file at the given
+    /// `path`, as with [`Store::open()`].
+    ///
+    /// # Errors
+    ///
+    /// Returns any error opening the file (if it exists).
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        Ok(Self {
+            store: Store::open(path)?,
+        })
+    }
+
+    /// Inserts `value`, tagged with its type, as the value for `key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::TypeMismatch`] if `value` can't be converted to
+    /// JSON, or any error from [`Store::try_insert()`].
+    pub fn insert_as<T: PolyValue>(&mut self, key: String, value: &T) -> Result<(), StoreError> {
+        let value = serde_json::to_value(value).map_err(|e| StoreError::TypeMismatch {
+            key: key.clone(),
+            message: e.to_string(),
+        })?;
+        let entry = PolyEntry {
+            type_name: T::TYPE.to_string(),
+            value,
+        };
+        self.store.try_insert(key, entry)?;
+        Ok(())
+    }
+
+    /// Returns the value for `key`, if any, as a `T`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::TypeMismatch`] if the value was stored as a
+    /// different type, or can't be converted to `T`.
+    pub fn get_as<T: PolyValue>(&self, key: &str) -> Result<Option<T>, StoreError> {
+        let Some(entry) = self.store.get(key) else {
+            return Ok(None);
+        };
+        let mismatch = |message| StoreError::TypeMismatch {
+            key: key.to_string(),
+            message,
+        };
+        if entry.type_name != T::TYPE {
+            return Err(mismatch(format!(
+                "expected {}, found {}",
+                T::TYPE,
+                entry.type_name
+            )));
+        }
+        T::deserialize(&entry.value)
+            .map(Some)
+            .map_err(|e| mismatch(e.to_string()))
+    }
+}
+
+impl Deref for PolyStore {
+    type Target = Store<PolyEntry>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.store
+    }
+}
+
+impl DerefMut for PolyStore {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.store
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn get_as_returns_values_of_matching_type_after_sync() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        let mut s = PolyStore::open(&path).unwrap();
+        s.insert_as("count".to_string(), &7_u32).unwrap();
+        s.insert_as("name".to_string(), &"x".to_string()).unwrap();
+        s.sync().unwrap();
+        let s = PolyStore::open(&path).unwrap();
+        assert_eq!(Some(7), s.get_as::<u32>("count").unwrap(), "wrong value");
+        assert_eq!(None, s.get_as::<u32>("missing").unwrap(), "unexpected data");
+        let err = s.get_as::<u64>("count").unwrap_err();
+        assert_eq!(
+            r#"value of "count" has the wrong type: expected u64, found u32"#,
+            err.to_string(),
+            "wrong error"
+        );
+    }
+}
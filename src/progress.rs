@@ -0,0 +1,40 @@
+//! Reporting the progress of long operations.
+
+/// Receives progress reports from long operations, such as
+/// [`Store::insert_flattened_with_progress()`](crate::Store::insert_flattened_with_progress).
+///
+/// All methods do nothing by default, and `()` implements the trait, for
+/// when no reporting is needed.
+///
+/// # Examples
+///
+/// ```
+/// use rskey::ProgressSink;
+/// use std::sync::atomic::{AtomicU64, Ordering};
+///
+/// #[derive(Default)]
+/// struct Counter(AtomicU64);
+///
+/// impl ProgressSink for Counter {
+///     fn advance(&self, n: u64) {
+///         self.0.fetch_add(n, Ordering::Relaxed);
+///     }
+/// }
+/// ```
+pub trait ProgressSink {
+    /// Called once, before any work is done, with the total number of
+    /// items the operation will process.
+    fn start(&self, total: u64) {
+        let _ = total;
+    }
+
+    /// Called as each batch of `n` items is processed.
+    fn advance(&self, n: u64) {
+        let _ = n;
+    }
+
+    /// Called once the operation has finished, whether or not it succeeded.
+    fn finish(&self) {}
+}
+
+impl ProgressSink for () {}
@@ -0,0 +1,174 @@
+//! Python bindings, as the `pyrskey` extension module.
+//!
+//! Build and install the module with [maturin](https://www.maturin.rs),
+//! using `pyproject.toml`: `maturin develop --release`.
+
+use pyo3::exceptions::{PyIOError, PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyIterator, PyList, PyType};
+use std::path::PathBuf;
+
+use crate::{Store, StoreError};
+
+/// A store of strings, used like a `dict`.
+///
+/// Changes are written to the data file by `sync()`, or when a `with` block
+/// using the store ends without an exception:
+///
+/// ```python
+/// from pyrskey import Store
+///
+/// with Store("store.kv") as s:
+///     s["model"] = "resnet50"
+///     print(s.get("epochs", "10"))
+/// ```
+#[pyclass(name = "Store", module = "pyrskey", mapping)]
+struct PyStore(Store<String>);
+
+fn store_error(e: StoreError) -> PyErr {
+    match e {
+        StoreError::Io(e) => PyIOError::new_err(e.to_string()),
+        e => PyValueError::new_err(e.to_string()),
+    }
+}
+
+#[pymethods]
+impl PyStore {
+    /// Opens the store whose data file is at `path`, creating an empty store
+    /// if there's no such file.
+    #[new]
+    #[allow(clippy::needless_pass_by_value)]
+    fn new(path: PathBuf) -> PyResult<Self> {
+        let store = Store::open(&path)
+            .map_err(|e| PyIOError::new_err(format!("opening {}: {e}", path.display())))?;
+        Ok(Self(store))
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.len()
+    }
+
+    fn __contains__(&self, key: &str) -> bool {
+        self.0.lookup(key).is_some()
+    }
+
+    fn __getitem__(&self, key: &str) -> PyResult<String> {
+        self.0
+            .lookup(key)
+            .cloned()
+            .ok_or_else(|| PyKeyError::new_err(key.to_string()))
+    }
+
+    fn __setitem__(&mut self, key: String, value: String) -> PyResult<()> {
+        self.0.try_insert(key, value).map_err(store_error)?;
+        Ok(())
+    }
+
+    fn __delitem__(&mut self, key: &str) -> PyResult<()> {
+        match self.0.remove(key).map_err(store_error)? {
+            Some(_) => Ok(()),
+            None => Err(PyKeyError::new_err(key.to_string())),
+        }
+    }
+
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyIterator>> {
+        self.keys(py)?.as_any().try_iter()
+    }
+
+    /// Returns the value of `key`, or `default` if there's no such key.
+    #[pyo3(signature = (key, default=None))]
+    fn get(&self, key: &str, default: Option<String>) -> Option<String> {
+        self.0.lookup(key).cloned().or(default)
+    }
+
+    /// Returns a list of the keys, in sorted order.
+    fn keys<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        let mut keys: Vec<_> = self.0.keys().collect();
+        keys.sort_unstable();
+        PyList::new(py, keys)
+    }
+
+    /// Returns a list of `(key, value)` pairs, sorted by key.
+    fn items<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        let mut items: Vec<_> = self.0.iter().collect();
+        items.sort_unstable();
+        PyList::new(py, items)
+    }
+
+    /// Atomically writes the store to its data file.
+    fn sync(&self) -> PyResult<()> {
+        self.0
+            .sync()
+            .map_err(|e| PyIOError::new_err(format!("writing {}: {e}", self.0.path().display())))
+    }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    /// Syncs the store, unless the block raised an exception.
+    #[pyo3(signature = (exc_type, _exc_value, _traceback))]
+    fn __exit__(
+        &self,
+        exc_type: Option<&Bound<'_, PyType>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<bool> {
+        if exc_type.is_none() {
+            self.sync()?;
+        }
+        Ok(false)
+    }
+}
+
+/// Shares rskey data files with Python programs.
+#[pymodule]
+fn pyrskey(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyStore>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+    use tempfile::TempDir;
+
+    #[test]
+    fn store_behaves_like_a_dict_and_syncs_on_exit() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        Python::initialize();
+        Python::attach(|py| {
+            let locals = PyDict::new(py);
+            locals.set_item("Store", py.get_type::<PyStore>()).unwrap();
+            locals.set_item("path", &path).unwrap();
+            py.run(
+                c"
+with Store(path) as s:
+    s['b'] = '2'
+    s['a'] = '1'
+    del s['b']
+    assert 'a' in s and 'b' not in s
+    assert s.get('b', 'none') == 'none'
+    assert list(s) == ['a'] and s.items() == [('a', '1')]
+try:
+    with Store(path) as s:
+        s['c'] = '3'
+        raise RuntimeError
+except RuntimeError:
+    pass
+s = Store(path)
+assert len(s) == 1, 'wrong number of entries'
+try:
+    s['c']
+    raise AssertionError('missing key found')
+except KeyError:
+    pass
+",
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+        });
+    }
+}
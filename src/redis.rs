@@ -0,0 +1,126 @@
+//! Copying entries to and from a Redis server.
+
+use ::redis::{Client, Connection, RedisError};
+use std::io;
+use std::time::Duration;
+
+use crate::{glob, Store, StoreError};
+
+impl Store<String> {
+    /// Copies the string keys matching `pattern` from the Redis server at
+    /// `url`, such as `redis://localhost/0`, into the store, replacing any
+    /// existing values for those keys, and returns the number copied.
+    ///
+    /// `pattern` is a Redis glob pattern, as for `SCAN`, so `app:*` copies
+    /// every key starting with `app:`. Keys holding anything other than a
+    /// string, such as a hash or a list, are skipped, as are values that
+    /// aren't valid UTF-8. A key with a TTL in Redis is set to expire after
+    /// the same time (see [`Self::expire()`]).
+    ///
+    /// This needs Redis 6 or later, and doesn't sync the store. The keys are
+    /// scanned incrementally, so keys added or removed in Redis meanwhile
+    /// may or may not be copied. Requires the `redis` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Io`] for any error connecting to or reading
+    /// from Redis, or [`StoreError::Protected`] if a key being copied is
+    /// protected, in which case the keys copied so far are kept.
+    pub fn import_redis(&mut self, url: &str, pattern: &str) -> Result<usize, StoreError> {
+        let mut con = connect(url)?;
+        let keys: Vec<String> = ::redis::cmd("SCAN")
+            .cursor_arg(0)
+            .arg("MATCH")
+            .arg(pattern)
+            .arg("TYPE")
+            .arg("string")
+            .clone()
+            .iter(&mut con)
+            .map_err(redis_error)?
+            .collect();
+        let mut copied = 0;
+        for key in keys {
+            let (value, ttl): (Option<Vec<u8>>, i64) = ::redis::pipe()
+                .cmd("GET")
+                .arg(&key)
+                .cmd("PTTL")
+                .arg(&key)
+                .query(&mut con)
+                .map_err(redis_error)?;
+            // The key may have been removed since it was scanned.
+            let Some(Ok(value)) = value.map(String::from_utf8) else {
+                continue;
+            };
+            let key = self.normalize_owned(key);
+            self.try_insert(key.clone(), value)?;
+            if let Ok(ttl) = u64::try_from(ttl) {
+                self.expire(&key, Duration::from_millis(ttl));
+            }
+            copied += 1;
+        }
+        Ok(copied)
+    }
+
+    /// Copies the entries whose keys match `pattern` to the Redis server at
+    /// `url`, replacing any existing values for those keys there, and
+    /// returns the number copied.
+    ///
+    /// `pattern` is a glob pattern where `*` matches any sequence of
+    /// characters and `?` matches any single character. A key with an
+    /// expiry time (see [`Self::expire()`]) is given the same TTL in Redis.
+    /// Requires the `redis` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Io`] for any error connecting to or writing to
+    /// Redis, in which case some of the entries may have been copied.
+    /// Returns [`StoreError::Encrypted`] if any of the entries has an
+    /// encrypted value (see [`Self::insert_encrypted()`]), in which case none
+    /// are copied.
+    pub fn export_redis(&self, url: &str, pattern: &str) -> Result<usize, StoreError> {
+        self.refuse_encrypted(|k| glob::matches(pattern, k))?;
+        let mut con = connect(url)?;
+        let mut pipe = ::redis::pipe();
+        let mut copied = 0;
+        for (key, value) in self.iter().filter(|(k, _)| glob::matches(pattern, k)) {
+            let set = pipe.cmd("SET").arg(key).arg(value);
+            if let Some(ttl) = self.ttl(key) {
+                // Redis rejects a TTL of zero, so keep already-expired keys
+                // for the shortest time it allows.
+                let millis = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+                set.arg("PX").arg(millis.max(1));
+            }
+            set.ignore();
+            copied += 1;
+        }
+        pipe.query::<()>(&mut con).map_err(redis_error)?;
+        Ok(copied)
+    }
+}
+
+fn connect(url: &str) -> Result<Connection, StoreError> {
+    Client::open(url)
+        .and_then(|client| client.get_connection())
+        .map_err(redis_error)
+}
+
+fn redis_error(e: RedisError) -> StoreError {
+    StoreError::Io(io::Error::other(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_redis_reports_unreachable_server_without_changing_store() {
+        let mut s = Store::<String>::new("unused.kv".into());
+        for url in ["redis://127.0.0.1:1/0", "not a url"] {
+            let err = s.import_redis(url, "*").unwrap_err();
+            assert!(matches!(err, StoreError::Io(_)), "wrong error {err:?}");
+            let err = s.export_redis(url, "*").unwrap_err();
+            assert!(matches!(err, StoreError::Io(_)), "wrong error {err:?}");
+        }
+        assert!(s.is_empty(), "store changed");
+    }
+}
@@ -0,0 +1,124 @@
+//! Changing the key that signs a store's files.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io;
+
+use crate::{format, SigningKey, Store, StoreError};
+
+impl<V> Store<V>
+where
+    V: DeserializeOwned + Serialize,
+{
+    /// Replaces the store's signing key with `key`, and re-signs the data
+    /// file and every snapshot (see [`Self::snapshot()`]) with it. If `key`
+    /// is `None`, the files are left unsigned.
+    ///
+    /// Every file is checked against the current signing key, if any,
+    /// before any is changed. Each file is then rewritten to a temporary
+    /// file which is renamed into place, so it's always signed with one key
+    /// or the other. The files' contents are unchanged, and any unsynced
+    /// changes to the store aren't written. Hold the store's lock (see
+    /// [`Self::lock()`]) so that no other process syncs it meanwhile. The
+    /// files are read and written through the store's backend.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// use rskey::{SigningKey, Store};
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let old = SigningKey::from_passphrase("old secret");
+    /// let mut s = Store::<String>::open_signed(&path, old.clone())?;
+    /// s.try_insert("key1".to_string(), "value1".to_string())?;
+    /// s.sync()?;
+    /// let new = SigningKey::from_passphrase("new secret");
+    /// s.rotate_signing_key(Some(new.clone()))?;
+    /// assert!(Store::<String>::open_signed(&path, old).is_err());
+    /// assert_eq!(Store::<String>::open_signed(&path, new)?["key1"], "value1");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Tampered`] if any file's signature is missing
+    /// or doesn't match the current key, in which case no file is changed,
+    /// or any error reading or writing the files.
+    pub fn rotate_signing_key(&mut self, key: Option<SigningKey>) -> Result<(), StoreError> {
+        let mut docs = Vec::new();
+        if let Some(file) = self.backend.read(&self.path)? {
+            docs.push((self.path.clone(), self.verify(&file)?.to_vec()));
+        }
+        for snapshot in self.snapshots()? {
+            let file = self
+                .backend
+                .read(&snapshot.path)?
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+            docs.push((snapshot.path, self.verify(&file)?.to_vec()));
+        }
+        self.signing_key = key;
+        for (path, doc) in docs {
+            format::write_file(&*self.backend, &path, doc, self.signing_key.as_ref())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn rotate_signing_key_re_signs_snapshots_or_nothing() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("data.kv");
+        let old = SigningKey::from_passphrase("old");
+        let new = SigningKey::from_passphrase("new");
+        let mut s = Store::<u8>::open_signed(&path, old.clone()).unwrap();
+        s.try_insert("a".to_string(), 1).unwrap();
+        s.sync().unwrap();
+        let snapshot = s.snapshot().unwrap();
+        s.rotate_signing_key(Some(new.clone())).unwrap();
+        let mut s = Store::<u8>::open_signed(&path, new).unwrap();
+        s.restore_snapshot(&snapshot).unwrap();
+        // A file the current key didn't sign stops the rotation.
+        let mut file = fs::read(&snapshot.path).unwrap();
+        file.push(b'x');
+        fs::write(&snapshot.path, file).unwrap();
+        let err = s.rotate_signing_key(Some(old.clone())).unwrap_err();
+        assert!(matches!(err, StoreError::Tampered), "wrong error {err:?}");
+        assert!(
+            Store::<u8>::open_signed(&path, old).is_err(),
+            "data file re-signed despite error"
+        );
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn rotate_signing_key_reads_snapshots_through_backend() {
+        let old = SigningKey::from_passphrase("old");
+        let new = SigningKey::from_passphrase("new");
+        let mock = crate::testing::StoreBackendMock::new();
+        let mut s: Store<u8> = Store::builder("store.kv")
+            .backend(mock.clone())
+            .signing_key(old)
+            .open()
+            .unwrap();
+        s.try_insert("a".to_string(), 1).unwrap();
+        s.sync().unwrap();
+        let snapshot = s.snapshot().unwrap();
+        s.rotate_signing_key(Some(new.clone())).unwrap();
+        let mut s: Store<u8> = Store::builder("store.kv")
+            .backend(mock)
+            .signing_key(new)
+            .open()
+            .unwrap();
+        s.restore_snapshot(&snapshot).unwrap();
+        assert_eq!(Some(&1), s.get("a"), "snapshot not re-signed");
+    }
+}
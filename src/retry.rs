@@ -0,0 +1,254 @@
+//! Retrying file operations that fail transiently.
+
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::Backend;
+
+/// A policy for retrying file operations that fail with errors likely to be
+/// transient, as set by [`StoreBuilder::retry()`](crate::StoreBuilder::retry).
+///
+/// Such errors include a file being busy or locked by another process
+/// (common on Windows, where virus scanners and indexers briefly hold files
+/// open), a stale NFS file handle, and interrupted or timed-out operations.
+/// Other errors, such as a missing directory or a full disk, are returned
+/// straight away.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), rskey::StoreError> {
+/// # use tempfile::TempDir;
+/// use rskey::{Retry, Store};
+/// use std::time::Duration;
+/// # let tmp_dir = TempDir::new()?;
+/// # let path = tmp_dir.path().join("data.kv");
+/// let s: Store<String> = Store::builder(path)
+///     .retry(Retry::exponential(5).max_delay(Duration::from_millis(500)))
+///     .open()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Retry {
+    attempts: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Retry {
+    /// Makes up to `attempts` attempts at each operation, including the
+    /// first, waiting 10 milliseconds before the second attempt and doubling
+    /// the wait each time after that, up to a maximum of one second.
+    #[must_use]
+    pub fn exponential(attempts: u32) -> Self {
+        Self {
+            attempts: attempts.max(1),
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(1),
+        }
+    }
+
+    /// Sets how long to wait before the second attempt.
+    #[must_use]
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// Sets the longest to wait between attempts.
+    #[must_use]
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Calls `op` until it succeeds, fails with an error that isn't
+    /// transient, or runs out of attempts, returning the last result.
+    fn run<T>(&self, mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let mut delay = self.initial_delay;
+        for _ in 1..self.attempts {
+            match op() {
+                Err(e) if is_transient(&e) => {
+                    thread::sleep(delay.min(self.max_delay));
+                    delay = delay.saturating_mul(2);
+                }
+                result => return result,
+            }
+        }
+        op()
+    }
+}
+
+/// Returns `true` if `e` is likely to go away if the operation is retried.
+fn is_transient(e: &io::Error) -> bool {
+    if matches!(
+        e.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    ) {
+        return true;
+    }
+    let Some(code) = e.raw_os_error() else {
+        return false;
+    };
+    if cfg!(windows) {
+        // ERROR_SHARING_VIOLATION or ERROR_LOCK_VIOLATION.
+        matches!(code, 32 | 33)
+    } else if cfg!(target_os = "linux") {
+        // EBUSY or ESTALE.
+        matches!(code, 16 | 116)
+    } else {
+        // EBUSY.
+        cfg!(unix) && code == 16
+    }
+}
+
+/// A [`Backend`] that retries the operations of another according to a
+/// [`Retry`] policy.
+#[derive(Debug)]
+pub(crate) struct RetryBackend {
+    inner: Arc<dyn Backend>,
+    retry: Retry,
+}
+
+impl RetryBackend {
+    pub(crate) fn new(inner: Arc<dyn Backend>, retry: Retry) -> Self {
+        Self { inner, retry }
+    }
+}
+
+impl Backend for RetryBackend {
+    fn read(&self, path: &Path) -> io::Result<Option<Vec<u8>>> {
+        self.retry.run(|| self.inner.read(path))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Option<Box<dyn Read + Send>>> {
+        self.retry.run(|| self.inner.open(path))
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.retry.run(|| self.inner.write(path, data))
+    }
+
+    fn append(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.retry.run(|| self.inner.append(path, data))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.retry.run(|| self.inner.rename(from, to))
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        self.retry.run(|| self.inner.remove(path))
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        self.retry.run(|| self.inner.exists(path))
+    }
+
+    fn size(&self, path: &Path) -> io::Result<Option<u64>> {
+        self.retry.run(|| self.inner.size(path))
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        self.retry.run(|| self.inner.list(dir))
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        self.retry.run(|| self.inner.create_dir_all(dir))
+    }
+
+    fn check_path(&self, path: &Path) -> io::Result<()> {
+        self.inner.check_path(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FileBackend, Store};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tempfile::TempDir;
+
+    /// A backend whose first few writes fail with the given error.
+    #[derive(Debug)]
+    struct Flaky {
+        failures: AtomicU32,
+        kind: io::ErrorKind,
+    }
+
+    impl Flaky {
+        fn new(failures: u32, kind: io::ErrorKind) -> Self {
+            Self {
+                failures: AtomicU32::new(failures),
+                kind,
+            }
+        }
+    }
+
+    impl Backend for Flaky {
+        fn read(&self, path: &Path) -> io::Result<Option<Vec<u8>>> {
+            FileBackend.read(path)
+        }
+
+        fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+            let failing = self
+                .failures
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                .is_ok();
+            if failing {
+                return Err(self.kind.into());
+            }
+            FileBackend.write(path, data)
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            FileBackend.rename(from, to)
+        }
+
+        fn remove(&self, path: &Path) -> io::Result<()> {
+            FileBackend.remove(path)
+        }
+
+        fn exists(&self, path: &Path) -> io::Result<bool> {
+            FileBackend.exists(path)
+        }
+    }
+
+    fn open_flaky(tmp_dir: &TempDir, flaky: Flaky, attempts: u32) -> Store<u8> {
+        Store::builder(tmp_dir.path().join("store.kv"))
+            .backend(flaky)
+            .retry(Retry::exponential(attempts).initial_delay(Duration::from_millis(1)))
+            .open()
+            .unwrap()
+    }
+
+    #[test]
+    fn retry_recovers_from_transient_errors() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut s = open_flaky(&tmp_dir, Flaky::new(2, io::ErrorKind::Interrupted), 3);
+        s.try_insert("key".to_string(), 1).unwrap();
+        s.sync().expect("transient errors not retried");
+        let s = Store::<u8>::open(tmp_dir.path().join("store.kv")).unwrap();
+        assert_eq!(Some(&1), s.get("key"), "expected data not returned");
+    }
+
+    #[test]
+    fn retry_gives_up_after_last_attempt() {
+        let tmp_dir = TempDir::new().unwrap();
+        let s = open_flaky(&tmp_dir, Flaky::new(3, io::ErrorKind::Interrupted), 3);
+        let err = s.sync().unwrap_err();
+        assert_eq!(io::ErrorKind::Interrupted, err.kind(), "wrong error");
+        s.sync().expect("later sync failed");
+    }
+
+    #[test]
+    fn retry_returns_other_errors_immediately() {
+        let tmp_dir = TempDir::new().unwrap();
+        let s = open_flaky(&tmp_dir, Flaky::new(1, io::ErrorKind::PermissionDenied), 3);
+        assert!(s.sync().is_err(), "permanent error retried");
+    }
+}
@@ -0,0 +1,113 @@
+//! Choosing entries at random.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::ops::ControlFlow;
+
+use crate::{Store, StoreError};
+
+/// Chooses `n` items uniformly at random from a sequence of unknown length,
+/// seeing each item only once (reservoir sampling).
+struct Reservoir<T> {
+    n: usize,
+    seen: usize,
+    items: Vec<T>,
+}
+
+impl<T> Reservoir<T> {
+    fn new(n: usize) -> Self {
+        Self {
+            n,
+            seen: 0,
+            items: Vec::new(),
+        }
+    }
+
+    fn offer(&mut self, item: T) {
+        self.seen += 1;
+        if self.items.len() < self.n {
+            self.items.push(item);
+        } else {
+            let i = fastrand::usize(..self.seen);
+            if i < self.n {
+                self.items[i] = item;
+            }
+        }
+    }
+}
+
+impl<V> Store<V> {
+    /// Returns `n` entries chosen at random, in arbitrary order, or all of
+    /// them if there are no more than `n`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let entries = (0..100).map(|i| (format!("key{i}"), i));
+    /// let s = Store::from_entries(path, entries);
+    /// assert_eq!(s.sample(10).len(), 10);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn sample(&self, n: usize) -> Vec<(&str, &V)> {
+        let mut reservoir = Reservoir::new(n);
+        for (key, value) in &self.inner {
+            reservoir.offer((key.as_str(), value));
+        }
+        reservoir.items
+    }
+}
+
+impl<V> Store<V>
+where
+    V: DeserializeOwned + Serialize,
+{
+    /// Like [`Self::sample()`], but chooses from the entries in the data
+    /// file, reading them one at a time as with [`Self::scan()`], so that
+    /// only the chosen entries are held in memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error reading or parsing the file (if it exists).
+    pub fn scan_sample(&self, n: usize) -> Result<Vec<(String, V)>, StoreError> {
+        let mut reservoir = Reservoir::new(n);
+        self.scan(|key, value| {
+            reservoir.offer((key, value));
+            ControlFlow::Continue(())
+        })?;
+        Ok(reservoir.items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use tempfile::TempDir;
+
+    #[test]
+    fn sample_returns_distinct_entries_up_to_n() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        let entries = (0..20).map(|i| (format!("key{i}"), i));
+        let s = Store::<u8>::from_entries(&path, entries);
+        s.sync().unwrap();
+        let keys: HashSet<_> = s.sample(5).into_iter().map(|(k, _)| k).collect();
+        assert_eq!(5, keys.len(), "wrong number of distinct entries");
+        assert_eq!(20, s.sample(50).len(), "not all entries returned");
+        let s = Store::<u8>::open_metadata(&path).unwrap();
+        let sample = s.scan_sample(5).unwrap();
+        let keys: HashSet<_> = sample.iter().map(|(k, _)| k).collect();
+        assert_eq!(5, keys.len(), "wrong number of distinct entries scanned");
+        assert!(
+            sample.iter().all(|(k, v)| *k == format!("key{v}")),
+            "entries don't match"
+        );
+    }
+}
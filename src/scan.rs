@@ -0,0 +1,160 @@
+//! Reading stores too large to fit in memory.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{self, BufReader, Read};
+use std::ops::ControlFlow;
+use std::path::Path;
+
+use crate::{format, Store, StoreError};
+
+impl<V> Store<V>
+where
+    V: DeserializeOwned + Serialize,
+{
+    /// Opens the store at `path` with its metadata, but none of its entries,
+    /// which can then be read one at a time with [`Self::scan()`]. This lets
+    /// a program read a store that's too large to fit in memory.
+    ///
+    /// The file is read from the filesystem, and its signature, if any, isn't
+    /// checked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// use rskey::Store;
+    /// use std::ops::ControlFlow;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// # let entries = (0..10).map(|i| (format!("key{i}"), i));
+    /// # Store::<usize>::from_entries(&path, entries).sync()?;
+    /// let s = Store::<usize>::open_metadata(path)?;
+    /// assert!(s.is_empty());
+    /// let mut total = 0;
+    /// s.scan(|_, value| {
+    ///     total += value;
+    ///     ControlFlow::Continue(())
+    /// })?;
+    /// assert_eq!(total, 45);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns any error reading or parsing the file (if it exists).
+    pub fn open_metadata(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        let mut store = Self::new(path.as_ref().into());
+        if let Some(reader) = store.reader()? {
+            store.meta = format::read_meta(reader)?;
+        }
+        Ok(store)
+    }
+
+    /// Reads the entries in the store's data file one at a time, calling
+    /// `f` with each key and value, until it returns [`ControlFlow::Break`].
+    ///
+    /// Expired keys, and scratch entries whose owning process has exited,
+    /// are skipped, as they would be when the store is opened. The entries
+    /// aren't added to the store, and any entries already in it are ignored.
+    /// The file is read through the store's backend (see
+    /// [`Backend::open()`](crate::Backend::open)).
+    ///
+    /// # Errors
+    ///
+    /// Returns any error reading or parsing the file (if it exists).
+    pub fn scan(&self, mut f: impl FnMut(String, V) -> ControlFlow<()>) -> Result<(), StoreError> {
+        let Some(reader) = self.reader()? else {
+            return Ok(());
+        };
+        format::scan_entries(reader, |key, value| {
+            if self.is_expired(&key) || self.is_orphaned(&key) {
+                return ControlFlow::Continue(());
+            }
+            f(key, value)
+        })?;
+        Ok(())
+    }
+
+    /// Returns a reader for the store's data file, or `None` if it doesn't
+    /// exist.
+    fn reader(&self) -> io::Result<Option<BufReader<Box<dyn Read + Send>>>> {
+        Ok(self.backend.open(&self.path)?.map(BufReader::new))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn scan_reads_entries_and_metadata_without_loading_store() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        let entries = [("a", 1), ("b", 2), ("c", 3)].map(|(k, v)| (k.to_string(), v));
+        let mut s = Store::<u8>::from_entries(&path, entries);
+        s.protect("a");
+        s.expire("c", Duration::ZERO);
+        s.sync().unwrap();
+
+        let s = Store::<u8>::open_metadata(&path).unwrap();
+        assert!(s.is_empty(), "entries loaded");
+        assert!(s.is_protected("a"), "metadata not loaded");
+        let mut seen = Vec::new();
+        s.scan(|key, value| {
+            seen.push((key, value));
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        seen.sort();
+        let want = vec![("a".to_string(), 1), ("b".to_string(), 2)];
+        assert_eq!(want, seen, "wrong entries scanned");
+
+        let mut count = 0;
+        s.scan(|_, _| {
+            count += 1;
+            ControlFlow::Break(())
+        })
+        .unwrap();
+        assert_eq!(1, count, "scan not stopped");
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn scan_reads_through_backend() {
+        let mut s: Store<u8> = Store::builder("store.kv")
+            .backend(crate::testing::StoreBackendMock::new())
+            .open()
+            .unwrap();
+        s.try_insert("a".to_string(), 1).unwrap();
+        s.sync().unwrap();
+        let mut seen = Vec::new();
+        s.scan(|key, value| {
+            seen.push((key, value));
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        assert_eq!(vec![("a".to_string(), 1)], seen, "wrong entries scanned");
+    }
+
+    #[test]
+    fn scan_reads_legacy_data_file() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        fs::write(&path, r#"{"format":"plain","k1":"v1"}"#).unwrap();
+        let s = Store::<String>::open_metadata(&path).unwrap();
+        let mut seen = Vec::new();
+        s.scan(|key, value| {
+            seen.push(format!("{key}={value}"));
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        seen.sort();
+        assert_eq!(vec!["format=plain", "k1=v1"], seen, "wrong entries scanned");
+    }
+}
@@ -0,0 +1,325 @@
+//! Checking values against JSON schemas.
+//!
+//! Only a subset of [JSON Schema](https://json-schema.org) is supported:
+//! the `type`, `enum`, `const`, `minimum`, `maximum`, `minLength`,
+//! `maxLength`, `items`, `minItems`, `maxItems`, `properties`, `required`,
+//! and `additionalProperties` keywords, plus the boolean schemas `true` and
+//! `false`. Any other keywords are ignored.
+
+use crate::format::Meta;
+use crate::{glob, Store, StoreError};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+impl<V> Store<V> {
+    /// Requires the values of all keys matching `pattern` to be valid
+    /// according to the JSON `schema`, replacing any schema previously set
+    /// for the same pattern. The pattern may contain the wildcards `*` and
+    /// `?`, as with [`Self::mark_secret()`], and the schema is persisted
+    /// with the store.
+    ///
+    /// Values are checked by [`Self::try_insert()`], [`Self::replace()`], and
+    /// scratch inserts, but not by [`Self::force_insert()`], or by changes
+    /// made through the underlying `HashMap` or an [`Entry`](crate::Entry).
+    ///
+    /// Only a subset of JSON Schema is supported: the `type`, `enum`,
+    /// `const`, `minimum`, `maximum`, `minLength`, `maxLength`, `items`,
+    /// `minItems`, `maxItems`, `properties`, `required`, and
+    /// `additionalProperties` keywords. Any others are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// use rskey::Store;
+    /// use serde_json::{json, Value};
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    ///
+    /// let mut s = Store::<Value>::open(path)?;
+    /// s.set_schema("server.*", json!({
+    ///     "type": "object",
+    ///     "required": ["port"],
+    ///     "properties": {"port": {"type": "integer", "maximum": 65535}},
+    /// }))?;
+    /// let err = s.try_insert("server.web".into(), json!({"port": 80000})).unwrap_err();
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     r#"value of "server.web" is invalid at /port: must be at most 65535"#,
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Invalid`] if any existing value for a key
+    /// matching `pattern` isn't valid according to `schema`. In that case,
+    /// the schema isn't set.
+    pub fn set_schema(&mut self, pattern: &str, schema: Value) -> Result<(), StoreError>
+    where
+        V: Serialize,
+    {
+        for (key, value) in &self.inner {
+            if glob::matches(pattern, key) {
+                check(&schema, key, value)?;
+            }
+        }
+        self.meta.schemas.insert(pattern.to_string(), schema);
+        self.touch();
+        Ok(())
+    }
+
+    /// Removes the schema set for `pattern`.
+    ///
+    /// Returns `false` if there was no schema for the pattern.
+    pub fn remove_schema(&mut self, pattern: &str) -> bool {
+        let changed = self.meta.schemas.remove(pattern).is_some();
+        if changed {
+            self.touch();
+        }
+        changed
+    }
+
+    /// Checks `value` against each schema whose pattern matches `key`.
+    pub(crate) fn validate(&self, key: &str, value: &V) -> Result<(), StoreError>
+    where
+        V: Serialize,
+    {
+        validate_entry(&self.meta, key, value)
+    }
+}
+
+/// Checks `value` against each schema in `meta` whose pattern matches `key`.
+pub(crate) fn validate_entry<V: Serialize>(
+    meta: &Meta,
+    key: &str,
+    value: &V,
+) -> Result<(), StoreError> {
+    for (pattern, schema) in &meta.schemas {
+        if glob::matches(pattern, key) {
+            check(schema, key, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks the value for `key` against `schema`.
+fn check<V: Serialize>(schema: &Value, key: &str, value: &V) -> Result<(), StoreError> {
+    let value = serde_json::to_value(value)?;
+    validate(schema, &value, "").map_err(|(path, message)| StoreError::Invalid {
+        key: key.to_string(),
+        path,
+        message,
+    })
+}
+
+/// Checks `value`, found at `path` (a JSON pointer), against `schema`.
+/// Returns the path and description of the first problem found.
+fn validate(schema: &Value, value: &Value, path: &str) -> Result<(), (String, String)> {
+    let fail = |message: String| Err((path.to_string(), message));
+    let schema = match schema {
+        Value::Bool(false) => return fail("no value is allowed".to_string()),
+        Value::Object(schema) => schema,
+        // `true`, or anything else that isn't a schema, allows any value.
+        _ => return Ok(()),
+    };
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| has_type(value, t)) {
+            return fail(format!("must be of type {}", types.join(" or ")));
+        }
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            return fail(format!("must be one of {}", Value::Array(allowed.clone())));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if value != expected {
+            return fail(format!("must be {expected}"));
+        }
+    }
+    if let Some(n) = value.as_f64() {
+        if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+            if n < min {
+                return fail(format!("must be at least {min}"));
+            }
+        }
+        if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+            if n > max {
+                return fail(format!("must be at most {max}"));
+            }
+        }
+    }
+    if let Value::String(s) = value {
+        let len = s.chars().count();
+        if let Some(min) = limit(schema, "minLength") {
+            if len < min {
+                return fail(format!("must be at least {min} characters long"));
+            }
+        }
+        if let Some(max) = limit(schema, "maxLength") {
+            if len > max {
+                return fail(format!("must be at most {max} characters long"));
+            }
+        }
+    }
+    if let Value::Array(items) = value {
+        validate_array(schema, items, path)?;
+    }
+    if let Value::Object(object) = value {
+        validate_object(schema, object, path)?;
+    }
+    Ok(())
+}
+
+fn validate_array(
+    schema: &Map<String, Value>,
+    items: &[Value],
+    path: &str,
+) -> Result<(), (String, String)> {
+    if let Some(min) = limit(schema, "minItems") {
+        if items.len() < min {
+            return Err((path.to_string(), format!("must have at least {min} items")));
+        }
+    }
+    if let Some(max) = limit(schema, "maxItems") {
+        if items.len() > max {
+            return Err((path.to_string(), format!("must have at most {max} items")));
+        }
+    }
+    if let Some(item_schema) = schema.get("items") {
+        for (i, item) in items.iter().enumerate() {
+            validate(item_schema, item, &format!("{path}/{i}"))?;
+        }
+    }
+    Ok(())
+}
+
+fn validate_object(
+    schema: &Map<String, Value>,
+    object: &Map<String, Value>,
+    path: &str,
+) -> Result<(), (String, String)> {
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for name in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(name) {
+                return Err((
+                    path.to_string(),
+                    format!("missing required property {name:?}"),
+                ));
+            }
+        }
+    }
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (name, value) in object {
+        let property_path = format!("{path}/{}", name.replace('~', "~0").replace('/', "~1"));
+        match properties.and_then(|p| p.get(name)) {
+            Some(property_schema) => validate(property_schema, value, &property_path)?,
+            None => {
+                if let Some(additional) = schema.get("additionalProperties") {
+                    validate(additional, value, &property_path)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns `true` if `value` is of the JSON Schema type named `name`.
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => false,
+    }
+}
+
+/// Returns the value of a keyword that limits a length or count.
+fn limit(schema: &Map<String, Value>, keyword: &str) -> Option<usize> {
+    schema
+        .get(keyword)
+        .and_then(Value::as_u64)
+        .and_then(|n| usize::try_from(n).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn validate_reports_path_of_first_problem() {
+        let schema = json!({
+            "type": "object",
+            "required": ["name", "tags"],
+            "properties": {
+                "name": {"type": "string", "minLength": 1},
+                "tags": {"type": "array", "items": {"enum": ["a", "b"]}},
+            },
+            "additionalProperties": false,
+        });
+        let cases = [
+            (json!({"name": "x", "tags": ["a"]}), None),
+            (json!([]), Some(("", "must be of type object"))),
+            (
+                json!({"name": "x"}),
+                Some(("", r#"missing required property "tags""#)),
+            ),
+            (
+                json!({"name": "", "tags": []}),
+                Some(("/name", "must be at least 1 characters long")),
+            ),
+            (
+                json!({"name": "x", "tags": ["a", "c"]}),
+                Some(("/tags/1", r#"must be one of ["a","b"]"#)),
+            ),
+            (
+                json!({"name": "x", "tags": [], "extra": 1}),
+                Some(("/extra", "no value is allowed")),
+            ),
+        ];
+        for (value, want) in cases {
+            let got = validate(&schema, &value, "").err();
+            let want = want.map(|(p, m)| (p.to_string(), m.to_string()));
+            assert_eq!(want, got, "wrong result for {value}");
+        }
+    }
+
+    #[test]
+    fn insert_checks_values_against_matching_schemas() {
+        let mut s = Store::<Value>::from_entries("unused.kv", [("n.a".to_string(), json!("x"))]);
+        assert!(
+            s.set_schema("n.*", json!({"type": "integer"})).is_err(),
+            "schema set despite invalid existing value"
+        );
+        s.force_remove("n.a");
+        s.set_schema("n.*", json!({"type": "integer"})).unwrap();
+        assert!(
+            s.try_insert("n.b".to_string(), json!(1)).is_ok(),
+            "valid value rejected"
+        );
+        assert!(
+            matches!(
+                s.try_insert("n.c".to_string(), json!("x")),
+                Err(StoreError::Invalid { .. })
+            ),
+            "invalid value accepted"
+        );
+        assert!(
+            s.try_insert("other".to_string(), json!("x")).is_ok(),
+            "unmatched key checked"
+        );
+    }
+}
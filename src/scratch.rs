@@ -0,0 +1,178 @@
+//! Short-lived entries that belong to a single process.
+
+use crate::{Store, StoreError};
+use serde::{Deserialize, Serialize};
+
+/// The process that created a scratch entry.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct Owner {
+    pid: u32,
+    /// Identifies the boot session, so that entries from before a reboot
+    /// can't be mistaken for ones owned by a new process with the same PID.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    boot: Option<String>,
+}
+
+impl Owner {
+    pub(crate) fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            boot: boot_id(),
+        }
+    }
+
+    pub(crate) fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Returns `true` unless the owning process is known to have exited.
+    pub(crate) fn is_alive(&self) -> bool {
+        if self.boot.is_some() && self.boot != boot_id() {
+            return false;
+        }
+        if self.pid == std::process::id() {
+            return true;
+        }
+        process_exists(self.pid)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn boot_id() -> Option<String> {
+    let id = std::fs::read_to_string("/proc/sys/kernel/random/boot_id").ok()?;
+    Some(id.trim().to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn boot_id() -> Option<String> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn process_exists(pid: u32) -> bool {
+    std::path::Path::new(&format!("/proc/{pid}")).exists()
+}
+
+/// Without `/proc`, there's no portable way to check, so assume the process
+/// is still running.
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn process_exists(_pid: u32) -> bool {
+    true
+}
+
+/// A view of a [`Store`] for adding scratch entries, as returned by
+/// [`Store::scratch()`].
+pub struct Scratch<'a, V> {
+    store: &'a mut Store<V>,
+}
+
+impl<V> Scratch<'_, V> {
+    /// Inserts a key-value pair that belongs to the current process,
+    /// returning the previous value for `key` (following any aliases), if
+    /// any.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Protected`] if `key` is protected, or
+    /// [`StoreError::Invalid`] if the value doesn't match the schema for
+    /// `key`.
+    pub fn insert(&mut self, key: String, value: V) -> Result<Option<V>, StoreError>
+    where
+        V: serde::Serialize,
+    {
+        let key = self.store.normalize_owned(key);
+        let key = self.store.resolve(&key).to_string();
+        let old = self.store.try_insert(key.clone(), value)?;
+        self.store.meta.scratch.insert(key, Owner::current());
+        Ok(old)
+    }
+}
+
+impl<V> Store<V> {
+    /// Returns a view of the store for adding scratch entries.
+    ///
+    /// A scratch entry belongs to the process that created it, and is
+    /// removed the next time the store is opened after that process has
+    /// exited (or the system has restarted). This is useful for scripts
+    /// that need to share short-lived state. Setting the key again with
+    /// [`Self::insert()`] makes it permanent.
+    ///
+    /// Detecting that the process has exited is currently only supported on
+    /// Linux. On other systems, scratch entries are kept until removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.scratch().insert("lock_holder".to_string(), "job-42".to_string())?;
+    /// assert!(s.is_scratch("lock_holder"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn scratch(&mut self) -> Scratch<'_, V> {
+        Scratch { store: self }
+    }
+
+    /// Returns `true` if `key` (following any aliases) is a scratch entry.
+    #[must_use]
+    pub fn is_scratch(&self, key: &str) -> bool {
+        let key = self.normalize(key);
+        self.meta.scratch.contains_key(self.resolve(&key))
+    }
+
+    /// Returns `true` if `key` is a scratch entry whose owning process has
+    /// exited.
+    pub(crate) fn is_orphaned(&self, key: &str) -> bool {
+        self.meta
+            .scratch
+            .get(key)
+            .is_some_and(|owner| !owner.is_alive())
+    }
+
+    /// Removes scratch entries whose owning process has exited.
+    pub(crate) fn purge_scratch(&mut self) {
+        let doomed: Vec<_> = self
+            .meta
+            .scratch
+            .keys()
+            .filter(|key| self.is_orphaned(key))
+            .cloned()
+            .collect();
+        for key in &doomed {
+            self.force_remove(key);
+            self.meta.scratch.remove(key);
+        }
+        if !doomed.is_empty() {
+            self.touch();
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn open_removes_scratch_entries_of_exited_processes() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        let mut s = Store::<u8>::open(&path).unwrap();
+        s.try_insert("permanent".to_string(), 1).unwrap();
+        s.scratch().insert("mine".to_string(), 2).unwrap();
+        s.scratch().insert("theirs".to_string(), 3).unwrap();
+        // No process can have this PID, as it's above the kernel's limit.
+        s.meta.scratch.get_mut("theirs").unwrap().pid = u32::MAX;
+        s.sync().unwrap();
+        let s = Store::<u8>::open(&path).unwrap();
+        assert!(s.contains_key("permanent"), "permanent entry removed");
+        assert!(s.contains_key("mine"), "live process's entry removed");
+        assert!(!s.contains_key("theirs"), "exited process's entry kept");
+        assert!(!s.is_scratch("theirs"), "exited process's entry kept");
+    }
+}
@@ -0,0 +1,66 @@
+//! Searching keys and values with regular expressions.
+
+use regex::Regex;
+
+use crate::Store;
+
+impl<V> Store<V> {
+    /// Returns an iterator over the keys matching the regular expression
+    /// `re`, in arbitrary order. A key matches if `re` matches any part of
+    /// it, so use `^` and `$` to match whole keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// use rskey::{Regex, Store};
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("db_host".to_string(), "localhost".to_string())?;
+    /// s.try_insert("db_port".to_string(), "5432".to_string())?;
+    /// s.try_insert("log_level".to_string(), "info".to_string())?;
+    /// let re = Regex::new("^db_(host|user)$").unwrap();
+    /// assert_eq!(s.keys_matching_regex(&re).collect::<Vec<_>>(), ["db_host"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn keys_matching_regex<'a>(&'a self, re: &'a Regex) -> impl Iterator<Item = &'a str> {
+        self.inner
+            .keys()
+            .map(String::as_str)
+            .filter(move |key| re.is_match(key))
+    }
+}
+
+impl Store<String> {
+    /// Returns an iterator over the key-value pairs whose value matches the
+    /// regular expression `re`, in arbitrary order.
+    ///
+    /// Secret values are searched too, so take care not to reveal them; see
+    /// [`Self::is_secret()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// use rskey::{Regex, Store};
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("db_host".to_string(), "localhost".to_string())?;
+    /// s.try_insert("db_port".to_string(), "5432".to_string())?;
+    /// let re = Regex::new(r"^\d+$").unwrap();
+    /// assert_eq!(s.find_values(&re).collect::<Vec<_>>(), [("db_port", "5432")]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn find_values<'a>(&'a self, re: &'a Regex) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.inner
+            .iter()
+            .filter(move |(_, value)| re.is_match(value))
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+}
@@ -0,0 +1,176 @@
+//! Rewriting a store's files to take up as little space as possible.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{Backend, Op, Store};
+
+/// The sizes of a store's files before and after [`Store::shrink()`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ShrinkReport {
+    /// The total size of the data file and operations log before shrinking,
+    /// in bytes.
+    pub before: u64,
+    /// Their total size afterwards, in bytes.
+    pub after: u64,
+    /// The number of superseded operations removed from the log.
+    pub ops_removed: usize,
+}
+
+impl<V> Store<V>
+where
+    V: DeserializeOwned + Serialize,
+{
+    /// Syncs the store, rewriting the data file in full, and removes
+    /// superseded operations from the operations log (see
+    /// [`Self::set_ops_log()`]), reporting the size of the files before and
+    /// after.
+    ///
+    /// Every change to a key is appended to the log, so it keeps growing,
+    /// even as keys are deleted. Shrinking it keeps only the latest
+    /// operation for each key. Replaying the operations since any sequence
+    /// number (see [`Self::ops_since()`]) still leaves another store with
+    /// the same data, but without the intermediate values.
+    ///
+    /// The log is rewritten to a temporary file which is renamed into
+    /// place. Hold the store's lock (see [`Self::lock()`]) so that no other
+    /// process syncs it meanwhile.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<u32>::builder(path).ops_log(true).open()?;
+    /// for n in 0..10 {
+    ///     s.try_insert("counter".to_string(), n)?;
+    ///     s.sync()?;
+    /// }
+    /// let report = s.shrink()?;
+    /// assert_eq!(report.ops_removed, 9);
+    /// assert!(report.after < report.before);
+    /// assert_eq!(s.ops_since(0)?.len(), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns any error syncing the store, or reading or writing the log,
+    /// or if the log contains invalid JSON.
+    pub fn shrink(&self) -> io::Result<ShrinkReport> {
+        let ops_path = self.ops_path();
+        let before = self.files_size()?;
+        self.sync()?;
+        let ops_removed = compact_log(&*self.backend, &ops_path)?;
+        let after = self.files_size()?;
+        Ok(ShrinkReport {
+            before,
+            after,
+            ops_removed,
+        })
+    }
+
+    /// Returns the total size of the data file and the operations log,
+    /// counting either as 0 if it doesn't exist.
+    fn files_size(&self) -> io::Result<u64> {
+        let file = self.backend.size(&self.path)?.unwrap_or_default();
+        let log = self.backend.size(&self.ops_path())?.unwrap_or_default();
+        Ok(file + log)
+    }
+}
+
+/// Rewrites the log at `path` with only the last operation on each key,
+/// keeping their order, and returns the number of operations removed.
+fn compact_log(backend: &dyn Backend, path: &Path) -> io::Result<usize> {
+    let Some(text) = backend.read(path)? else {
+        return Ok(0);
+    };
+    let text =
+        String::from_utf8(text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let ops = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<Op<Value>>, _>>()?;
+    let mut last = HashMap::new();
+    for (i, op) in ops.iter().enumerate() {
+        let (Op::Set { key, .. } | Op::Delete { key, .. }) = op;
+        last.insert(key.as_str(), i);
+    }
+    let mut buf = Vec::new();
+    for (i, op) in ops.iter().enumerate() {
+        let (Op::Set { key, .. } | Op::Delete { key, .. }) = op;
+        if last[key.as_str()] == i {
+            serde_json::to_writer(&mut buf, op)?;
+            buf.push(b'\n');
+        }
+    }
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    backend.write(&tmp_path, &buf)?;
+    backend.rename(&tmp_path, path)?;
+    Ok(ops.len() - last.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn shrink_keeps_the_latest_op_for_each_key_in_order() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut s = Store::<u8>::open(tmp_dir.path().join("store.kv")).unwrap();
+        s.set_ops_log(true);
+        s.try_insert("a".to_string(), 1).unwrap();
+        s.try_insert("b".to_string(), 2).unwrap();
+        s.sync().unwrap();
+        s.remove("a").unwrap();
+        s.sync().unwrap();
+        s.try_insert("b".to_string(), 3).unwrap();
+        let report = s.shrink().unwrap();
+        assert_eq!(2, report.ops_removed, "wrong ops removed: {report:?}");
+        assert_eq!(
+            vec![
+                Op::Delete {
+                    seq: 2,
+                    key: "a".to_string()
+                },
+                Op::Set {
+                    seq: 3,
+                    key: "b".to_string(),
+                    value: 3
+                },
+            ],
+            s.ops_since(0).unwrap()
+        );
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn shrink_works_through_backend() {
+        let mut s: Store<u8> = Store::builder("store.kv")
+            .backend(crate::testing::StoreBackendMock::new())
+            .ops_log(true)
+            .open()
+            .unwrap();
+        for value in 1..=3 {
+            s.try_insert("a".to_string(), value).unwrap();
+            s.sync().unwrap();
+        }
+        let report = s.shrink().unwrap();
+        assert_eq!(2, report.ops_removed, "wrong ops removed: {report:?}");
+        assert!(report.after < report.before, "nothing shrunk: {report:?}");
+        assert_eq!(1, s.ops_since(0).unwrap().len(), "wrong ops kept");
+    }
+}
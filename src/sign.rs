@@ -0,0 +1,147 @@
+//! HMAC signatures for detecting tampering with data files.
+//!
+//! A signed data file is the usual JSON document followed by a trailer line
+//! containing an HMAC-SHA256 over the document:
+//!
+//! ```text
+//! {"format":"rskey/1","meta":{},"data":{"key1":"value1"}}
+//! hmac-sha256:5c1b...
+//! ```
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt::{self, Debug, Write};
+use std::fs;
+use std::path::Path;
+
+const TRAILER_PREFIX: &str = "\nhmac-sha256:";
+
+/// A secret key used to sign and verify a store's data file.
+///
+/// See [`Store::open_signed()`](crate::Store::open_signed).
+#[derive(Clone)]
+pub struct SigningKey(Vec<u8>);
+
+impl SigningKey {
+    /// Creates a signing key from a passphrase.
+    #[must_use]
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        Self(passphrase.as_bytes().to_vec())
+    }
+
+    /// Creates a signing key from the contents of a key file.
+    ///
+    /// The whole file is used as the key, including any trailing newline.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error reading the file.
+    pub fn from_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self(fs::read(path)?))
+    }
+
+    /// Creates a signing key from a passphrase held in the operating system's
+    /// credential store: the macOS Keychain, Windows Credential Manager, or
+    /// the Secret Service on Linux. The passphrase is the one stored for
+    /// `user` under `service`.
+    ///
+    /// Requires the `keyring` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`std::io::ErrorKind::NotFound`] if there is
+    /// no such entry, or any other error accessing the credential store.
+    #[cfg(feature = "keyring")]
+    pub fn from_keyring(service: &str, user: &str) -> std::io::Result<Self> {
+        let passphrase = keyring::Entry::new(service, user)
+            .and_then(|entry| entry.get_password())
+            .map_err(|e| match e {
+                keyring::Error::NoEntry => std::io::Error::new(std::io::ErrorKind::NotFound, e),
+                e => std::io::Error::other(e),
+            })?;
+        Ok(Self::from_passphrase(&passphrase))
+    }
+
+    fn mac(&self, data: &[u8]) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC accepts keys of any length");
+        mac.update(data);
+        mac
+    }
+
+    /// Returns the signature trailer to be appended to `data`.
+    pub(crate) fn trailer(&self, data: &[u8]) -> String {
+        let tag = self.mac(data).finalize().into_bytes();
+        format!("{TRAILER_PREFIX}{}\n", encode_hex(&tag))
+    }
+
+    /// Returns `true` if `signature` is a valid hex-encoded signature for
+    /// `data`.
+    pub(crate) fn verify(&self, data: &[u8], signature: &str) -> bool {
+        decode_hex(signature).is_some_and(|tag| self.mac(data).verify_slice(&tag).is_ok())
+    }
+}
+
+impl Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SigningKey(..)")
+    }
+}
+
+/// Splits a data file into the JSON document and its signature, if any.
+pub(crate) fn split(file: &[u8]) -> (&[u8], Option<&str>) {
+    let prefix = TRAILER_PREFIX.as_bytes();
+    let Some(start) = file
+        .windows(prefix.len())
+        .rposition(|window| window == prefix)
+    else {
+        return (file, None);
+    };
+    match std::str::from_utf8(&file[start + prefix.len()..]) {
+        Ok(signature) if signature.trim_end().bytes().all(|b| b.is_ascii_hexdigit()) => {
+            (&file[..start], Some(signature.trim_end()))
+        }
+        _ => (file, None),
+    }
+}
+
+pub(crate) fn encode_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        write!(hex, "{byte:02x}").expect("writing to a String can't fail");
+    }
+    hex
+}
+
+pub(crate) fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_separates_document_from_valid_trailer() {
+        let key = SigningKey::from_passphrase("secret");
+        let doc = br#"{"format":"rskey/1","data":{}}"#;
+        let mut file = doc.to_vec();
+        file.extend_from_slice(key.trailer(doc).as_bytes());
+        let (got_doc, signature) = split(&file);
+        assert_eq!(doc.as_slice(), got_doc, "wrong document");
+        assert!(
+            key.verify(got_doc, signature.unwrap()),
+            "signature should verify"
+        );
+        assert!(
+            !SigningKey::from_passphrase("wrong").verify(got_doc, signature.unwrap()),
+            "signature should not verify with wrong key"
+        );
+    }
+}
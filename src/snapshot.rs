@@ -0,0 +1,279 @@
+//! Keeping timestamped copies of a store.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::format::Contents;
+use crate::periodic::Periodic;
+use crate::{clock, Store, StoreError};
+
+/// A copy of a store taken at a particular time, as returned by
+/// [`Store::snapshot()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    /// The name of the snapshot, which is the time it was taken, in seconds
+    /// since the Unix epoch, followed by `-` and a counter if an earlier
+    /// snapshot was taken in the same second.
+    pub name: String,
+    /// The path of the snapshot file.
+    pub path: PathBuf,
+    /// When the snapshot was taken.
+    pub taken: SystemTime,
+}
+
+impl Snapshot {
+    fn from_path(path: PathBuf) -> Option<Self> {
+        let name = path.file_stem()?.to_str()?.to_string();
+        if path.extension()? != "kv" {
+            return None;
+        }
+        let (secs, _) = parse_name(&name)?;
+        Some(Self {
+            name,
+            path,
+            taken: UNIX_EPOCH + Duration::from_secs(secs),
+        })
+    }
+}
+
+impl<V> Store<V> {
+    /// Returns the directory holding the store's snapshots, which is named
+    /// after the data file, with `.snapshots` appended.
+    pub(crate) fn snapshot_dir(&self) -> PathBuf {
+        let mut dir = self.path.as_os_str().to_owned();
+        dir.push(".snapshots");
+        dir.into()
+    }
+
+    /// Returns the store's snapshots, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error reading the snapshot directory.
+    pub fn snapshots(&self) -> io::Result<Vec<Snapshot>> {
+        let mut snapshots: Vec<_> = self
+            .backend
+            .list(&self.snapshot_dir())?
+            .into_iter()
+            .filter_map(Snapshot::from_path)
+            .collect();
+        snapshots.sort_by_cached_key(|s| parse_name(&s.name));
+        Ok(snapshots)
+    }
+
+    /// Deletes snapshots taken more than `retention` ago, returning how many
+    /// were deleted. The newest snapshot is always kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error listing or deleting the snapshots.
+    pub fn prune_snapshots(&self, retention: Duration) -> io::Result<usize> {
+        let cutoff = self
+            .clock
+            .now()
+            .checked_sub(retention)
+            .unwrap_or(UNIX_EPOCH);
+        let mut snapshots = self.snapshots()?;
+        snapshots.pop();
+        let mut pruned = 0;
+        for snapshot in snapshots.iter().filter(|s| s.taken < cutoff) {
+            self.backend.remove(&snapshot.path)?;
+            pruned += 1;
+        }
+        Ok(pruned)
+    }
+}
+
+impl<V> Store<V>
+where
+    V: DeserializeOwned + Serialize,
+{
+    /// Writes a copy of the store, including any unsynced changes, to a new
+    /// snapshot file, named after the current time. If a snapshot was
+    /// already taken in the same second, a counter is added to the name, so
+    /// that it isn't replaced.
+    ///
+    /// Snapshots are kept in a directory alongside the data file, named
+    /// after it, with `.snapshots` appended. See [`Self::snapshots()`],
+    /// [`Self::prune_snapshots()`], and [`Self::restore_snapshot()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("mode".to_string(), "auto".to_string())?;
+    /// let snapshot = s.snapshot()?;
+    /// s.try_insert("mode".to_string(), "manual".to_string())?;
+    /// s.restore_snapshot(&snapshot)?;
+    /// assert_eq!(s["mode"], "auto");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns any error creating the snapshot directory or writing the
+    /// file.
+    pub fn snapshot(&self) -> io::Result<Snapshot> {
+        let dir = self.snapshot_dir();
+        self.backend.create_dir_all(&dir)?;
+        let secs = clock::unix_secs(&*self.clock);
+        let mut name = secs.to_string();
+        let mut path = dir.join(format!("{name}.kv"));
+        let mut counter = 0;
+        while self.backend.exists(&path)? {
+            counter += 1;
+            name = format!("{secs}-{counter}");
+            path = dir.join(format!("{name}.kv"));
+        }
+        self.sync_to(&path)?;
+        Ok(Snapshot {
+            name,
+            path,
+            taken: UNIX_EPOCH + Duration::from_secs(secs),
+        })
+    }
+
+    /// Replaces the store's contents with those of `snapshot`. The store is
+    /// marked dirty, so the restored contents are written to the data file
+    /// by the next sync.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Tampered`] if the store has a signing key and
+    /// the snapshot's signature is missing or doesn't match, or any error
+    /// reading the snapshot.
+    pub fn restore_snapshot(&mut self, snapshot: &Snapshot) -> Result<(), StoreError> {
+        let file = self
+            .backend
+            .read(&snapshot.path)?
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        let mut contents =
+            serde_json::from_slice::<Contents<V>>(self.verify(&file)?)?.decompress()?;
+        // Keep counting generations from the current file, so that readers
+        // notice the change.
+        contents.generation = self.generation();
+        self.replace_contents(contents);
+        self.touch();
+        Ok(())
+    }
+}
+
+impl<V> Store<V>
+where
+    V: DeserializeOwned + Serialize + Send + 'static,
+{
+    /// Starts a background thread that takes a snapshot of `store` every
+    /// `interval`, then deletes snapshots older than `retention`.
+    ///
+    /// The thread stops when the returned [`Snapshotter`] is dropped. Any
+    /// error taking or pruning snapshots is ignored, and the operation is
+    /// retried next time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// use std::sync::{Arc, Mutex};
+    /// use std::time::Duration;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let store = Arc::new(Mutex::new(Store::<String>::open(path)?));
+    /// let hour = Duration::from_secs(60 * 60);
+    /// let snapshotter = Store::spawn_snapshotter(&store, hour, 24 * 7 * hour);
+    /// // ...
+    /// drop(snapshotter);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn spawn_snapshotter(
+        store: &Arc<Mutex<Self>>,
+        interval: Duration,
+        retention: Duration,
+    ) -> Snapshotter {
+        let store = Arc::clone(store);
+        let task = Periodic::spawn(interval, move || {
+            let store = store.lock().unwrap_or_else(PoisonError::into_inner);
+            if store.snapshot().is_ok() {
+                let _ = store.prune_snapshots(retention);
+            }
+        });
+        Snapshotter { _task: task }
+    }
+}
+
+/// A background thread that takes snapshots of a store, as returned by
+/// [`Store::spawn_snapshotter()`].
+///
+/// Dropping the `Snapshotter` stops the thread, waiting for any snapshot in
+/// progress to finish.
+#[derive(Debug)]
+pub struct Snapshotter {
+    _task: Periodic,
+}
+
+/// Returns the time and counter in a snapshot's name.
+fn parse_name(name: &str) -> Option<(u64, u64)> {
+    let (secs, counter) = name.split_once('-').unwrap_or((name, "0"));
+    Some((secs.parse().ok()?, counter.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn prune_snapshots_removes_only_old_snapshots() {
+        let tmp_dir = TempDir::new().unwrap();
+        let s = Store::<u8>::open(tmp_dir.path().join("store.kv")).unwrap();
+        let dir = tmp_dir.path().join("store.kv.snapshots");
+        fs::create_dir(&dir).unwrap();
+        for name in ["100.kv", "200.kv", "not-a-snapshot.kv", "300.txt"] {
+            fs::write(dir.join(name), "{}").unwrap();
+        }
+        let recent = s.snapshot().unwrap();
+        let names: Vec<_> = s.snapshots().unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(vec!["100", "200", &recent.name], names, "wrong snapshots");
+        let day = Duration::from_secs(24 * 60 * 60);
+        assert_eq!(2, s.prune_snapshots(day).unwrap(), "wrong number pruned");
+        assert_eq!(vec![recent], s.snapshots().unwrap(), "wrong snapshots kept");
+        assert_eq!(
+            0,
+            s.prune_snapshots(Duration::ZERO).unwrap(),
+            "newest pruned"
+        );
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn snapshots_go_through_backend() {
+        let mut s: Store<u8> = Store::builder("store.kv")
+            .backend(crate::testing::StoreBackendMock::new())
+            .clock(crate::testing::MockClock::new())
+            .open()
+            .unwrap();
+        s.try_insert("a".to_string(), 1).unwrap();
+        let snapshot = s.snapshot().unwrap();
+        assert!(!snapshot.path.exists(), "snapshot written to filesystem");
+        assert_eq!(vec![snapshot.clone()], s.snapshots().unwrap());
+        let again = s.snapshot().unwrap();
+        assert_eq!(format!("{}-1", snapshot.name), again.name, "wrong name");
+        assert_eq!(2, s.snapshots().unwrap().len(), "wrong number of snapshots");
+        s.try_insert("a".to_string(), 2).unwrap();
+        s.restore_snapshot(&snapshot).unwrap();
+        assert_eq!(Some(&1), s.get("a"), "snapshot not restored");
+    }
+}
@@ -0,0 +1,290 @@
+//! Splitting a store into one store per key prefix, and joining stores
+//! into one.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{Store, StoreError};
+
+/// The stores written by [`Store::split_by_prefix()`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SplitReport {
+    /// The number of keys written to the store for each prefix.
+    pub parts: BTreeMap<String, usize>,
+    /// The keys that weren't written to any store, because they have no
+    /// prefix, or one that can't be used as a file name.
+    pub skipped: Vec<String>,
+}
+
+/// What [`Store::join()`] does with a key that has different values in the
+/// two stores.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Conflict {
+    /// Return [`StoreError::JoinConflict`], without changing the store.
+    #[default]
+    Fail,
+    /// Keep the store's own value.
+    Keep,
+    /// Replace the store's value with the other store's.
+    Replace,
+}
+
+impl<V> Store<V>
+where
+    V: DeserializeOwned + Serialize + Clone,
+{
+    /// Writes the store's entries to a new store for each top-level key
+    /// prefix, ending at the first `separator`, in the directory `dir`,
+    /// which is created if necessary. Each store is named after its
+    /// prefix, with `.kv` appended, replacing any existing file of that
+    /// name. The directory and stores are written through the store's
+    /// backend.
+    ///
+    /// Keys are written in full, prefix and all, so the stores can be
+    /// joined again with [`Self::join()`]. Each key's metadata, such as its
+    /// expiry time and version, and any aliases starting with the same
+    /// prefix, go with it. Secret patterns, schemas, and key normalization
+    /// apply to every store. The store itself is unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// # let parts = tmp_dir.path().join("parts");
+    /// let entries = [("app:port", "80"), ("app:host", "web1"), ("db:port", "5432")];
+    /// let s = Store::from_entries(path, entries.map(|(k, v)| (k.to_string(), v.to_string())));
+    /// let report = s.split_by_prefix(":", &parts)?;
+    /// assert_eq!(report.parts["app"], 2);
+    /// let app = Store::<String>::open(parts.join("app.kv"))?;
+    /// assert_eq!(app["app:host"], "web1");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Io`] for any error creating the directory or
+    /// writing the stores, in which case some may have been written.
+    pub fn split_by_prefix(
+        &self,
+        separator: &str,
+        dir: impl AsRef<Path>,
+    ) -> Result<SplitReport, StoreError> {
+        let mut report = SplitReport::default();
+        let mut parts: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        let keys: BTreeSet<&str> = self
+            .inner
+            .keys()
+            .chain(self.meta.encrypted.keys())
+            .map(String::as_str)
+            .collect();
+        for key in keys {
+            match prefix(key, separator) {
+                Some(prefix) => parts.entry(prefix).or_default().push(key),
+                None => report.skipped.push(key.to_string()),
+            }
+        }
+        let dir = dir.as_ref();
+        self.backend.create_dir_all(dir)?;
+        for (prefix, keys) in parts {
+            let entries = keys
+                .iter()
+                .filter_map(|&key| Some((key.to_string(), self.inner.get(key)?.clone())));
+            let mut part = Store::from_entries(dir.join(format!("{prefix}.kv")), entries);
+            part.meta = self.meta.subset(|key| {
+                key.strip_prefix(prefix)
+                    .is_some_and(|k| k.starts_with(separator))
+            });
+            part.signing_key.clone_from(&self.signing_key);
+            part.backend = Arc::clone(&self.backend);
+            part.sync()?;
+            report.parts.insert(prefix.to_string(), keys.len());
+        }
+        Ok(report)
+    }
+
+    /// Copies each entry of `other` into the store, along with its
+    /// metadata, such as its expiry time and protection, and returns the
+    /// number copied. Entries with the same value in both stores aren't
+    /// copied, and `conflict` decides what happens to those with different
+    /// values.
+    ///
+    /// Encrypted values (see [`Self::insert_encrypted()`]) are copied as
+    /// they are, so they can be read with the same key as before. The other
+    /// store's secret patterns, schemas, and aliases are added to the
+    /// store's own, except where it already has a schema for the same
+    /// pattern, or the same alias. This doesn't sync the store.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// use rskey::{Conflict, Store};
+    /// # let tmp_dir = TempDir::new()?;
+    /// let mut s = Store::<u32>::open(tmp_dir.path().join("combined.kv"))?;
+    /// s.try_insert("port".to_string(), 80)?;
+    /// let mut other = Store::<u32>::open(tmp_dir.path().join("other.kv"))?;
+    /// other.try_insert("port".to_string(), 8080)?;
+    /// other.try_insert("workers".to_string(), 4)?;
+    /// assert!(s.join(&other, Conflict::Fail).is_err());
+    /// assert_eq!(s.join(&other, Conflict::Keep)?, 1);
+    /// assert_eq!(s["port"], 80);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::JoinConflict`] if a key has different values
+    /// in the two stores and `conflict` is [`Conflict::Fail`], in which case
+    /// the store is unchanged. Otherwise, returns any error from
+    /// [`Self::try_insert()`] or [`Self::alias()`], in which case the entries
+    /// copied so far are kept.
+    pub fn join(&mut self, other: &Store<V>, conflict: Conflict) -> Result<usize, StoreError>
+    where
+        V: PartialEq,
+    {
+        let keys: BTreeSet<&String> = other
+            .inner
+            .keys()
+            .chain(other.meta.encrypted.keys())
+            .collect();
+        let mut copy = Vec::new();
+        for key in keys {
+            let ours = (self.inner.get(key), self.meta.encrypted.get(key));
+            let theirs = (other.inner.get(key), other.meta.encrypted.get(key));
+            if ours == (None, None) {
+                copy.push(key);
+            } else if ours != theirs {
+                match conflict {
+                    Conflict::Fail => return Err(StoreError::JoinConflict(key.clone())),
+                    Conflict::Keep => {}
+                    Conflict::Replace => copy.push(key),
+                }
+            }
+        }
+        for key in &copy {
+            if let Some(value) = other.inner.get(*key) {
+                self.try_insert((*key).clone(), value.clone())?;
+            } else {
+                self.insert_sealed(key, &other.meta.encrypted[*key])?;
+            }
+            if let Some(&at) = other.meta.expires.get(*key) {
+                self.meta.expires.insert((*key).clone(), at);
+            }
+            if other.meta.protected.contains(*key) {
+                self.protect(key);
+            }
+        }
+        self.meta.secret.extend(other.meta.secret.iter().cloned());
+        for (pattern, schema) in &other.meta.schemas {
+            self.meta
+                .schemas
+                .entry(pattern.clone())
+                .or_insert_with(|| schema.clone());
+        }
+        for (alias, key) in &other.meta.aliases {
+            if !self.meta.aliases.contains_key(alias) {
+                self.alias(alias, key)?;
+            }
+        }
+        self.touch();
+        Ok(copy.len())
+    }
+
+    /// Stores `sealed`, an encrypted value copied from another store, for
+    /// `key`, replacing any value it already has.
+    fn insert_sealed(&mut self, key: &str, sealed: &str) -> Result<(), StoreError> {
+        if self.is_protected(key) {
+            return Err(StoreError::Protected(key.to_string()));
+        }
+        if self.inner.remove(key).is_some() {
+            self.unindex_key(key);
+        }
+        self.meta.expires.remove(key);
+        self.meta.scratch.remove(key);
+        self.bump_version(key);
+        self.meta
+            .encrypted
+            .insert(key.to_string(), sealed.to_string());
+        self.touch();
+        Ok(())
+    }
+}
+
+/// Returns the part of `key` before the first `separator`, if it has one
+/// that can be used as a file name.
+fn prefix<'k>(key: &'k str, separator: &str) -> Option<&'k str> {
+    let (prefix, _) = key.split_once(separator)?;
+    let valid =
+        !prefix.is_empty() && !prefix.starts_with('.') && !prefix.contains(['/', '\\', '\0']);
+    valid.then_some(prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn split_then_join_restores_entries_and_metadata() {
+        let tmp_dir = TempDir::new().unwrap();
+        let entries = [("a:1", 1), ("a:2", 2), ("b:1", 3), ("c", 4), ("../x:1", 5)];
+        let mut s = Store::from_entries(
+            tmp_dir.path().join("store.kv"),
+            entries.map(|(k, v)| (k.to_string(), v)),
+        );
+        s.protect("a:2");
+        s.meta.expires.insert("b:1".to_string(), 4_000_000_000);
+        let report = s
+            .split_by_prefix(":", tmp_dir.path().join("parts"))
+            .unwrap();
+        assert_eq!(
+            BTreeMap::from([("a".to_string(), 2), ("b".to_string(), 1)]),
+            report.parts
+        );
+        assert_eq!(vec!["../x:1", "c"], report.skipped);
+        let mut joined = Store::<u8>::from_entries(tmp_dir.path().join("joined.kv"), []);
+        for part in ["a", "b"] {
+            let path = tmp_dir.path().join("parts").join(format!("{part}.kv"));
+            let part = Store::open(path).unwrap();
+            joined.join(&part, Conflict::Fail).unwrap();
+        }
+        assert_eq!(3, joined.len(), "wrong keys: {joined:?}");
+        assert!(joined.is_protected("a:2"), "protection not kept");
+        assert_eq!(Some(&4_000_000_000), joined.meta.expires.get("b:1"));
+        let mut other = Store::<u8>::from_entries("other.kv", [("a:1".to_string(), 9)]);
+        other.meta.versions.insert("a:1".to_string(), 7);
+        let err = joined.join(&other, Conflict::Fail).unwrap_err();
+        assert!(
+            matches!(err, StoreError::JoinConflict(ref k) if k == "a:1"),
+            "wrong error {err:?}"
+        );
+        assert_eq!(1, joined.join(&other, Conflict::Replace).unwrap());
+        assert_eq!(9, joined["a:1"]);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn split_by_prefix_writes_through_backend() {
+        let mock = crate::testing::StoreBackendMock::new();
+        let mut s: Store<u8> = Store::builder("store.kv")
+            .backend(mock.clone())
+            .open()
+            .unwrap();
+        s.try_insert("a:1".to_string(), 1).unwrap();
+        s.split_by_prefix(":", "parts").unwrap();
+        assert!(mock.contents("parts/a.kv").is_some(), "part not in backend");
+        assert!(!Path::new("parts").exists(), "directory created");
+    }
+}
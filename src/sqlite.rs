@@ -0,0 +1,186 @@
+//! Dumping entries to, and restoring them from, SQLite databases.
+
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::io;
+use std::path::Path;
+
+use crate::{Store, StoreError};
+
+/// The statement that creates the table of entries.
+const CREATE_TABLE: &str = "CREATE TABLE entries (
+    key TEXT PRIMARY KEY NOT NULL,
+    value TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    expires INTEGER,
+    protected INTEGER NOT NULL,
+    secret INTEGER NOT NULL
+)";
+
+impl Store<String> {
+    /// Writes the store's entries to the SQLite database at `path`, creating
+    /// it if necessary, and returns the number written.
+    ///
+    /// The entries are written to a table called `entries`, replacing any
+    /// existing table of that name, with a row for each key. The columns
+    /// are:
+    ///
+    /// * `key` and `value`, as text.
+    /// * `version`, the key's version (see [`Self::version()`]).
+    /// * `expires`, when the key expires (see [`Self::expire()`]), in seconds
+    ///   since the Unix epoch, or `NULL` if it doesn't.
+    /// * `protected` and `secret`, which are 1 if the key is protected (see
+    ///   [`Self::protect()`]) or secret (see [`Self::mark_secret()`]), and 0
+    ///   otherwise. Secret values are written in plaintext.
+    ///
+    /// The table is replaced in a single transaction, so readers of the
+    /// database see either the old table or the new one. Requires the
+    /// `sqlite` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// # let db = tmp_dir.path().join("data.db");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("key1".to_string(), "value1".to_string())?;
+    /// s.protect("key1");
+    /// assert_eq!(s.export_sqlite(&db)?, 1);
+    /// let mut copy = Store::<String>::open(tmp_dir.path().join("copy.kv"))?;
+    /// assert_eq!(copy.import_sqlite(&db)?, 1);
+    /// assert_eq!(copy["key1"], "value1");
+    /// assert!(copy.is_protected("key1"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Io`] for any error opening or writing to the
+    /// database, or [`StoreError::Encrypted`] if any entry has an encrypted
+    /// value (see [`Self::insert_encrypted()`]), in which case it's left
+    /// unchanged.
+    pub fn export_sqlite(&self, path: impl AsRef<Path>) -> Result<usize, StoreError> {
+        self.refuse_encrypted(|_| true)?;
+        let mut db = Connection::open(path).map_err(sqlite_error)?;
+        let tx = db.transaction().map_err(sqlite_error)?;
+        tx.execute("DROP TABLE IF EXISTS entries", [])
+            .and_then(|_| tx.execute(CREATE_TABLE, []))
+            .map_err(sqlite_error)?;
+        let mut insert = tx
+            .prepare("INSERT INTO entries VALUES (?1, ?2, ?3, ?4, ?5, ?6)")
+            .map_err(sqlite_error)?;
+        for (key, value) in self.iter() {
+            insert
+                .execute(params![
+                    key,
+                    value,
+                    self.version(key),
+                    self.meta.expires.get(key),
+                    self.meta.protected.contains(key),
+                    self.is_secret(key),
+                ])
+                .map_err(sqlite_error)?;
+        }
+        drop(insert);
+        tx.commit().map_err(sqlite_error)?;
+        Ok(self.len())
+    }
+
+    /// Sets keys from the `entries` table of the SQLite database at `path`,
+    /// as written by [`Self::export_sqlite()`], replacing any existing values
+    /// for those keys, and returns the number set.
+    ///
+    /// Each key's expiry time and protection are restored from the
+    /// `expires` and `protected` columns. The `version` and `secret` columns
+    /// are ignored, since versions only increase, and secret keys are
+    /// marked by pattern, rather than one by one. This doesn't sync the
+    /// store. Requires the `sqlite` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Io`] for any error reading the database,
+    /// including if it has no `entries` table, or
+    /// [`StoreError::Protected`] if a key being set is protected, in which
+    /// case the keys set so far are kept.
+    pub fn import_sqlite(&mut self, path: impl AsRef<Path>) -> Result<usize, StoreError> {
+        let db = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(sqlite_error)?;
+        let table: Option<String> = db
+            .query_row(
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'entries'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(sqlite_error)?;
+        if table.is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "no entries table").into());
+        }
+        let mut select = db
+            .prepare("SELECT key, value, expires, protected FROM entries")
+            .map_err(sqlite_error)?;
+        let rows = select
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<u64>>(2)?,
+                    row.get::<_, bool>(3)?,
+                ))
+            })
+            .map_err(sqlite_error)?;
+        let mut imported = 0;
+        for row in rows {
+            let (key, value, expires, protected) = row.map_err(sqlite_error)?;
+            let key = self.normalize_owned(key);
+            self.try_insert(key.clone(), value)?;
+            if let Some(at) = expires {
+                self.meta.expires.insert(key.clone(), at);
+            }
+            if protected {
+                self.protect(&key);
+            }
+            imported += 1;
+        }
+        Ok(imported)
+    }
+}
+
+fn sqlite_error(e: rusqlite::Error) -> StoreError {
+    StoreError::Io(io::Error::other(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn export_sqlite_round_trips_expiry_and_replaces_old_table() {
+        let tmp_dir = TempDir::new().unwrap();
+        let db = tmp_dir.path().join("data.db");
+        let mut s = Store::<String>::new("unused.kv".into());
+        s.try_insert("a".to_string(), "1".to_string()).unwrap();
+        s.export_sqlite(&db).unwrap();
+        s.try_insert("b".to_string(), "2".to_string()).unwrap();
+        s.meta.expires.insert("b".to_string(), 4_000_000_000);
+        s.mark_secret("b");
+        assert_eq!(2, s.export_sqlite(&db).unwrap(), "wrong count");
+        let secret: bool = Connection::open(&db)
+            .unwrap()
+            .query_row("SELECT secret FROM entries WHERE key = 'b'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert!(secret, "secret column not set");
+        let mut copy = Store::<String>::new("unused.kv".into());
+        assert_eq!(2, copy.import_sqlite(&db).unwrap(), "wrong count");
+        assert_eq!(Some(&4_000_000_000), copy.meta.expires.get("b"));
+        let err = copy.import_sqlite(tmp_dir.path().join("none.db"));
+        assert!(matches!(err, Err(StoreError::Io(_))), "wrong error {err:?}");
+    }
+}
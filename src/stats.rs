@@ -0,0 +1,159 @@
+//! Reporting on what a store holds.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use crate::limit::entry_size;
+use crate::Store;
+
+/// Statistics about a store's data, as returned by [`Store::stats()`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Stats {
+    /// The number of keys.
+    pub keys: usize,
+    /// The size of the data, in bytes, as returned by [`Store::bytes_used()`].
+    pub bytes: usize,
+    /// The number of distinct values. Two values are the same if they
+    /// serialize to the same JSON.
+    pub distinct_values: usize,
+    /// The number of bytes taken up by values identical to another value,
+    /// which is how much smaller the data would be if each distinct value
+    /// were stored only once.
+    pub duplicate_bytes: usize,
+}
+
+impl<V: Serialize> Store<V> {
+    /// Returns statistics about the store's data, including how much of it
+    /// is taken up by duplicate values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("a".to_string(), "same".to_string())?;
+    /// s.try_insert("b".to_string(), "same".to_string())?;
+    /// let stats = s.stats();
+    /// assert_eq!(stats.keys, 2);
+    /// assert_eq!(stats.distinct_values, 1);
+    /// assert_eq!(stats.duplicate_bytes, 6);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn stats(&self) -> Stats {
+        // Count each distinct value by its hash, so that large values
+        // needn't be kept in memory twice.
+        let mut sizes = HashMap::new();
+        let mut duplicate_bytes = 0;
+        for value in self.inner.values() {
+            // Serializing a value that's already in the store can't fail.
+            let json = serde_json::to_vec(value).unwrap_or_default();
+            if sizes.insert(Sha256::digest(&json), json.len()).is_some() {
+                duplicate_bytes += json.len();
+            }
+        }
+        Stats {
+            keys: self.inner.len(),
+            bytes: self.bytes_used(),
+            distinct_values: sizes.len(),
+            duplicate_bytes,
+        }
+    }
+
+    /// Returns the `n` largest entries, largest first, with the number of
+    /// bytes each uses, as counted by [`Self::bytes_used()`]. Entries of the
+    /// same size are ordered by key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<String>::open(path)?;
+    /// s.try_insert("small".to_string(), "x".to_string())?;
+    /// s.try_insert("big".to_string(), "x".repeat(100))?;
+    /// assert_eq!(s.largest(1), [("big", 105)]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn largest(&self, n: usize) -> Vec<(&str, usize)> {
+        let mut sizes: Vec<_> = self
+            .inner
+            .iter()
+            .map(|(key, value)| (key.as_str(), entry_size(key, value)))
+            .collect();
+        sizes.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        sizes.truncate(n);
+        sizes
+    }
+
+    /// Returns the total number of bytes used by the entries with each key
+    /// prefix, largest first.
+    ///
+    /// A key's prefix is everything up to and including its first character
+    /// that isn't a letter or digit, so `db_host` and `db_port` share the
+    /// prefix `db_`, and `app.name` has the prefix `app.`. A key with no
+    /// such character is its own prefix.
+    #[must_use]
+    pub fn prefix_sizes(&self) -> Vec<(&str, usize)> {
+        let mut sizes = HashMap::<&str, usize>::new();
+        for (key, value) in &self.inner {
+            let end = key
+                .char_indices()
+                .find(|(_, c)| !c.is_alphanumeric())
+                .map_or(key.len(), |(i, c)| i + c.len_utf8());
+            *sizes.entry(&key[..end]).or_default() += entry_size(key, value);
+        }
+        let mut sizes: Vec<_> = sizes.into_iter().collect();
+        sizes.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        sizes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_counts_duplicate_values_once() {
+        let entries = [("a", "xyz"), ("b", "xyz"), ("c", "xyz"), ("d", "w")]
+            .map(|(k, v)| (k.to_string(), v.to_string()));
+        let s = Store::from_entries("unused.kv", entries);
+        let want = Stats {
+            keys: 4,
+            bytes: 4 + 3 * 5 + 3,
+            distinct_values: 2,
+            duplicate_bytes: 2 * 5,
+        };
+        assert_eq!(want, s.stats(), "wrong stats");
+    }
+
+    #[test]
+    fn prefix_sizes_groups_keys_by_first_separator() {
+        let entries = [
+            ("db_host", "x"),
+            ("db_port", "xyz"),
+            ("app.name", "x"),
+            ("solo", ""),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        let s = Store::from_entries("unused.kv", entries);
+        assert_eq!(
+            vec![("db_", 22), ("app.", 11), ("solo", 6)],
+            s.prefix_sizes(),
+            "wrong prefix sizes"
+        );
+        assert_eq!(vec![("db_port", 12)], s.largest(1), "wrong largest");
+    }
+}
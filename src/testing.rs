@@ -0,0 +1,505 @@
+//! Helpers for testing code that uses a [`Store`].
+//!
+//! Requires the `testing` feature. Enable it for your dev-dependencies only:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! rskey = { version = "*", features = ["testing"] }
+//! ```
+
+use crate::format;
+use crate::{Backend, Clock, Store, StoreError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+use tempfile::TempDir;
+
+/// Creates an empty store that keeps its data in memory, using a
+/// [`StoreBackendMock`], instead of in a file.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> std::io::Result<()> {
+/// let mut s = rskey::testing::memory_store::<String>();
+/// s.try_insert("key".into(), "value".into())?;
+/// s.sync()?;
+/// # Ok(())
+/// # }
+/// ```
+#[must_use]
+pub fn memory_store<V>() -> Store<V> {
+    let mut store = Store::new(PathBuf::from("store.kv"));
+    store.backend = Arc::new(StoreBackendMock::new());
+    store
+}
+
+impl<V> Store<V>
+where
+    V: DeserializeOwned + Serialize + PartialEq + Debug,
+{
+    /// Checks that the store's data survives being synced and opened again
+    /// unchanged.
+    ///
+    /// This is useful for property-testing custom value types, to catch
+    /// mistakes in their `serde` attributes. The data is written to a
+    /// [`StoreBackendMock`], not to the store's own file.
+    ///
+    /// Requires the `testing` feature. With the `arbitrary` feature, stores
+    /// also implement [`Arbitrary`](https://docs.rs/arbitrary), so you can
+    /// generate random ones to check.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// use rskey::Store;
+    /// use serde::{Deserialize, Serialize};
+    ///
+    /// #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    /// struct Point {
+    ///     x: i32,
+    ///     #[serde(skip)]
+    ///     y: i32,
+    /// }
+    ///
+    /// let mut s = rskey::testing::memory_store();
+    /// s.try_insert("p".into(), Point { x: 1, y: 2 })?;
+    /// assert!(s.verify_roundtrip().is_err(), "y was not persisted");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::Io`] if the data can't be written or read
+    /// back, or if any value or metadata changed in the process.
+    pub fn verify_roundtrip(&self) -> Result<(), StoreError> {
+        let backend = StoreBackendMock::new();
+        let path = Path::new("store.kv");
+        let doc = format::to_vec(self.generation(), &self.meta, &self.inner)?;
+        format::write_file(&backend, path, doc, None)?;
+        let copy = Store::<V>::builder(path).backend(backend).open()?;
+        for (key, value) in &self.inner {
+            let copied = copy.inner.get(key);
+            if copied != Some(value) {
+                return Err(changed(format!(
+                    "value for key {key:?} changed from {value:?} to {copied:?}"
+                )));
+            }
+        }
+        if copy.inner.len() != self.inner.len() {
+            return Err(changed(format!(
+                "number of entries changed from {} to {}",
+                self.inner.len(),
+                copy.inner.len()
+            )));
+        }
+        if copy.meta != self.meta {
+            return Err(changed(format!(
+                "metadata changed from {:?} to {:?}",
+                self.meta, copy.meta
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn changed(msg: String) -> StoreError {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg).into()
+}
+
+/// A [`Backend`] that keeps data files in memory, and can be told to fail.
+///
+/// Clones of a mock share the same files, so you can keep one to inspect
+/// what a store has written.
+///
+/// Each sync writes the data to a temporary file, then renames it over the
+/// data file, so syncing once takes one write.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), rskey::StoreError> {
+/// use rskey::testing::StoreBackendMock;
+/// use rskey::Store;
+///
+/// let mock = StoreBackendMock::new().fail_write(2);
+/// let mut s: Store<String> = Store::builder("store.kv")
+///     .backend(mock.clone())
+///     .open()?;
+/// s.sync()?;
+/// assert!(s.sync().is_err());
+/// assert_eq!(mock.writes(), 2);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct StoreBackendMock {
+    files: Arc<Mutex<HashMap<PathBuf, Vec<u8>>>>,
+    writes: Arc<AtomicUsize>,
+    fault: Option<(usize, Fault)>,
+}
+
+/// A simulated I/O failure, injected by a [`StoreBackendMock`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Fault {
+    /// The write fails without writing anything.
+    Error,
+    /// The write fails because the disk is full (`ENOSPC`).
+    NoSpace,
+    /// Only the given number of bytes are written before the write fails,
+    /// as if the process had crashed.
+    PartialWrite(usize),
+    /// The write fails because the file isn't writable.
+    PermissionDenied,
+}
+
+impl Fault {
+    fn error(self) -> std::io::Error {
+        match self {
+            Self::Error => std::io::Error::other("injected write failure"),
+            // ENOSPC on Unix, or ERROR_DISK_FULL on Windows.
+            Self::NoSpace => {
+                std::io::Error::from_raw_os_error(if cfg!(windows) { 112 } else { 28 })
+            }
+            Self::PartialWrite(n) => std::io::Error::new(
+                std::io::ErrorKind::WriteZero,
+                format!("injected partial write of {n} bytes"),
+            ),
+            Self::PermissionDenied => std::io::ErrorKind::PermissionDenied.into(),
+        }
+    }
+}
+
+impl StoreBackendMock {
+    /// Creates a mock containing no files.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes the `n`th write to this mock (counting from 1) fail. The file
+    /// is left unchanged.
+    #[must_use]
+    pub fn fail_write(self, n: usize) -> Self {
+        self.fail_write_with(n, Fault::Error)
+    }
+
+    /// Makes the `n`th write to this mock (counting from 1) fail with the
+    /// given `fault`.
+    #[must_use]
+    pub fn fail_write_with(mut self, n: usize, fault: Fault) -> Self {
+        self.fault = Some((n, fault));
+        self
+    }
+
+    /// Returns the contents of the file at `path`, if any.
+    #[must_use]
+    pub fn contents(&self, path: impl AsRef<Path>) -> Option<Vec<u8>> {
+        self.files().get(path.as_ref()).cloned()
+    }
+
+    /// Returns the number of writes attempted so far, including any that
+    /// failed.
+    #[must_use]
+    pub fn writes(&self) -> usize {
+        self.writes.load(Ordering::Relaxed)
+    }
+
+    fn files(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, Vec<u8>>> {
+        self.files.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Backend for StoreBackendMock {
+    fn read(&self, path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+        Ok(self.contents(path))
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+        let n = self.writes.fetch_add(1, Ordering::Relaxed) + 1;
+        match self.fault {
+            Some((fail, fault)) if fail == n => {
+                if let Fault::PartialWrite(len) = fault {
+                    let partial = data[..len.min(data.len())].to_vec();
+                    self.files().insert(path.to_path_buf(), partial);
+                }
+                Err(fault.error())
+            }
+            _ => {
+                self.files().insert(path.to_path_buf(), data.to_vec());
+                Ok(())
+            }
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        let mut files = self.files();
+        let data = files.remove(from).ok_or(std::io::ErrorKind::NotFound)?;
+        files.insert(to.to_path_buf(), data);
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        self.files()
+            .remove(path)
+            .map(drop)
+            .ok_or_else(|| std::io::ErrorKind::NotFound.into())
+    }
+
+    fn exists(&self, path: &Path) -> std::io::Result<bool> {
+        Ok(self.files().contains_key(path))
+    }
+
+    fn list(&self, dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+        Ok(self
+            .files()
+            .keys()
+            .filter(|path| crate::backend::in_dir(path, dir))
+            .cloned()
+            .collect())
+    }
+}
+
+/// A [`Clock`] that only moves when told to, for testing expiry times and
+/// snapshots.
+///
+/// Clones of a mock clock share the same time, so you can keep one to
+/// advance the clock of a store using another.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), rskey::StoreError> {
+/// use rskey::testing::{MockClock, StoreBackendMock};
+/// use rskey::Store;
+/// use std::time::Duration;
+///
+/// let clock = MockClock::new();
+/// let mut s: Store<String> = Store::builder("store.kv")
+///     .backend(StoreBackendMock::new())
+///     .clock(clock.clone())
+///     .open()?;
+/// s.try_insert("session".into(), "abc123".into())?;
+/// s.expire("session", Duration::from_secs(60));
+/// clock.advance(Duration::from_secs(59));
+/// assert_eq!(s.purge_expired(), 0);
+/// clock.advance(Duration::from_secs(1));
+/// assert_eq!(s.purge_expired(), 1);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+impl MockClock {
+    /// Creates a clock set to the current system time.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::at(SystemTime::now())
+    }
+
+    /// Creates a clock set to `time`.
+    #[must_use]
+    pub fn at(time: SystemTime) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(time)),
+        }
+    }
+
+    /// Moves the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) += by;
+    }
+
+    /// Sets the clock to `time`.
+    pub fn set(&self, time: SystemTime) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) = time;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A store whose data file is in a temporary directory, which is deleted
+/// when the `TempStore` is dropped.
+///
+/// `TempStore` dereferences to [`Store`], so it can be used just like one.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> std::io::Result<()> {
+/// use rskey::testing::TempStore;
+///
+/// let mut s = TempStore::<String>::new()?;
+/// s.try_insert("key".into(), "value".into())?;
+/// s.sync()?;
+/// assert_eq!(s.reopen()?.get("key"), Some(&"value".to_string()));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TempStore<V> {
+    store: Store<V>,
+    _dir: TempDir,
+}
+
+impl<V> TempStore<V>
+where
+    V: DeserializeOwned + Serialize,
+{
+    /// Creates an empty store in a new temporary directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error creating the directory.
+    pub fn new() -> std::io::Result<Self> {
+        let dir = TempDir::new()?;
+        let store = Store::open(dir.path().join("store.kv"))?;
+        Ok(Self { store, _dir: dir })
+    }
+
+    /// Opens the data file again, as a separate store, so you can check
+    /// what was synced.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error opening the file.
+    pub fn reopen(&self) -> std::io::Result<Store<V>> {
+        Store::open(self.store.path())
+    }
+}
+
+impl<V> Deref for TempStore<V> {
+    type Target = Store<V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.store
+    }
+}
+
+impl<V> DerefMut for TempStore<V> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.store
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_store_syncs_to_mock_backend() {
+        let mock = StoreBackendMock::new();
+        let mut s = Store::<String>::builder("data.kv")
+            .backend(mock.clone())
+            .open()
+            .unwrap();
+        s.try_insert("key".into(), "value".into()).unwrap();
+        s.sync().unwrap();
+        assert!(mock.contents("data.kv").is_some(), "nothing written");
+        let s2 = Store::<String>::builder("data.kv")
+            .backend(mock)
+            .open()
+            .unwrap();
+        assert_eq!(
+            Some(&"value".to_string()),
+            s2.get("key"),
+            "expected data not returned"
+        );
+    }
+
+    #[test]
+    fn fail_write_fails_only_nth_write() {
+        let mock = StoreBackendMock::new().fail_write(2);
+        let s = Store::<String>::builder("data.kv")
+            .backend(mock.clone())
+            .open()
+            .unwrap();
+        assert!(s.sync().is_ok(), "first write failed");
+        assert!(s.sync().is_err(), "second write didn't fail");
+        assert!(s.sync().is_ok(), "third write failed");
+        assert_eq!(3, mock.writes(), "wrong number of writes");
+    }
+
+    #[test]
+    fn verify_roundtrip_detects_values_that_change() {
+        #[derive(Debug, PartialEq, serde::Deserialize, Serialize)]
+        struct Lossy {
+            kept: u8,
+            #[serde(skip)]
+            lost: u8,
+        }
+
+        let mut s = memory_store();
+        s.try_insert("ok".into(), Lossy { kept: 1, lost: 0 })
+            .unwrap();
+        assert!(s.verify_roundtrip().is_ok(), "default value didn't survive");
+        s.try_insert("bad".into(), Lossy { kept: 1, lost: 1 })
+            .unwrap();
+        assert!(s.verify_roundtrip().is_err(), "lost field not detected");
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn arbitrary_stores_round_trip() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let bytes: Vec<u8> = (0..4096_u32)
+            .map(|i| i.wrapping_mul(2_654_435_761).to_le_bytes()[3])
+            .collect();
+        for chunk in bytes.chunks(256) {
+            let s = Store::<Vec<Option<u32>>>::arbitrary(&mut Unstructured::new(chunk)).unwrap();
+            assert!(s.verify_roundtrip().is_ok(), "store didn't round-trip");
+        }
+    }
+
+    #[test]
+    fn failed_sync_leaves_previous_data_intact() {
+        for fault in [
+            Fault::NoSpace,
+            Fault::PartialWrite(10),
+            Fault::PermissionDenied,
+        ] {
+            let mock = StoreBackendMock::new().fail_write_with(2, fault);
+            let mut s = Store::<String>::builder("data.kv")
+                .backend(mock.clone())
+                .open()
+                .unwrap();
+            s.try_insert("k1".into(), "v1".into()).unwrap();
+            s.sync().unwrap();
+            s.try_insert("k2".into(), "v2".into()).unwrap();
+            assert!(s.sync().is_err(), "{fault:?}: sync didn't fail");
+            assert!(s.is_dirty(), "{fault:?}: failed sync cleared dirty flag");
+            assert!(
+                !mock.exists(Path::new("data.kv.tmp")).unwrap(),
+                "{fault:?}: temporary file left behind"
+            );
+            let s2 = Store::<String>::builder("data.kv")
+                .backend(mock)
+                .open()
+                .unwrap();
+            assert_eq!(1, s2.len(), "{fault:?}: previous data not preserved");
+        }
+    }
+}
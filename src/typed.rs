@@ -0,0 +1,119 @@
+//! Typed access to stores of JSON values.
+
+use crate::{Store, StoreError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::marker::PhantomData;
+
+impl Store<Value> {
+    /// Returns a view of the store that converts values to and from `T`.
+    ///
+    /// This lets a program use its own types for values, while the data file
+    /// holds plain JSON that can still be edited with the `rskey` tool, or
+    /// shared with programs using other types.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// use rskey::Store;
+    /// use serde::{Deserialize, Serialize};
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    ///
+    /// #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    /// struct Server {
+    ///     host: String,
+    ///     port: u16,
+    /// }
+    ///
+    /// let mut s = Store::<serde_json::Value>::open(path)?;
+    /// let mut servers = s.typed::<Server>();
+    /// servers.insert("web".into(), &Server { host: "web1".into(), port: 80 })?;
+    /// assert_eq!(servers.get("web")?.unwrap().port, 80);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn typed<T>(&mut self) -> Typed<'_, T> {
+        Typed {
+            store: self,
+            _value: PhantomData,
+        }
+    }
+}
+
+/// A view of a store of JSON values as values of type `T`, as returned by
+/// [`Store::typed()`].
+pub struct Typed<'a, T> {
+    store: &'a mut Store<Value>,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> Typed<'_, T>
+where
+    T: DeserializeOwned + Serialize,
+{
+    /// Returns the value for `key`, if any, converted to `T`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::TypeMismatch`] if the value can't be converted
+    /// to `T`.
+    pub fn get(&self, key: &str) -> Result<Option<T>, StoreError> {
+        let Some(value) = self.store.get(key) else {
+            return Ok(None);
+        };
+        T::deserialize(value)
+            .map(Some)
+            .map_err(|e| StoreError::TypeMismatch {
+                key: key.to_string(),
+                message: e.to_string(),
+            })
+    }
+
+    /// Converts `value` to JSON, and inserts it into the store, as with
+    /// [`Store::try_insert()`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::TypeMismatch`] if `value` can't be converted to
+    /// JSON, or any error from [`Store::try_insert()`].
+    pub fn insert(&mut self, key: String, value: &T) -> Result<(), StoreError> {
+        let value = serde_json::to_value(value).map_err(|e| StoreError::TypeMismatch {
+            key: key.clone(),
+            message: e.to_string(),
+        })?;
+        self.store.try_insert(key, value)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn typed_get_reports_values_of_wrong_type() {
+        let mut s = Store::<Value>::from_entries("unused.kv", [("n".to_string(), json!("x"))]);
+        let mut numbers = s.typed::<u32>();
+        numbers.insert("m".to_string(), &7).unwrap();
+        assert_eq!(
+            Some(7),
+            numbers.get("m").unwrap(),
+            "expected data not returned"
+        );
+        assert_eq!(None, numbers.get("missing").unwrap(), "unexpected data");
+        let err = numbers.get("n").unwrap_err();
+        assert!(
+            matches!(&err, StoreError::TypeMismatch { key, .. } if key == "n"),
+            "wrong error {err:?}"
+        );
+        assert!(
+            err.to_string().contains("expected u32"),
+            "unhelpful error {err}"
+        );
+    }
+}
@@ -0,0 +1,375 @@
+//! Checking a store and its files for inconsistencies.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::path::Path;
+
+use crate::index::KeyIndex;
+use crate::{format, sign, Op, Store, StoreError};
+
+/// The problems found by [`Store::check_integrity()`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct IntegrityReport {
+    /// Each problem found, in the order they were checked.
+    pub issues: Vec<Issue>,
+}
+
+impl IntegrityReport {
+    /// Returns `true` if no problems were found.
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// A problem found by [`Store::check_integrity()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Issue {
+    /// The store has a signing key, but the data file isn't signed.
+    Unsigned,
+    /// The data file's signature doesn't match its contents.
+    BadSignature,
+    /// The data file has no format header, so it was written by an old
+    /// version of `rskey`, or by hand.
+    NoHeader,
+    /// Metadata of the given kind, such as an expiry time, is kept for a key
+    /// that has no value.
+    OrphanedMetadata {
+        /// The kind of metadata.
+        kind: &'static str,
+        /// The key it's kept for.
+        key: String,
+    },
+    /// A key has both a plaintext and an encrypted value.
+    DoubleValue(String),
+    /// A key isn't in the form that the store's key normalization (see
+    /// [`Store::set_key_normalization()`]) would give it, so it can't be
+    /// looked up.
+    Unnormalized(String),
+    /// An alias is also a key, so the alias is never used.
+    ShadowedAlias(String),
+    /// Following an alias never reaches a key, because it leads round in a
+    /// cycle.
+    AliasCycle(String),
+    /// A value doesn't match the schema for its key.
+    SchemaViolation {
+        /// The key.
+        key: String,
+        /// What's wrong with the value.
+        message: String,
+    },
+    /// The key index (see [`Store::set_key_index()`]) doesn't list exactly
+    /// the store's keys.
+    IndexMismatch,
+    /// A line of the operations log can't be read.
+    BadOp {
+        /// The line number, counting from 1.
+        line: usize,
+        /// Why it can't be read.
+        message: String,
+    },
+    /// An operation in the log has a lower sequence number than the one
+    /// before it, or a higher one than the data file's generation.
+    OpOutOfOrder {
+        /// The line number, counting from 1.
+        line: usize,
+        /// The operation's sequence number.
+        seq: u64,
+    },
+    /// The last operation logged for a key doesn't match its value, so
+    /// replaying the log on another store wouldn't give the same data.
+    OpMismatch(String),
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::Unsigned => write!(f, "data file isn't signed"),
+            Issue::BadSignature => write!(f, "data file's signature doesn't match"),
+            Issue::NoHeader => write!(f, "data file has no format header"),
+            Issue::OrphanedMetadata { kind, key } => {
+                write!(f, "{kind} kept for missing key {key:?}")
+            }
+            Issue::DoubleValue(key) => {
+                write!(f, "key {key:?} has both a plaintext and an encrypted value")
+            }
+            Issue::Unnormalized(key) => write!(f, "key {key:?} isn't normalized"),
+            Issue::ShadowedAlias(alias) => write!(f, "alias {alias:?} is also a key"),
+            Issue::AliasCycle(alias) => write!(f, "alias {alias:?} leads round in a cycle"),
+            Issue::SchemaViolation { key, message } => {
+                write!(f, "value for {key:?} doesn't match schema: {message}")
+            }
+            Issue::IndexMismatch => write!(f, "key index doesn't match keys"),
+            Issue::BadOp { line, message } => {
+                write!(f, "operations log line {line}: {message}")
+            }
+            Issue::OpOutOfOrder { line, seq } => {
+                write!(f, "operations log line {line}: sequence {seq} out of order")
+            }
+            Issue::OpMismatch(key) => {
+                write!(
+                    f,
+                    "last logged operation for {key:?} doesn't match its value"
+                )
+            }
+        }
+    }
+}
+
+impl<V> Store<V>
+where
+    V: DeserializeOwned + Serialize,
+{
+    /// Opens the store at `path`, as with [`Self::open()`], and checks it
+    /// with [`Self::check_integrity()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// std::fs::write(&path, r#"{"key1":"value1"}"#)?;
+    /// let (s, report) = Store::<String>::open_verified(&path)?;
+    /// assert_eq!(s["key1"], "value1");
+    /// assert_eq!(report.issues[0].to_string(), "data file has no format header");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns any error opening the store, such as if the data file isn't
+    /// valid JSON, or reading the operations log.
+    pub fn open_verified(path: impl AsRef<Path>) -> Result<(Self, IntegrityReport), StoreError> {
+        let store = Self::open(path)?;
+        let report = store.check_integrity()?;
+        Ok((store, report))
+    }
+
+    /// Checks the store, its data file, and its operations log (see
+    /// [`Self::set_ops_log()`]) for inconsistencies that could cause
+    /// surprises later, and reports them.
+    ///
+    /// The data file is checked for a valid signature, if the store has a
+    /// signing key, and for a format header. The store's entries are
+    /// checked against their metadata, aliases, schemas, and key index.
+    /// Each line of the operations log is checked to be in order, and the
+    /// last operation logged for each key to match its value. Unsynced
+    /// changes are checked too, so the operations log may not match them.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error reading the data file or the operations log.
+    pub fn check_integrity(&self) -> Result<IntegrityReport, StoreError> {
+        let mut issues = Vec::new();
+        if let Some(file) = self.backend.read(&self.path)? {
+            self.check_file(&file, &mut issues)?;
+        }
+        self.check_metadata(&mut issues);
+        self.check_entries(&mut issues);
+        self.check_ops(&mut issues)?;
+        Ok(IntegrityReport { issues })
+    }
+
+    fn check_file(&self, file: &[u8], issues: &mut Vec<Issue>) -> Result<(), StoreError> {
+        let (doc, signature) = sign::split(file);
+        if let Some(key) = &self.signing_key {
+            match signature {
+                None => issues.push(Issue::Unsigned),
+                Some(signature) if !key.verify(doc, signature) => {
+                    issues.push(Issue::BadSignature);
+                }
+                Some(_) => {}
+            }
+        }
+        if !format::has_header(doc)? {
+            issues.push(Issue::NoHeader);
+        }
+        Ok(())
+    }
+
+    fn check_metadata(&self, issues: &mut Vec<Issue>) {
+        let has_value =
+            |key: &String| self.inner.contains_key(key) || self.meta.encrypted.contains_key(key);
+        let meta = &self.meta;
+        let keys = [
+            ("expiry time", meta.expires.keys().collect::<Vec<_>>()),
+            ("scratch owner", meta.scratch.keys().collect()),
+            ("version", meta.versions.keys().collect()),
+        ];
+        for (kind, keys) in keys {
+            for key in keys.into_iter().filter(|key| !has_value(key)) {
+                issues.push(Issue::OrphanedMetadata {
+                    kind,
+                    key: key.clone(),
+                });
+            }
+        }
+        for key in meta.encrypted.keys() {
+            if self.inner.contains_key(key) {
+                issues.push(Issue::DoubleValue(key.clone()));
+            }
+        }
+        for alias in meta.aliases.keys() {
+            if self.inner.contains_key(alias) {
+                issues.push(Issue::ShadowedAlias(alias.clone()));
+            }
+            let end = self.resolve_chain(alias).last().unwrap_or(alias);
+            if meta.aliases.contains_key(end) {
+                issues.push(Issue::AliasCycle(alias.clone()));
+            }
+        }
+    }
+
+    fn check_entries(&self, issues: &mut Vec<Issue>) {
+        let mut keys: Vec<_> = self.inner.iter().collect();
+        keys.sort_unstable_by_key(|(key, _)| *key);
+        for (key, value) in keys {
+            if self.normalize(key) != key.as_str() {
+                issues.push(Issue::Unnormalized(key.clone()));
+            }
+            if let Err(e) = self.validate(key, value) {
+                issues.push(Issue::SchemaViolation {
+                    key: key.clone(),
+                    message: e.to_string(),
+                });
+            }
+        }
+        if let KeyIndex::Fresh(index) = &self.key_index {
+            if index.len() != self.inner.len() || !index.iter().all(|k| self.inner.contains_key(k))
+            {
+                issues.push(Issue::IndexMismatch);
+            }
+        }
+    }
+
+    fn check_ops(&self, issues: &mut Vec<Issue>) -> Result<(), StoreError> {
+        let Some(text) = self.backend.read(&self.ops_path())? else {
+            return Ok(());
+        };
+        // Lines that aren't valid UTF-8 are reported as unreadable below.
+        let text = String::from_utf8_lossy(&text);
+        let mut last_seq = 0;
+        let mut last_ops = HashMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let op: Op<Value> = match serde_json::from_str(line) {
+                Ok(op) => op,
+                Err(e) => {
+                    issues.push(Issue::BadOp {
+                        line: line_number,
+                        message: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+            let seq = op.seq();
+            if seq < last_seq || seq > self.generation() {
+                issues.push(Issue::OpOutOfOrder {
+                    line: line_number,
+                    seq,
+                });
+            }
+            last_seq = last_seq.max(seq);
+            let (Op::Set { key, .. } | Op::Delete { key, .. }) = &op;
+            last_ops.insert(key.clone(), op);
+        }
+        let mut mismatched = Vec::new();
+        for (key, op) in last_ops {
+            let matches = match &op {
+                Op::Set { value, .. } => self
+                    .inner
+                    .get(&key)
+                    .is_some_and(|v| serde_json::to_value(v).ok().as_ref() == Some(value)),
+                Op::Delete { .. } => !self.inner.contains_key(&key),
+            };
+            if !matches {
+                mismatched.push(key);
+            }
+        }
+        mismatched.sort_unstable();
+        issues.extend(mismatched.into_iter().map(Issue::OpMismatch));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SigningKey;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn check_integrity_reports_each_kind_of_problem() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("store.kv");
+        let key = SigningKey::from_passphrase("secret");
+        let mut s = Store::<u8>::open_signed(&path, key.clone()).unwrap();
+        s.set_ops_log(true);
+        s.try_insert("a".to_string(), 1).unwrap();
+        s.sync().unwrap();
+        assert!(s.check_integrity().unwrap().is_ok(), "clean store failed");
+        s.meta.expires.insert("gone".to_string(), 0);
+        s.meta.aliases.insert("x".to_string(), "y".to_string());
+        s.meta.aliases.insert("y".to_string(), "x".to_string());
+        s.inner.insert("a".to_string(), 2);
+        let file = fs::read_to_string(&path).unwrap();
+        fs::write(&path, file.replace("\"a\":1", "\"a\":3")).unwrap();
+        fs::write(
+            s.ops_path(),
+            "not json\n{\"op\":\"delete\",\"seq\":9,\"key\":\"b\"}\n",
+        )
+        .unwrap();
+        let issues: Vec<_> = s
+            .check_integrity()
+            .unwrap()
+            .issues
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            vec![
+                "data file's signature doesn't match",
+                "expiry time kept for missing key \"gone\"",
+                "alias \"x\" leads round in a cycle",
+                "alias \"y\" leads round in a cycle",
+                "operations log line 1: expected ident at line 1 column 2",
+                "operations log line 2: sequence 9 out of order",
+            ],
+            issues
+        );
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn check_integrity_reads_ops_log_through_backend() {
+        use crate::Backend;
+        let mock = crate::testing::StoreBackendMock::new();
+        let mut s: Store<u8> = Store::builder("store.kv")
+            .backend(mock.clone())
+            .ops_log(true)
+            .open()
+            .unwrap();
+        s.try_insert("a".to_string(), 1).unwrap();
+        s.sync().unwrap();
+        assert!(s.check_integrity().unwrap().is_ok(), "clean store failed");
+        mock.append(Path::new("store.kv.ops"), b"\xff\n").unwrap();
+        let issues = s.check_integrity().unwrap().issues;
+        assert!(
+            matches!(issues[..], [Issue::BadOp { line: 2, .. }]),
+            "wrong issues {issues:?}"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! Per-entry version numbers, for optimistic concurrency.
+
+use serde::Serialize;
+
+use crate::{Store, StoreError};
+
+impl<V> Store<V> {
+    /// Returns the version of the value for `key`, which increases by one
+    /// each time it's changed through the store's methods. Versions are
+    /// persisted with the store.
+    ///
+    /// A key that isn't present has version 0, and a newly-inserted key has
+    /// version 1, as does a key that hasn't been changed since versions were
+    /// added to `rskey`. Changes made through the underlying `HashMap` don't
+    /// affect the version.
+    #[must_use]
+    pub fn version(&self, key: &str) -> u64 {
+        let key = self.normalize(key);
+        if !self.inner.contains_key(key.as_ref()) {
+            return 0;
+        }
+        self.meta.versions.get(key.as_ref()).copied().unwrap_or(1)
+    }
+
+    /// Inserts a key-value pair into the store, as with [`Self::try_insert()`],
+    /// but only if the current version of `key` is `expected` (see
+    /// [`Self::version()`]). This lets several processes update the same
+    /// store without overwriting each other's changes: each reads a value
+    /// and its version, and only writes back if nobody else has changed it
+    /// in the meantime.
+    ///
+    /// To insert `key` only if it isn't already present, expect version 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), rskey::StoreError> {
+    /// # use tempfile::TempDir;
+    /// # use rskey::Store;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("data.kv");
+    /// let mut s = Store::<usize>::open(path)?;
+    /// s.insert_if_version("counter".to_string(), 1, 0)?;
+    /// let version = s.version("counter");
+    /// s.try_insert("counter".to_string(), 5)?;
+    /// assert!(s.insert_if_version("counter".to_string(), 2, version).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StoreError::VersionConflict`] if `key` is at a different
+    /// version, or any error from [`Self::try_insert()`].
+    pub fn insert_if_version(
+        &mut self,
+        key: String,
+        value: V,
+        expected: u64,
+    ) -> Result<Option<V>, StoreError>
+    where
+        V: Serialize,
+    {
+        let key = self.normalize_owned(key);
+        let actual = self.version(&key);
+        if actual != expected {
+            return Err(StoreError::VersionConflict {
+                key,
+                expected,
+                actual,
+            });
+        }
+        self.try_insert(key, value)
+    }
+
+    /// Records that the value for `key` is about to change, increasing its
+    /// version.
+    pub(crate) fn bump_version(&mut self, key: &str) {
+        let version = self.version(key) + 1;
+        self.meta.versions.insert(key.to_string(), version);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn changes_increase_version_and_stale_writes_are_rejected() {
+        let mut s = Store::<String>::new(PathBuf::from("unused.kv"));
+        assert_eq!(0, s.version("k"), "absent key has a version");
+        s.try_insert("k".to_string(), "a".to_string()).unwrap();
+        assert_eq!(1, s.version("k"), "wrong version after insert");
+        s.append_str("k", "b").unwrap();
+        s.replace("k", "c".to_string()).unwrap();
+        assert_eq!(3, s.version("k"), "changes didn't increase version");
+        let err = s
+            .insert_if_version("k".to_string(), "d".to_string(), 2)
+            .unwrap_err();
+        assert!(
+            matches!(err, StoreError::VersionConflict { actual: 3, .. }),
+            "wrong error {err:?}"
+        );
+        s.insert_if_version("k".to_string(), "d".to_string(), 3)
+            .unwrap();
+        assert_eq!("d", s["k"], "value not inserted");
+        s.remove("k").unwrap();
+        assert_eq!(0, s.version("k"), "removed key has a version");
+    }
+}
@@ -0,0 +1,145 @@
+//! Keeping a store's data in the browser.
+
+use js_sys::wasm_bindgen::{JsCast, JsValue};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::Backend;
+
+/// A [`Backend`] that keeps data files in the browser's `localStorage`, so
+/// that a store compiled to WebAssembly persists between visits to a page.
+///
+/// Each file is kept as one `localStorage` item, named by the file's path
+/// after a prefix, which is `rskey:` unless set with [`Self::with_prefix()`].
+/// `localStorage` is limited to a few megabytes per site, so this suits
+/// small amounts of state, such as settings; when it's full, syncing fails.
+///
+/// Requires the `web` feature, and only works in a browser's main thread.
+/// Elsewhere, every operation fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> Result<(), rskey::StoreError> {
+/// use rskey::{LocalStorageBackend, Store};
+///
+/// let mut s: Store<String> = Store::builder("settings.kv")
+///     .backend(LocalStorageBackend::new())
+///     .open()?;
+/// s.try_insert("theme".to_string(), "dark".to_string())?;
+/// s.sync()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct LocalStorageBackend {
+    prefix: String,
+}
+
+impl LocalStorageBackend {
+    /// Creates a backend whose items are named with the prefix `rskey:`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_prefix("rskey:")
+    }
+
+    /// Creates a backend whose items are named with the given prefix, to
+    /// keep them apart from other items on the same site.
+    #[must_use]
+    pub fn with_prefix(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+
+    /// Returns the name of the item for the file at `path`.
+    fn item(&self, path: &Path) -> String {
+        format!("{}{}", self.prefix, path.to_string_lossy())
+    }
+}
+
+impl Default for LocalStorageBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the page's `localStorage`.
+fn storage() -> io::Result<web_sys::Storage> {
+    let window = web_sys::window()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "no browser window"))?;
+    window
+        .local_storage()
+        .map_err(js_error)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "localStorage unavailable"))
+}
+
+/// Converts an exception thrown by the browser to an I/O error.
+#[allow(clippy::needless_pass_by_value)]
+fn js_error(e: JsValue) -> io::Error {
+    let message = e
+        .dyn_ref::<js_sys::Error>()
+        .map_or_else(|| format!("{e:?}"), |e| String::from(e.message()));
+    io::Error::other(message)
+}
+
+impl Backend for LocalStorageBackend {
+    fn read(&self, path: &Path) -> io::Result<Option<Vec<u8>>> {
+        let item = storage()?.get_item(&self.item(path)).map_err(js_error)?;
+        Ok(item.map(String::into_bytes))
+    }
+
+    /// Fails with [`io::ErrorKind::InvalidData`] unless `data` is valid
+    /// UTF-8, since `localStorage` can only hold strings.
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let text =
+            std::str::from_utf8(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        storage()?
+            .set_item(&self.item(path), text)
+            .map_err(js_error)
+    }
+
+    /// Copies the item for `from` to `to`, and removes it. Pages run
+    /// JavaScript on a single thread, so no other code on the page can see
+    /// the store in between.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let storage = storage()?;
+        let from = self.item(from);
+        let data = storage
+            .get_item(&from)
+            .map_err(js_error)?
+            .ok_or(io::ErrorKind::NotFound)?;
+        storage.set_item(&self.item(to), &data).map_err(js_error)?;
+        storage.remove_item(&from).map_err(js_error)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        let storage = storage()?;
+        let item = self.item(path);
+        if storage.get_item(&item).map_err(js_error)?.is_none() {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        storage.remove_item(&item).map_err(js_error)
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        let item = storage()?.get_item(&self.item(path)).map_err(js_error)?;
+        Ok(item.is_some())
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let storage = storage()?;
+        let mut paths = Vec::new();
+        for i in 0..storage.length().map_err(js_error)? {
+            let Some(item) = storage.key(i).map_err(js_error)? else {
+                continue;
+            };
+            if let Some(path) = item.strip_prefix(&self.prefix).map(PathBuf::from) {
+                if crate::backend::in_dir(&path, dir) {
+                    paths.push(path);
+                }
+            }
+        }
+        Ok(paths)
+    }
+}
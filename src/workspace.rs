@@ -0,0 +1,188 @@
+//! Naming the stores used by a project in a workspace file.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::Store;
+
+/// The name of the workspace file that [`Workspace::find()`] looks for.
+pub const WORKSPACE_FILE: &str = "rskey.toml";
+
+/// A set of named stores, listed in a TOML workspace file, so that they can
+/// be opened by name instead of by path.
+///
+/// The file has a `stores` table mapping each name to the path of its data
+/// file:
+///
+/// ```toml
+/// [stores]
+/// prod = "/srv/prod.kv"
+/// dev = "./dev.kv"
+/// ```
+///
+/// Relative paths are relative to the directory containing the workspace
+/// file, not the current directory.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Workspace {
+    stores: BTreeMap<String, PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct WorkspaceFile {
+    #[serde(default)]
+    stores: BTreeMap<String, PathBuf>,
+}
+
+impl Workspace {
+    /// Reads the workspace file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error reading the file, or if it isn't a valid
+    /// workspace file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let file: WorkspaceFile = toml::from_str(&text).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e.message()),
+            )
+        })?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let stores = file
+            .stores
+            .into_iter()
+            .map(|(name, store_path)| (name, dir.join(store_path)))
+            .collect();
+        Ok(Self { stores })
+    }
+
+    /// Reads the workspace file named [`WORKSPACE_FILE`] in the current
+    /// directory or, failing that, the nearest parent directory that has
+    /// one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`io::ErrorKind::NotFound`] if there's no workspace file,
+    /// or any error reading it.
+    pub fn find() -> io::Result<Self> {
+        let cwd = env::current_dir()?;
+        for dir in cwd.ancestors() {
+            let path = dir.join(WORKSPACE_FILE);
+            if path.is_file() {
+                return Self::load(path);
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "no {WORKSPACE_FILE} in {} or any parent directory",
+                cwd.display()
+            ),
+        ))
+    }
+
+    /// Opens the store called `name` in the workspace found by
+    /// [`Self::find()`].
+    ///
+    /// # Errors
+    ///
+    /// Returns any error finding the workspace, if it has no store called
+    /// `name`, or any error opening the store.
+    pub fn open<V>(name: &str) -> io::Result<Store<V>>
+    where
+        V: DeserializeOwned + Serialize,
+    {
+        Self::find()?.store(name)
+    }
+
+    /// Returns the names of the workspace's stores, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.stores.keys().map(String::as_str)
+    }
+
+    /// Returns the path of the data file for the store called `name`, if
+    /// there is one.
+    #[must_use]
+    pub fn path(&self, name: &str) -> Option<&Path> {
+        self.stores.get(name).map(PathBuf::as_path)
+    }
+
+    /// Opens the store called `name`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// # use tempfile::TempDir;
+    /// use rskey::Workspace;
+    /// # let tmp_dir = TempDir::new()?;
+    /// # let path = tmp_dir.path().join("rskey.toml");
+    /// std::fs::write(&path, "stores.dev = \"dev.kv\"")?;
+    /// let ws = Workspace::load(&path)?;
+    /// let mut s = ws.store::<String>("dev")?;
+    /// s.try_insert("key1".to_string(), "value1".to_string())?;
+    /// s.sync()?;
+    /// assert!(tmp_dir.path().join("dev.kv").exists());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`io::ErrorKind::NotFound`] if there's no store called
+    /// `name`, or any error opening it.
+    pub fn store<V>(&self, name: &str) -> io::Result<Store<V>>
+    where
+        V: DeserializeOwned + Serialize,
+    {
+        let path = self.path(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no store named {name:?} in workspace"),
+            )
+        })?;
+        Store::open(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn load_resolves_store_paths_relative_to_workspace_file() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join(WORKSPACE_FILE);
+        fs::write(
+            &path,
+            "[stores]\nprod = \"/srv/prod.kv\"\ndev = \"./dev.kv\"\n",
+        )
+        .unwrap();
+        let ws = Workspace::load(&path).unwrap();
+        assert_eq!(vec!["dev", "prod"], ws.names().collect::<Vec<_>>());
+        assert_eq!(Some(Path::new("/srv/prod.kv")), ws.path("prod"));
+        assert_eq!(
+            Some(tmp_dir.path().join("./dev.kv").as_path()),
+            ws.path("dev")
+        );
+        let err = ws.store::<String>("staging").unwrap_err();
+        assert_eq!(io::ErrorKind::NotFound, err.kind(), "wrong error: {err}");
+    }
+
+    #[test]
+    fn load_rejects_invalid_workspace_file() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join(WORKSPACE_FILE);
+        fs::write(&path, "stores = 1").unwrap();
+        let err = Workspace::load(&path).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind(), "wrong error: {err}");
+    }
+}
@@ -1,5 +1,6 @@
 use assert_cmd::Command;
 use predicates::prelude::*;
+use std::time::Duration;
 use tempfile::TempDir;
 
 #[test]
@@ -41,3 +42,1588 @@ fn binary_with_get_reads_existing_data() {
         .success()
         .stdout(predicate::eq("key2: value2\n"));
 }
+
+#[test]
+fn binary_with_set_refuses_to_change_protected_key_without_force() {
+    let tmp_dir = TempDir::new().unwrap();
+    for args in [["set", "key3", "value3"].as_slice(), &["protect", "key3"]] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir).args(args).assert().success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["set", "key3", "value4"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("protected"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["set", "--force", "key3", "value4"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["get", "key3"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("key3: value4\n"));
+}
+
+#[test]
+fn binary_refuses_to_read_data_file_signed_with_different_key() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_SIGNING_KEY", "secret")
+        .args(["set", "key1", "value1"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_SIGNING_KEY", "secret")
+        .args(["get", "key1"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("key1: value1\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_SIGNING_KEY", "wrong")
+        .args(["get", "key1"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("signature"));
+}
+
+#[test]
+fn binary_with_list_hides_secret_values_unless_revealed() {
+    let tmp_dir = TempDir::new().unwrap();
+    for args in [
+        ["set", "api_token", "abc123"].as_slice(),
+        &["secret", "*_token"],
+    ] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir).args(args).assert().success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.arg("list")
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("api_token: *****\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["list", "--reveal"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("api_token: abc123\n"));
+}
+
+#[test]
+fn binary_with_keys_and_values_prints_one_per_line() {
+    let tmp_dir = TempDir::new().unwrap();
+    for args in [
+        ["set", "db_host", "localhost:5432"],
+        ["set", "db_user", "admin"],
+        ["set", "log_level", "info"],
+    ] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir).args(args).assert().success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.arg("keys")
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("db_host\ndb_user\nlog_level\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["keys", "--prefix", "db_"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("db_host\ndb_user\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["values", "db_*"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("localhost:5432\nadmin\n"));
+}
+
+#[test]
+fn binary_with_list_format_tsv_escapes_special_characters() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["set", "key1", "line 1\nline 2\tend\\"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["list", "--format", "tsv"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("key1\tline 1\\nline 2\\tend\\\\\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["list", "-0"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("key1\0line 1\nline 2\tend\\\0"));
+}
+
+#[test]
+fn binary_with_count_and_exists_reports_keys() {
+    let tmp_dir = TempDir::new().unwrap();
+    for args in [
+        ["set", "db_host", "localhost"],
+        ["set", "log_level", "info"],
+    ] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir).args(args).assert().success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.arg("count")
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("2\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["count", "--prefix", "db_"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("1\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["exists", "db_host"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["exists", "db_user"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::is_empty());
+}
+
+#[test]
+fn binary_with_dash_runs_commands_from_stdin() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.arg("-")
+        .current_dir(&tmp_dir)
+        .write_stdin("set a 1\nset b two words\n\n# comment\ndel a\nget b\n")
+        .assert()
+        .success()
+        .stdout(predicate::eq("b: two words\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.arg("list")
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("b: two words\n"));
+}
+
+#[test]
+fn binary_with_dash_atomic_discards_batch_on_error() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["-", "--atomic"])
+        .current_dir(&tmp_dir)
+        .write_stdin("set a 1\nprotect a\nset a 2\nset b 3\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("line 3"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.arg("count")
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("0\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.arg("-")
+        .current_dir(&tmp_dir)
+        .write_stdin("set a 1\nprotect a\nset a 2\nset b 3\n")
+        .assert()
+        .failure();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.arg("list")
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("a: 1\n"));
+}
+
+#[test]
+fn binary_with_set_dash_reads_value_from_stdin() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["set", "key1", "-"])
+        .current_dir(&tmp_dir)
+        .write_stdin("line 1\nline 2\n")
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["set", "--stdin", "key2"])
+        .current_dir(&tmp_dir)
+        .write_stdin("value: 2")
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["values", "key*"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("line 1\nline 2\n\nvalue: 2\n"));
+}
+
+#[test]
+fn binary_with_append_adds_to_value() {
+    let tmp_dir = TempDir::new().unwrap();
+    for args in [["append", "key1", "foo"], ["append", "key1", "bar"]] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir).args(args).assert().success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["get", "key1"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("key1: foobar\n"));
+}
+
+#[test]
+fn binary_with_getset_prints_old_value_and_sets_new_one() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["getset", "key1", "v1"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["getset", "key1", "v2"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("v1\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["get", "key1"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("key1: v2\n"));
+}
+
+#[test]
+fn binary_with_expire_deletes_key_after_ttl() {
+    let tmp_dir = TempDir::new().unwrap();
+    for args in [
+        ["set", "key1", "v1"].as_slice(),
+        &["set", "key2", "v2"],
+        &["expire", "key1", "1h"],
+        &["expire", "key2", "0s"],
+    ] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir).args(args).assert().success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["ttl", "key1"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match("^3[56][0-9]{2}s\n$").unwrap());
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.arg("keys")
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("key1\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["persist", "key1"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["ttl", "key1"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("key \"key1\" does not expire\n"));
+}
+
+#[test]
+fn binary_with_alias_gets_and_sets_aliased_key() {
+    let tmp_dir = TempDir::new().unwrap();
+    for args in [
+        ["set", "new_name", "v1"].as_slice(),
+        &["alias", "old_name", "new_name"],
+        &["set", "old_name", "v2"],
+    ] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir).args(args).assert().success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["get", "old_name"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("old_name: v2\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.arg("keys")
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("new_name\n"));
+}
+
+#[test]
+fn binary_with_get_resolves_references_unless_told_not_to() {
+    let tmp_dir = TempDir::new().unwrap();
+    for args in [["set", "root", "/srv"], ["set", "logs", "${root}/logs"]] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir).args(args).assert().success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["get", "logs"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("logs: /srv/logs\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["get", "--no-resolve", "logs"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("logs: ${root}/logs\n"));
+}
+
+#[test]
+fn binary_with_set_rejects_value_not_matching_schema() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["schema", "log_*", r#"{"enum": ["info", "warn"]}"#])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["set", "log_level", "verbose"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("must be one of"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["set", "log_level", "info"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success();
+}
+
+#[test]
+fn binary_with_namespace_uses_separate_file() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["-n", "staging", "set", "key1", "value1"])
+        .assert()
+        .success();
+    assert!(
+        tmp_dir.path().join(".rskey/ns/staging.kv").exists(),
+        "namespace file not created"
+    );
+    assert!(
+        !tmp_dir.path().join("store.kv").exists(),
+        "default store written"
+    );
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["-n", "staging", "get", "key1"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("key1: value1\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["-n", "../escape", "list"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid namespace"));
+}
+
+#[test]
+fn binary_with_store_name_uses_path_from_workspace_file() {
+    let tmp_dir = TempDir::new().unwrap();
+    std::fs::write(
+        tmp_dir.path().join("rskey.toml"),
+        "stores.dev = \"data/dev.kv\"\n",
+    )
+    .unwrap();
+    let sub_dir = tmp_dir.path().join("sub");
+    std::fs::create_dir(&sub_dir).unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&sub_dir)
+        .args(["-s", "dev", "set", "key1", "value1"])
+        .assert()
+        .success();
+    assert!(
+        tmp_dir.path().join("data/dev.kv").exists(),
+        "workspace store not created"
+    );
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["-s", "dev", "get", "key1"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("key1: value1\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["-s", "prod", "list"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no store named \"prod\""));
+}
+
+#[test]
+fn binary_with_overlay_reads_all_layers_and_writes_top() {
+    let tmp_dir = TempDir::new().unwrap();
+    for (file, pairs) in [
+        ("base.kv", [("level", "debug"), ("port", "8080")]),
+        ("prod.kv", [("level", "warn"), ("host", "web1")]),
+    ] {
+        for (k, v) in pairs {
+            let mut cmd = Command::cargo_bin("rskey").unwrap();
+            cmd.current_dir(&tmp_dir)
+                .args(["--overlay", file, "set", k, v])
+                .assert()
+                .success();
+        }
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["--overlay", "base.kv,prod.kv", "keys"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("host\nlevel\nport\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["--overlay", "base.kv,prod.kv", "get", "level"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("level: warn\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["--overlay", "base.kv,prod.kv", "set", "port", "80"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["--overlay", "base.kv", "get", "port"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("port: 8080\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["--overlay", "base.kv,prod.kv", "get", "port"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("port: 80\n"));
+}
+
+#[test]
+fn binary_with_derive_computes_key_without_storing_it() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["set", "host", "web1"])
+        .assert()
+        .success();
+    let derive = ["--derive", "url=https://${host}/"];
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(derive)
+        .args(["get", "url"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("url: https://web1/\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(derive)
+        .args(["list", "--include-derived"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("host: web1\nurl: https://web1/\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(derive)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::eq("host: web1\n"));
+}
+
+#[test]
+fn binary_get_as_converts_or_rejects_value() {
+    let tmp_dir = TempDir::new().unwrap();
+    for (k, v) in [("workers", "1_000"), ("timeout", "1m30s"), ("name", "web")] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir)
+            .args(["set", k, v])
+            .assert()
+            .success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["get", "--as", "int", "workers"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("workers: 1000\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["get", "--as", "duration", "timeout"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("timeout: 90\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["get", "--as", "int", "name"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected an integer"));
+}
+
+#[test]
+fn binary_rekey_re_signs_data_file_with_new_key() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_SIGNING_KEY", "old")
+        .args(["set", "key1", "value1"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_SIGNING_KEY", "old")
+        .arg("rekey")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("RSKEY_NEW_SIGNING_KEY"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_SIGNING_KEY", "old")
+        .env("RSKEY_NEW_SIGNING_KEY", "new")
+        .arg("rekey")
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_SIGNING_KEY", "new")
+        .args(["get", "key1"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("key1: value1\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_SIGNING_KEY", "old")
+        .args(["get", "key1"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn binary_set_secret_encrypts_value_in_data_file() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_ENCRYPTION_KEY", "secret")
+        .args(["set", "--secret", "token", "xyz"])
+        .assert()
+        .success();
+    let file = std::fs::read_to_string(tmp_dir.path().join("store.kv")).unwrap();
+    assert!(!file.contains("xyz"), "plaintext in data file: {file}");
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_ENCRYPTION_KEY", "secret")
+        .args(["get", "token"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("token: xyz\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::eq("token: *****\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_ENCRYPTION_KEY", "wrong")
+        .args(["get", "token"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("can't decrypt"));
+}
+
+#[test]
+fn binary_counts_and_exports_encrypted_values() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.arg("-")
+        .current_dir(&tmp_dir)
+        .env("RSKEY_ENCRYPTION_KEY", "secret")
+        .write_stdin("set host web1\nset --secret token xyz\n")
+        .assert()
+        .success();
+    // Derived keys stop the file being scanned, so both ways of reading it
+    // are checked.
+    for opts in [&[][..], &["--derive", "x=1"]] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir)
+            .args(opts)
+            .args(["exists", "token"])
+            .assert()
+            .success();
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir)
+            .args(opts)
+            .arg("count")
+            .assert()
+            .success()
+            .stdout(predicate::eq("2\n"));
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir)
+            .args(opts)
+            .args(["keys", "--prefix", "t"])
+            .assert()
+            .success()
+            .stdout(predicate::eq("token\n"));
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .arg("export")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("RSKEY_ENCRYPTION_KEY"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_ENCRYPTION_KEY", "secret")
+        .arg("export")
+        .assert()
+        .success()
+        .stdout(predicate::eq(
+            "{\n  \"host\": \"web1\",\n  \"token\": \"xyz\"\n}\n",
+        ));
+    let file = std::fs::read_to_string(tmp_dir.path().join("store.kv")).unwrap();
+    assert!(!file.contains("xyz"), "plaintext in data file: {file}");
+}
+
+#[test]
+fn binary_with_dash_set_secret_encrypts_value() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.arg("-")
+        .current_dir(&tmp_dir)
+        .env("RSKEY_ENCRYPTION_KEY", "secret")
+        .write_stdin("set --secret token two words\n")
+        .assert()
+        .success();
+    let file = std::fs::read_to_string(tmp_dir.path().join("store.kv")).unwrap();
+    assert!(
+        !file.contains("two words"),
+        "plaintext in data file: {file}"
+    );
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_ENCRYPTION_KEY", "secret")
+        .args(["get", "token"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("token: two words\n"));
+}
+
+#[test]
+fn binary_rekey_re_encrypts_secret_values_with_new_key() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_ENCRYPTION_KEY", "old")
+        .args(["set", "--secret", "token", "xyz"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_ENCRYPTION_KEY", "wrong")
+        .env("RSKEY_NEW_ENCRYPTION_KEY", "new")
+        .arg("rekey")
+        .assert()
+        .failure();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_ENCRYPTION_KEY", "old")
+        .env("RSKEY_NEW_ENCRYPTION_KEY", "new")
+        .arg("rekey")
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_ENCRYPTION_KEY", "new")
+        .args(["get", "token"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("token: xyz\n"));
+}
+
+#[test]
+fn binary_read_only_commands_dont_lock_or_create_directories() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args([
+            "-n",
+            "app",
+            "--derive",
+            "url=https://${host}/",
+            "get",
+            "url",
+        ])
+        .assert()
+        .success();
+    assert!(
+        !tmp_dir.path().join(".rskey").exists(),
+        "namespace directory created"
+    );
+}
+
+#[test]
+fn binary_with_import_from_redis_rejects_unknown_options() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args([
+            "import",
+            "--from-redis",
+            "redis://127.0.0.1:1/0",
+            "--prefix",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown options"));
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn binary_with_export_format_sqlite_and_import_round_trips_store() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["set", "key1", "value1"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["export", "--format", "sqlite", "store.db"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["-n", "copy", "import", "store.db"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["-n", "copy", "get", "key1"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("key1: value1\n"));
+}
+
+#[test]
+fn binary_with_shrink_drops_superseded_ops() {
+    let tmp_dir = TempDir::new().unwrap();
+    for value in ["1", "2"] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir)
+            .args(["--log", "set", "key1", value])
+            .assert()
+            .success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .arg("shrink")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("removed 1 superseded operations"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .arg("export-ops")
+        .assert()
+        .success()
+        .stdout(predicate::eq(
+            "{\"op\":\"set\",\"seq\":2,\"key\":\"key1\",\"value\":\"2\"}\n",
+        ));
+}
+
+#[test]
+fn binary_with_verify_reports_problems_and_fails() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["set", "key1", "value1"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .arg("verify")
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+    std::fs::write(tmp_dir.path().join("store.kv"), r#"{"key1":"value1"}"#).unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .arg("verify")
+        .assert()
+        .failure()
+        .stdout(predicate::eq("data file has no format header\n"));
+}
+
+#[test]
+fn binary_with_export_prefix_writes_matching_keys_to_file() {
+    let tmp_dir = TempDir::new().unwrap();
+    for (key, value) in [("app:port", "80"), ("db:port", "5432")] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir)
+            .args(["set", key, value])
+            .assert()
+            .success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args([
+            "export",
+            "--prefix",
+            "app:",
+            "--format",
+            "json",
+            "subset.kv",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+    let subset = std::fs::read_to_string(tmp_dir.path().join("subset.kv")).unwrap();
+    assert_eq!("{\n  \"app:port\": \"80\"\n}\n", subset);
+}
+
+#[test]
+fn binary_with_split_and_join_round_trips_keys() {
+    let tmp_dir = TempDir::new().unwrap();
+    for (key, value) in [("app:port", "80"), ("db:port", "5432")] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir)
+            .args(["set", key, value])
+            .assert()
+            .success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["split", "--by-prefix", ":", "--out-dir", "parts"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("app.kv: 1 keys"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args([
+            "join",
+            "parts/app.kv",
+            "parts/db.kv",
+            "--out",
+            "combined.kv",
+        ])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["--overlay", "combined.kv", "get", "db:port"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("db:port: 5432\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["set", "app:port", "8080"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["join", "store.kv", "--out", "combined.kv"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--on-conflict"));
+}
+
+#[test]
+fn binary_with_dry_run_lists_changes_without_making_them() {
+    let tmp_dir = TempDir::new().unwrap();
+    for (key, value) in [("tmp_a", "1"), ("tmp_b", "2"), ("keep", "3")] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir)
+            .args(["set", key, value])
+            .assert()
+            .success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["--dry-run", "del", "--prefix", "tmp_"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("delete tmp_a\ndelete tmp_b\n"));
+    std::fs::write(tmp_dir.path().join("new.json"), r#"{"keep":"4","new":"5"}"#).unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["--dry-run", "import", "new.json"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("change keep\nadd new\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["--dry-run", "set", "keep", "5"])
+        .assert()
+        .failure();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["-y", "del", "--prefix", "tmp_"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .arg("count")
+        .assert()
+        .success()
+        .stdout(predicate::eq("1\n"));
+}
+
+#[test]
+fn binary_with_dry_run_lists_keys_clear_and_import_overwrite_would_delete() {
+    let tmp_dir = TempDir::new().unwrap();
+    for args in [
+        &["set", "a", "1"][..],
+        &["set", "b", "2"],
+        &["set", "c", "3"],
+        &["protect", "c"],
+    ] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir).args(args).assert().success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["--dry-run", "clear"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("delete a\ndelete b\n"));
+    std::fs::write(tmp_dir.path().join("new.json"), r#"{"b":"4","d":"5"}"#).unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["--dry-run", "import", "--overwrite", "new.json"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("delete a\nchange b\nadd d\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .arg("keys")
+        .assert()
+        .success()
+        .stdout(predicate::eq("a\nb\nc\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["-y", "import", "--overwrite", "new.json"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .arg("keys")
+        .assert()
+        .success()
+        .stdout(predicate::eq("b\nc\nd\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["-y", "clear"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .arg("keys")
+        .assert()
+        .success()
+        .stdout(predicate::eq("c\n"));
+}
+
+#[test]
+fn binary_with_dry_run_applies_protection_and_schemas() {
+    let tmp_dir = TempDir::new().unwrap();
+    for args in [
+        &["set", "tmp_a", "1"][..],
+        &["set", "tmp_b", "2"],
+        &["protect", "tmp_b"],
+        &["schema", "n*", r#"{"type":"string","maxLength":2}"#],
+    ] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir).args(args).assert().success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["--dry-run", "del", "--prefix", "tmp_"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("delete tmp_a\n"));
+    std::fs::write(tmp_dir.path().join("bad.json"), r#"{"n1":"toolong"}"#).unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["--dry-run", "import", "bad.json"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("at most 2 characters"));
+    std::fs::write(tmp_dir.path().join("good.json"), r#"{"n1":"ok"}"#).unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["--dry-run", "import", "good.json"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("add n1\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .arg("count")
+        .assert()
+        .success()
+        .stdout(predicate::eq("2\n"));
+}
+
+#[test]
+fn binary_with_import_flatten_and_export_unflatten_round_trips_config() {
+    let tmp_dir = TempDir::new().unwrap();
+    std::fs::write(
+        tmp_dir.path().join("config.yaml"),
+        "server:\n  host: web1\n  port: 8080\n",
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["import", "--flatten", "config.yaml"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["get", "server.port"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("server.port: 8080\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["export", "--unflatten", "--format", "yaml"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("server:\n  host: web1\n  port: 8080\n"));
+}
+
+#[test]
+fn binary_with_export_markdown_prints_table_with_metadata_columns() {
+    let tmp_dir = TempDir::new().unwrap();
+    for args in [
+        ["set", "key1", "a|b"].as_slice(),
+        &["set", "key2", "value2"],
+        &["protect", "key2"],
+    ] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir).args(args).assert().success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["export", "--format", "markdown"])
+        .assert()
+        .success()
+        .stdout(predicate::eq(
+            "| Key | Value | Protected |\n\
+             | --- | --- | --- |\n\
+             | key1 | a\\|b |  |\n\
+             | key2 | value2 | yes |\n",
+        ));
+}
+
+#[test]
+fn binary_with_set_if_version_rejects_stale_version() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["set", "--if-version", "0", "key1", "value1"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["version", "key1"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("1\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["set", "--if-version", "0", "key1", "value2"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is at version 1"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["set", "--if-version", "1", "key1", "value2"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn binary_waits_for_lock_held_by_another_process() {
+    let tmp_dir = TempDir::new().unwrap();
+    let lock = rskey::FileLock::acquire(tmp_dir.path().join("store.kv"), Duration::ZERO).unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .env("RSKEY_LOCK_TIMEOUT", "0")
+        .args(["set", "key1", "value1"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is locked"));
+    drop(lock);
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["set", "key1", "value1"])
+        .assert()
+        .success();
+    assert!(
+        !tmp_dir.path().join("store.kv.lock").exists(),
+        "lock file not removed"
+    );
+}
+
+#[cfg(windows)]
+#[test]
+fn sync_on_windows_keeps_attributes_of_replaced_file() {
+    use std::os::windows::fs::{MetadataExt, OpenOptionsExt};
+
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 2;
+    let tmp_dir = TempDir::new().unwrap();
+    let path = tmp_dir.path().join("store.kv");
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .attributes(FILE_ATTRIBUTE_HIDDEN)
+        .open(&path)
+        .unwrap();
+    std::io::Write::write_all(&mut file, b"{}").unwrap();
+    drop(file);
+    let mut s = rskey::Store::<String>::open(&path).unwrap();
+    s.insert("key1".to_string(), "value1".to_string()).unwrap();
+    s.sync().unwrap();
+    let attributes = std::fs::metadata(&path).unwrap().file_attributes();
+    assert_ne!(0, attributes & FILE_ATTRIBUTE_HIDDEN, "attributes not kept");
+    let s = rskey::Store::<String>::open(&path).unwrap();
+    assert_eq!("value1", s["key1"], "expected data not returned");
+}
+
+#[cfg(windows)]
+#[test]
+fn sync_on_windows_replaces_file_open_for_reading() {
+    let tmp_dir = TempDir::new().unwrap();
+    let path = tmp_dir.path().join("store.kv");
+    let mut s = rskey::Store::<String>::open(&path).unwrap();
+    s.insert("key1".to_string(), "value1".to_string()).unwrap();
+    s.sync().unwrap();
+    // The standard library opens files with FILE_SHARE_DELETE, as other
+    // readers sharing the store should.
+    let reader = std::fs::File::open(&path).unwrap();
+    s.insert("key1".to_string(), "value2".to_string()).unwrap();
+    s.sync().expect("file open for reading not replaced");
+    drop(reader);
+    let s = rskey::Store::<String>::open(&path).unwrap();
+    assert_eq!("value2", s["key1"], "expected data not returned");
+}
+
+#[test]
+fn binary_with_git_commits_each_change() {
+    let tmp_dir = TempDir::new().unwrap();
+    let git = |args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .current_dir(&tmp_dir)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?} failed");
+        String::from_utf8(output.stdout).unwrap()
+    };
+    git(&["init", "-q"]);
+    git(&["config", "user.name", "Test"]);
+    git(&["config", "user.email", "test@example.com"]);
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["--git", "set", "key1", "value1"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["--git", "del", "key1"])
+        .assert()
+        .success();
+    assert_eq!(
+        "delete key1\nset key1\n",
+        git(&["log", "--format=%s"]),
+        "wrong commits"
+    );
+}
+
+#[test]
+fn binary_with_snapshots_restore_reverts_changes() {
+    let tmp_dir = TempDir::new().unwrap();
+    let rskey = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        let output = cmd.current_dir(&tmp_dir).args(args).assert().success();
+        String::from_utf8(output.get_output().stdout.clone()).unwrap()
+    };
+    rskey(&["set", "key1", "value1"]);
+    let name = rskey(&["snapshot", "--keep", "7d"]);
+    let name = name.trim();
+    assert!(
+        rskey(&["snapshots", "list"]).starts_with(&format!("{name}\t")),
+        "snapshot not listed"
+    );
+    rskey(&["set", "key1", "value2"]);
+    rskey(&["snapshots", "restore", name]);
+    assert_eq!("key1: value1\n", rskey(&["get", "key1"]), "not restored");
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["snapshots", "restore", "1"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no snapshot named"));
+}
+
+#[test]
+fn binary_with_import_ops_replays_exported_changes() {
+    let tmp_dir = TempDir::new().unwrap();
+    let rskey = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        let output = cmd.current_dir(&tmp_dir).args(args).assert().success();
+        String::from_utf8(output.get_output().stdout.clone()).unwrap()
+    };
+    rskey(&["--log", "set", "key1", "value1"]);
+    rskey(&["--log", "set", "key2", "value2"]);
+    rskey(&["--log", "del", "key1"]);
+    let ops = rskey(&["export-ops", "--since", "1"]);
+    assert_eq!(
+        "{\"op\":\"set\",\"seq\":2,\"key\":\"key2\",\"value\":\"value2\"}\n\
+         {\"op\":\"delete\",\"seq\":3,\"key\":\"key1\"}\n",
+        ops,
+        "wrong ops exported"
+    );
+    std::fs::write(tmp_dir.path().join("ops.jsonl"), rskey(&["export-ops"])).unwrap();
+    rskey(&["-n", "copy", "import-ops", "ops.jsonl"]);
+    assert_eq!(
+        "key2: value2\n",
+        rskey(&["-n", "copy", "list"]),
+        "ops not replayed"
+    );
+}
+
+#[test]
+fn binary_with_stats_reports_duplicate_values() {
+    let tmp_dir = TempDir::new().unwrap();
+    for args in [["set", "a", "same"], ["set", "b", "same"]] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir).args(args).assert().success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.arg("stats")
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq(
+            "keys: 2\nbytes: 14\ndistinct values: 1\nduplicate value bytes: 6\n",
+        ));
+}
+
+#[test]
+fn binary_with_top_lists_largest_keys_and_prefixes() {
+    let tmp_dir = TempDir::new().unwrap();
+    for args in [
+        ["set", "db_host", "localhost"],
+        ["set", "db_port", "5432"],
+        ["set", "log_level", "info"],
+    ] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir).args(args).assert().success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["top", "1"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("18\tdb_host\n\n31\tdb_*\n15\tlog_*\n"));
+}
+
+#[test]
+fn binary_with_del_prefix_deletes_matching_keys() {
+    let tmp_dir = TempDir::new().unwrap();
+    for args in [
+        ["set", "db_host", "localhost"],
+        ["set", "db_port", "5432"],
+        ["set", "log_level", "info"],
+    ] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir).args(args).assert().success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["del", "--prefix", "db_"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.arg("keys")
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("log_level\n"));
+}
+
+#[test]
+fn binary_with_grep_lists_pairs_matching_regex() {
+    let tmp_dir = TempDir::new().unwrap();
+    for args in [
+        ["set", "db_host", "localhost"],
+        ["set", "db_port", "5432"],
+        ["set", "log_level", "info"],
+        ["set", "token", "4321"],
+    ] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir).args(args).assert().success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["secret", "token"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["grep", r"^\d+$|^log"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("db_port: 5432\nlog_level: info\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["grep", "host", "--values-only"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("db_host: localhost\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["grep", "local", "--keys-only"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn binary_with_sample_lists_n_random_pairs() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    let commands = (0..10)
+        .map(|i| format!("set key{i} value{i}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    cmd.current_dir(&tmp_dir)
+        .arg("-")
+        .write_stdin(commands)
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    let output = cmd
+        .args(["sample", "3"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success();
+    let stdout = String::from_utf8(output.get_output().stdout.clone()).unwrap();
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(3, lines.len(), "wrong number of pairs: {stdout}");
+    for line in lines {
+        let (k, v) = line.split_once(": ").unwrap();
+        assert_eq!(k.replace("key", "value"), v, "mismatched pair");
+    }
+}
+
+#[test]
+fn binary_with_agg_combines_numeric_values() {
+    let tmp_dir = TempDir::new().unwrap();
+    for args in [
+        ["set", "metrics:hits", "40"],
+        ["set", "metrics:misses", "2"],
+        ["set", "limit", "100"],
+    ] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir).args(args).assert().success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["agg", "sum", "--prefix", "metrics:"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("42\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["agg", "avg"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq("47.333333333333336\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["agg", "median"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown aggregate"));
+}
+
+#[test]
+fn binary_with_list_group_by_prefix_prints_tree() {
+    let tmp_dir = TempDir::new().unwrap();
+    for args in [
+        ["set", "db:host", "localhost"],
+        ["set", "db:pass", "hunter2"],
+        ["set", "log:level", "info"],
+        ["set", "log", "on"],
+    ] {
+        let mut cmd = Command::cargo_bin("rskey").unwrap();
+        cmd.current_dir(&tmp_dir).args(args).assert().success();
+    }
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["secret", "db:pass"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["list", "--group-by-prefix", ":"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq(
+            "db\n  host: localhost\n  pass: *****\nlog: on\n  level: info\n",
+        ));
+}
+
+#[test]
+fn binary_with_bench_prints_timings_without_touching_store() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["bench", "10"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("10 entries, "))
+        .stdout(predicate::str::contains("\ninsert "))
+        .stdout(predicate::str::contains("\nopen "));
+    assert!(
+        !tmp_dir.path().join("store.kv").exists(),
+        "store created in working directory"
+    );
+}
+
+#[test]
+fn binary_with_size_thresholds_warns_and_enforces_limit() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["set", "key1", "value1"])
+        .env("RSKEY_WARN_AT_ENTRIES", "0")
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "warning: store has 1 entries, more than 0",
+        ));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["set", "key2", "value2"])
+        .env("RSKEY_BYTE_LIMIT", "20")
+        .current_dir(&tmp_dir)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("exceed its limit of 20 bytes"));
+}
+
+#[test]
+fn binary_with_gc_removes_orphaned_temp_file() {
+    let tmp_dir = TempDir::new().unwrap();
+    std::fs::write(tmp_dir.path().join("store.kv.tmp"), "{\"partial").unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.arg("gc")
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::ends_with(
+            "store.kv.tmp\nreclaimed 9 bytes\n",
+        ));
+    assert!(
+        !tmp_dir.path().join("store.kv.tmp").exists(),
+        "temp file not removed"
+    );
+}
+
+#[test]
+fn binary_normalizes_keys_for_append_exists_and_ttl() {
+    let tmp_dir = TempDir::new().unwrap();
+    let path = tmp_dir.path().join("store.kv");
+    let mut s = rskey::Store::<String>::open(&path).unwrap();
+    s.set_key_normalization(rskey::KeyNormalization {
+        case_fold: true,
+        nfc: false,
+    })
+    .unwrap();
+    s.insert("foo".to_string(), "a".to_string());
+    s.sync().unwrap();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["append", "--force", "Foo", "b"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["get", "FOO"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("FOO: ab\n"));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["--derive", "x=1", "exists", "Foo"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.current_dir(&tmp_dir)
+        .args(["ttl", "Foo"])
+        .assert()
+        .success()
+        .stdout(predicate::eq("key \"Foo\" does not expire\n"));
+}
+
+#[test]
+fn binary_with_compress_shrinks_large_values_in_data_file() {
+    let tmp_dir = TempDir::new().unwrap();
+    let path = tmp_dir.path().join("store.kv");
+    let big = "abc".repeat(100);
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["compress", "100"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["set", "big", &big])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success();
+    let file = std::fs::read_to_string(&path).unwrap();
+    assert!(!file.contains(&big), "value not compressed: {file}");
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["get", "big"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success()
+        .stdout(predicate::eq(format!("big: {big}\n")));
+    let mut cmd = Command::cargo_bin("rskey").unwrap();
+    cmd.args(["uncompress"])
+        .current_dir(&tmp_dir)
+        .assert()
+        .success();
+    let file = std::fs::read_to_string(&path).unwrap();
+    assert!(file.contains(&big), "value still compressed: {file}");
+}